@@ -1,3 +1,4 @@
+use chrono_tz::Tz;
 use tokio_rusqlite::Connection;
 
 use crate::ai::chat::ChatBuilder;
@@ -8,13 +9,14 @@ use crate::openai::{BoxedToolCall, Message, Role};
 pub async fn daily_agenda_response(
     db: &Connection,
     api_base_url: &str,
+    timezone: Tz,
     calendar_emails: Vec<String>,
     openai_api_hostname: &str,
     openai_api_key: &str,
     openai_model: &str,
 ) -> (String, Vec<Message>) {
-    let tasks_due_today_tool = TasksDueTodayTool::new(api_base_url);
-    let tasks_scheduled_today_tool = TasksScheduledTodayTool::new(api_base_url);
+    let tasks_due_today_tool = TasksDueTodayTool::new(api_base_url, timezone);
+    let tasks_scheduled_today_tool = TasksScheduledTodayTool::new(api_base_url, timezone);
     let calendar_tool = CalendarTool::new(db.clone(), api_base_url);
 
     let tools: Vec<BoxedToolCall> = vec![
@@ -13,7 +13,9 @@ pub async fn email_chat_response(
     openai_api_key: &str,
     openai_model: &str,
 ) -> (String, Vec<Message>) {
-    let email_unread_tool = EmailUnreadTool::new(api_base_url);
+    // Mark threads read once they're processed so the next run only
+    // summarizes new emails
+    let email_unread_tool = EmailUnreadTool::new(api_base_url).mark_read(true);
     let tools: Vec<BoxedToolCall> = vec![Box::new(email_unread_tool)];
 
     let system_msg = format!(
@@ -31,3 +33,82 @@ pub async fn email_chat_response(
     let response = chat.next_msg(user_msg).await.expect("Chat session failed");
     (chat.session_id.unwrap(), response)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::test_db;
+
+    #[tokio::test]
+    async fn it_marks_processed_threads_read() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let tool_call_response = r#"{
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": {
+                            "name": "get_unread_emails",
+                            "arguments": "{\"email\":\"test@example.com\"}"
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }"#;
+        let final_response = r#"{
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "No unread emails."},
+                "finish_reason": "stop"
+            }]
+        }"#;
+        let _tool_call_mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(tool_call_response)
+            .create_async()
+            .await;
+        let _final_mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(final_response)
+            .create_async()
+            .await;
+        let unread_mock = server
+            .mock(
+                "GET",
+                "/api/email/unread?email=test%40example.com&mark_read=true",
+            )
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = test_db(dir.path()).await;
+        let (_session_id, messages) = email_chat_response(
+            &db,
+            &url,
+            vec![String::from("test@example.com")],
+            &url,
+            "test-key",
+            "gpt-4",
+        )
+        .await;
+
+        unread_mock.assert_async().await;
+        assert_eq!(
+            messages.last().unwrap().content(),
+            Some("No unread emails.")
+        );
+    }
+}
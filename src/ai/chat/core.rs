@@ -1,16 +1,115 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
 use anyhow::{Error, Result, anyhow, bail};
 use futures_util::future::try_join_all;
+use serde::Serialize;
 use serde_json::Value;
-use tokio::sync::mpsc;
+use tokio::sync::{Semaphore, mpsc};
 use tokio_rusqlite::Connection;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use super::db::{get_or_create_session, insert_chat_message};
 use super::models::Transcript;
+use crate::ai::tokens;
+use crate::core::metrics::{MetricName, insert_metric_event};
+use crate::core::redact::redact_secrets;
+use crate::core::{DEFAULT_MAX_CONCURRENT_TOOLS, DEFAULT_RETRY_BUDGET, DEFAULT_TOOL_TIMEOUT};
 use crate::openai::{
-    BoxedToolCall, FunctionCall, FunctionCallFn, Message, Role, completion, completion_stream,
+    BoxedToolCall, CompletionCancelled, CompletionOptions, CompletionParams, FunctionCall,
+    FunctionCallFn, Message, OpenAiApiError, Provider, ReasoningEffort, RetryHook, Role,
+    ToolOutput, completion, completion_stream,
 };
 
+/// Sent on the streaming channel around each tool call so clients can
+/// show progress while tools run. Tagged with a `type` to tell them
+/// apart from completion chunks.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolEvent<'a> {
+    ToolStart { name: &'a str },
+    ToolEnd { name: &'a str },
+}
+
+/// A named bundle of instructions and tools that can be enabled on a
+/// chat e.g. "calendar" with a prompt about scheduling conventions and
+/// the calendar tools.
+#[derive(Clone, Debug)]
+pub struct Skill {
+    pub name: String,
+    /// Appended to the system message when the skill is enabled
+    pub prompt_fragment: String,
+    /// Names of the tools the skill needs
+    pub tool_names: Vec<String>,
+}
+
+impl ToolEvent<'_> {
+    /// Send the event on `tx`. Nothing is sent when not streaming.
+    fn send(&self, tx: Option<&mpsc::UnboundedSender<String>>) {
+        if let Some(tx) = tx {
+            let event = serde_json::to_string(self).expect("Failed to serialize tool event");
+            let _ = tx.send(event);
+        }
+    }
+}
+
+/// Error returned when a chat turn gives up because it used all of its
+/// retries
+#[derive(Debug)]
+pub struct RetryBudgetExhausted {
+    pub budget: usize,
+    /// The error that would have been retried
+    pub last_error: String,
+}
+
+impl std::fmt::Display for RetryBudgetExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Giving up after {} retries in this chat turn. Last error: {}",
+            self.budget, self.last_error
+        )
+    }
+}
+
+impl std::error::Error for RetryBudgetExhausted {}
+
+/// Retries left in a chat turn. Shared by completion retries and
+/// failed tool calls so a turn that keeps failing gives up instead of
+/// retrying indefinitely. Embeddings aren't retried in a chat turn,
+/// note search falls back to full-text instead, so there's nothing
+/// to charge for them.
+struct RetryBudget {
+    budget: usize,
+    used: AtomicUsize,
+}
+
+impl RetryBudget {
+    fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Use a retry because of `err`. Returns the number of retries
+    /// used so far or an error if there are none left.
+    fn spend(&self, err: &Error) -> Result<usize, Error> {
+        let used = self.used.fetch_add(1, Ordering::SeqCst) + 1;
+        if used > self.budget {
+            return Err(RetryBudgetExhausted {
+                budget: self.budget,
+                last_error: redact_secrets(&format!("{:#}", err)),
+            }
+            .into());
+        }
+        Ok(used)
+    }
+}
+
 /// The core abstraction around interacting with an LLM in a chat
 /// completion style using an OpenAI compatible API.
 ///
@@ -29,18 +128,27 @@ pub struct Chat {
     streaming: bool,
     tx: Option<mpsc::UnboundedSender<String>>,
     tools: Option<Vec<BoxedToolCall>>,
+    max_concurrent_tools: usize,
+    tool_timeout: Duration,
+    permissions: Option<Vec<String>>,
+    store_reasoning: bool,
+    retry_budget: usize,
+    max_context_tokens: Option<usize>,
     transcript: Transcript,
     pub session_id: Option<String>,
     tags: Option<Vec<String>>,
-    // TODO: Skills
+    completion_options: CompletionOptions,
     // TODO: MCP
-    // TODO: Permissions
 }
 
 impl Chat {
     async fn handle_tool_call(
         tools: &Vec<BoxedToolCall>,
+        db: &Option<Connection>,
         tool_call: &Value,
+        tool_timeout: Duration,
+        permissions: Option<&[String]>,
+        budget: &RetryBudget,
     ) -> Result<Vec<Message>, Error> {
         let tool_call_id = &tool_call["id"]
             .as_str()
@@ -59,17 +167,119 @@ impl Chat {
             &tool_call_args
         );
 
-        // Call the tool and get the next completion from the result
-        let tool_call_result = tools
-            .iter()
-            .find(|i| *i.function_name() == *tool_call_name)
-            .ok_or(anyhow!(
-                "Received tool call that doesn't exist: {}",
+        // Tools that aren't permitted in this chat are never called,
+        // the model is told so it can carry on without it
+        if permissions.is_some_and(|allowed| !allowed.iter().any(|name| name == tool_call_name)) {
+            tracing::warn!(
+                "Received tool call that isn't permitted: {}",
                 tool_call_name
-            ))?
-            .call(tool_call_args)
-            .await?;
+            );
+            return Ok(Self::tool_call_messages(
+                tool_call_id,
+                tool_call_name,
+                tool_call_args,
+                ToolOutput::from(format!("Tool not permitted: {}", tool_call_name)),
+            ));
+        }
+
+        // Call the tool and get the next completion from the result.
+        // Models sometimes hallucinate tool names so rather than
+        // failing the chat, tell the model the tool doesn't exist so it
+        // can recover.
+        let Some(tool) = tools.iter().find(|i| *i.function_name() == *tool_call_name) else {
+            tracing::warn!("Received tool call that doesn't exist: {}", tool_call_name);
+            let available = tools
+                .iter()
+                .map(|t| t.function_name())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let tool_call_result = format!(
+                "Tool call failed: no tool named {} exists. Available tools: {}",
+                tool_call_name, available
+            );
+            return Ok(Self::tool_call_messages(
+                tool_call_id,
+                tool_call_name,
+                tool_call_args,
+                ToolOutput::from(tool_call_result),
+            ));
+        };
+        let start = Instant::now();
+        // A tool that hangs would otherwise hold up the whole turn
+        let tool_call_result =
+            match tokio::time::timeout(tool_timeout, tool.call_structured(tool_call_args)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow!("Tool timed out after {:?}", tool_timeout)),
+            };
+        let elapsed_ms = start.elapsed().as_millis() as i64;
+
+        // Record how long the tool call took. Failing to record a
+        // metric shouldn't fail the chat.
+        if let Some(db) = db
+            && let Err(e) = insert_metric_event(
+                db,
+                MetricName::ToolLatency,
+                elapsed_ms,
+                Some(tool_call_name.to_string()),
+            )
+            .await
+        {
+            tracing::error!("Failed to record tool latency metric: {}", e);
+        }
+
+        let tool_call_result = match tool_call_result {
+            Ok(result) => result,
+            Err(e) => {
+                // The model usually tries again after a failed tool
+                // call so each failure uses one of the turn's retries
+                budget.spend(&e)?;
+
+                // Give the error back to the model so the chat can
+                // continue. Errors (e.g. from reqwest) can include URLs
+                // with API keys so they need to be redacted first.
+                let err = redact_secrets(&format!("{:#}", e));
+                tracing::warn!("Tool call {} failed: {}", tool_call_name, err);
+                ToolOutput::from(format!("Tool call failed: {}", err))
+            }
+        };
+
+        Ok(Self::tool_call_messages(
+            tool_call_id,
+            tool_call_name,
+            tool_call_args,
+            tool_call_result,
+        ))
+    }
+
+    /// Record the total tokens used by a completion response labeled
+    /// with the model that answered, which is a fallback's model when
+    /// `model` was unavailable. Failing to record a metric shouldn't
+    /// fail the chat.
+    async fn record_token_usage(db: &Option<Connection>, model: &str, resp: &Value) {
+        let (Some(db), Some(total_tokens)) = (db, resp["usage"]["total_tokens"].as_i64()) else {
+            return;
+        };
+        let model = resp["model"].as_str().unwrap_or(model);
+        if let Err(e) = insert_metric_event(
+            db,
+            MetricName::TokenCount,
+            total_tokens,
+            Some(model.to_string()),
+        )
+        .await
+        {
+            tracing::error!("Failed to record token count metric: {}", e);
+        }
+    }
 
+    /// The tool call request and response messages to add to the
+    /// transcript for a tool call
+    fn tool_call_messages(
+        tool_call_id: &str,
+        tool_call_name: &str,
+        tool_call_args: &str,
+        tool_call_result: ToolOutput,
+    ) -> Vec<Message> {
         let tool_call_request = vec![FunctionCall {
             function: FunctionCallFn {
                 arguments: tool_call_args.to_string(),
@@ -78,18 +288,28 @@ impl Chat {
             id: tool_call_id.to_string(),
             r#type: String::from("function"),
         }];
-        let results = vec![
+        vec![
             Message::new_tool_call_request(tool_call_request),
-            Message::new_tool_call_response(&tool_call_result, tool_call_id),
-        ];
-
-        Ok(results)
+            Message::new_tool_call_response(&tool_call_result.text, tool_call_id)
+                .with_structured_content(tool_call_result.structured),
+        ]
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_tool_calls(
         tools: &Vec<BoxedToolCall>,
+        db: &Option<Connection>,
         tool_calls: &[Value],
+        max_concurrent_tools: usize,
+        tool_timeout: Duration,
+        permissions: Option<&[String]>,
+        budget: &RetryBudget,
+        tx: Option<&mpsc::UnboundedSender<String>>,
     ) -> Result<Vec<Message>, Error> {
+        // Limit how many tools run at once so a model requesting a
+        // lot of tool calls doesn't overwhelm the APIs they call
+        let semaphore = Semaphore::new(max_concurrent_tools.max(1));
+
         // Run each tool call concurrently and return them in order. I'm
         // not sure if ordering really matters for OpenAI compatible API
         // implementations, but better to be safe. This could also be
@@ -97,21 +317,62 @@ impl Chat {
         // would be more efficient as it runs on the same thread, but that
         // causes lifetime issues that I don't understand how to get
         // around.
-        let futures = tool_calls
-            .iter()
-            .map(|call| Self::handle_tool_call(tools, call));
+        let futures = tool_calls.iter().map(|call| async {
+            let _permit = semaphore.acquire().await?;
+            let name = call["function"]["name"].as_str().unwrap_or_default();
+            ToolEvent::ToolStart { name }.send(tx);
+            let result =
+                Self::handle_tool_call(tools, db, call, tool_timeout, permissions, budget).await;
+            ToolEvent::ToolEnd { name }.send(tx);
+            result
+        });
         // Flatten the results to match what the API is expecting.
         let results = try_join_all(futures).await?.into_iter().flatten().collect();
         Ok(results)
     }
 
+    /// Find the tool call that a message is requesting or responding
+    /// to from the messages in the same turn.
+    fn find_tool_call<'a>(msg: &'a Message, messages: &'a [Message]) -> Option<&'a FunctionCallFn> {
+        match msg.role() {
+            Role::Assistant => match msg.tool_calls() {
+                Some([call]) => Some(&call.function),
+                _ => None,
+            },
+            Role::Tool => {
+                let tool_call_id = msg.tool_call_id()?;
+                messages
+                    .iter()
+                    .flat_map(|m| m.tool_calls().unwrap_or_default())
+                    .find(|call| call.id == tool_call_id)
+                    .map(|call| &call.function)
+            }
+            _ => None,
+        }
+    }
+
     /// The inner chat loop that handles sending and receiving the
     /// next response from the LLM, tool calls,
     /// Runs the next turn in chat by passing a transcript to the LLM for
     /// the next response. Can return multiple messages when there are
     /// tool calls.
     pub async fn next_msg(&mut self, msg: Message) -> Result<Vec<Message>, Error> {
+        // Nothing is sent in a dry run so the transcript and DB are
+        // left untouched and the payload is returned as the response
+        if self.completion_options.dry_run {
+            let payload = self.preview_msg(msg).await?;
+            return Ok(vec![Message::new(
+                Role::Assistant,
+                &serde_json::to_string_pretty(&payload)?,
+            )]);
+        }
+
         self.transcript.push(msg.clone());
+        if let Err(e) = self.transcript.validate() {
+            // Don't leave the transcript in an invalid state
+            self.transcript.pop();
+            return Err(e);
+        }
 
         let messages = if self.streaming {
             // ChatBuilder enforces that `streaming` and `tx` are
@@ -120,22 +381,44 @@ impl Chat {
             Self::chat_stream(
                 tx.clone(),
                 &self.tools,
+                self.max_concurrent_tools,
+                self.tool_timeout,
+                self.permissions.as_deref(),
+                self.retry_budget,
+                self.max_context_tokens,
+                &self.db,
                 &self.transcript,
                 &self.api_hostname,
                 &self.api_key,
                 &self.model,
+                &self.completion_options,
             )
             .await?
         } else {
             Self::chat(
                 &self.tools,
+                self.max_concurrent_tools,
+                self.tool_timeout,
+                self.permissions.as_deref(),
+                self.retry_budget,
+                self.max_context_tokens,
+                &self.db,
                 &self.transcript,
                 &self.api_hostname,
                 &self.api_key,
                 &self.model,
+                &self.completion_options,
             )
             .await?
         };
+        let messages: Vec<Message> = if self.store_reasoning {
+            messages
+        } else {
+            messages
+                .into_iter()
+                .map(|m| m.with_reasoning(None))
+                .collect()
+        };
 
         // Store the new messages in the DB
         // ChatBuilder enforces that these are always set together
@@ -150,12 +433,13 @@ impl Chat {
             get_or_create_session(db, session_id, tags).await?;
 
             // Save the input message
-            insert_chat_message(db, session_id, &msg).await?;
+            insert_chat_message(db, session_id, &msg, None).await?;
 
             // Save each message
             for m in messages.iter() {
                 self.transcript.push(m.clone());
-                insert_chat_message(db, session_id, m).await?;
+                let tool = Self::find_tool_call(m, &messages);
+                insert_chat_message(db, session_id, m, tool).await?;
             }
         } else {
             for m in messages.iter() {
@@ -166,21 +450,167 @@ impl Chat {
         Ok(messages)
     }
 
+    /// Returns the payload that would be sent to the LLM for the next
+    /// turn without sending it or modifying the transcript.
+    pub async fn preview_msg(&self, msg: Message) -> Result<Value, Error> {
+        let mut transcript = Transcript::new_with_messages(self.transcript.messages());
+        transcript.push(msg);
+        transcript.validate()?;
+        let history = transcript.messages();
+        let options = CompletionOptions {
+            dry_run: true,
+            ..self.completion_options.clone()
+        };
+
+        if self.streaming {
+            // The transmitter is never used in a dry run
+            let (tx, _rx) = mpsc::unbounded_channel::<String>();
+            completion_stream(
+                tx,
+                &history,
+                &self.tools,
+                &self.api_hostname,
+                &self.api_key,
+                &self.model,
+                &options,
+            )
+            .await
+        } else {
+            completion(
+                &history,
+                &self.tools,
+                &self.api_hostname,
+                &self.api_key,
+                &self.model,
+                &options,
+            )
+            .await
+        }
+    }
+
+    /// Returns the messages in `history` that fit in `max_tokens` by
+    /// leaving out the oldest messages after the system message. The
+    /// latest message can't be left out so if it doesn't fit on its
+    /// own, all of `history` is returned for the provider to reject.
+    fn fit_to_context(history: &[Message], max_tokens: usize) -> Vec<Message> {
+        let trimmed = Transcript::new_with_messages(history.to_vec())
+            .trim_to_token_budget(max_tokens, true)
+            .messages();
+        if trimmed.iter().all(|m| *m.role() == Role::System) {
+            tracing::warn!(
+                "Latest message doesn't fit in the context budget of {} tokens",
+                max_tokens
+            );
+            return history.to_vec();
+        }
+        if trimmed.len() < history.len() {
+            tracing::debug!(
+                "Sending {} of {} messages to fit the context budget of {} tokens",
+                trimmed.len(),
+                history.len(),
+                max_tokens
+            );
+        }
+        trimmed
+    }
+
+    /// Charge every request retry in `options` to the turn's `budget`
+    /// so a turn can't retry more than its budget allows
+    fn with_retry_budget(
+        options: &CompletionOptions,
+        budget: &Arc<RetryBudget>,
+    ) -> CompletionOptions {
+        let budget = budget.clone();
+        CompletionOptions {
+            on_retry: Some(RetryHook::new(move |err| budget.spend(err).map(|_| ()))),
+            ..options.clone()
+        }
+    }
+
+    /// Send the messages in `history` using `send`. Transient errors
+    /// are retried by the request itself, charged to the turn's budget
+    /// by `with_retry_budget`. The oldest messages are trimmed from
+    /// `history` first to fit in
+    /// `max_context_tokens`. If the provider rejects the messages for
+    /// exceeding the context length, the oldest messages are trimmed
+    /// from `history` and it's retried once.
+    async fn complete_with_retries<F, Fut>(
+        history: &mut Vec<Message>,
+        budget: &RetryBudget,
+        max_context_tokens: Option<usize>,
+        send: F,
+    ) -> Result<Value, Error>
+    where
+        F: Fn(Vec<Message>) -> Fut,
+        Fut: Future<Output = Result<Value, Error>>,
+    {
+        if let Some(max_tokens) = max_context_tokens {
+            *history = Self::fit_to_context(history, max_tokens);
+        }
+        let mut trimmed = false;
+        loop {
+            let err = match send(history.clone()).await {
+                Ok(resp) => return Ok(resp),
+                Err(e) => e,
+            };
+
+            let context_length_exceeded = err
+                .downcast_ref::<OpenAiApiError>()
+                .is_some_and(OpenAiApiError::is_context_length_exceeded);
+            if context_length_exceeded && !trimmed {
+                // Halve the transcript, keeping the system message,
+                // which should leave enough room for the response
+                let total: usize = history.iter().map(tokens::estimate_message).sum();
+                let trimmed_history = Transcript::new_with_messages(history.clone())
+                    .trim_to_token_budget(total / 2, true)
+                    .messages();
+                if trimmed_history.len() == history.len() {
+                    return Err(err);
+                }
+                budget.spend(&err)?;
+                tracing::warn!(
+                    "Context length exceeded, retrying with {} of {} messages",
+                    trimmed_history.len(),
+                    history.len()
+                );
+                *history = trimmed_history;
+                trimmed = true;
+            } else {
+                return Err(err);
+            }
+        }
+    }
+
     /// Runs the next turn in chat by passing a transcript to the LLM for
     /// the next response. Can return multiple messages when there are
     /// tool calls.
+    #[allow(clippy::too_many_arguments)]
     async fn chat(
         tools: &Option<Vec<BoxedToolCall>>,
+        max_concurrent_tools: usize,
+        tool_timeout: Duration,
+        permissions: Option<&[String]>,
+        retry_budget: usize,
+        max_context_tokens: Option<usize>,
+        db: &Option<Connection>,
         transcript: &Transcript,
         api_hostname: &str,
         api_key: &str,
         model: &str,
+        options: &CompletionOptions,
     ) -> Result<Vec<Message>, Error> {
-        let history = transcript.messages();
-        let mut updated_history = history.to_owned();
+        let mut history = transcript.messages();
         let mut messages = Vec::new();
+        let budget = Arc::new(RetryBudget::new(retry_budget));
+        let options = &Self::with_retry_budget(options, &budget);
+        let send = |history: Vec<Message>| async move {
+            let resp = completion(&history, tools, api_hostname, api_key, model, options).await?;
+            Self::record_token_usage(db, model, &resp).await;
+            Ok(resp)
+        };
 
-        let mut resp = completion(&history, tools, api_hostname, api_key, model).await?;
+        let mut resp =
+            Self::complete_with_retries(&mut history, &budget, max_context_tokens, send).await?;
 
         // Tool calls need to be handled for the chat to proceed
         while let Some(tool_calls) = resp["choices"][0]["message"]["tool_calls"].as_array() {
@@ -192,18 +622,42 @@ impl Chat {
                 .as_ref()
                 .expect("Received tool call but no tools were specified");
 
-            let tool_call_msgs = Self::handle_tool_calls(tools_ref, tool_calls).await?;
-            for m in tool_call_msgs.into_iter() {
+            let mut reasoning = resp["choices"][0]["message"]["reasoning"]
+                .as_str()
+                .map(String::from);
+            let tool_call_msgs = Self::handle_tool_calls(
+                tools_ref,
+                db,
+                tool_calls,
+                max_concurrent_tools,
+                tool_timeout,
+                permissions,
+                &budget,
+                None,
+            )
+            .await?;
+            for (i, m) in tool_call_msgs.into_iter().enumerate() {
+                // Keep the reasoning that led to the tool calls on the
+                // first request like for a content response
+                let m = if i == 0 {
+                    m.with_reasoning(reasoning.take())
+                } else {
+                    m
+                };
                 messages.push(m.clone());
-                updated_history.push(m);
+                history.push(m);
             }
 
             // Provide the results of the tool calls back to the chat
-            resp = completion(&updated_history, tools, api_hostname, api_key, model).await?;
+            resp = Self::complete_with_retries(&mut history, &budget, max_context_tokens, send)
+                .await?;
         }
 
         if let Some(msg) = resp["choices"][0]["message"]["content"].as_str() {
-            messages.push(Message::new(Role::Assistant, msg));
+            let reasoning = resp["choices"][0]["message"]["reasoning"]
+                .as_str()
+                .map(String::from);
+            messages.push(Message::new(Role::Assistant, msg).with_reasoning(reasoning));
         } else {
             panic!("No message received. Resp:\n\n {}", resp);
         }
@@ -215,51 +669,89 @@ impl Chat {
     /// the next response is streamed via the transmitter channel
     /// `tx`. Also returns the next messages so they can be processed
     /// further. Can return multiple messages when there are tool calls.
+    #[allow(clippy::too_many_arguments)]
     async fn chat_stream(
         tx: mpsc::UnboundedSender<String>,
         tools: &Option<Vec<BoxedToolCall>>,
+        max_concurrent_tools: usize,
+        tool_timeout: Duration,
+        permissions: Option<&[String]>,
+        retry_budget: usize,
+        max_context_tokens: Option<usize>,
+        db: &Option<Connection>,
         transcript: &Transcript,
         api_hostname: &str,
         api_key: &str,
         model: &str,
+        options: &CompletionOptions,
     ) -> Result<Vec<Message>, Error> {
-        let history = transcript.messages();
-        let mut updated_history = history.to_owned();
+        let mut history = transcript.messages();
         let mut messages = Vec::new();
+        let budget = Arc::new(RetryBudget::new(retry_budget));
+        let options = &Self::with_retry_budget(options, &budget);
+        let send = |history: Vec<Message>| {
+            let tx = tx.clone();
+            async move {
+                let resp =
+                    completion_stream(tx, &history, tools, api_hostname, api_key, model, options)
+                        .await?;
+                Self::record_token_usage(db, model, &resp).await;
+                Ok(resp)
+            }
+        };
 
         let mut resp =
-            completion_stream(tx.clone(), &history, tools, api_hostname, api_key, model).await?;
+            Self::complete_with_retries(&mut history, &budget, max_context_tokens, send).await?;
 
         // Tool calls need to be handled for the chat to proceed
         while let Some(tool_calls) = resp["choices"][0]["message"]["tool_calls"].as_array() {
             if tool_calls.is_empty() {
                 break;
             }
+            // Don't run tools for a turn nobody is waiting on
+            if options.is_cancelled() {
+                return Err(CompletionCancelled.into());
+            }
             let tools_ref = tools
                 .as_ref()
                 .expect("Received tool call but no tools were specified");
 
-            // TODO: Update this to be streaming
-            let tool_call_msgs = Self::handle_tool_calls(tools_ref, tool_calls).await?;
-            for m in tool_call_msgs.into_iter() {
+            let mut reasoning = resp["choices"][0]["message"]["reasoning"]
+                .as_str()
+                .map(String::from);
+            let tool_call_msgs = Self::handle_tool_calls(
+                tools_ref,
+                db,
+                tool_calls,
+                max_concurrent_tools,
+                tool_timeout,
+                permissions,
+                &budget,
+                Some(&tx),
+            )
+            .await?;
+            for (i, m) in tool_call_msgs.into_iter().enumerate() {
+                // Keep the reasoning that led to the tool calls on the
+                // first request like for a content response
+                let m = if i == 0 {
+                    m.with_reasoning(reasoning.take())
+                } else {
+                    m
+                };
                 messages.push(m.clone());
-                updated_history.push(m);
+                history.push(m);
             }
 
             // Provide the results of the tool calls back to the chat
-            resp = completion_stream(
-                tx.clone(),
-                &updated_history,
-                tools,
-                api_hostname,
-                api_key,
-                model,
-            )
-            .await?;
+            resp = Self::complete_with_retries(&mut history, &budget, max_context_tokens, send)
+                .await?;
         }
 
         if let Some(msg) = resp["choices"][0]["message"]["content"].as_str() {
-            messages.push(Message::new(Role::Assistant, msg));
+            let reasoning = resp["choices"][0]["message"]["reasoning"]
+                .as_str()
+                .map(String::from);
+            messages.push(Message::new(Role::Assistant, msg).with_reasoning(reasoning));
         } else {
             bail!("No message received. Resp:\n\n {}", resp);
         }
@@ -276,10 +768,18 @@ pub struct ChatBuilder {
     db: Option<Connection>,
     session_id: Option<String>,
     tools: Option<Vec<BoxedToolCall>>,
+    max_concurrent_tools: usize,
+    tool_timeout: Duration,
+    permissions: Option<Vec<String>>,
+    store_reasoning: bool,
+    retry_budget: usize,
+    max_context_tokens: Option<usize>,
     transcript: Transcript,
     streaming: bool,
     tx: Option<mpsc::UnboundedSender<String>>,
     tags: Option<Vec<String>>,
+    skills: Vec<Skill>,
+    completion_options: CompletionOptions,
 }
 
 impl ChatBuilder {
@@ -295,12 +795,21 @@ impl ChatBuilder {
             session_id: None,
             tx: None,
             tools: None,
+            max_concurrent_tools: DEFAULT_MAX_CONCURRENT_TOOLS,
+            tool_timeout: DEFAULT_TOOL_TIMEOUT,
+            permissions: None,
+            store_reasoning: false,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            max_context_tokens: None,
             streaming: false,
             tags: None,
+            skills: Vec::new(),
+            completion_options: CompletionOptions::default(),
         }
     }
 
-    pub fn build(self) -> Chat {
+    pub fn build(mut self) -> Chat {
+        self.apply_skills();
         Chat {
             api_hostname: self.api_hostname,
             api_key: self.api_key,
@@ -309,9 +818,16 @@ impl ChatBuilder {
             streaming: self.streaming,
             tx: self.tx,
             tools: self.tools,
+            max_concurrent_tools: self.max_concurrent_tools,
+            tool_timeout: self.tool_timeout,
+            permissions: self.permissions,
+            store_reasoning: self.store_reasoning,
+            retry_budget: self.retry_budget,
+            max_context_tokens: self.max_context_tokens,
             transcript: self.transcript,
             session_id: self.session_id,
             tags: self.tags,
+            completion_options: self.completion_options,
         }
     }
 
@@ -353,14 +869,173 @@ impl ChatBuilder {
         self
     }
 
-    pub fn skills(self) -> Self {
-        unimplemented!()
+    /// Set the maximum number of tool calls that run at once when the
+    /// model requests several in one turn. Defaults to
+    /// `DEFAULT_MAX_CONCURRENT_TOOLS`.
+    pub fn max_concurrent_tools(mut self, max: usize) -> Self {
+        self.max_concurrent_tools = max;
+        self
+    }
+
+    /// Only allow the tools named in `allowed` to be called. The model
+    /// is told a tool isn't permitted instead of calling it. All tools
+    /// are allowed by default.
+    pub fn permissions(mut self, allowed: Vec<String>) -> Self {
+        self.permissions = Some(allowed);
+        self
+    }
+
+    /// Keep the reasoning of reasoning models on assistant messages so
+    /// it's stored with the chat. It's dropped by default.
+    pub fn store_reasoning(mut self, store_reasoning: bool) -> Self {
+        self.store_reasoning = store_reasoning;
+        self
+    }
+
+    /// Set the maximum time a tool call can take before the model is
+    /// told it timed out. Defaults to `DEFAULT_TOOL_TIMEOUT`.
+    pub fn tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_timeout = timeout;
+        self
+    }
+
+    /// Set the maximum number of retries in a chat turn, counting
+    /// completion retries and failed tool calls, before the turn fails.
+    /// Defaults to `DEFAULT_RETRY_BUDGET`.
+    pub fn retry_budget(mut self, budget: usize) -> Self {
+        self.retry_budget = budget;
+        self
+    }
+
+    /// Set the maximum number of tokens sent to the LLM in each
+    /// request. The oldest messages after the system message are
+    /// left out of requests to stay within it while the full
+    /// transcript is still kept. Defaults to `None` for no limit.
+    pub fn max_context_tokens(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_context_tokens = max_tokens;
+        self
+    }
+
+    /// Set the timeout for each request to the LLM. Defaults to
+    /// `DEFAULT_COMPLETION_TIMEOUT` or `DEFAULT_COMPLETION_STREAM_TIMEOUT`
+    /// when streaming.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.completion_options.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the number of times each request to the LLM is retried
+    /// after a rate limit or server error and the delay before the
+    /// first retry. Defaults to `DEFAULT_COMPLETION_MAX_RETRIES` and
+    /// `DEFAULT_COMPLETION_RETRY_BASE_DELAY`.
+    pub fn completion_retries(mut self, max_retries: usize, base_delay: Duration) -> Self {
+        self.completion_options.max_retries = Some(max_retries);
+        self.completion_options.retry_base_delay = Some(base_delay);
+        self
+    }
+
+    /// Combine streamed content and reasoning deltas that arrive within
+    /// `window` into one event on the streaming channel. Defaults to
+    /// `None` which sends every delta as it arrives.
+    pub fn coalesce_window(mut self, window: Option<Duration>) -> Self {
+        self.completion_options.coalesce_window = window;
+        self
+    }
+
+    /// Stop streaming the turn when `token` is cancelled e.g. when the
+    /// client disconnects. `next_msg` returns a `CompletionCancelled`
+    /// error and nothing from the turn is saved.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.completion_options.cancel = Some(token);
+        self
+    }
+
+    /// Providers to send completions to in order when the chat's
+    /// provider is unavailable e.g. a hosted model when a local model
+    /// server is down.
+    pub fn fallbacks(mut self, fallbacks: Vec<Provider>) -> Self {
+        self.completion_options.fallbacks = fallbacks;
+        self
+    }
+
+    /// Constrain the format of responses from the LLM e.g.
+    /// `{"type": "json_object"}` for models that support JSON mode.
+    pub fn response_format(mut self, response_format: Value) -> Self {
+        self.completion_options.response_format = Some(response_format);
+        self
+    }
+
+    /// Sampling parameters used for every completion in the chat e.g.
+    /// a low temperature for more deterministic responses.
+    pub fn params(mut self, params: CompletionParams) -> Self {
+        self.completion_options.params = params;
+        self
+    }
+
+    /// How much reasoning models do before responding. Only set this
+    /// for models that support it, `None` uses the model's default.
+    pub fn reasoning_effort(mut self, reasoning_effort: Option<ReasoningEffort>) -> Self {
+        self.completion_options.reasoning_effort = reasoning_effort;
+        self
+    }
+
+    /// Return the payload that would be sent to the LLM instead of
+    /// sending it. Useful for debugging prompts and tool definitions.
+    pub fn dry_run(mut self) -> Self {
+        self.completion_options.dry_run = true;
+        self
+    }
+
+    /// Enable `skills` on the chat. Their prompt fragments are appended
+    /// to the system message and the tools are limited to the ones the
+    /// skills need. Applied when the chat is built so the order of
+    /// builder calls doesn't matter.
+    pub fn skills(mut self, skills: Vec<Skill>) -> Self {
+        self.skills = skills;
+        self
+    }
+
+    fn apply_skills(&mut self) {
+        if self.skills.is_empty() {
+            return;
+        }
+
+        let system = self
+            .transcript
+            .iter_mut()
+            .find(|m| *m.role() == Role::System);
+        // A resumed session's system message already has the fragments
+        // from when the skills were first applied
+        let existing = system.as_ref().and_then(|m| m.content()).unwrap_or("");
+        let fragments = self
+            .skills
+            .iter()
+            .map(|s| s.prompt_fragment.as_str())
+            .filter(|f| !existing.contains(f))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        match system {
+            _ if fragments.is_empty() => {}
+            Some(system) => system.push_content(&fragments),
+            None => {
+                let mut messages = vec![Message::new(Role::System, &fragments)];
+                messages.extend(self.transcript.messages());
+                self.transcript = Transcript::new_with_messages(messages);
+            }
+        }
+
+        let tool_names: Vec<&String> = self.skills.iter().flat_map(|s| &s.tool_names).collect();
+        if let Some(tools) = self.tools.as_mut() {
+            tools.retain(|t| tool_names.contains(&&t.function_name()));
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ai::chat::db::find_chat_messages_by_session_id;
+    use crate::core::testing::test_db;
     use crate::openai::{Message, Role};
     use tokio::sync::mpsc;
 
@@ -440,6 +1115,112 @@ mod tests {
         assert_eq!(builder.tools.as_ref().unwrap().len(), 1);
     }
 
+    #[test]
+    fn test_builder_skills() {
+        #[derive(serde::Serialize)]
+        struct MockTool(&'static str);
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for MockTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                Ok("mock result".to_string())
+            }
+            fn function_name(&self) -> String {
+                self.0.to_string()
+            }
+        }
+
+        let tools = ["calendar", "email", "web_search", "notes"]
+            .into_iter()
+            .map(|name| Box::new(MockTool(name)) as crate::openai::BoxedToolCall)
+            .collect();
+        let skills = vec![
+            Skill {
+                name: "scheduling".to_string(),
+                prompt_fragment: "Use the user's timezone.".to_string(),
+                tool_names: vec!["calendar".to_string(), "email".to_string()],
+            },
+            Skill {
+                name: "research".to_string(),
+                prompt_fragment: "Cite your sources.".to_string(),
+                tool_names: vec!["web_search".to_string(), "email".to_string()],
+            },
+        ];
+
+        let chat = ChatBuilder::new("https://api.example.com", "test-key", "gpt-4")
+            .skills(skills)
+            .tools(tools)
+            .transcript(vec![
+                Message::new(Role::System, "You are a helpful assistant."),
+                Message::new(Role::User, "Hello"),
+            ])
+            .build();
+
+        let messages = chat.transcript.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].content(),
+            Some("You are a helpful assistant.\n\nUse the user's timezone.\n\nCite your sources.")
+        );
+        let tool_names: Vec<String> = chat
+            .tools
+            .unwrap()
+            .iter()
+            .map(|t| t.function_name())
+            .collect();
+        assert_eq!(tool_names, vec!["calendar", "email", "web_search"]);
+    }
+
+    #[test]
+    fn test_builder_skills_without_system_message() {
+        let skills = vec![Skill {
+            name: "research".to_string(),
+            prompt_fragment: "Cite your sources.".to_string(),
+            tool_names: vec![],
+        }];
+
+        let chat = ChatBuilder::new("https://api.example.com", "test-key", "gpt-4")
+            .transcript(vec![Message::new(Role::User, "Hello")])
+            .skills(skills)
+            .build();
+
+        let messages = chat.transcript.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(*messages[0].role(), Role::System);
+        assert_eq!(messages[0].content(), Some("Cite your sources."));
+        assert_eq!(messages[1].content(), Some("Hello"));
+    }
+
+    #[test]
+    fn test_builder_skills_resumed_session() {
+        let skills = vec![Skill {
+            name: "research".to_string(),
+            prompt_fragment: "Cite your sources.".to_string(),
+            tool_names: vec![],
+        }];
+
+        let chat = ChatBuilder::new("https://api.example.com", "test-key", "gpt-4")
+            .transcript(vec![
+                Message::new(Role::System, "You are a helpful assistant."),
+                Message::new(Role::User, "Hello"),
+                Message::new(Role::Assistant, "Hi there!"),
+            ])
+            .skills(skills.clone())
+            .build();
+
+        // Resume the session with the same skills
+        let chat = ChatBuilder::new("https://api.example.com", "test-key", "gpt-4")
+            .transcript(chat.transcript.messages())
+            .skills(skills)
+            .build();
+
+        let messages = chat.transcript.messages();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0].content(),
+            Some("You are a helpful assistant.\n\nCite your sources.")
+        );
+    }
+
     #[test]
     fn test_builder_chaining() {
         let messages = vec![Message::new(Role::User, "Hello")];
@@ -484,6 +1265,124 @@ mod tests {
         assert_eq!(chat.transcript.messages().len(), 0);
     }
 
+    #[test]
+    fn test_builder_timeout() {
+        let builder = ChatBuilder::new("https://api.example.com", "test-key", "gpt-4");
+        assert!(builder.completion_options.timeout.is_none());
+
+        let chat = builder.timeout(Duration::from_secs(30)).build();
+        assert_eq!(
+            chat.completion_options.timeout,
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_next_msg_invalid_transcript() {
+        let mut server = mockito::Server::new_async().await;
+
+        // Invalid transcripts are rejected before sending
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(0)
+            .create();
+
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .transcript(vec![Message::new(Role::User, "Hi")])
+            .build();
+
+        let result = chat
+            .next_msg(Message::new(Role::System, "You are a helpful assistant"))
+            .await;
+
+        mock.assert();
+        assert!(result.is_err());
+        assert_eq!(chat.transcript.messages().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let mut server = mockito::Server::new_async().await;
+
+        // Dry run should never hit the API
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(0)
+            .create();
+
+        #[derive(serde::Serialize)]
+        struct MockTool {
+            name: String,
+        }
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for MockTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                Ok("mock result".to_string())
+            }
+            fn function_name(&self) -> String {
+                self.name.clone()
+            }
+        }
+
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .transcript(vec![Message::new(
+                Role::System,
+                "You are a helpful assistant",
+            )])
+            .tools(vec![Box::new(MockTool {
+                name: "mock_tool".to_string(),
+            })])
+            .dry_run()
+            .build();
+
+        let messages = chat.next_msg(Message::new(Role::User, "Hi")).await.unwrap();
+
+        mock.assert();
+        assert_eq!(messages.len(), 1);
+        let payload: Value = serde_json::from_str(messages[0].content().unwrap()).unwrap();
+        assert_eq!(payload["model"], "gpt-4");
+        assert_eq!(payload["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(payload["messages"][0]["role"], "system");
+        assert_eq!(payload["messages"][1]["content"], "Hi");
+        assert_eq!(payload["tools"][0]["name"], "mock_tool");
+
+        // The transcript is unchanged since nothing was sent
+        assert_eq!(chat.transcript.messages().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_builder_params() {
+        let mut chat = ChatBuilder::new("https://api.example.com", "test-key", "gpt-4")
+            .params(CompletionParams {
+                temperature: Some(0.0),
+                top_p: Some(0.9),
+                ..CompletionParams::default()
+            })
+            .dry_run()
+            .build();
+
+        let messages = chat.next_msg(Message::new(Role::User, "Hi")).await.unwrap();
+        let payload: Value = serde_json::from_str(messages[0].content().unwrap()).unwrap();
+        assert_eq!(payload["temperature"], serde_json::json!(0.0));
+        assert_eq!(payload["top_p"], serde_json::json!(0.9));
+        assert!(payload.get("max_tokens").is_none());
+        assert!(payload.get("presence_penalty").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_builder_reasoning_effort() {
+        let mut chat = ChatBuilder::new("https://api.example.com", "test-key", "o3")
+            .reasoning_effort(Some(ReasoningEffort::Low))
+            .dry_run()
+            .build();
+
+        let messages = chat.next_msg(Message::new(Role::User, "Hi")).await.unwrap();
+        let payload: Value = serde_json::from_str(messages[0].content().unwrap()).unwrap();
+        assert_eq!(payload["reasoning_effort"], "low");
+    }
+
     #[test]
     fn test_builder_default_streaming_disabled() {
         let builder = ChatBuilder::new("https://api.example.com", "test-key", "gpt-4");
@@ -572,7 +1471,7 @@ mod tests {
         let messages = result.unwrap();
         // Should return the assistant's response
         assert_eq!(messages.len(), 1);
-        let content = messages[0].content.as_ref().expect("Should have content");
+        let content = messages[0].content().expect("Should have content");
         assert_eq!(content, "Hello! How can I help you today?");
     }
 
@@ -668,7 +1567,614 @@ mod tests {
         assert_eq!(messages.len(), 3);
     }
 
-    // Tests for Chat::chat_stream (tested through next_msg with streaming enabled)
+    #[tokio::test]
+    async fn test_chat_records_tool_latency_metric() {
+        let mut server = mockito::Server::new_async().await;
+
+        let tool_call_response = r#"{
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1694268190,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": {
+                            "name": "mock_tool",
+                            "arguments": "{\"query\":\"test\"}"
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }"#;
+
+        let final_response = r#"{
+            "id": "chatcmpl-124",
+            "object": "chat.completion",
+            "created": 1694268191,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "I found some results for your query."
+                },
+                "finish_reason": "stop"
+            }]
+        }"#;
+
+        let _mock1 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(tool_call_response)
+            .create();
+
+        let _mock2 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(final_response)
+            .create();
+
+        #[derive(serde::Serialize)]
+        struct MockTool;
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for MockTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                Ok("mock result".to_string())
+            }
+            fn function_name(&self) -> String {
+                "mock_tool".to_string()
+            }
+        }
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = test_db(dir.path()).await;
+
+        let url = server.url();
+        let tools = vec![Box::new(MockTool) as crate::openai::BoxedToolCall];
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .tools(tools)
+            .database(&db, None, None)
+            .build();
+
+        chat.next_msg(Message::new(Role::User, "Search for test"))
+            .await
+            .unwrap();
+
+        let (name, label): (MetricName, Option<String>) = db
+            .call(|conn| {
+                Ok(
+                    conn.query_row("SELECT name, label FROM metric_event", [], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?,
+                )
+            })
+            .await
+            .unwrap();
+        assert!(matches!(name, MetricName::ToolLatency));
+        assert_eq!(label, Some("mock_tool".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_chat_stores_structured_tool_output() {
+        let mut server = mockito::Server::new_async().await;
+
+        let tool_call_response = r#"{
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1694268190,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": {
+                            "name": "mock_calendar",
+                            "arguments": "{}"
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }"#;
+
+        let final_response = r#"{
+            "id": "chatcmpl-124",
+            "object": "chat.completion",
+            "created": 1694268191,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "You have standup at 9am."
+                },
+                "finish_reason": "stop"
+            }]
+        }"#;
+
+        let _mock1 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(tool_call_response)
+            .create();
+
+        let _mock2 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(final_response)
+            .create();
+
+        #[derive(serde::Serialize)]
+        struct MockCalendarTool;
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for MockCalendarTool {
+            async fn call(&self, args: &str) -> anyhow::Result<String> {
+                Ok(self.call_structured(args).await?.text)
+            }
+            async fn call_structured(&self, _args: &str) -> anyhow::Result<ToolOutput> {
+                Ok(ToolOutput {
+                    text: "Standup at 9am".to_string(),
+                    structured: Some(serde_json::json!({
+                        "events": [{"summary": "Standup", "start": "09:00"}]
+                    })),
+                })
+            }
+            fn function_name(&self) -> String {
+                "mock_calendar".to_string()
+            }
+        }
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = test_db(dir.path()).await;
+
+        let url = server.url();
+        let tools = vec![Box::new(MockCalendarTool) as crate::openai::BoxedToolCall];
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .tools(tools)
+            .database(&db, Some("structured-session"), None)
+            .build();
+
+        let messages = chat
+            .next_msg(Message::new(Role::User, "What's on my calendar?"))
+            .await
+            .unwrap();
+
+        // The model only sees the rendered text
+        let tool_response = &messages[1];
+        assert_eq!(tool_response.content(), Some("Standup at 9am"));
+        assert!(
+            serde_json::json!(tool_response)
+                .get("structured_content")
+                .is_none()
+        );
+
+        let stored = find_chat_messages_by_session_id(&db, "structured-session")
+            .await
+            .unwrap();
+        let stored_response = stored
+            .iter()
+            .find(|m| m.message.tool_call_id() == Some("call_abc123"))
+            .unwrap();
+        assert_eq!(stored_response.message.content(), Some("Standup at 9am"));
+        assert_eq!(
+            stored_response.tool_output,
+            Some(serde_json::json!({
+                "events": [{"summary": "Standup", "start": "09:00"}]
+            }))
+        );
+
+        // Only tool call responses have structured output
+        assert!(
+            stored
+                .iter()
+                .filter(|m| m.message.tool_call_id().is_none())
+                .all(|m| m.tool_output.is_none())
+        );
+    }
+
+    async fn stored_reasoning(store_reasoning: bool) -> Option<String> {
+        let mut server = mockito::Server::new_async().await;
+        let sse_response = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"reasoning":"Thinking"},"finish_reason":null}]}
+
+data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"reasoning":" hard"},"finish_reason":null}]}
+
+data: {"id":"chunk3","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Done!"},"finish_reason":null}]}
+
+data: {"id":"chunk4","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}
+
+data: [DONE]
+
+"#;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(sse_response)
+            .create();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = test_db(dir.path()).await;
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .database(&db, Some("reasoning-session"), None)
+            .store_reasoning(store_reasoning)
+            .streaming(tx)
+            .build();
+        let messages = chat
+            .next_msg(Message::new(Role::User, "Think about this"))
+            .await
+            .unwrap();
+
+        // Reasoning is never sent back to the model
+        assert_eq!(messages[0].content(), Some("Done!"));
+        assert!(serde_json::json!(messages[0]).get("reasoning").is_none());
+
+        let stored = find_chat_messages_by_session_id(&db, "reasoning-session")
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 2);
+        stored[1].reasoning.clone()
+    }
+
+    #[tokio::test]
+    async fn test_chat_stores_reasoning_when_enabled() {
+        assert_eq!(
+            stored_reasoning(true).await,
+            Some(String::from("Thinking hard"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_drops_reasoning_by_default() {
+        assert_eq!(stored_reasoning(false).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_records_token_usage() {
+        let mut server = mockito::Server::new_async().await;
+
+        let sse_response = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}
+
+data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}
+
+data: [DONE]
+
+"#;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(sse_response)
+            .create();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = test_db(dir.path()).await;
+
+        let (tx, _rx) = mpsc::unbounded_channel::<String>();
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .database(&db, None, None)
+            .streaming(tx)
+            .build();
+
+        chat.next_msg(Message::new(Role::User, "Say hello"))
+            .await
+            .unwrap();
+
+        let (name, value, label): (MetricName, i64, Option<String>) = db
+            .call(|conn| {
+                Ok(
+                    conn.query_row("SELECT name, value, label FROM metric_event", [], |row| {
+                        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                    })?,
+                )
+            })
+            .await
+            .unwrap();
+        assert!(matches!(name, MetricName::TokenCount));
+        assert_eq!(value, 15);
+        assert_eq!(label, Some("gpt-4".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_error_is_redacted() {
+        #[derive(serde::Serialize)]
+        struct FailingTool;
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for FailingTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                Err(anyhow!(
+                    "error sending request for url (https://www.googleapis.com/customsearch/v1?key=secret123&q=test)"
+                ))
+            }
+            fn function_name(&self) -> String {
+                "failing_tool".to_string()
+            }
+        }
+
+        let tools = vec![Box::new(FailingTool) as crate::openai::BoxedToolCall];
+        let tool_call = serde_json::json!({
+            "id": "call_abc123",
+            "type": "function",
+            "function": {
+                "name": "failing_tool",
+                "arguments": "{\"query\":\"test\"}"
+            }
+        });
+
+        let budget = RetryBudget::new(DEFAULT_RETRY_BUDGET);
+        let messages = Chat::handle_tool_call(
+            &tools,
+            &None,
+            &tool_call,
+            DEFAULT_TOOL_TIMEOUT,
+            None,
+            &budget,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(messages.len(), 2);
+        let content = messages[1].content().unwrap();
+        assert!(content.starts_with("Tool call failed:"));
+        assert!(content.contains("key=[REDACTED]"));
+        assert!(!content.contains("secret123"));
+        assert_eq!(messages[1].tool_call_id(), Some("call_abc123"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_times_out() {
+        #[derive(serde::Serialize)]
+        struct HangingTool;
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for HangingTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok("Too late".to_string())
+            }
+            fn function_name(&self) -> String {
+                "hanging_tool".to_string()
+            }
+        }
+
+        let tools = vec![Box::new(HangingTool) as crate::openai::BoxedToolCall];
+        let tool_call = serde_json::json!({
+            "id": "call_abc123",
+            "type": "function",
+            "function": {"name": "hanging_tool", "arguments": "{}"}
+        });
+
+        let budget = RetryBudget::new(DEFAULT_RETRY_BUDGET);
+        let messages = tokio::time::timeout(
+            Duration::from_secs(5),
+            Chat::handle_tool_call(
+                &tools,
+                &None,
+                &tool_call,
+                Duration::from_millis(50),
+                None,
+                &budget,
+            ),
+        )
+        .await
+        .expect("Tool call should time out instead of hanging")
+        .unwrap();
+
+        assert_eq!(messages.len(), 2);
+        let content = messages[1].content().unwrap();
+        assert_eq!(content, "Tool call failed: Tool timed out after 50ms");
+        assert_eq!(messages[1].tool_call_id(), Some("call_abc123"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_continues_after_unknown_tool_call() {
+        let mut server = mockito::Server::new_async().await;
+
+        // First response: model calls a tool that doesn't exist
+        let tool_call_response = r#"{
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1694268190,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": {
+                            "name": "made_up_tool",
+                            "arguments": "{}"
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }"#;
+
+        // Second response: model recovers after the tool error
+        let final_response = r#"{
+            "id": "chatcmpl-124",
+            "object": "chat.completion",
+            "created": 1694268191,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Sorry, I can't do that."
+                },
+                "finish_reason": "stop"
+            }]
+        }"#;
+
+        let mock1 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(tool_call_response)
+            .create();
+
+        let mock2 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(final_response)
+            .create();
+
+        #[derive(serde::Serialize)]
+        struct MockTool;
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for MockTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                Ok("mock result".to_string())
+            }
+            fn function_name(&self) -> String {
+                "mock_tool".to_string()
+            }
+        }
+
+        let url = server.url();
+        let tools = vec![Box::new(MockTool) as crate::openai::BoxedToolCall];
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .tools(tools)
+            .build();
+
+        let msg = Message::new(Role::User, "Do something");
+        let messages = chat.next_msg(msg).await.unwrap();
+
+        mock1.assert();
+        mock2.assert();
+
+        assert_eq!(messages.len(), 3);
+        let content = messages[1].content().unwrap();
+        assert_eq!(
+            content,
+            "Tool call failed: no tool named made_up_tool exists. Available tools: mock_tool"
+        );
+        assert_eq!(messages[1].tool_call_id(), Some("call_abc123"));
+        assert_eq!(messages[2].content(), Some("Sorry, I can't do that."));
+    }
+
+    #[tokio::test]
+    async fn test_chat_rejects_tool_without_permission() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        let mut server = mockito::Server::new_async().await;
+
+        // First response: model calls a tool that isn't permitted
+        let tool_call_response = r#"{
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1694268190,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": {
+                            "name": "delete_everything",
+                            "arguments": "{}"
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }"#;
+
+        // Second response: model carries on without the tool
+        let final_response = r#"{
+            "id": "chatcmpl-124",
+            "object": "chat.completion",
+            "created": 1694268191,
+            "model": "gpt-4",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "I'm not allowed to do that."
+                },
+                "finish_reason": "stop"
+            }]
+        }"#;
+
+        let mock1 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(tool_call_response)
+            .create();
+
+        let mock2 = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(final_response)
+            .create();
+
+        #[derive(serde::Serialize)]
+        struct DeleteTool {
+            #[serde(skip)]
+            called: Arc<AtomicBool>,
+        }
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for DeleteTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                self.called.store(true, Ordering::SeqCst);
+                Ok("Deleted".to_string())
+            }
+            fn function_name(&self) -> String {
+                "delete_everything".to_string()
+            }
+        }
+
+        let called = Arc::new(AtomicBool::new(false));
+        let url = server.url();
+        let tools = vec![Box::new(DeleteTool {
+            called: called.clone(),
+        }) as crate::openai::BoxedToolCall];
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .tools(tools)
+            .permissions(vec![String::from("search_notes")])
+            .build();
+
+        let msg = Message::new(Role::User, "Delete everything");
+        let messages = chat.next_msg(msg).await.unwrap();
+
+        mock1.assert();
+        mock2.assert();
+
+        assert!(!called.load(Ordering::SeqCst));
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[1].content(),
+            Some("Tool not permitted: delete_everything")
+        );
+        assert_eq!(messages[1].tool_call_id(), Some("call_abc123"));
+        assert_eq!(messages[2].content(), Some("I'm not allowed to do that."));
+    }
+
+    // Tests for Chat::chat_stream (tested through next_msg with streaming enabled)
     #[tokio::test]
     async fn test_chat_stream_basic() {
         let mut server = mockito::Server::new_async().await;
@@ -709,7 +2215,7 @@ data: [DONE]
         // Note: The last chunk with finish_reason="stop" doesn't add content,
         // so only "Hello World" (not the "!") is assembled
         assert_eq!(messages.len(), 1);
-        let content = messages[0].content.as_ref().expect("Should have content");
+        let content = messages[0].content().expect("Should have content");
         assert_eq!(content, "Hello World");
 
         // Verify the raw chunks were also sent to the streaming channel
@@ -728,8 +2234,10 @@ data: [DONE]
     async fn test_chat_stream_with_tool_calls() {
         let mut server = mockito::Server::new_async().await;
 
-        // First response: streaming tool call chunks
-        let sse_tool_call = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"id":"call_abc123","index":0,"function":{"name":"mock_tool","arguments":"{\"query\":"},"type":"function"}]},"finish_reason":null}]}
+        // First response: streaming reasoning and tool call chunks
+        let sse_tool_call = r#"data: {"id":"chunk0","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"reasoning":"Looking it up"},"finish_reason":null}]}
+
+data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"id":"call_abc123","index":0,"function":{"name":"mock_tool","arguments":"{\"query\":"},"type":"function"}]},"finish_reason":null}]}
 
 data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"test\"}"}}]},"finish_reason":null}]}
 
@@ -781,6 +2289,7 @@ data: [DONE]
         let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
             .streaming(tx)
             .tools(tools)
+            .store_reasoning(true)
             .build();
 
         let msg = Message::new(Role::User, "Search for test");
@@ -796,5 +2305,284 @@ data: [DONE]
         // 2. Tool call response
         // 3. Assistant's final content
         assert_eq!(messages.len(), 3);
+        // The reasoning streamed before the tool call is kept
+        assert_eq!(messages[0].reasoning(), Some("Looking it up"));
+        assert_eq!(messages[1].reasoning(), None);
+    }
+
+    #[tokio::test]
+    async fn test_handle_tool_calls_limits_concurrency() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Tracks how many calls are running at once and the most that
+        // ever ran at the same time
+        #[derive(serde::Serialize)]
+        struct SlowTool {
+            #[serde(skip)]
+            running: Arc<AtomicUsize>,
+            #[serde(skip)]
+            max_running: Arc<AtomicUsize>,
+        }
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for SlowTool {
+            async fn call(&self, args: &str) -> anyhow::Result<String> {
+                let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_running.fetch_max(running, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                Ok(args.to_string())
+            }
+            fn function_name(&self) -> String {
+                "slow_tool".to_string()
+            }
+        }
+
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let tools = vec![Box::new(SlowTool {
+            running: Arc::new(AtomicUsize::new(0)),
+            max_running: max_running.clone(),
+        }) as crate::openai::BoxedToolCall];
+        let tool_calls: Vec<Value> = (0..6)
+            .map(|i| {
+                serde_json::json!({
+                    "id": format!("call_{}", i),
+                    "type": "function",
+                    "function": {"name": "slow_tool", "arguments": format!("{{\"i\": {}}}", i)}
+                })
+            })
+            .collect();
+
+        let budget = RetryBudget::new(DEFAULT_RETRY_BUDGET);
+        let messages = Chat::handle_tool_calls(
+            &tools,
+            &None,
+            &tool_calls,
+            2,
+            DEFAULT_TOOL_TIMEOUT,
+            None,
+            &budget,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        // Results are returned in the same order as the tool calls
+        let ids: Vec<&str> = messages.iter().filter_map(|m| m.tool_call_id()).collect();
+        assert_eq!(
+            ids,
+            vec!["call_0", "call_1", "call_2", "call_3", "call_4", "call_5"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_next_msg_trims_and_retries_on_context_length_error() {
+        use std::sync::{Arc, Mutex};
+
+        let mut server = mockito::Server::new_async().await;
+
+        let too_long = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"error": {"message": "This model's maximum context length is 128000 tokens.", "type": "invalid_request_error", "code": "context_length_exceeded"}}"#,
+            )
+            .expect(1)
+            .create();
+
+        // Keep the body of the retried request to check what was sent
+        let retried_body = Arc::new(Mutex::new(String::new()));
+        let captured = retried_body.clone();
+        let retry = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |req| {
+                *captured.lock().unwrap() = String::from_utf8_lossy(req.body().unwrap()).into();
+                br#"{"choices": [{"message": {"role": "assistant", "content": "Hello!"}}]}"#
+                    .to_vec()
+            })
+            .expect(1)
+            .create();
+
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .transcript(vec![
+                Message::new(Role::System, "You are a helpful assistant"),
+                Message::new(
+                    Role::User,
+                    &format!("Old question {}", "lorem ".repeat(500)),
+                ),
+                Message::new(Role::Assistant, "Old answer"),
+            ])
+            .build();
+
+        let messages = chat
+            .next_msg(Message::new(Role::User, "New question"))
+            .await
+            .unwrap();
+
+        too_long.assert();
+        retry.assert();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content(), Some("Hello!"));
+
+        let retried_body = retried_body.lock().unwrap();
+        assert!(retried_body.contains("You are a helpful assistant"));
+        assert!(retried_body.contains("New question"));
+        assert!(!retried_body.contains("Old question"));
+    }
+
+    #[test]
+    fn test_fit_to_context() {
+        let mut history = vec![Message::new(Role::System, "You are a helpful assistant")];
+        for i in 0..20 {
+            history.push(Message::new(
+                Role::User,
+                &format!("Question {} {}", i, "lorem ".repeat(50)),
+            ));
+            history.push(Message::new(Role::Assistant, &format!("Answer {}", i)));
+        }
+        history.push(Message::new(Role::User, "Latest question"));
+        let total: usize = history.iter().map(tokens::estimate_message).sum();
+        let max_tokens = total / 4;
+
+        let fitted = Chat::fit_to_context(&history, max_tokens);
+        let fitted_tokens: usize = fitted.iter().map(tokens::estimate_message).sum();
+        assert!(fitted_tokens <= max_tokens);
+        assert!(fitted.len() < history.len());
+        assert_eq!(*fitted[0].role(), Role::System);
+        assert_eq!(fitted[0].content(), Some("You are a helpful assistant"));
+        assert_eq!(fitted.last().unwrap().content(), Some("Latest question"));
+
+        // Nothing is left out when the history fits
+        assert_eq!(Chat::fit_to_context(&history, total).len(), history.len());
+
+        // The latest message is never left out
+        let fitted = Chat::fit_to_context(&history, 1);
+        assert_eq!(fitted.len(), history.len());
+    }
+
+    #[tokio::test]
+    async fn test_next_msg_trims_to_max_context_tokens() {
+        use std::sync::{Arc, Mutex};
+
+        let mut server = mockito::Server::new_async().await;
+        let sent_body = Arc::new(Mutex::new(String::new()));
+        let captured = sent_body.clone();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |req| {
+                *captured.lock().unwrap() = String::from_utf8_lossy(req.body().unwrap()).into();
+                br#"{"choices": [{"message": {"role": "assistant", "content": "Hello!"}}]}"#
+                    .to_vec()
+            })
+            .expect(1)
+            .create();
+
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .transcript(vec![
+                Message::new(Role::System, "You are a helpful assistant"),
+                Message::new(
+                    Role::User,
+                    &format!("Old question {}", "lorem ".repeat(500)),
+                ),
+                Message::new(Role::Assistant, "Old answer"),
+            ])
+            .max_context_tokens(Some(100))
+            .build();
+
+        chat.next_msg(Message::new(Role::User, "New question"))
+            .await
+            .unwrap();
+
+        mock.assert();
+        let sent_body = sent_body.lock().unwrap();
+        assert!(sent_body.contains("You are a helpful assistant"));
+        assert!(sent_body.contains("New question"));
+        assert!(!sent_body.contains("Old question"));
+
+        // The full transcript is kept
+        assert_eq!(chat.transcript.messages().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_next_msg_gives_up_after_retry_budget() {
+        let mut server = mockito::Server::new_async().await;
+
+        // The endpoint keeps failing so only the budget stops the
+        // retries: the first attempt plus two retries even though each
+        // request would be retried more on its own.
+        let flapping = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error": {"message": "Service unavailable", "type": "server_error"}}"#)
+            .expect(3)
+            .create();
+
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .retry_budget(2)
+            .completion_retries(5, Duration::ZERO)
+            .build();
+
+        let err = chat
+            .next_msg(Message::new(Role::User, "Hello"))
+            .await
+            .unwrap_err();
+
+        flapping.assert();
+        let err = err.downcast_ref::<RetryBudgetExhausted>().unwrap();
+        assert_eq!(err.budget, 2);
+        assert!(err.last_error.contains("Service unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_next_msg_counts_failed_tool_calls_against_retry_budget() {
+        #[derive(serde::Serialize)]
+        struct FailingTool {}
+        #[async_trait::async_trait]
+        impl crate::openai::ToolCall for FailingTool {
+            async fn call(&self, _args: &str) -> anyhow::Result<String> {
+                Err(anyhow!("Connection refused"))
+            }
+            fn function_name(&self) -> String {
+                "failing_tool".to_string()
+            }
+        }
+
+        let mut server = mockito::Server::new_async().await;
+
+        // The model asks for the tool again every time it fails
+        let tool_call = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"choices": [{"message": {"role": "assistant", "content": null, "tool_calls": [{"id": "call_1", "type": "function", "function": {"name": "failing_tool", "arguments": "{}"}}]}}]}"#,
+            )
+            .expect(3)
+            .create();
+
+        let url = server.url();
+        let mut chat = ChatBuilder::new(&url, "test-key", "gpt-4")
+            .tools(vec![Box::new(FailingTool {})])
+            .retry_budget(2)
+            .build();
+
+        let err = chat
+            .next_msg(Message::new(Role::User, "Hello"))
+            .await
+            .unwrap_err();
+
+        tool_call.assert();
+        let err = err.downcast_ref::<RetryBudgetExhausted>().unwrap();
+        assert!(err.last_error.contains("Connection refused"));
     }
 }
@@ -2,20 +2,38 @@ use anyhow::{Error, Result};
 use serde_json::json;
 use tokio_rusqlite::Connection;
 
-use crate::openai::Message;
+use super::models::ChatMessage;
+use crate::openai::{FunctionCallFn, Message};
 
+/// Insert a message into the chat session. Pass the `tool` call
+/// for tool call requests and responses so that the tool name and
+/// arguments are stored alongside the message. Structured output of
+/// a tool call response is stored separately from the message.
 pub async fn insert_chat_message(
     db: &Connection,
     session_id: &str,
     msg: &Message,
+    tool: Option<&FunctionCallFn>,
 ) -> Result<usize, Error> {
     let s_id = session_id.to_owned();
     let data = json!(msg).to_string();
+    let tool_name = tool.map(|t| t.name.clone());
+    let tool_args = tool.map(|t| t.arguments.clone());
+    let tool_output = msg.structured_content().map(|v| v.to_string());
+    let reasoning = msg.reasoning().map(String::from);
     let result = db
         .call(move |conn| {
-            let mut stmt =
-                conn.prepare("INSERT INTO chat_message (session_id, data) VALUES (?, ?)")?;
-            let result = stmt.execute([s_id, data])?;
+            let mut stmt = conn.prepare(
+                "INSERT INTO chat_message (session_id, data, tool_name, tool_args, tool_output, reasoning) VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            let result = stmt.execute(tokio_rusqlite::params![
+                s_id,
+                data,
+                tool_name,
+                tool_args,
+                tool_output,
+                reasoning
+            ])?;
             Ok(result)
         })
         .await?;
@@ -93,3 +111,261 @@ pub async fn find_chat_session_by_id(
     });
     Ok(history.await?)
 }
+
+/// Get all messages in the chat session including the tool name,
+/// arguments, and structured output for tool related messages.
+pub async fn find_chat_messages_by_session_id(
+    db: &Connection,
+    session_id: &str,
+) -> Result<Vec<ChatMessage>, Error> {
+    let s_id = session_id.to_owned();
+    let messages = db.call(move |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT data, tool_name, tool_args, tool_output, reasoning FROM chat_message WHERE session_id=?",
+        )?;
+        let rows = stmt
+            .query_map([s_id], |i| {
+                let data: String = i.get(0)?;
+                let tool_name: Option<String> = i.get(1)?;
+                let tool_args: Option<String> = i.get(2)?;
+                let tool_output: Option<String> = i.get(3)?;
+                let reasoning: Option<String> = i.get(4)?;
+                let message: Message = serde_json::from_str(&data).unwrap();
+                // Arguments should always be JSON, but fall back to
+                // the raw string if the model returned something else
+                let tool_args = tool_args.map(|args| {
+                    serde_json::from_str(&args).unwrap_or(serde_json::Value::String(args))
+                });
+                let tool_output = tool_output.and_then(|output| serde_json::from_str(&output).ok());
+                Ok(ChatMessage {
+                    message,
+                    tool_name,
+                    tool_args,
+                    tool_output,
+                    reasoning,
+                })
+            })?
+            .filter_map(Result::ok)
+            .collect::<Vec<ChatMessage>>();
+        Ok(rows)
+    });
+    Ok(messages.await?)
+}
+
+/// Copy the messages of a chat session up to and including the
+/// message at `upto` into a new session with the same tags. Returns
+/// the number of messages copied.
+pub async fn fork_chat_session(
+    db: &Connection,
+    session_id: &str,
+    new_session_id: &str,
+    upto: usize,
+) -> Result<usize, Error> {
+    let s_id = session_id.to_owned();
+    let new_s_id = new_session_id.to_owned();
+    let copied = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("INSERT INTO session (id) VALUES (?)", [&new_s_id])?;
+            tx.execute(
+                "INSERT INTO session_tag (session_id, tag_id)
+                 SELECT ?, tag_id FROM session_tag WHERE session_id = ?",
+                [&new_s_id, &s_id],
+            )?;
+            let copied = tx.execute(
+                "INSERT INTO chat_message (session_id, data, tool_name, tool_args, tool_output, reasoning)
+                 SELECT ?, data, tool_name, tool_args, tool_output, reasoning FROM chat_message
+                 WHERE session_id = ? ORDER BY rowid LIMIT ?",
+                tokio_rusqlite::params![new_s_id, s_id, upto + 1],
+            )?;
+            tx.commit()?;
+            Ok(copied)
+        })
+        .await?;
+
+    Ok(copied)
+}
+
+/// Sessions tagged with this are never evicted
+pub const PINNED_TAG: &str = "pinned";
+
+/// Delete the least recently used sessions, along with their messages
+/// and tags, so there are no more than `max_sessions`. Sessions tagged
+/// with `PINNED_TAG` are kept even if that leaves more than
+/// `max_sessions`. Returns the number of sessions deleted.
+pub async fn evict_chat_sessions(db: &Connection, max_sessions: usize) -> Result<usize, Error> {
+    let evicted = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let total: usize = tx.query_row("SELECT COUNT(*) FROM session", [], |r| r.get(0))?;
+            let excess = total.saturating_sub(max_sessions);
+            if excess == 0 {
+                return Ok(0);
+            }
+
+            // A session is used when a message is added to it so the
+            // latest message is when it was last used. Sessions without
+            // messages were only just created.
+            let session_ids = tx
+                .prepare(
+                    "SELECT s.id FROM session s
+                     WHERE s.id NOT IN (
+                       SELECT st.session_id FROM session_tag st
+                       JOIN tag t ON t.id = st.tag_id
+                       WHERE t.name = ?1
+                     )
+                     ORDER BY
+                       (SELECT MAX(rowid) FROM chat_message WHERE session_id = s.id) NULLS LAST,
+                       s.created_at
+                     LIMIT ?2",
+                )?
+                .query_map(tokio_rusqlite::params![PINNED_TAG, excess], |r| {
+                    r.get::<_, String>(0)
+                })?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            for session_id in &session_ids {
+                tx.execute(
+                    "DELETE FROM chat_message WHERE session_id = ?",
+                    [session_id],
+                )?;
+                tx.execute("DELETE FROM session_tag WHERE session_id = ?", [session_id])?;
+                tx.execute("DELETE FROM session WHERE id = ?", [session_id])?;
+            }
+            tx.commit()?;
+            Ok(session_ids.len())
+        })
+        .await?;
+
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::test_db;
+    use crate::openai::{FunctionCall, Role};
+    use tempfile::TempDir;
+
+    async fn setup_db(dir: &TempDir) -> Connection {
+        test_db(dir.path()).await
+    }
+
+    async fn session_ids(db: &Connection) -> Vec<String> {
+        db.call(|conn| {
+            let ids = conn
+                .prepare("SELECT id FROM session ORDER BY id")?
+                .query_map([], |r| r.get(0))?
+                .collect::<std::result::Result<Vec<String>, _>>()?;
+            Ok(ids)
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_evict_least_recently_used_sessions() {
+        let dir = TempDir::new().unwrap();
+        let db = setup_db(&dir).await;
+        for (session_id, tags) in [
+            ("s1", vec![PINNED_TAG]),
+            ("s2", vec![]),
+            ("s3", vec!["work"]),
+            ("s4", vec![]),
+            ("s5", vec![]),
+        ] {
+            get_or_create_session(&db, session_id, &tags).await.unwrap();
+            insert_chat_message(&db, session_id, &Message::new(Role::User, "Hi"), None)
+                .await
+                .unwrap();
+        }
+        // Using a session again makes it the most recently used
+        insert_chat_message(&db, "s2", &Message::new(Role::User, "Hi again"), None)
+            .await
+            .unwrap();
+
+        let evicted = evict_chat_sessions(&db, 3).await.unwrap();
+
+        assert_eq!(evicted, 2);
+        assert_eq!(session_ids(&db).await, vec!["s1", "s2", "s5"]);
+        let messages = find_chat_messages_by_session_id(&db, "s3").await.unwrap();
+        assert!(messages.is_empty());
+
+        // Nothing to evict when under the limit
+        assert_eq!(evict_chat_sessions(&db, 3).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_evict_keeps_pinned_sessions_over_the_limit() {
+        let dir = TempDir::new().unwrap();
+        let db = setup_db(&dir).await;
+        for session_id in ["s1", "s2"] {
+            get_or_create_session(&db, session_id, &[PINNED_TAG])
+                .await
+                .unwrap();
+        }
+        get_or_create_session(&db, "s3", &[]).await.unwrap();
+
+        let evicted = evict_chat_sessions(&db, 1).await.unwrap();
+
+        assert_eq!(evicted, 1);
+        assert_eq!(session_ids(&db).await, vec!["s1", "s2"]);
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let db = setup_db(&dir).await;
+        let session_id = "test-session";
+        get_or_create_session(&db, session_id, &[]).await.unwrap();
+
+        let tool_call = FunctionCallFn {
+            arguments: "{\"query\":\"books\"}".to_string(),
+            name: "search_notes".to_string(),
+        };
+        let request = Message::new_tool_call_request(vec![FunctionCall {
+            function: tool_call.clone(),
+            id: "call_1".to_string(),
+            r#type: String::from("function"),
+        }]);
+        let response = Message::new_tool_call_response("Found 3 notes", "call_1");
+
+        insert_chat_message(
+            &db,
+            session_id,
+            &Message::new(Role::User, "Find books"),
+            None,
+        )
+        .await
+        .unwrap();
+        insert_chat_message(&db, session_id, &request, Some(&tool_call))
+            .await
+            .unwrap();
+        insert_chat_message(&db, session_id, &response, Some(&tool_call))
+            .await
+            .unwrap();
+
+        let messages = find_chat_messages_by_session_id(&db, session_id)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 3);
+
+        assert!(messages[0].tool_name.is_none());
+        assert!(messages[0].tool_args.is_none());
+
+        for msg in &messages[1..] {
+            assert_eq!(msg.tool_name.as_deref(), Some("search_notes"));
+            assert_eq!(msg.tool_args, Some(json!({"query": "books"})));
+        }
+        assert_eq!(messages[2].message.tool_call_id(), Some("call_1"));
+
+        // Tool fields are included when serialized alongside the message
+        let serialized = json!(messages[2]);
+        assert_eq!(serialized["role"], "tool");
+        assert_eq!(serialized["tool_name"], "search_notes");
+        assert_eq!(serialized["tool_args"]["query"], "books");
+
+        // The plain transcript is unaffected
+        let transcript = find_chat_session_by_id(&db, session_id).await.unwrap();
+        assert_eq!(transcript.len(), 3);
+    }
+}
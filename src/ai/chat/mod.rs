@@ -2,4 +2,4 @@ pub mod db;
 pub use db::*;
 pub mod core;
 pub mod models;
-pub use core::{Chat, ChatBuilder};
+pub use core::{Chat, ChatBuilder, RetryBudgetExhausted, Skill};
@@ -1,5 +1,12 @@
 //! The core models for managing a stateful chat with an LLM.
-use crate::openai::Message;
+use std::collections::HashSet;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ai::tokens;
+use crate::openai::{Message, Role};
 
 // TODO: Should there be an app specific `Message` object instead of
 // building around OpenAI?
@@ -24,6 +31,10 @@ impl Transcript {
         self.0.push(msg)
     }
 
+    pub fn pop(&mut self) -> Option<Message> {
+        self.0.pop()
+    }
+
     pub fn iter(&self) -> std::slice::Iter<'_, Message> {
         self.0.iter()
     }
@@ -31,6 +42,112 @@ impl Transcript {
     pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Message> {
         self.0.iter_mut()
     }
+
+    /// Returns a copy of the transcript that fits within `budget`
+    /// tokens by dropping the oldest messages first. When
+    /// `keep_system` is true a leading system message is always kept.
+    ///
+    /// Tool responses whose tool call was dropped are also dropped so
+    /// the result is still a valid transcript.
+    pub fn trim_to_token_budget(&self, budget: usize, keep_system: bool) -> Transcript {
+        let (system, rest) = match self.0.split_first() {
+            Some((first, rest)) if keep_system && *first.role() == Role::System => {
+                (Some(first), rest)
+            }
+            _ => (None, self.0.as_slice()),
+        };
+
+        let costs: Vec<usize> = rest.iter().map(tokens::estimate_message).collect();
+        let mut total: usize =
+            system.map(tokens::estimate_message).unwrap_or(0) + costs.iter().sum::<usize>();
+
+        let mut start = 0;
+        while start < rest.len() && (total > budget || *rest[start].role() == Role::Tool) {
+            total -= costs[start];
+            start += 1;
+        }
+
+        let messages = system
+            .into_iter()
+            .chain(rest[start..].iter())
+            .cloned()
+            .collect();
+        Transcript::new_with_messages(messages)
+    }
+
+    /// Checks that the ordering of messages is something the LLM API
+    /// will accept. Catching this before sending gives a much more
+    /// useful error than the 400 returned by the API.
+    ///
+    /// - A system message, if present, must be the first message,
+    ///   have content, and can't carry tool calls or tool responses
+    /// - A tool message must reference the id of a tool call made by
+    ///   a preceding assistant message and each tool call can only be
+    ///   responded to once
+    pub fn validate(&self) -> Result<()> {
+        let mut pending_tool_calls: HashSet<&str> = HashSet::new();
+
+        for (idx, msg) in self.0.iter().enumerate() {
+            match msg.role() {
+                Role::System => {
+                    if idx != 0 {
+                        bail!(
+                            "System message must be the first message but found one at position {}",
+                            idx
+                        );
+                    }
+                    if msg.content().is_none_or(|c| c.trim().is_empty()) {
+                        bail!("System message at position {} is empty", idx);
+                    }
+                    if msg.tool_calls().is_some() || msg.tool_call_id().is_some() {
+                        bail!(
+                            "System message at position {} can't include tool calls or a tool_call_id",
+                            idx
+                        );
+                    }
+                }
+                Role::Assistant => {
+                    if let Some(tool_calls) = msg.tool_calls() {
+                        pending_tool_calls.extend(tool_calls.iter().map(|c| c.id.as_str()));
+                    }
+                }
+                Role::Tool => {
+                    let Some(tool_call_id) = msg.tool_call_id() else {
+                        bail!("Tool message at position {} is missing a tool_call_id", idx);
+                    };
+                    if !pending_tool_calls.remove(tool_call_id) {
+                        bail!(
+                            "Tool message at position {} references tool call '{}' which does not match a preceding assistant tool call",
+                            idx,
+                            tool_call_id
+                        );
+                    }
+                }
+                Role::User => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A chat message as stored in the db. Tool call requests and
+/// responses also include the name and arguments of the tool that
+/// was called for easier inspection. Tool call responses include
+/// structured output if the tool returned any. Assistant messages
+/// include the model's reasoning when storing reasoning is enabled.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChatMessage {
+    #[serde(flatten)]
+    pub message: Message,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_args: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_output: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
 }
 
 // TODO: Consider a session model to keep track of things like
@@ -39,3 +156,192 @@ impl Transcript {
 //     id: String,
 //     transcript: Transcript,
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openai::{FunctionCall, FunctionCallFn};
+
+    fn tool_call_request(id: &str) -> Message {
+        Message::new_tool_call_request(vec![FunctionCall {
+            function: FunctionCallFn {
+                arguments: "{}".to_string(),
+                name: "search_notes".to_string(),
+            },
+            id: id.to_string(),
+            r#type: String::from("function"),
+        }])
+    }
+
+    fn total_tokens(transcript: &Transcript) -> usize {
+        transcript.iter().map(tokens::estimate_message).sum()
+    }
+
+    fn long_transcript() -> Transcript {
+        let mut transcript = Transcript::new_with_messages(vec![Message::new(
+            Role::System,
+            "You are a helpful assistant",
+        )]);
+        for i in 0..50 {
+            transcript.push(Message::new(
+                Role::User,
+                &format!("Question {} about something in my notes", i),
+            ));
+            transcript.push(tool_call_request(&format!("call_{}", i)));
+            transcript.push(Message::new_tool_call_response(
+                "A long list of search results that take up lots of tokens",
+                &format!("call_{}", i),
+            ));
+            transcript.push(Message::new(
+                Role::Assistant,
+                &format!("Answer {} summarizing the results", i),
+            ));
+        }
+        transcript
+    }
+
+    #[test]
+    fn test_trim_to_token_budget_keeps_system() {
+        let transcript = long_transcript();
+        let budget = 200;
+        assert!(total_tokens(&transcript) > budget);
+
+        let trimmed = transcript.trim_to_token_budget(budget, true);
+        let messages = trimmed.messages();
+
+        assert!(total_tokens(&trimmed) <= budget);
+        assert!(messages.len() > 1);
+        assert_eq!(*messages[0].role(), Role::System);
+        assert_eq!(messages[0].content(), Some("You are a helpful assistant"));
+        // The most recent message is kept
+        assert_eq!(
+            messages.last().unwrap().content(),
+            Some("Answer 49 summarizing the results")
+        );
+        assert!(trimmed.validate().is_ok());
+    }
+
+    #[test]
+    fn test_trim_to_token_budget_drop_system() {
+        let transcript = long_transcript();
+        let trimmed = transcript.trim_to_token_budget(200, false);
+        let messages = trimmed.messages();
+
+        assert!(total_tokens(&trimmed) <= 200);
+        assert!(!messages.is_empty());
+        assert_ne!(*messages[0].role(), Role::System);
+        assert!(trimmed.validate().is_ok());
+    }
+
+    #[test]
+    fn test_trim_to_token_budget_under_budget() {
+        let transcript = long_transcript();
+        let total = total_tokens(&transcript);
+        let trimmed = transcript.trim_to_token_budget(total, true);
+        assert_eq!(trimmed.messages().len(), transcript.messages().len());
+    }
+
+    #[test]
+    fn test_trim_to_token_budget_drops_orphaned_tool_responses() {
+        let transcript = Transcript::new_with_messages(vec![
+            Message::new(Role::System, "You are a helpful assistant"),
+            tool_call_request("call_1"),
+            Message::new_tool_call_response("results", "call_1"),
+            Message::new(Role::Assistant, "Done"),
+        ]);
+        let system = tokens::estimate_message(&transcript.messages()[0]);
+        let last = tokens::estimate_message(&transcript.messages()[3]);
+        let tool_response = tokens::estimate_message(&transcript.messages()[2]);
+
+        // Enough budget for the tool response but not the tool call
+        let trimmed = transcript.trim_to_token_budget(system + tool_response + last, true);
+        let messages = trimmed.messages();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content(), Some("Done"));
+        assert!(trimmed.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_valid_transcript() {
+        let transcript = Transcript::new_with_messages(vec![
+            Message::new(Role::System, "You are a helpful assistant"),
+            Message::new(Role::User, "Search my notes"),
+            tool_call_request("call_1"),
+            Message::new_tool_call_response("results", "call_1"),
+            Message::new(Role::Assistant, "Here's what I found"),
+            Message::new(Role::User, "Thanks"),
+        ]);
+        assert!(transcript.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_empty_and_no_system_message() {
+        assert!(Transcript::new().validate().is_ok());
+
+        let transcript = Transcript::new_with_messages(vec![Message::new(Role::User, "Hi")]);
+        assert!(transcript.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_system_message_not_first() {
+        let transcript = Transcript::new_with_messages(vec![
+            Message::new(Role::User, "Hi"),
+            Message::new(Role::System, "You are a helpful assistant"),
+        ]);
+        let err = transcript.validate().unwrap_err().to_string();
+        assert!(err.contains("System message must be the first message"));
+        assert!(err.contains("position 1"));
+    }
+
+    #[test]
+    fn test_validate_empty_system_message() {
+        let transcript = Transcript::new_with_messages(vec![Message::new(Role::System, "  ")]);
+        let err = transcript.validate().unwrap_err().to_string();
+        assert!(err.contains("System message at position 0 is empty"));
+    }
+
+    #[test]
+    fn test_validate_tool_message_without_tool_call() {
+        let transcript = Transcript::new_with_messages(vec![
+            Message::new(Role::User, "Search my notes"),
+            Message::new_tool_call_response("results", "call_1"),
+        ]);
+        let err = transcript.validate().unwrap_err().to_string();
+        assert!(err.contains("call_1"));
+        assert!(err.contains("position 1"));
+    }
+
+    #[test]
+    fn test_validate_tool_message_before_tool_call() {
+        let transcript = Transcript::new_with_messages(vec![
+            Message::new(Role::User, "Search my notes"),
+            Message::new_tool_call_response("results", "call_1"),
+            tool_call_request("call_1"),
+        ]);
+        assert!(transcript.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_tool_message_wrong_id() {
+        let transcript = Transcript::new_with_messages(vec![
+            Message::new(Role::User, "Search my notes"),
+            tool_call_request("call_1"),
+            Message::new_tool_call_response("results", "call_2"),
+        ]);
+        let err = transcript.validate().unwrap_err().to_string();
+        assert!(err.contains("call_2"));
+    }
+
+    #[test]
+    fn test_validate_duplicate_tool_response() {
+        let transcript = Transcript::new_with_messages(vec![
+            Message::new(Role::User, "Search my notes"),
+            tool_call_request("call_1"),
+            Message::new_tool_call_response("results", "call_1"),
+            Message::new_tool_call_response("results", "call_1"),
+        ]);
+        let err = transcript.validate().unwrap_err().to_string();
+        assert!(err.contains("position 3"));
+    }
+}
@@ -1,4 +1,5 @@
 pub mod agents;
 pub mod chat;
 pub mod prompt;
+pub mod tokens;
 pub mod tools;
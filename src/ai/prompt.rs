@@ -17,6 +17,7 @@ handlebars_helper!(inc: |v: i64| format!("{}", v + 1));
 pub enum Prompt {
     NoteSummary,
     UnreadEmails,
+    EmailAssist,
 }
 
 impl fmt::Display for Prompt {
@@ -68,6 +69,30 @@ The following is a list of unread emails and their related email thread in rever
 {{/each}}
 ";
 
+const EMAIL_ASSIST_PROMPT: &str = r"
+Summarize this email thread (THREAD) concisely and draft a reply to the most recent message on behalf of {{email}}. Match the tone of the thread and don't make commitments that aren't in the thread.
+
+Respond with a JSON object with a `summary` key for the summary and a `draft` key for the body of the reply.
+
+THREAD:
+
+## {{thread.subject}}
+
+{{#each thread.messages}}
+### Message {{inc @index}}
+
+**From:** {{from}}
+**To:** {{to}}
+**Date:** {{received}}
+**Subject:** {{subject}}
+**Body:**
+{{body}}
+
+---
+
+{{/each}}
+";
+
 pub fn templates<'a>() -> Handlebars<'a> {
     let mut registry = Handlebars::new();
     registry.set_strict_mode(true);
@@ -79,4 +104,7 @@ pub fn templates<'a>() -> Handlebars<'a> {
         .register_template_string(&Prompt::UnreadEmails.to_string(), UNREAD_EMAILS_PROMPT)
         .expect("Failed to register template");
     registry
+        .register_template_string(&Prompt::EmailAssist.to_string(), EMAIL_ASSIST_PROMPT)
+        .expect("Failed to register template");
+    registry
 }
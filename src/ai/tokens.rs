@@ -0,0 +1,107 @@
+//! Estimate token counts without calling the LLM API. Useful for
+//! managing the context window and reporting usage.
+use std::sync::OnceLock;
+
+use tiktoken_rs::{CoreBPE, cl100k_base, o200k_base};
+
+use crate::openai::Message;
+
+// Approximate number of tokens the API adds for each message to
+// delimit roles and content
+const TOKENS_PER_MESSAGE: usize = 4;
+
+// Model name prefixes that use the newer o200k_base encoding
+const O200K_MODEL_PREFIXES: [&str; 6] = ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"];
+
+static CL100K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+static O200K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+
+fn cl100k() -> &'static CoreBPE {
+    CL100K_BASE.get_or_init(|| cl100k_base().expect("Failed to load cl100k_base tokenizer"))
+}
+
+fn o200k() -> &'static CoreBPE {
+    O200K_BASE.get_or_init(|| o200k_base().expect("Failed to load o200k_base tokenizer"))
+}
+
+/// Returns the tokenizer used by `model`. Falls back to cl100k_base
+/// for unknown models (e.g. local models) which is a reasonable
+/// approximation.
+fn tokenizer_for_model(model: &str) -> &'static CoreBPE {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    if O200K_MODEL_PREFIXES.iter().any(|p| model.starts_with(p)) {
+        o200k()
+    } else {
+        cl100k()
+    }
+}
+
+/// Estimates the number of tokens in `text` using the cl100k_base
+/// encoding.
+pub fn estimate(text: &str) -> usize {
+    cl100k().encode_ordinary(text).len()
+}
+
+/// Estimates the number of tokens in `text` using the encoding for
+/// `model`.
+pub fn estimate_for_model(text: &str, model: &str) -> usize {
+    tokenizer_for_model(model).encode_ordinary(text).len()
+}
+
+/// Estimates the number of tokens a message will take up in the
+/// prompt including any tool calls.
+pub fn estimate_message(msg: &Message) -> usize {
+    let content = msg.content().map(estimate).unwrap_or(0);
+    let tool_calls: usize = msg
+        .tool_calls()
+        .unwrap_or_default()
+        .iter()
+        .map(|c| estimate(&c.function.name) + estimate(&c.function.arguments))
+        .sum();
+    TOKENS_PER_MESSAGE + content + tool_calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openai::Role;
+
+    // Allow for a small difference so that estimates don't need to
+    // match the tokenizer exactly
+    const TOLERANCE: usize = 1;
+
+    fn assert_close(estimate: usize, expected: usize) {
+        assert!(
+            estimate.abs_diff(expected) <= TOLERANCE,
+            "Expected ~{} tokens, got {}",
+            expected,
+            estimate
+        );
+    }
+
+    #[test]
+    fn test_estimate_known_counts() {
+        assert_eq!(estimate(""), 0);
+        assert_close(estimate("hello world"), 2);
+        assert_close(estimate("tiktoken is great!"), 6);
+        assert_close(estimate("The quick brown fox jumps over the lazy dog."), 10);
+    }
+
+    #[test]
+    fn test_estimate_for_model() {
+        assert_close(estimate_for_model("tiktoken is great!", "gpt-4"), 6);
+        assert_close(estimate_for_model("tiktoken is great!", "gpt-4o-mini"), 5);
+        assert_close(estimate_for_model("tiktoken is great!", "openai/gpt-4o"), 5);
+        // Unknown models fall back to cl100k_base
+        assert_eq!(
+            estimate_for_model("tiktoken is great!", "llama3.2"),
+            estimate("tiktoken is great!")
+        );
+    }
+
+    #[test]
+    fn test_estimate_message() {
+        let msg = Message::new(Role::User, "hello world");
+        assert_close(estimate_message(&msg), TOKENS_PER_MESSAGE + 2);
+    }
+}
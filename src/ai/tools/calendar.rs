@@ -1,5 +1,6 @@
 use crate::api::public::calendar::CalendarResponse;
-use crate::openai::{Function, Parameters, Property, ToolCall, ToolType};
+use crate::core::http;
+use crate::openai::{Function, Parameters, Property, ToolCall, ToolOutput, ToolType};
 use anyhow::{Error, Result};
 use async_trait::async_trait;
 use reqwest;
@@ -32,6 +33,11 @@ pub struct CalendarTool {
 #[async_trait]
 impl ToolCall for CalendarTool {
     async fn call(&self, args: &str) -> Result<String, Error> {
+        Ok(self.call_structured(args).await?.text)
+    }
+
+    /// Also returns the events as JSON so clients can render them
+    async fn call_structured(&self, args: &str) -> Result<ToolOutput, Error> {
         let fn_args: CalendarArgs = serde_json::from_str(args).unwrap();
 
         // Get all authorized email addresses from the database
@@ -46,10 +52,11 @@ impl ToolCall for CalendarTool {
         }).await?;
 
         if emails.is_empty() {
-            return Ok("No authorized calendar accounts found.".to_string());
+            return Ok(ToolOutput::from("No authorized calendar accounts found.".to_string()));
         }
 
         let mut all_events = vec![];
+        let mut events = vec![];
 
         for email in emails {
             // Build URL for this email
@@ -68,7 +75,7 @@ impl ToolCall for CalendarTool {
                     .append_pair("calendar_id", &calendar_id);
             }
 
-            let resp = reqwest::Client::new()
+            let resp = http::shared_client()?
                 .get(url.as_str())
                 .header("Content-Type", "application/json")
                 .send()
@@ -101,12 +108,16 @@ impl ToolCall for CalendarTool {
                 all_events.push(format!(
                     "## {}\nStart: {}\nEnd: {}\n{}\n",
                     event.summary, event.start, event.end, attendees_str
-                ))
+                ));
+                events.push(event);
             }
         }
 
         let out = all_events.join("\n\n");
-        Ok(out)
+        Ok(ToolOutput {
+            text: out,
+            structured: Some(serde_json::json!({ "events": events })),
+        })
     }
 
     fn function_name(&self) -> String {
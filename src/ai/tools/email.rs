@@ -1,6 +1,10 @@
 use crate::ai::prompt::{self, Prompt};
 use crate::api::public;
-use crate::openai::{Function, Parameters, Property, ToolCall, ToolType};
+use crate::core::http;
+use crate::openai::{
+    CompletionOptions, Function, Message, Parameters, Property, Role, ToolCall, ToolType,
+    completion,
+};
 use anyhow::{Context, Error, Result};
 use async_trait::async_trait;
 use reqwest;
@@ -23,6 +27,8 @@ pub struct EmailUnreadTool {
     pub r#type: ToolType,
     pub function: Function<EmailUnreadProps>,
     api_base_url: String,
+    #[serde(skip)]
+    mark_read: bool,
 }
 
 #[async_trait]
@@ -33,8 +39,11 @@ impl ToolCall for EmailUnreadTool {
         let mut url = reqwest::Url::parse(&format!("{}/api/email/unread", self.api_base_url))
             .expect("Invalid URL");
         url.query_pairs_mut().append_pair("email", &fn_args.email);
+        if self.mark_read {
+            url.query_pairs_mut().append_pair("mark_read", "true");
+        }
 
-        let resp: Value = reqwest::Client::new()
+        let resp: Value = http::shared_client()?
             .get(url.as_str())
             .header("Content-Type", "application/json")
             .send()
@@ -82,8 +91,17 @@ impl EmailUnreadTool {
             r#type: ToolType::Function,
             function,
             api_base_url: api_base_url.to_string(),
+            mark_read: false,
         }
     }
+
+    /// Mark the unread emails as read once they are fetched so they
+    /// aren't returned the next time the tool is called. Off by
+    /// default so the tool doesn't change the mailbox.
+    pub fn mark_read(mut self, mark_read: bool) -> Self {
+        self.mark_read = mark_read;
+        self
+    }
 }
 
 impl Default for EmailUnreadTool {
@@ -92,6 +110,142 @@ impl Default for EmailUnreadTool {
     }
 }
 
+#[derive(Serialize)]
+pub struct EmailAssistProps {
+    pub email: Property,
+    pub thread_id: Property,
+}
+
+#[derive(Deserialize)]
+pub struct EmailAssistArgs {
+    pub email: String,
+    pub thread_id: String,
+}
+
+/// Summary and draft reply from the LLM
+#[derive(Deserialize)]
+struct EmailAssistResponse {
+    summary: String,
+    draft: String,
+}
+
+/// Summarizes an email thread and drafts a reply in one call for the
+/// common "help me respond" flow
+#[derive(Serialize)]
+pub struct EmailAssistTool {
+    pub r#type: ToolType,
+    pub function: Function<EmailAssistProps>,
+    #[serde(skip)]
+    api_base_url: String,
+    #[serde(skip)]
+    openai_api_hostname: String,
+    #[serde(skip)]
+    openai_api_key: String,
+    #[serde(skip)]
+    openai_model: String,
+}
+
+#[async_trait]
+impl ToolCall for EmailAssistTool {
+    async fn call(&self, args: &str) -> Result<String, Error> {
+        let fn_args: EmailAssistArgs = serde_json::from_str(args)?;
+
+        let mut url = reqwest::Url::parse(&format!("{}/api/email/thread", self.api_base_url))
+            .expect("Invalid URL");
+        url.path_segments_mut()
+            .expect("Invalid URL")
+            .push(&fn_args.thread_id);
+        url.query_pairs_mut().append_pair("email", &fn_args.email);
+
+        let thread: public::email::EmailThread = http::shared_client()?
+            .get(url.as_str())
+            .header("Content-Type", "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| "Attempted to parse email thread from json")?;
+
+        let templates = prompt::templates();
+        let prompt = templates.render(
+            &Prompt::EmailAssist.to_string(),
+            &json!({"email": fn_args.email, "thread": thread}),
+        )?;
+
+        let options = CompletionOptions {
+            response_format: Some(json!({"type": "json_object"})),
+            ..Default::default()
+        };
+        let resp = completion(
+            &vec![Message::new(Role::User, &prompt)],
+            &None,
+            &self.openai_api_hostname,
+            &self.openai_api_key,
+            &self.openai_model,
+            &options,
+        )
+        .await?;
+        let content = resp["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("No content in completion: {}", resp))?;
+        let assist: EmailAssistResponse = serde_json::from_str(content)
+            .with_context(|| "Attempted to parse summary and draft from json")?;
+
+        Ok(format!(
+            "# Summary\n\n{}\n\n# Draft Reply\n\n{}",
+            assist.summary.trim(),
+            assist.draft.trim()
+        ))
+    }
+
+    fn function_name(&self) -> String {
+        self.function.name.clone()
+    }
+}
+
+impl EmailAssistTool {
+    pub fn new(
+        api_base_url: &str,
+        openai_api_hostname: &str,
+        openai_api_key: &str,
+        openai_model: &str,
+    ) -> Self {
+        let function = Function {
+            name: String::from("summarize_and_draft_email_reply"),
+            description: String::from(
+                "Summarize an email thread and draft a reply to the most recent message.",
+            ),
+            parameters: Parameters {
+                r#type: String::from("object"),
+                properties: EmailAssistProps {
+                    email: Property {
+                        r#type: String::from("string"),
+                        description: String::from("The email address the thread belongs to."),
+                        r#enum: None,
+                    },
+                    thread_id: Property {
+                        r#type: String::from("string"),
+                        description: String::from("The ID of the email thread to reply to."),
+                        r#enum: None,
+                    },
+                },
+                required: vec![String::from("email"), String::from("thread_id")],
+                additional_properties: false,
+            },
+            strict: true,
+        };
+        Self {
+            r#type: ToolType::Function,
+            function,
+            api_base_url: api_base_url.to_string(),
+            openai_api_hostname: openai_api_hostname.to_string(),
+            openai_api_key: openai_api_key.to_string(),
+            openai_model: openai_model.to_string(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -121,4 +275,95 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn it_marks_unread_emails_read_when_enabled() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock(
+                "GET",
+                "/api/email/unread?email=test%40example.com&mark_read=true",
+            )
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let tool = EmailUnreadTool::new(&url).mark_read(true);
+        tool.call(r#"{"email": "test@example.com"}"#).await?;
+        mock.assert_async().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_summarizes_and_drafts_a_reply() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let thread = json!({
+            "id": "thr_001",
+            "received": "1731399323000",
+            "from": "alice@example.com",
+            "to": "bob@example.org",
+            "subject": "Project kickoff meeting",
+            "messages": [{
+                "id": "msg_001",
+                "thread_id": "thr_001",
+                "from": "alice@example.com",
+                "to": "bob@example.org",
+                "received": "1731399323000",
+                "subject": "Project kickoff meeting",
+                "body": "Can we schedule a call tomorrow to go over the kickoff agenda?"
+            }]
+        });
+        let thread_mock = server
+            .mock("GET", "/api/email/thread/thr_001?email=bob%40example.org")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(thread.to_string())
+            .create_async()
+            .await;
+
+        let content = json!({
+            "summary": "Alice wants a call tomorrow about the kickoff agenda.",
+            "draft": "Hi Alice, tomorrow at 10am works for me."
+        });
+        let completion_mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                "go over the kickoff agenda".to_string(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": content.to_string()},
+                        "finish_reason": "stop"
+                    }]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let tool = EmailAssistTool::new(&url, &url, "test-key", "gpt-4");
+        let actual = tool
+            .call(r#"{"email": "bob@example.org", "thread_id": "thr_001"}"#)
+            .await?;
+        thread_mock.assert_async().await;
+        completion_mock.assert_async().await;
+
+        assert_eq!(
+            actual,
+            "# Summary\n\nAlice wants a call tomorrow about the kickoff agenda.\n\n# Draft Reply\n\nHi Alice, tomorrow at 10am works for me."
+        );
+
+        Ok(())
+    }
 }
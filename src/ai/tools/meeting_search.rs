@@ -1,4 +1,5 @@
 use crate::api::public::notes::SearchResponse;
+use crate::core::http;
 use crate::openai::{Function, Parameters, Property, ToolCall, ToolType};
 use anyhow::{Error, Result};
 use async_trait::async_trait;
@@ -35,7 +36,7 @@ impl ToolCall for MeetingSearchTool {
         let query = format!("tags:meeting {}", &fn_args.query);
         url.query_pairs_mut().append_pair("query", &query);
 
-        let resp = reqwest::Client::new()
+        let resp = http::shared_client()?
             .get(url.as_str())
             .header("Content-Type", "application/json")
             .send()
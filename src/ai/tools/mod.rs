@@ -8,7 +8,9 @@ pub mod calendar;
 pub use calendar::CalendarTool;
 
 pub mod email;
-pub use email::EmailUnreadTool;
+pub use email::{EmailAssistTool, EmailUnreadTool};
+
+mod readability;
 
 pub mod website_view;
 pub use website_view::WebsiteViewTool;
@@ -17,7 +19,7 @@ pub mod web_search;
 pub use web_search::WebSearchTool;
 
 pub mod tasks;
-pub use tasks::{TasksDueTodayTool, TasksScheduledTodayTool};
+pub use tasks::{TaskCompleteTool, TaskSnoozeTool, TasksDueTodayTool, TasksScheduledTodayTool};
 
 pub mod memory;
 pub use memory::MemoryTool;
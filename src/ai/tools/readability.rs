@@ -0,0 +1,202 @@
+//! Main content extraction for web pages, loosely based on Mozilla's
+//! Readability. Paragraphs are scored by how much prose they contain
+//! and the score is propagated to their ancestors. The highest scoring
+//! container is treated as the article body so navigation, ads, and
+//! other boilerplate are left out.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use htmd::Node;
+use markup5ever_rcdom::NodeData;
+
+/// Tags that never contain the main content of a page
+const BOILERPLATE_TAGS: [&str; 9] = [
+    "nav", "header", "footer", "aside", "form", "script", "style", "noscript", "svg",
+];
+
+/// Tags whose text is counted as prose when scoring
+const PARAGRAPH_TAGS: [&str; 3] = ["p", "pre", "blockquote"];
+
+fn tag_name(node: &Node) -> Option<&str> {
+    match &node.data {
+        NodeData::Element { name, .. } => Some(name.local.as_ref()),
+        _ => None,
+    }
+}
+
+fn is_boilerplate(node: &Node) -> bool {
+    tag_name(node).is_some_and(|tag| BOILERPLATE_TAGS.contains(&tag))
+}
+
+/// Text content of the node and its descendants, ignoring
+/// boilerplate elements
+fn text_content(node: &Node) -> String {
+    let mut text = String::new();
+    collect_text(node, &mut text);
+    text
+}
+
+fn collect_text(node: &Node, text: &mut String) {
+    if is_boilerplate(node) {
+        return;
+    }
+    if let NodeData::Text { contents } = &node.data {
+        text.push_str(&contents.borrow());
+    }
+    for child in node.children.borrow().iter() {
+        collect_text(child, text);
+    }
+}
+
+/// Find the first element with the given tag that has the most text
+fn largest_element(node: &Rc<Node>, tag: &str) -> Option<Rc<Node>> {
+    let mut found = Vec::new();
+    find_elements(node, tag, &mut found);
+    // `min_by_key` keeps the first of equal elements unlike `max_by_key`
+    found
+        .into_iter()
+        .min_by_key(|n| std::cmp::Reverse(text_content(n).trim().len()))
+}
+
+fn find_elements(node: &Rc<Node>, tag: &str, found: &mut Vec<Rc<Node>>) {
+    if is_boilerplate(node) {
+        return;
+    }
+    if tag_name(node) == Some(tag) {
+        found.push(Rc::clone(node));
+    }
+    for child in node.children.borrow().iter() {
+        find_elements(child, tag, found);
+    }
+}
+
+/// Score paragraphs and add the score to their parent and half of it
+/// to their grandparent, keyed by node pointer.
+fn score_paragraphs(
+    node: &Rc<Node>,
+    ancestors: &mut Vec<Rc<Node>>,
+    scores: &mut HashMap<*const Node, (f32, Rc<Node>)>,
+) {
+    if is_boilerplate(node) {
+        return;
+    }
+
+    if tag_name(node).is_some_and(|tag| PARAGRAPH_TAGS.contains(&tag)) {
+        let text = text_content(node);
+        let text = text.trim();
+        // Skip short snippets like captions and button labels
+        if text.len() >= 25 {
+            let commas = text.matches(',').count() as f32;
+            let length_bonus = (text.len() as f32 / 100.0).min(3.0);
+            let score = 1.0 + commas + length_bonus;
+            let mut weight = 1.0;
+            for ancestor in ancestors.iter().rev().take(2) {
+                let entry = scores
+                    .entry(Rc::as_ptr(ancestor))
+                    .or_insert_with(|| (0.0, Rc::clone(ancestor)));
+                entry.0 += score * weight;
+                weight /= 2.0;
+            }
+        }
+        return;
+    }
+
+    ancestors.push(Rc::clone(node));
+    for child in node.children.borrow().iter() {
+        score_paragraphs(child, ancestors, scores);
+    }
+    ancestors.pop();
+}
+
+/// Returns the node containing the main content of the page. Prefers
+/// explicit `<article>` or `<main>` elements, otherwise picks the
+/// container with the highest paragraph score. Falls back to the
+/// whole document if nothing looks like an article.
+pub fn main_content(document: &Rc<Node>) -> Rc<Node> {
+    if let Some(article) = largest_element(document, "article") {
+        return article;
+    }
+    if let Some(main) = largest_element(document, "main") {
+        return main;
+    }
+
+    let mut scores = HashMap::new();
+    score_paragraphs(document, &mut Vec::new(), &mut scores);
+    scores
+        .into_values()
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, node)| node)
+        .unwrap_or_else(|| Rc::clone(document))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use htmd::HtmlToMarkdown;
+
+    fn extract(html: &str) -> String {
+        let converter = HtmlToMarkdown::new();
+        let tree = converter.html_to_tree(html).unwrap();
+        converter.tree_to_markdown(&main_content(&tree))
+    }
+
+    #[test]
+    fn it_extracts_article_and_drops_nav() {
+        let html = r#"
+<html><body>
+  <nav><a href="/">Home</a> <a href="/about">About us</a></nav>
+  <article>
+    <h1>The article title</h1>
+    <p>This is the body of the article, with enough prose to count as content.</p>
+  </article>
+  <footer>Copyright footer text</footer>
+</body></html>
+"#;
+        let content = extract(html);
+        assert!(content.contains("The article title"));
+        assert!(content.contains("This is the body of the article"));
+        assert!(!content.contains("About us"));
+        assert!(!content.contains("Copyright footer"));
+    }
+
+    #[test]
+    fn it_scores_paragraphs_without_article_tag() {
+        let html = r#"
+<html><body>
+  <div class="menu"><nav><a href="/">Home</a></nav><p>Sign up for our newsletter today</p></div>
+  <div class="sidebar"><a href="/ad">Buy now</a></div>
+  <div class="content">
+    <p>The first paragraph of the story, which goes on for a while, and has commas.</p>
+    <p>The second paragraph continues the story, adding more detail, and more commas.</p>
+    <p>The third paragraph wraps things up, concluding the story, for the reader.</p>
+  </div>
+</body></html>
+"#;
+        let content = extract(html);
+        assert!(content.contains("The first paragraph of the story"));
+        assert!(content.contains("The third paragraph wraps things up"));
+        assert!(!content.contains("Home"));
+        assert!(!content.contains("Buy now"));
+        assert!(!content.contains("newsletter"));
+    }
+
+    #[test]
+    fn it_keeps_the_first_of_equally_large_articles() {
+        let html = r#"
+<html><body>
+  <article><p>First article body</p></article>
+  <article><p>Later article body</p></article>
+</body></html>
+"#;
+        let content = extract(html);
+        assert!(content.contains("First article body"));
+        assert!(!content.contains("Later article body"));
+    }
+
+    #[test]
+    fn it_falls_back_to_the_whole_document() {
+        let html = "<html><body><span>Short</span></body></html>";
+        let content = extract(html);
+        assert!(content.contains("Short"));
+    }
+}
@@ -1,8 +1,12 @@
-use crate::api::public::notes::SearchResponse;
-use crate::openai::{Function, Parameters, ToolCall, ToolType};
+use crate::api::public::notes::{
+    CompleteTaskRequest, CompleteTaskResponse, SearchResponse, SnoozeTaskRequest,
+    SnoozeTaskResponse,
+};
+use crate::core::{http, time};
+use crate::openai::{Function, Parameters, Property, ToolCall, ToolType};
 use anyhow::{Error, Result};
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono_tz::Tz;
 use reqwest;
 use serde::{Deserialize, Serialize};
 
@@ -17,12 +21,14 @@ pub struct TasksDueTodayTool {
     pub r#type: ToolType,
     pub function: Function<TasksDueTodayProps>,
     api_base_url: String,
+    #[serde(skip)]
+    timezone: Tz,
 }
 
 #[async_trait]
 impl ToolCall for TasksDueTodayTool {
     async fn call(&self, _args: &str) -> Result<String, Error> {
-        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let today = time::today(self.timezone).format("%Y-%m-%d").to_string();
 
         // Build query: deadline:<TODAY> -status:done -status:canceled -title:journal
         let query = format!("deadline:<={} -status:done -status:canceled", today);
@@ -33,7 +39,7 @@ impl ToolCall for TasksDueTodayTool {
             .append_pair("query", &query)
             .append_pair("include_similarity", "false");
 
-        let search_resp: SearchResponse = reqwest::Client::new()
+        let search_resp: SearchResponse = http::shared_client()?
             .get(url.as_str())
             .header("Content-Type", "application/json")
             .send()
@@ -60,7 +66,7 @@ impl ToolCall for TasksDueTodayTool {
 }
 
 impl TasksDueTodayTool {
-    pub fn new(api_base_url: &str) -> Self {
+    pub fn new(api_base_url: &str, timezone: Tz) -> Self {
         let function = Function {
             name: String::from("tasks_due_today"),
             description: String::from(
@@ -78,13 +84,14 @@ impl TasksDueTodayTool {
             r#type: ToolType::Function,
             function,
             api_base_url: api_base_url.to_string(),
+            timezone,
         }
     }
 }
 
 impl Default for TasksDueTodayTool {
     fn default() -> Self {
-        Self::new("http://localhost:2222")
+        Self::new("http://localhost:2222", Tz::UTC)
     }
 }
 
@@ -99,12 +106,14 @@ pub struct TasksScheduledTodayTool {
     pub r#type: ToolType,
     pub function: Function<TasksScheduledTodayProps>,
     api_base_url: String,
+    #[serde(skip)]
+    timezone: Tz,
 }
 
 #[async_trait]
 impl ToolCall for TasksScheduledTodayTool {
     async fn call(&self, _args: &str) -> Result<String, Error> {
-        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let today = time::today(self.timezone).format("%Y-%m-%d").to_string();
 
         // Build query: scheduled:<TODAY> -status:done -status:canceled -title:journal
         let query = format!("scheduled:<={} -status:done -status:canceled", today);
@@ -115,7 +124,7 @@ impl ToolCall for TasksScheduledTodayTool {
             .append_pair("query", &query)
             .append_pair("include_similarity", "false");
 
-        let resp = reqwest::Client::new()
+        let resp = http::shared_client()?
             .get(url.as_str())
             .header("Content-Type", "application/json")
             .send()
@@ -142,7 +151,7 @@ impl ToolCall for TasksScheduledTodayTool {
 }
 
 impl TasksScheduledTodayTool {
-    pub fn new(api_base_url: &str) -> Self {
+    pub fn new(api_base_url: &str, timezone: Tz) -> Self {
         let function = Function {
             name: String::from("tasks_scheduled_today"),
             description: String::from(
@@ -160,11 +169,209 @@ impl TasksScheduledTodayTool {
             r#type: ToolType::Function,
             function,
             api_base_url: api_base_url.to_string(),
+            timezone,
         }
     }
 }
 
 impl Default for TasksScheduledTodayTool {
+    fn default() -> Self {
+        Self::new("http://localhost:2222", Tz::UTC)
+    }
+}
+
+#[derive(Serialize)]
+pub struct TaskSnoozeProps {
+    pub id: Property,
+    pub date: Property,
+    pub kind: Property,
+}
+
+#[derive(Deserialize)]
+pub struct TaskSnoozeArgs {
+    pub id: String,
+    pub date: String,
+    pub kind: String,
+}
+
+#[derive(Serialize)]
+pub struct TaskSnoozeTool {
+    pub r#type: ToolType,
+    pub function: Function<TaskSnoozeProps>,
+    #[serde(skip)]
+    api_base_url: String,
+}
+
+#[async_trait]
+impl ToolCall for TaskSnoozeTool {
+    async fn call(&self, args: &str) -> Result<String, Error> {
+        let fn_args: TaskSnoozeArgs = serde_json::from_str(args)?;
+
+        let mut url = reqwest::Url::parse(&self.api_base_url).expect("Invalid URL");
+        url.path_segments_mut().expect("Invalid URL").extend([
+            "api",
+            "notes",
+            &fn_args.id,
+            "snooze",
+        ]);
+
+        let resp = http::shared_client()?
+            .post(url.as_str())
+            .json(&SnoozeTaskRequest {
+                date: fn_args.date,
+                kind: Some(fn_args.kind),
+            })
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let message = resp.text().await.unwrap_or_default();
+            return Ok(format!("Failed to snooze task ({}): {}", status, message));
+        }
+
+        let snoozed: SnoozeTaskResponse = resp.json().await?;
+        Ok(format!(
+            "Task {} is now {} for {}",
+            snoozed.id, snoozed.kind, snoozed.date
+        ))
+    }
+
+    fn function_name(&self) -> String {
+        self.function.name.clone()
+    }
+}
+
+impl TaskSnoozeTool {
+    pub fn new(api_base_url: &str) -> Self {
+        let function = Function {
+            name: String::from("snooze_task"),
+            description: String::from(
+                "Reschedule a task by changing its scheduled date or deadline.",
+            ),
+            parameters: Parameters {
+                r#type: String::from("object"),
+                properties: TaskSnoozeProps {
+                    id: Property::new("string", "The ID of the task."),
+                    date: Property::new(
+                        "string",
+                        "The new date as YYYY-MM-DD, 'today', 'tomorrow', or relative to today like '+3d', '+2w', or '+1m'.",
+                    ),
+                    kind: Property::new(
+                        "string",
+                        "Which date to change, use 'scheduled' unless asked to move the deadline.",
+                    )
+                    .with_enum(vec![String::from("scheduled"), String::from("deadline")]),
+                },
+                required: vec![
+                    String::from("id"),
+                    String::from("date"),
+                    String::from("kind"),
+                ],
+                additional_properties: false,
+            },
+            strict: true,
+        };
+        Self {
+            r#type: ToolType::Function,
+            function,
+            api_base_url: api_base_url.to_string(),
+        }
+    }
+}
+
+impl Default for TaskSnoozeTool {
+    fn default() -> Self {
+        Self::new("http://localhost:2222")
+    }
+}
+
+#[derive(Serialize)]
+pub struct TaskCompleteProps {
+    pub id: Property,
+    pub heading: Property,
+}
+
+#[derive(Deserialize)]
+pub struct TaskCompleteArgs {
+    pub id: String,
+    pub heading: String,
+}
+
+#[derive(Serialize)]
+pub struct TaskCompleteTool {
+    pub r#type: ToolType,
+    pub function: Function<TaskCompleteProps>,
+    #[serde(skip)]
+    api_base_url: String,
+}
+
+#[async_trait]
+impl ToolCall for TaskCompleteTool {
+    async fn call(&self, args: &str) -> Result<String, Error> {
+        let fn_args: TaskCompleteArgs = serde_json::from_str(args)?;
+
+        let mut url = reqwest::Url::parse(&self.api_base_url).expect("Invalid URL");
+        url.path_segments_mut().expect("Invalid URL").extend([
+            "api",
+            "notes",
+            &fn_args.id,
+            "complete",
+        ]);
+
+        let heading = Some(fn_args.heading).filter(|h| !h.trim().is_empty());
+        let resp = http::shared_client()?
+            .post(url.as_str())
+            .json(&CompleteTaskRequest { heading })
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let message = resp.text().await.unwrap_or_default();
+            return Ok(format!("Failed to complete task ({}): {}", status, message));
+        }
+
+        let completed: CompleteTaskResponse = resp.json().await?;
+        Ok(format!(
+            "Marked task \"{}\" ({}) as done",
+            completed.title, completed.id
+        ))
+    }
+
+    fn function_name(&self) -> String {
+        self.function.name.clone()
+    }
+}
+
+impl TaskCompleteTool {
+    pub fn new(api_base_url: &str) -> Self {
+        let function = Function {
+            name: String::from("complete_task"),
+            description: String::from("Mark a task as done."),
+            parameters: Parameters {
+                r#type: String::from("object"),
+                properties: TaskCompleteProps {
+                    id: Property::new("string", "The ID of the task or the note it's in."),
+                    heading: Property::new(
+                        "string",
+                        "Title of the task when the ID is for a note with multiple tasks. Use an empty string to complete the note's first open task.",
+                    ),
+                },
+                required: vec![String::from("id"), String::from("heading")],
+                additional_properties: false,
+            },
+            strict: true,
+        };
+        Self {
+            r#type: ToolType::Function,
+            function,
+            api_base_url: api_base_url.to_string(),
+        }
+    }
+}
+
+impl Default for TaskCompleteTool {
     fn default() -> Self {
         Self::new("http://localhost:2222")
     }
@@ -190,7 +397,7 @@ mod tests {
             .with_body(mock_resp)
             .create();
 
-        let tool = TasksDueTodayTool::new(&url);
+        let tool = TasksDueTodayTool::new(&url, Tz::UTC);
         let result = tool.call("{}").await;
         assert!(result.is_ok());
 
@@ -218,7 +425,7 @@ mod tests {
             .with_body(mock_resp)
             .create();
 
-        let tool = TasksScheduledTodayTool::new(&url);
+        let tool = TasksScheduledTodayTool::new(&url, Tz::UTC);
         let result = tool.call("{}").await;
         assert!(result.is_ok());
 
@@ -245,7 +452,7 @@ mod tests {
             .with_body(empty_resp)
             .create();
 
-        let tool = TasksDueTodayTool::new(&url);
+        let tool = TasksDueTodayTool::new(&url, Tz::UTC);
         let result = tool.call("{}").await;
         assert!(result.is_ok());
 
@@ -268,7 +475,7 @@ mod tests {
             .with_body(empty_resp)
             .create();
 
-        let tool = TasksScheduledTodayTool::new(&url);
+        let tool = TasksScheduledTodayTool::new(&url, Tz::UTC);
         let result = tool.call("{}").await;
         assert!(result.is_ok());
 
@@ -291,4 +498,110 @@ mod tests {
         assert_eq!(tool.api_base_url, "http://localhost:2222");
         assert_eq!(tool.function_name(), "tasks_scheduled_today");
     }
+
+    #[tokio::test]
+    async fn it_uses_the_configured_timezone_for_today() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        // UTC+14 is always a day ahead of UTC-12 so only the date in
+        // the configured timezone can match
+        let timezone = Tz::Pacific__Kiritimati;
+        let today = time::today(timezone).format("%Y-%m-%d").to_string();
+        let empty_resp = r#"{"raw_query": "", "parsed_query": "", "results": []}"#;
+        let mock = server
+            .mock("GET", "/api/notes/search")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "query".into(),
+                format!("deadline:<={} -status:done -status:canceled", today),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(empty_resp)
+            .create();
+
+        let tool = TasksDueTodayTool::new(&url, timezone);
+        tool.call("{}").await?;
+        mock.assert();
+
+        let tool = TasksDueTodayTool::new(&url, Tz::Etc__GMTPlus12);
+        assert!(tool.call("{}").await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_snoozes_a_task() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("POST", "/api/notes/note-123/snooze")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "date": "+3d",
+                "kind": "scheduled",
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "note-123", "kind": "scheduled", "date": "2026-02-09"}"#)
+            .create();
+
+        let tool = TaskSnoozeTool::new(&url);
+        let output = tool
+            .call(r#"{"id": "note-123", "date": "+3d", "kind": "scheduled"}"#)
+            .await?;
+
+        mock.assert();
+        assert_eq!(output, "Task note-123 is now scheduled for 2026-02-09");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reports_failed_snooze() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _mock = server
+            .mock("POST", "/api/notes/missing/snooze")
+            .with_status(404)
+            .with_body("Note not found")
+            .create();
+
+        let tool = TaskSnoozeTool::new(&url);
+        let output = tool
+            .call(r#"{"id": "missing", "date": "tomorrow", "kind": "deadline"}"#)
+            .await?;
+
+        assert!(output.contains("Note not found"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_completes_a_task() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("POST", "/api/notes/note-123/complete")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "heading": null,
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "task-1", "title": "Complete project report"}"#)
+            .create();
+
+        let tool = TaskCompleteTool::new(&url);
+        let output = tool.call(r#"{"id": "note-123", "heading": ""}"#).await?;
+
+        mock.assert();
+        assert_eq!(
+            output,
+            "Marked task \"Complete project report\" (task-1) as done"
+        );
+
+        Ok(())
+    }
 }
@@ -1,3 +1,4 @@
+use crate::core::http;
 use crate::openai::{Function, Parameters, Property, ToolCall, ToolType};
 use anyhow::{Error, Result};
 use async_trait::async_trait;
@@ -40,7 +41,7 @@ impl ToolCall for WebSearchTool {
         )
         .expect("Invalid URL");
 
-        let resp: Value = reqwest::Client::new()
+        let resp: Value = http::shared_client()?
             .get(url.as_str())
             .header("Content-Type", "application/json")
             .send()
@@ -1,19 +1,119 @@
+use super::readability;
+use crate::core::http::shared_client;
 use crate::openai::{Function, Parameters, Property, ToolCall, ToolType};
 use anyhow::{Context, Error, Result};
 use async_trait::async_trait;
 use htmd::HtmlToMarkdown;
 use http::StatusCode;
+use regex::Regex;
 use reqwest;
 use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
 
 #[derive(Serialize)]
 pub struct WebsiteViewProps {
     pub url: Property,
+    pub format: Property,
+    pub main_content: Property,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebsiteViewFormat {
+    #[default]
+    Markdown,
+    Org,
 }
 
 #[derive(Deserialize)]
 pub struct WebsiteViewArgs {
     pub url: String,
+    #[serde(default)]
+    pub format: Option<WebsiteViewFormat>,
+    /// Extract only the main content of the page, dropping
+    /// navigation and other boilerplate. Defaults to true.
+    #[serde(default)]
+    pub main_content: Option<bool>,
+}
+
+/// Convert HTML to markdown, skipping tags that are noise for reading
+/// the content of a page. If `main_content` is set, only the main
+/// content of the page is converted (see `readability`).
+pub fn html_to_markdown(html: &str, main_content: bool) -> Result<String> {
+    let converter = HtmlToMarkdown::builder()
+        .skip_tags(vec!["script", "style", "footer", "img", "svg"])
+        .build();
+    if main_content {
+        let tree = converter.html_to_tree(html)?;
+        Ok(converter.tree_to_markdown(&readability::main_content(&tree)))
+    } else {
+        Ok(converter.convert(html)?)
+    }
+}
+
+/// Convert HTML to org-mode by converting it to markdown first and
+/// then rewriting the markdown syntax line by line. This covers
+/// headings, links, lists, emphasis, and code which is enough to
+/// save a page as a note.
+pub fn html_to_org(html: &str, main_content: bool) -> Result<String> {
+    let markdown = html_to_markdown(html, main_content)?;
+    Ok(markdown_to_org(&markdown))
+}
+
+static HEADING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(#{1,6})\s+(.*)$").unwrap());
+static LIST_ITEM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\s*)[-*+]\s+(.*)$").unwrap());
+static LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\[([^\]]*)\]\(([^)\s]+)(?:\s+"[^"]*")?\)"#).unwrap());
+static BOLD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\*\*([^*]+)\*\*").unwrap());
+static ITALIC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\*([^*\s][^*]*)\*").unwrap());
+static INLINE_CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`([^`]+)`").unwrap());
+static ESCAPED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\([\\`*_\[\]])").unwrap());
+
+fn markdown_to_org(markdown: &str) -> String {
+    let mut in_code_block = false;
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some(lang) = trimmed.strip_prefix("```") {
+            if in_code_block {
+                lines.push(String::from("#+END_SRC"));
+            } else {
+                lines.push(format!("#+BEGIN_SRC {}", lang).trim_end().to_string());
+            }
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let line = if let Some(caps) = HEADING.captures(line) {
+            format!("{} {}", "*".repeat(caps[1].len()), &caps[2])
+        } else if let Some(caps) = LIST_ITEM.captures(line) {
+            format!("{}- {}", &caps[1], &caps[2])
+        } else {
+            line.to_string()
+        };
+
+        // Inline markup. Bold uses a placeholder so it isn't picked
+        // up again as italic since org uses a single `*` for bold.
+        let line = INLINE_CODE.replace_all(&line, "~$1~");
+        let line = LINK.replace_all(&line, |caps: &regex::Captures| {
+            if caps[1].is_empty() {
+                format!("[[{}]]", &caps[2])
+            } else {
+                format!("[[{}][{}]]", &caps[2], &caps[1])
+            }
+        });
+        let line = BOLD.replace_all(&line, "\u{1}$1\u{1}");
+        let line = ITALIC.replace_all(&line, "/$1/");
+        let line = line.replace('\u{1}', "*");
+        let line = ESCAPED.replace_all(&line, "$1");
+        lines.push(line.into_owned());
+    }
+
+    lines.join("\n")
 }
 
 #[derive(Serialize)]
@@ -33,7 +133,7 @@ impl ToolCall for WebsiteViewTool {
         // viewing the content but that's a fair tradeoff to prevent
         // accidental data leakage.
         let url = reqwest::Url::parse(fn_args.url.trim())
-            .context(fn_args.url)
+            .context(fn_args.url.clone())
             .expect("Invalid URL");
         let clean_url = format!(
             "{}://{}{}",
@@ -53,17 +153,18 @@ impl ToolCall for WebsiteViewTool {
         // params?
 
         // Fetch the HTML content from the URL
-        let response = reqwest::Client::new().get(&clean_url).send().await;
+        let response = shared_client()?.get(&clean_url).send().await;
 
         // Handle request errors like timeouts
         let content = match response {
             Ok(resp) => {
-                // Convert HTML to markdown
+                // Convert HTML to markdown or org
                 let html_content = resp.text().await?;
-                let converter = HtmlToMarkdown::builder()
-                    .skip_tags(vec!["script", "style", "footer", "img", "svg"])
-                    .build();
-                converter.convert(&html_content)?
+                let main_content = fn_args.main_content.unwrap_or(true);
+                match fn_args.format.unwrap_or_default() {
+                    WebsiteViewFormat::Markdown => html_to_markdown(&html_content, main_content)?,
+                    WebsiteViewFormat::Org => html_to_org(&html_content, main_content)?,
+                }
             }
             Err(e) => {
                 // If the request failed, provide a default answer so we
@@ -128,7 +229,7 @@ impl WebsiteViewTool {
         let function = Function {
             name: String::from("view_website"),
             description: String::from(
-                "Fetch and convert a website's content to markdown for viewing.",
+                "Fetch and convert a website's content to markdown or org-mode for viewing.",
             ),
             parameters: Parameters {
                 r#type: String::from("object"),
@@ -140,8 +241,26 @@ impl WebsiteViewTool {
                         ),
                         r#enum: None,
                     },
+                    format: Property {
+                        r#type: String::from("string"),
+                        description: String::from(
+                            "Output format of the content. Use org when the page will be saved as a note.",
+                        ),
+                        r#enum: Some(vec![String::from("markdown"), String::from("org")]),
+                    },
+                    main_content: Property {
+                        r#type: String::from("boolean"),
+                        description: String::from(
+                            "Only return the main content of the page like an article body, leaving out navigation and other boilerplate. Set to false to view the whole page.",
+                        ),
+                        r#enum: None,
+                    },
                 },
-                required: vec![String::from("url")],
+                required: vec![
+                    String::from("url"),
+                    String::from("format"),
+                    String::from("main_content"),
+                ],
                 additional_properties: false,
             },
             strict: true,
@@ -158,3 +277,74 @@ impl Default for WebsiteViewTool {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_html_to_org() {
+        let html = r#"
+<html>
+  <body>
+    <h1>Heading</h1>
+    <h2>Sub heading</h2>
+    <p>Some <strong>bold</strong> and <em>italic</em> text with <code>code</code>.</p>
+    <p>A <a href="https://example.com/page">link</a> to a page.</p>
+    <ul>
+      <li>First</li>
+      <li>Second</li>
+    </ul>
+  </body>
+</html>
+"#;
+        let org = html_to_org(html, false).unwrap();
+        assert!(org.contains("* Heading"));
+        assert!(org.contains("** Sub heading"));
+        assert!(org.contains("[[https://example.com/page][link]]"));
+        assert!(org.contains("*bold*"));
+        assert!(org.contains("/italic/"));
+        assert!(org.contains("~code~"));
+        assert!(org.contains("- First"));
+        assert!(org.contains("- Second"));
+        assert!(!org.contains("# Heading"));
+        assert!(!org.contains("](https://"));
+    }
+
+    #[test]
+    fn it_converts_code_blocks_to_org() {
+        let html = r#"<pre><code class="language-rust">let x = **y**;</code></pre>"#;
+        let org = html_to_org(html, false).unwrap();
+        assert!(org.contains("#+BEGIN_SRC rust"));
+        assert!(org.contains("let x = **y**;"));
+        assert!(org.contains("#+END_SRC"));
+    }
+
+    #[test]
+    fn it_converts_only_main_content() {
+        let html = r#"
+<html><body>
+  <nav><a href="/">Home</a> <a href="/about">About us</a></nav>
+  <article><h1>Title</h1><p>The article body text.</p></article>
+</body></html>
+"#;
+        let markdown = html_to_markdown(html, true).unwrap();
+        assert!(markdown.contains("The article body text."));
+        assert!(!markdown.contains("About us"));
+
+        let markdown = html_to_markdown(html, false).unwrap();
+        assert!(markdown.contains("The article body text."));
+        assert!(markdown.contains("About us"));
+    }
+
+    #[test]
+    fn it_defaults_to_markdown_format() {
+        let args: WebsiteViewArgs =
+            serde_json::from_str(r#"{"url":"https://example.com"}"#).unwrap();
+        assert!(args.format.unwrap_or_default() == WebsiteViewFormat::Markdown);
+
+        let args: WebsiteViewArgs =
+            serde_json::from_str(r#"{"url":"https://example.com","format":"org"}"#).unwrap();
+        assert!(args.format.unwrap_or_default() == WebsiteViewFormat::Org);
+    }
+}
@@ -1,18 +1,80 @@
 //! Public API types
 
+use std::time::Duration;
+
 use axum::response::{IntoResponse, Response};
-use http::StatusCode;
+use http::{StatusCode, header};
+use serde_json::json;
+
+use crate::google::GoogleApiError;
+use crate::openai::OpenAiApiError;
+use crate::search::IndexBusy;
+use crate::search::aql::AqlError;
 
 // Errors
 
 pub struct ApiError(anyhow::Error);
 
+/// Status, message, and retry delay of a failed request to an upstream
+/// API like OpenAI or Google
+fn upstream_error(err: &anyhow::Error) -> Option<(u16, &str, Option<Duration>)> {
+    if let Some(err) = err.chain().find_map(|e| e.downcast_ref::<OpenAiApiError>()) {
+        return Some((err.status.as_u16(), &err.message, err.retry_after));
+    }
+    if let Some(err) = err.chain().find_map(|e| e.downcast_ref::<GoogleApiError>()) {
+        return Some((err.status.as_u16(), &err.message, err.retry_after));
+    }
+    None
+}
+
 /// Convert `AppError` into an Axum compatible response.
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        // Invalid search queries are the caller's fault so tell them
+        // what's wrong with it instead of failing the request
+        if let Some(err) = self.0.downcast_ref::<AqlError>() {
+            return (
+                StatusCode::BAD_REQUEST,
+                axum::Json(json!({
+                    "error": err.to_string(),
+                    "kind": err.kind(),
+                })),
+            )
+                .into_response();
+        }
+
+        // Indexing can be retried once the run holding the index is
+        // done
+        if let Some(err) = self.0.chain().find_map(|e| e.downcast_ref::<IndexBusy>()) {
+            return (StatusCode::CONFLICT, err.to_string()).into_response();
+        }
+
         // Always log the error
         tracing::error!("{}", self.0);
 
+        // Upstream rate limits and bad requests are passed on so the
+        // caller knows to back off or fix the request. An upstream
+        // auth failure is a problem with the server's credentials, not
+        // the caller's, so it's a bad gateway.
+        if let Some((status, message, retry_after)) = upstream_error(&self.0) {
+            let status = match status {
+                429 => Some(StatusCode::TOO_MANY_REQUESTS),
+                401 | 403 => Some(StatusCode::BAD_GATEWAY),
+                400 => Some(StatusCode::BAD_REQUEST),
+                _ => None,
+            };
+            if let Some(status) = status {
+                let mut resp = (status, axum::Json(json!({"error": message}))).into_response();
+                if let Some(retry_after) =
+                    retry_after.filter(|_| status == StatusCode::TOO_MANY_REQUESTS)
+                {
+                    resp.headers_mut()
+                        .insert(header::RETRY_AFTER, retry_after.as_secs().into());
+                }
+                return resp;
+            }
+        }
+
         // Respond with an error status
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -43,6 +105,10 @@ pub mod email {
     pub use crate::api::routes::email::public::*;
 }
 
+pub mod inbox {
+    pub use crate::api::routes::inbox::public::*;
+}
+
 pub mod metrics {
     pub use crate::api::routes::metrics::public::*;
 }
@@ -3,5 +3,7 @@
 mod db;
 pub mod public;
 mod router;
+mod stream;
 
 pub use router::router;
+pub use stream::ChatStreams;
@@ -1,4 +1,5 @@
 //! Public types for the chat API
+use crate::ai::chat::models::ChatMessage;
 use crate::openai::Message;
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +15,27 @@ pub struct ChatSession {
 pub struct ChatRequest {
     pub session_id: String,
     pub message: String,
+    /// Name of a configured persona to chat with
+    pub persona: Option<String>,
+}
+
+/// Messages a client can send over the chat websocket
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatWsRequest {
+    /// Send the next message in a chat session
+    Chat(ChatRequest),
+    /// Stop generating the current response
+    Cancel,
+}
+
+/// Sent over the chat websocket in between the streamed completion
+/// chunks to signal the end of a response
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatWsEvent {
+    Done,
+    Cancelled,
 }
 
 #[derive(Deserialize)]
@@ -48,7 +70,34 @@ impl ChatResponse {
     }
 }
 
+#[derive(Deserialize)]
+pub struct ChatForkQuery {
+    /// Index of the last message copied into the fork
+    pub upto: usize,
+}
+
+#[derive(Serialize)]
+pub struct ChatForkResponse {
+    pub session_id: String,
+}
+
 #[derive(Serialize)]
 pub struct ChatTranscriptResponse {
-    pub transcript: Vec<Message>,
+    pub transcript: Vec<ChatMessage>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatPreviewRequest {
+    pub message: String,
+    /// Name of a configured persona to chat with
+    pub persona: Option<String>,
+}
+
+/// The payload that would be sent to the LLM
+#[derive(Serialize, Deserialize)]
+pub struct ChatPreviewResponse {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub tools: Vec<serde_json::Value>,
 }
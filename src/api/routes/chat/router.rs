@@ -6,30 +6,44 @@ use std::time::Duration;
 
 use axum::{
     Router,
-    extract::{Path, State},
-    http::StatusCode,
-    response::{IntoResponse, sse::Event, sse::KeepAlive, sse::Sse},
+    extract::{
+        Path, State,
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, sse::Event},
     routing::{get, post},
 };
 use axum_extra::extract::Query;
+use chrono_tz::Tz;
 use serde_json::json;
 use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio_rusqlite::Connection;
 use tokio_stream::StreamExt as _;
 use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
 use super::db::{chat_session_count, chat_session_list};
 use super::public;
-use crate::ai::chat::{ChatBuilder, find_chat_session_by_id};
+use crate::ai::chat::{
+    Chat, ChatBuilder, find_chat_messages_by_session_id, find_chat_session_by_id, fork_chat_session,
+};
+use crate::ai::tokens::estimate_for_model;
 use crate::ai::tools::{
-    CalendarTool, EmailUnreadTool, MemoryTool, MeetingSearchTool, NoteSearchTool,
-    TasksDueTodayTool, TasksScheduledTodayTool, WebSearchTool, WebsiteViewTool,
+    CalendarTool, EmailAssistTool, EmailUnreadTool, MemoryTool, MeetingSearchTool,
+    NoteSearchTool, TaskCompleteTool, TaskSnoozeTool, TasksDueTodayTool, TasksScheduledTodayTool,
+    WebSearchTool, WebsiteViewTool,
 };
 use crate::api::state::AppState;
-use crate::core::AppConfig;
+use crate::core::{AppConfig, Persona};
 use crate::notify::{
     PushNotificationPayload, broadcast_push_notification, find_all_notification_subscriptions,
 };
-use crate::openai::{BoxedToolCall, Message, Role};
+use crate::openai::{
+    BoxedToolCall, CompletionCancelled, Message, Provider, ReasoningEffort, Role, ToolCall,
+};
 
 type SharedState = Arc<RwLock<AppState>>;
 
@@ -39,7 +53,7 @@ async fn chat_session(
     Path(id): Path<String>,
 ) -> Result<impl IntoResponse, crate::api::public::ApiError> {
     let db = state.read().expect("Unable to read share state").db.clone();
-    let transcript = find_chat_session_by_id(&db, &id).await?.to_owned();
+    let transcript = find_chat_messages_by_session_id(&db, &id).await?;
 
     if transcript.is_empty() {
         return Ok((
@@ -52,6 +66,62 @@ async fn chat_session(
     Ok(axum::Json(public::ChatTranscriptResponse { transcript }).into_response())
 }
 
+/// Branch a chat session into a new session with a copy of the
+/// messages up to and including the message at `upto` so an
+/// alternative can be explored without changing the original. The
+/// cut can't separate tool calls from their responses.
+async fn chat_fork(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    Query(params): Query<public::ChatForkQuery>,
+) -> Result<impl IntoResponse, crate::api::public::ApiError> {
+    let db = state.read().expect("Unable to read share state").db.clone();
+    let transcript = find_chat_session_by_id(&db, &id).await?;
+
+    if transcript.is_empty() {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            format!("Chat session {} not found", id),
+        )
+            .into_response());
+    }
+    if params.upto >= transcript.len() {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Message {} is out of range for chat session {} with {} messages",
+                params.upto,
+                id,
+                transcript.len()
+            ),
+        )
+            .into_response());
+    }
+    // The model rejects a transcript with tool calls that are missing
+    // responses so the fork couldn't be continued
+    let splits_tool_calls = transcript[params.upto]
+        .tool_calls()
+        .is_some_and(|calls| !calls.is_empty())
+        || transcript
+            .get(params.upto + 1)
+            .is_some_and(|msg| *msg.role() == Role::Tool);
+    if splits_tool_calls {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Message {} would separate tool calls from their responses in chat session {}",
+                params.upto, id
+            ),
+        )
+            .into_response());
+    }
+
+    let session_id = Uuid::new_v4().to_string();
+    fork_chat_session(&db, &id, &session_id, params.upto).await?;
+
+    Ok(axum::Json(public::ChatForkResponse { session_id }).into_response())
+}
+
 /// Get a list of all chat sessions
 async fn chat_list(
     State(state): State<SharedState>,
@@ -77,105 +147,376 @@ async fn chat_list(
     }))
 }
 
-/// Initiate or add to a chat session and stream the response
+/// Look up the persona requested for a chat session. Responds with a
+/// bad request if there is no persona with that name.
+fn find_persona<'a>(
+    config: &'a AppConfig,
+    name: Option<&str>,
+) -> Result<Option<&'a Persona>, (StatusCode, String)> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    config.personas.get(name).map(Some).ok_or((
+        StatusCode::BAD_REQUEST,
+        format!("Unknown persona: {}", name),
+    ))
+}
+
+/// Reject messages with more tokens than allowed before they are sent
+/// to the model.
+fn check_message_length(config: &AppConfig, message: &str) -> Result<(), (StatusCode, String)> {
+    let tokens = estimate_for_model(message, &config.openai_model);
+    if tokens > config.chat_max_message_tokens {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Message is too long: {} tokens exceeds the maximum of {}",
+                tokens, config.chat_max_message_tokens
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The tools available to the assistant in a chat session, limited to
+/// the persona's tools if there is one. Tools that aren't enabled in
+/// the config are never available.
+fn chat_tools(
+    db: &Connection,
+    config: &AppConfig,
+    persona: Option<&Persona>,
+) -> Vec<BoxedToolCall> {
+    let AppConfig {
+        note_search_api_url,
+        storage_path,
+        timezone,
+        openai_api_hostname,
+        openai_api_key,
+        openai_model,
+        ..
+    } = config;
+    let tools: Vec<BoxedToolCall> = vec![
+        Box::new(NoteSearchTool::new(note_search_api_url)),
+        Box::new(MeetingSearchTool::new(note_search_api_url)),
+        Box::new(WebSearchTool::new(note_search_api_url)),
+        Box::new(EmailUnreadTool::new(note_search_api_url)),
+        Box::new(EmailAssistTool::new(
+            note_search_api_url,
+            openai_api_hostname,
+            openai_api_key,
+            openai_model,
+        )),
+        Box::new(CalendarTool::new(db.clone(), note_search_api_url)),
+        Box::new(WebsiteViewTool::new()),
+        Box::new(TasksDueTodayTool::new(note_search_api_url, *timezone)),
+        Box::new(TasksScheduledTodayTool::new(note_search_api_url, *timezone)),
+        Box::new(TaskSnoozeTool::new(note_search_api_url)),
+        Box::new(TaskCompleteTool::new(note_search_api_url)),
+        Box::new(MemoryTool::new(storage_path)),
+    ];
+
+    let persona_tools = persona.and_then(|p| p.tools.as_ref());
+    tools
+        .into_iter()
+        .filter(|t| config.tool_enabled(&t.function_name()))
+        .filter(|t| persona_tools.is_none_or(|names| names.contains(&t.function_name())))
+        .collect()
+}
+
+/// System message for new chat sessions using the persona's message
+/// if there is one
+struct SystemPrompt {
+    message: String,
+    /// Look up today's tasks to add to the message
+    include_tasks: bool,
+    note_search_api_url: String,
+    timezone: Tz,
+}
+
+impl SystemPrompt {
+    fn new(config: &AppConfig, persona: Option<&Persona>) -> Self {
+        Self {
+            message: persona
+                .map(|p| p.system_message.clone())
+                .unwrap_or_else(|| config.system_message.clone()),
+            include_tasks: persona
+                .and_then(|p| p.include_tasks)
+                .unwrap_or(config.chat_include_tasks),
+            note_search_api_url: config.note_search_api_url.clone(),
+            timezone: config.timezone,
+        }
+    }
+
+    /// The system message with the tasks due today added when enabled.
+    /// Chatting still works without the tasks if they can't be found.
+    async fn render(&self) -> String {
+        if !self.include_tasks {
+            return self.message.clone();
+        }
+        let tool = TasksDueTodayTool::new(&self.note_search_api_url, self.timezone);
+        match tool.call("{}").await {
+            Ok(tasks) => format!("{}\n\n# Tasks due today\n\n{}", self.message, tasks),
+            Err(e) => {
+                tracing::warn!("Failed to add tasks to the system message: {}", e);
+                self.message.clone()
+            }
+        }
+    }
+}
+
+/// Fetch the transcript for a chat session or start a new one with
+/// the system prompt if it doesn't exist yet
+async fn session_transcript(
+    db: &Connection,
+    session_id: &str,
+    system_prompt: &SystemPrompt,
+) -> Result<Vec<Message>, anyhow::Error> {
+    let mut transcript = find_chat_session_by_id(db, session_id).await?;
+    if transcript.is_empty() {
+        transcript.push(Message::new(Role::System, &system_prompt.render().await));
+    }
+    Ok(transcript)
+}
+
+/// Everything from the config needed to run a turn of a chat session,
+/// read up front so the shared state isn't locked while chatting
+struct ChatTurnConfig {
+    tools: Vec<BoxedToolCall>,
+    openai_api_hostname: String,
+    openai_api_key: String,
+    openai_model: String,
+    system_prompt: SystemPrompt,
+    max_concurrent_tools: usize,
+    tool_timeout: Duration,
+    coalesce_window: Option<Duration>,
+    retry_budget: usize,
+    max_retries: usize,
+    retry_base_delay: Duration,
+    fallbacks: Vec<Provider>,
+    reasoning_effort: Option<ReasoningEffort>,
+    max_context_tokens: Option<usize>,
+    store_reasoning: bool,
+}
+
+impl ChatTurnConfig {
+    /// Errors with the status to respond with if the message is too
+    /// long or the persona doesn't exist
+    fn new(
+        db: &Connection,
+        config: &AppConfig,
+        message: &str,
+        persona: Option<&str>,
+    ) -> Result<Self, (StatusCode, String)> {
+        check_message_length(config, message)?;
+        let persona = find_persona(config, persona)?;
+        Ok(Self {
+            tools: chat_tools(db, config, persona),
+            openai_api_hostname: config.openai_api_hostname.clone(),
+            openai_api_key: config.openai_api_key.clone(),
+            openai_model: config.openai_model.clone(),
+            system_prompt: SystemPrompt::new(config, persona),
+            max_concurrent_tools: config.chat_max_concurrent_tools,
+            tool_timeout: Duration::from_secs(config.chat_tool_timeout_secs),
+            coalesce_window: config.chat_stream_coalesce_ms.map(Duration::from_millis),
+            retry_budget: config.chat_retry_budget,
+            max_retries: config.openai_max_retries,
+            retry_base_delay: Duration::from_millis(config.openai_retry_base_delay_ms),
+            fallbacks: config.openai_fallbacks.clone(),
+            reasoning_effort: config.openai_reasoning_effort,
+            max_context_tokens: config.chat_max_context_tokens,
+            store_reasoning: config.chat_store_reasoning,
+        })
+    }
+
+    /// Build the chat for the next turn of `session_id`, streaming the
+    /// response to `tx` until it's done or `cancel` is cancelled
+    async fn chat(
+        self,
+        db: &Connection,
+        session_id: &str,
+        tx: mpsc::UnboundedSender<String>,
+        cancel: CancellationToken,
+    ) -> Result<Chat, anyhow::Error> {
+        let transcript = session_transcript(db, session_id, &self.system_prompt).await?;
+        let chat = ChatBuilder::new(
+            &self.openai_api_hostname,
+            &self.openai_api_key,
+            &self.openai_model,
+        )
+        .database(db, Some(session_id), None)
+        .transcript(transcript)
+        .tools(self.tools)
+        .max_concurrent_tools(self.max_concurrent_tools)
+        .tool_timeout(self.tool_timeout)
+        .coalesce_window(self.coalesce_window)
+        .retry_budget(self.retry_budget)
+        .max_context_tokens(self.max_context_tokens)
+        .store_reasoning(self.store_reasoning)
+        .completion_retries(self.max_retries, self.retry_base_delay)
+        .fallbacks(self.fallbacks)
+        .reasoning_effort(self.reasoning_effort)
+        .cancellation(cancel)
+        .streaming(tx)
+        .build();
+        Ok(chat)
+    }
+}
+
+/// Preview the payload that would be sent to the LLM for the next
+/// message in a chat session without calling the model
+async fn chat_preview(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    axum::Json(payload): axum::Json<public::ChatPreviewRequest>,
+) -> Result<impl IntoResponse, crate::api::public::ApiError> {
+    let db = state.read().expect("Unable to read share state").db.clone();
+    let (tools, openai_api_hostname, openai_api_key, openai_model, system_prompt) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        let config = &shared_state.config;
+        if let Err(resp) = check_message_length(config, &payload.message) {
+            return Ok(resp.into_response());
+        }
+        let persona = match find_persona(config, payload.persona.as_deref()) {
+            Ok(persona) => persona,
+            Err(resp) => return Ok(resp.into_response()),
+        };
+        (
+            chat_tools(&db, config, persona),
+            config.openai_api_hostname.clone(),
+            config.openai_api_key.clone(),
+            config.openai_model.clone(),
+            SystemPrompt::new(config, persona),
+        )
+    };
+
+    let transcript = session_transcript(&db, &id, &system_prompt).await?;
+    let chat = ChatBuilder::new(&openai_api_hostname, &openai_api_key, &openai_model)
+        .transcript(transcript)
+        .tools(tools)
+        .dry_run()
+        .build();
+    let preview = chat
+        .preview_msg(Message::new(Role::User, &payload.message))
+        .await?;
+
+    let preview: public::ChatPreviewResponse = serde_json::from_value(preview)?;
+    Ok(axum::Json(preview).into_response())
+}
+
+/// A completion chunk for showing an error to the user in place of
+/// the assistant's response
+fn error_chunk(e: &anyhow::Error) -> String {
+    let err_msg = format!("Something went wrong: {}", e);
+    json!({
+        "id": "error",
+        "choices": [
+            {
+                "finish_reason": "error",
+                "delta": { "content": err_msg }
+            }
+        ]
+    })
+    .to_string()
+}
+
+/// An SSE event for a chunk of a chat response with an ID that the
+/// client can send back as `Last-Event-ID` when reconnecting
+fn chunk_event((id, chunk): (usize, String)) -> Result<Event, Infallible> {
+    Ok(Event::default().id(id.to_string()).data(chunk))
+}
+
+/// Initiate or add to a chat session and stream the response. If the
+/// request has a `Last-Event-ID` header, the client is reconnecting so
+/// the latest response is resumed from after that event instead or
+/// 404 if there is no stream to resume.
 async fn chat_handler(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     axum::Json(payload): axum::Json<public::ChatRequest>,
 ) -> Result<impl IntoResponse, crate::api::public::ApiError> {
-    use crate::api::utils::DetectDisconnect;
+    use crate::api::utils::{DetectDisconnect, sse_response};
 
     let session_id = payload.session_id;
-    let (tx, rx) = mpsc::unbounded_channel::<String>();
+    let chat_streams = state
+        .read()
+        .expect("Unable to read share state")
+        .chat_streams
+        .clone();
+
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if let Some(last_event_id) = last_event_id {
+        return match chat_streams.resume(&session_id, last_event_id) {
+            Some(events) => Ok(sse_response(events.map(chunk_event))),
+            None => Ok((StatusCode::NOT_FOUND, "Chat stream not found").into_response()),
+        };
+    }
 
-    let sse_stream = UnboundedReceiverStream::new(rx)
-        .map(|chunk| Ok::<Event, Infallible>(Event::default().data(chunk)));
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let (client_tx, client_rx) = mpsc::unbounded_channel::<(usize, String)>();
+
+    let sse_stream = UnboundedReceiverStream::new(client_rx).map(chunk_event);
     let (disconnect_notifier, mut disconnect_receiver) = broadcast::channel::<()>(1);
+    let mut cancel_receiver = disconnect_notifier.subscribe();
     let wrapped_sse_stream = DetectDisconnect::new(sse_stream, disconnect_notifier);
 
+    // Stop reading the upstream response when the client goes away
+    // rather than generating a response nobody is waiting on
+    let cancel = CancellationToken::new();
+    let disconnect_cancel = cancel.clone();
+    tokio::spawn(async move {
+        if cancel_receiver.recv().await.is_ok() {
+            disconnect_cancel.cancel();
+        }
+    });
+
     let db = state.read().expect("Unable to read share state").db.clone();
 
-    let (
-        note_search_tool,
-        meeting_search_tool,
-        web_search_tool,
-        email_unread_tool,
-        calendar_tool,
-        website_view_tool,
-        tasks_due_today_tool,
-        tasks_scheduled_today_tool,
-        memory_tool,
-        openai_api_hostname,
-        openai_api_key,
-        openai_model,
-        vapid_key_path,
-    ) = {
+    let (turn_config, vapid_key_path, push_max_concurrency) = {
         let shared_state = state.read().expect("Unable to read share state");
-        let AppConfig {
-            note_search_api_url,
-            storage_path,
-            openai_api_hostname,
-            openai_api_key,
-            openai_model,
-            vapid_key_path,
-            ..
-        } = &shared_state.config;
+        let config = &shared_state.config;
+        let turn_config =
+            match ChatTurnConfig::new(&db, config, &payload.message, payload.persona.as_deref()) {
+                Ok(turn_config) => turn_config,
+                Err(resp) => return Ok(resp.into_response()),
+            };
         (
-            NoteSearchTool::new(note_search_api_url),
-            MeetingSearchTool::new(note_search_api_url),
-            WebSearchTool::new(note_search_api_url),
-            EmailUnreadTool::new(note_search_api_url),
-            CalendarTool::new(db.clone(), note_search_api_url),
-            WebsiteViewTool::new(),
-            TasksDueTodayTool::new(note_search_api_url),
-            TasksScheduledTodayTool::new(note_search_api_url),
-            MemoryTool::new(storage_path),
-            openai_api_hostname.clone(),
-            openai_api_key.clone(),
-            openai_model.clone(),
-            vapid_key_path.clone(),
+            turn_config,
+            config.vapid_key_path.clone(),
+            config.push_max_concurrency,
         )
     };
 
-    let tools: Vec<BoxedToolCall> = vec![
-        Box::new(note_search_tool),
-        Box::new(meeting_search_tool),
-        Box::new(web_search_tool),
-        Box::new(email_unread_tool),
-        Box::new(calendar_tool),
-        Box::new(website_view_tool),
-        Box::new(tasks_due_today_tool),
-        Box::new(tasks_scheduled_today_tool),
-        Box::new(memory_tool),
-    ];
     let user_msg = Message::new(Role::User, &payload.message);
 
-    let db = state.read().expect("Unable to read share state").db.clone();
-
-    // Create session in database if it doesn't already exist
-    // get_or_create_session(&db, &session_id, &[]).await?;
-
-    // Try to fetch the session from the db
-    let mut transcript = find_chat_session_by_id(&db, &session_id).await?;
-    // Initialize a new transcript
-    if transcript.is_empty() {
-        let shared_state = state.read().expect("Unable to read share state");
-        let default_system_msg = Message::new(Role::System, &shared_state.config.system_message);
-        transcript.push(default_system_msg.clone());
-    }
+    let mut chat = turn_config
+        .chat(&db, &session_id, tx.clone(), cancel)
+        .await?;
 
-    let mut chat = ChatBuilder::new(&openai_api_hostname, &openai_api_key, &openai_model)
-        .database(&db, Some(&session_id), None)
-        .transcript(transcript)
-        .tools(tools)
-        .streaming(tx.clone())
-        .build();
+    // Record each chunk before sending it to the client so that a
+    // client that reconnects can resume from the last event it got
+    let turn = chat_streams.start(&session_id);
+    let stream_session_id = session_id.clone();
+    let stream_client_tx = client_tx.clone();
+    tokio::spawn(async move {
+        let mut id = 0;
+        while let Some(chunk) = rx.recv().await {
+            id = chat_streams
+                .push(&stream_session_id, turn, chunk.clone())
+                .unwrap_or(id + 1);
+            let _ = stream_client_tx.send((id, chunk));
+        }
+        chat_streams.finish(&stream_session_id, turn);
+    });
 
     tokio::spawn(async move {
         let result = chat.next_msg(user_msg.clone()).await;
         match result {
             Ok(_messages) => {
                 // Send a notification if the client disconnected
-                if tx.is_closed() {
+                if client_tx.is_closed() {
                     let _ = disconnect_receiver
                         .recv()
                         .await
@@ -194,47 +535,159 @@ async fn chat_handler(
                                 subscriptions,
                                 vapid_key_path.to_string(),
                                 payload,
+                                push_max_concurrency,
                             )
                             .await;
                         })?
                         .await;
                 };
             }
+            Err(e) if e.is::<CompletionCancelled>() => {
+                tracing::info!("Chat cancelled after the client disconnected");
+            }
             Err(e) => {
                 tracing::error!("Chat handler error: {}. Root cause: {}", e, e.root_cause());
-
-                let err_msg = format!("Something went wrong: {}", e);
-                let completion_chunk = json!({
-                    "id": "error",
-                    "choices": [
-                        {
-                            "finish_reason": "error",
-                            "delta": { "content": err_msg }
-                        }
-                    ]
-                })
-                .to_string();
-                tx.send(completion_chunk)?;
+                tx.send(error_chunk(&e))?;
             }
         }
         Ok::<(), anyhow::Error>(())
     });
 
-    let resp = Sse::new(wrapped_sse_stream)
-        .keep_alive(
-            KeepAlive::default()
-                .text("keep-alive")
-                .interval(Duration::from_millis(100)),
+    Ok(sse_response(wrapped_sse_stream))
+}
+
+/// Chat over a websocket as an alternative to SSE. Accepts
+/// `ChatWsRequest` messages and streams back the same completion
+/// chunks as `chat_handler` followed by a `ChatWsEvent`.
+async fn chat_ws(State(state): State<SharedState>, ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| chat_ws_session(state, socket))
+}
+
+async fn chat_ws_session(state: SharedState, mut socket: WebSocket) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    // The response currently being generated, if any, and the token
+    // to stop it
+    let mut current: Option<(JoinHandle<()>, CancellationToken)> = None;
+
+    loop {
+        tokio::select! {
+            Some(chunk) = rx.recv() => {
+                if socket.send(WsMessage::Text(chunk.into())).await.is_err() {
+                    break;
+                }
+            }
+            msg = socket.recv() => {
+                let text = match msg {
+                    Some(Ok(WsMessage::Text(text))) => text,
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                match serde_json::from_str::<public::ChatWsRequest>(&text) {
+                    Ok(public::ChatWsRequest::Chat(payload)) => {
+                        if let Some((handle, cancel)) = current.take() {
+                            if !handle.is_finished() && !cancel.is_cancelled() {
+                                current = Some((handle, cancel));
+                                let e = anyhow::anyhow!("A response is already in progress");
+                                let _ = tx.send(error_chunk(&e));
+                                continue;
+                            }
+                            // Let a cancelled turn finish shutting down
+                            // before the next one reads the session
+                            let _ = handle.await;
+                        }
+                        let cancel = CancellationToken::new();
+                        match chat_ws_turn(&state, payload, tx.clone(), cancel.clone()) {
+                            Ok(handle) => current = Some((handle, cancel)),
+                            Err(e) => {
+                                let _ = tx.send(error_chunk(&e));
+                            }
+                        }
+                    }
+                    Ok(public::ChatWsRequest::Cancel) => {
+                        // The turn sends the cancelled event once it
+                        // stops. Nothing from the cancelled turn is
+                        // saved to the session.
+                        match current.as_ref() {
+                            Some((handle, cancel)) if !handle.is_finished() => cancel.cancel(),
+                            _ => {
+                                let event = serde_json::to_string(&public::ChatWsEvent::Cancelled)
+                                    .expect("Failed to serialize event");
+                                let _ = tx.send(event);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx.send(error_chunk(&e.into()));
+                    }
+                }
+            }
+        }
+    }
+
+    // Stop generating if the client went away
+    if let Some((_handle, cancel)) = current {
+        cancel.cancel();
+    }
+}
+
+/// Start the next turn of a chat session over the websocket,
+/// streaming the response to `tx` until it's done or `cancel` is
+/// cancelled
+fn chat_ws_turn(
+    state: &SharedState,
+    payload: public::ChatRequest,
+    tx: mpsc::UnboundedSender<String>,
+    cancel: CancellationToken,
+) -> Result<JoinHandle<()>, anyhow::Error> {
+    let db = state.read().expect("Unable to read share state").db.clone();
+    let turn_config = {
+        let shared_state = state.read().expect("Unable to read share state");
+        ChatTurnConfig::new(
+            &db,
+            &shared_state.config,
+            &payload.message,
+            payload.persona.as_deref(),
         )
-        .into_response();
+        .map_err(|(_, msg)| anyhow::anyhow!(msg))?
+    };
+
+    let handle = tokio::spawn(async move {
+        let result = async {
+            let mut chat = turn_config
+                .chat(&db, &payload.session_id, tx.clone(), cancel)
+                .await?;
+            chat.next_msg(Message::new(Role::User, &payload.message))
+                .await
+        }
+        .await;
+
+        let event = match result {
+            Ok(_messages) => public::ChatWsEvent::Done,
+            Err(e) if e.is::<CompletionCancelled>() => public::ChatWsEvent::Cancelled,
+            Err(e) => {
+                tracing::error!(
+                    "Chat websocket error: {}. Root cause: {}",
+                    e,
+                    e.root_cause()
+                );
+                let _ = tx.send(error_chunk(&e));
+                return;
+            }
+        };
+        let msg = serde_json::to_string(&event).expect("Failed to serialize event");
+        let _ = tx.send(msg);
+    });
 
-    Ok(resp)
+    Ok(handle)
 }
 
 /// Create the chat router
 pub fn router() -> Router<SharedState> {
     Router::new()
         .route("/", post(chat_handler))
+        .route("/ws", get(chat_ws))
         .route("/{id}", get(chat_session))
+        .route("/{id}/preview", post(chat_preview))
+        .route("/{id}/fork", post(chat_fork))
         .route("/sessions", get(chat_list))
 }
@@ -0,0 +1,224 @@
+//! Log of streamed chat events so that SSE clients can reconnect
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::Stream;
+use tokio::sync::watch;
+
+/// How long the events of a finished response are kept for clients
+/// that reconnect after it finished
+const FINISHED_STREAM_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Events streamed for one turn of a chat session. Event
+/// IDs start at 1 and are the position of the event in the log.
+struct StreamLog {
+    events: Vec<String>,
+    /// Number of events in the log and whether the response finished
+    progress: watch::Sender<(usize, bool)>,
+    finished_at: Option<Instant>,
+}
+
+/// Stream logs keyed by session and turn so that events from an
+/// earlier turn that's still winding down can't end up in the log of
+/// the next one
+#[derive(Default)]
+struct StreamLogs {
+    logs: HashMap<(String, u64), StreamLog>,
+    /// The turn of each session's latest response
+    latest: HashMap<String, u64>,
+    next_turn: u64,
+}
+
+/// Keeps the events of the latest response for each chat session so
+/// a client that reconnects with a `Last-Event-ID` can resume where it
+/// left off instead of starting over.
+#[derive(Clone, Default)]
+pub struct ChatStreams(Arc<Mutex<StreamLogs>>);
+
+impl ChatStreams {
+    /// Start a new log for the session's next turn and return the turn
+    /// to push its events to. Clients that reconnect resume the latest
+    /// turn.
+    pub fn start(&self, session_id: &str) -> u64 {
+        self.evict_finished(FINISHED_STREAM_TTL);
+        let (progress, _) = watch::channel((0, false));
+        let mut streams = self.0.lock().unwrap();
+        let turn = streams.next_turn;
+        streams.next_turn += 1;
+        streams.logs.insert(
+            (session_id.to_string(), turn),
+            StreamLog {
+                events: Vec::new(),
+                progress,
+                finished_at: None,
+            },
+        );
+        streams.latest.insert(session_id.to_string(), turn);
+        turn
+    }
+
+    /// Append an event to the turn's log and return its ID or `None`
+    /// if the log was already dropped
+    pub fn push(&self, session_id: &str, turn: u64, data: String) -> Option<usize> {
+        let mut streams = self.0.lock().unwrap();
+        let log = streams.logs.get_mut(&(session_id.to_string(), turn))?;
+        log.events.push(data);
+        let id = log.events.len();
+        log.progress.send_replace((id, false));
+        Some(id)
+    }
+
+    /// Mark the turn's response as finished
+    pub fn finish(&self, session_id: &str, turn: u64) {
+        let mut streams = self.0.lock().unwrap();
+        if let Some(log) = streams.logs.get_mut(&(session_id.to_string(), turn)) {
+            let count = log.events.len();
+            log.progress.send_replace((count, true));
+            log.finished_at = Some(Instant::now());
+        }
+    }
+
+    /// Drop the logs of responses that finished more than `ttl` ago
+    fn evict_finished(&self, ttl: Duration) {
+        let mut streams = self.0.lock().unwrap();
+        let StreamLogs { logs, latest, .. } = &mut *streams;
+        logs.retain(|_, log| log.finished_at.is_none_or(|t| t.elapsed() < ttl));
+        latest.retain(|session_id, turn| logs.contains_key(&(session_id.clone(), *turn)));
+    }
+
+    /// Stream the events of the session's latest turn after
+    /// `last_event_id` followed by any new events until the response
+    /// finishes. Returns `None` if nothing was streamed for the session
+    /// or it finished too long ago.
+    pub fn resume(
+        &self,
+        session_id: &str,
+        last_event_id: usize,
+    ) -> Option<impl Stream<Item = (usize, String)> + use<>> {
+        self.evict_finished(FINISHED_STREAM_TTL);
+        let key = {
+            let streams = self.0.lock().unwrap();
+            (session_id.to_string(), *streams.latest.get(session_id)?)
+        };
+        let mut progress = self.0.lock().unwrap().logs.get(&key)?.progress.subscribe();
+        let streams = self.clone();
+
+        Some(async_stream::stream! {
+            let mut next_id = last_event_id + 1;
+            loop {
+                let (count, finished) = *progress.borrow_and_update();
+                let events = streams.events(&key, next_id, count);
+                for event in events {
+                    yield (next_id, event);
+                    next_id += 1;
+                }
+                if finished || progress.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Events with IDs from `from_id` up to and including `to_id`
+    fn events(&self, key: &(String, u64), from_id: usize, to_id: usize) -> Vec<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .logs
+            .get(key)
+            .map(|log| {
+                log.events
+                    .iter()
+                    .take(to_id)
+                    .skip(from_id.saturating_sub(1))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_resumes_after_last_event_id() {
+        let streams = ChatStreams::default();
+        let turn = streams.start("session");
+        for chunk in ["a", "b", "c"] {
+            streams.push("session", turn, chunk.to_string());
+        }
+        streams.finish("session", turn);
+
+        let events: Vec<(usize, String)> = streams.resume("session", 1).unwrap().collect().await;
+
+        assert_eq!(events, vec![(2, String::from("b")), (3, String::from("c"))]);
+    }
+
+    #[tokio::test]
+    async fn it_follows_live_events_until_finished() {
+        let streams = ChatStreams::default();
+        let turn = streams.start("session");
+        streams.push("session", turn, String::from("a"));
+
+        let resumed = streams.resume("session", 0).unwrap();
+        let writer = streams.clone();
+        tokio::spawn(async move {
+            writer.push("session", turn, String::from("b"));
+            writer.finish("session", turn);
+        });
+
+        let events: Vec<(usize, String)> = resumed.collect().await;
+        assert_eq!(events, vec![(1, String::from("a")), (2, String::from("b"))]);
+    }
+
+    #[test]
+    fn it_returns_none_for_unknown_session() {
+        let streams = ChatStreams::default();
+        assert!(streams.resume("missing", 0).is_none());
+    }
+
+    #[test]
+    fn it_evicts_finished_streams() {
+        let streams = ChatStreams::default();
+        let turn = streams.start("finished");
+        streams.push("finished", turn, String::from("a"));
+        streams.finish("finished", turn);
+        streams.start("running");
+
+        streams.evict_finished(Duration::ZERO);
+
+        assert!(streams.resume("finished", 0).is_none());
+        assert!(streams.resume("running", 0).is_some());
+        // Events for an evicted log are dropped
+        assert_eq!(streams.push("finished", turn, String::from("b")), None);
+    }
+
+    #[tokio::test]
+    async fn it_ignores_events_from_an_earlier_turn() {
+        let streams = ChatStreams::default();
+        let first = streams.start("session");
+        streams.push("session", first, String::from("a"));
+        let second = streams.start("session");
+        streams.push("session", second, String::from("b"));
+
+        // The first turn finishing late doesn't finish the second
+        streams.push("session", first, String::from("late"));
+        streams.finish("session", first);
+
+        let resumed = streams.resume("session", 0).unwrap();
+        let writer = streams.clone();
+        tokio::spawn(async move {
+            writer.push("session", second, String::from("c"));
+            writer.finish("session", second);
+        });
+
+        let events: Vec<(usize, String)> = resumed.collect().await;
+        assert_eq!(events, vec![(1, String::from("b")), (2, String::from("c"))]);
+    }
+}
@@ -0,0 +1,6 @@
+//! Config API routes
+
+pub mod public;
+mod router;
+
+pub use router::router;
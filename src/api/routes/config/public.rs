@@ -0,0 +1,70 @@
+//! Public types for the config API
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Effective server configuration with secrets redacted
+#[derive(Serialize, Deserialize)]
+pub struct ConfigResponse {
+    pub notes_path: String,
+    pub index_path: String,
+    pub vec_db_path: String,
+    pub storage_path: String,
+    pub deploy_key_path: String,
+    pub vapid_key_path: String,
+    pub note_search_api_url: String,
+    pub gmail_api_client_id: String,
+    pub gmail_api_client_secret: String,
+    pub google_search_api_key: String,
+    pub google_search_cx_id: String,
+    pub openai_model: String,
+    pub openai_api_hostname: String,
+    pub openai_api_key: String,
+    pub system_message: String,
+    pub openai_max_retries: usize,
+    pub openai_retry_base_delay_ms: u64,
+    pub openai_fallbacks: Vec<ProviderConfig>,
+    pub openai_reasoning_effort: Option<String>,
+    pub search_default_limit: usize,
+    pub search_max_limit: usize,
+    pub title_boost: f32,
+    pub tags_boost: f32,
+    pub body_boost: f32,
+    pub snippet_strategy: String,
+    pub search_mode: String,
+    pub rerank_url: Option<String>,
+    pub http_user_agent: String,
+    pub http_proxy: Option<String>,
+    pub chat_max_message_tokens: usize,
+    pub chat_max_concurrent_tools: usize,
+    pub chat_tool_timeout_secs: u64,
+    pub chat_stream_coalesce_ms: Option<u64>,
+    pub chat_retry_budget: usize,
+    pub chat_include_tasks: bool,
+    pub chat_store_reasoning: bool,
+    pub chat_max_context_tokens: Option<usize>,
+    pub chat_max_sessions: Option<usize>,
+    pub push_max_concurrency: usize,
+    pub attachments_max_bytes: usize,
+    pub attachments_allowed_types: Vec<String>,
+    pub note_id_scheme: String,
+    /// System message and tools of each persona by name
+    pub personas: HashMap<String, PersonaConfig>,
+    pub enabled_tools: Option<Vec<String>>,
+    pub llm_log_path: Option<String>,
+    pub timezone: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub api_hostname: String,
+    pub api_key: String,
+    pub model: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PersonaConfig {
+    pub system_message: String,
+    pub tools: Option<Vec<String>>,
+    pub include_tasks: Option<bool>,
+}
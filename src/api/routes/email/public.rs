@@ -5,6 +5,15 @@ use serde::{Deserialize, Serialize};
 pub struct EmailUnreadQuery {
     pub email: String,
     pub limit: Option<i64>,
+    /// Mark each returned thread as read so it isn't returned again.
+    /// Defaults to `false` which leaves the threads unread.
+    #[serde(default)]
+    pub mark_read: bool,
+}
+
+#[derive(Deserialize)]
+pub struct EmailThreadQuery {
+    pub email: String,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
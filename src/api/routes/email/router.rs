@@ -2,29 +2,33 @@
 
 use std::sync::{Arc, RwLock};
 
-use axum::{Router, extract::State, response::Json};
+use axum::{
+    Router,
+    extract::{Path, State},
+    response::Json,
+};
 use axum_extra::extract::Query;
 use tokio::task::JoinSet;
 
 use super::public;
 use crate::api::state::AppState;
 use crate::core::AppConfig;
-use crate::google::gmail::{Thread, extract_body, fetch_thread, list_unread_messages};
+use crate::google::gmail::{
+    Thread, extract_body, fetch_thread, list_unread_messages, mark_thread_read,
+};
 use crate::google::oauth::refresh_access_token;
 
 type SharedState = Arc<RwLock<AppState>>;
 
-async fn email_unread_handler(
-    State(state): State<SharedState>,
-    Query(params): Query<public::EmailUnreadQuery>,
-) -> Result<Json<Vec<public::EmailThread>>, crate::api::public::ApiError> {
+/// Get a Gmail access token for `email` using its stored refresh token
+async fn gmail_access_token(state: &SharedState, email: String) -> Result<String, anyhow::Error> {
     let refresh_token: String = {
         let db = state.read().unwrap().db.clone();
 
         db.call(move |conn| {
             let result = conn
                 .prepare("SELECT refresh_token FROM auth WHERE id = ?1")
-                .and_then(|mut stmt| stmt.query_row([&params.email], |row| row.get(0)))?;
+                .and_then(|mut stmt| stmt.query_row([&email], |row| row.get(0)))?;
             Ok(result)
         })
         .await?
@@ -40,7 +44,64 @@ async fn email_unread_handler(
         (gmail_api_client_id.clone(), gmail_api_client_secret.clone())
     };
     let oauth = refresh_access_token(&client_id, &client_secret, &refresh_token).await?;
-    let access_token = oauth.access_token;
+    Ok(oauth.access_token)
+}
+
+/// Transform a Gmail thread and its messages into a simpler format
+fn email_thread(t: Thread) -> public::EmailThread {
+    let mut messages: Vec<public::EmailMessage> = Vec::new();
+    for m in t.messages {
+        let body = extract_body(&m).trim().to_string();
+        if body == "Failed to decode" {
+            tracing::error!("Decode error: {:?}", m.payload);
+        }
+        let payload = m.payload.unwrap();
+        let headers = payload.headers.unwrap();
+
+        let from = headers
+            .iter()
+            .find(|h| h.name == "From")
+            .map(|h| h.value.clone())
+            .unwrap();
+        let to = headers
+            .iter()
+            .find(|h| h.name == "To")
+            .map(|h| h.value.clone())
+            .unwrap();
+        let subject = headers
+            .iter()
+            .find(|h| h.name == "Subject")
+            .map(|h| h.value.clone())
+            .unwrap();
+
+        messages.push(public::EmailMessage {
+            id: m.id,
+            thread_id: m.thread_id,
+            received: m.internal_date,
+            from,
+            to,
+            subject,
+            body,
+        })
+    }
+
+    let latest_msg = messages[0].clone();
+
+    public::EmailThread {
+        id: t.id,
+        received: latest_msg.received,
+        subject: latest_msg.subject,
+        from: latest_msg.from,
+        to: latest_msg.to,
+        messages,
+    }
+}
+
+async fn email_unread_handler(
+    State(state): State<SharedState>,
+    Query(params): Query<public::EmailUnreadQuery>,
+) -> Result<Json<Vec<public::EmailThread>>, crate::api::public::ApiError> {
+    let access_token = gmail_access_token(&state, params.email).await?;
     let limit = params.limit.unwrap_or(7);
 
     // Query Gmail for unread messages
@@ -61,62 +122,43 @@ async fn email_unread_handler(
         .collect();
 
     // Transform the threads and messages into a simpler format
-    let mut threads: Vec<public::EmailThread> = Vec::new();
-    for t in results {
-        let mut messages: Vec<public::EmailMessage> = Vec::new();
-        for m in t.messages {
-            let body = extract_body(&m).trim().to_string();
-            if body == "Failed to decode" {
-                tracing::error!("Decode error: {:?}", m.payload);
-            }
-            let payload = m.payload.unwrap();
-            let headers = payload.headers.unwrap();
-
-            let from = headers
-                .iter()
-                .find(|h| h.name == "From")
-                .map(|h| h.value.clone())
-                .unwrap();
-            let to = headers
-                .iter()
-                .find(|h| h.name == "To")
-                .map(|h| h.value.clone())
-                .unwrap();
-            let subject = headers
-                .iter()
-                .find(|h| h.name == "Subject")
-                .map(|h| h.value.clone())
-                .unwrap();
-
-            messages.push(public::EmailMessage {
-                id: m.id,
-                thread_id: m.thread_id,
-                received: m.internal_date,
-                from,
-                to,
-                subject,
-                body,
-            })
-        }
+    let mut threads: Vec<public::EmailThread> = results.into_iter().map(email_thread).collect();
 
-        let latest_msg = messages[0].clone();
+    threads.sort_by_key(|i| std::cmp::Reverse(i.received.clone()));
 
-        threads.push(public::EmailThread {
-            id: t.id,
-            received: latest_msg.received,
-            subject: latest_msg.subject,
-            from: latest_msg.from,
-            to: latest_msg.to,
-            messages,
-        });
+    if params.mark_read {
+        // A thread with several unread messages is fetched once for
+        // each of them so only mark it once
+        let mut thread_ids: Vec<String> = threads.iter().map(|t| t.id.clone()).collect();
+        thread_ids.sort();
+        thread_ids.dedup();
+        let mut tasks = JoinSet::new();
+        for thread_id in thread_ids {
+            let access_token = access_token.clone();
+            tasks.spawn(async move { mark_thread_read(&access_token, &thread_id).await });
+        }
+        for result in tasks.join_all().await {
+            result?;
+        }
     }
 
-    threads.sort_by_key(|i| std::cmp::Reverse(i.received.clone()));
-
     Ok(Json(threads))
 }
 
+/// Get a single email thread by ID
+async fn email_thread_handler(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    Query(params): Query<public::EmailThreadQuery>,
+) -> Result<Json<public::EmailThread>, crate::api::public::ApiError> {
+    let access_token = gmail_access_token(&state, params.email).await?;
+    let thread = fetch_thread(access_token, id).await?;
+    Ok(Json(email_thread(thread)))
+}
+
 /// Create the email router
 pub fn router() -> Router<SharedState> {
-    Router::new().route("/unread", axum::routing::get(email_unread_handler))
+    Router::new()
+        .route("/unread", axum::routing::get(email_unread_handler))
+        .route("/thread/{id}", axum::routing::get(email_thread_handler))
 }
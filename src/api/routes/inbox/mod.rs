@@ -0,0 +1,6 @@
+//! Inbox API routes
+
+pub mod public;
+mod router;
+
+pub use router::router;
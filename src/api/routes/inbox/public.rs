@@ -0,0 +1,30 @@
+//! Public types for the inbox API
+use serde::{Deserialize, Serialize};
+
+use crate::api::routes::calendar::public::CalendarResponse;
+use crate::api::routes::email::public::EmailThread;
+use crate::api::routes::notes::public::SearchResult;
+
+#[derive(Deserialize)]
+pub struct InboxQuery {
+    pub email: String,
+}
+
+/// A section of the inbox that could not be fetched.
+#[derive(Serialize, Deserialize)]
+pub struct InboxError {
+    /// One of `emails`, `tasks`, or `events`
+    pub section: String,
+    pub message: String,
+}
+
+/// Unread email, tasks due today, and today's calendar events in a
+/// single response. Sections that fail to load are left empty and
+/// the failure is listed in `errors`.
+#[derive(Serialize, Deserialize)]
+pub struct InboxResponse {
+    pub emails: Vec<EmailThread>,
+    pub tasks: Vec<SearchResult>,
+    pub events: Vec<CalendarResponse>,
+    pub errors: Vec<InboxError>,
+}
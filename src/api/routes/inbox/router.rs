@@ -0,0 +1,95 @@
+//! Router for the inbox API
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use axum::{Router, extract::State, response::Json};
+use axum_extra::extract::Query;
+use serde::de::DeserializeOwned;
+
+use super::public;
+use crate::api::routes::calendar::public::CalendarResponse;
+use crate::api::routes::email::public::EmailThread;
+use crate::api::routes::notes::public::SearchResponse;
+use crate::api::state::AppState;
+use crate::core::{http, time};
+
+type SharedState = Arc<RwLock<AppState>>;
+
+/// Fetch JSON from another endpoint of the API.
+async fn get_json<T: DeserializeOwned>(
+    api_base_url: &str,
+    path: &str,
+    params: &[(&str, &str)],
+) -> Result<T> {
+    let mut url = reqwest::Url::parse(&format!("{}{}", api_base_url, path))?;
+    url.query_pairs_mut().extend_pairs(params);
+    let resp = http::shared_client()?
+        .get(url.as_str())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(resp)
+}
+
+/// Collects the result of fetching a section, recording the error
+/// and falling back to an empty section if it failed.
+fn section<T>(name: &str, result: Result<Vec<T>>, errors: &mut Vec<public::InboxError>) -> Vec<T> {
+    result.unwrap_or_else(|e| {
+        tracing::warn!("Failed to fetch inbox {}: {}", name, e);
+        errors.push(public::InboxError {
+            section: name.to_string(),
+            message: e.to_string(),
+        });
+        Vec::new()
+    })
+}
+
+async fn inbox_handler(
+    State(state): State<SharedState>,
+    Query(params): Query<public::InboxQuery>,
+) -> Json<public::InboxResponse> {
+    let (api_base_url, timezone) = {
+        let shared_state = state.read().unwrap();
+        (
+            shared_state.config.note_search_api_url.clone(),
+            shared_state.config.timezone,
+        )
+    };
+    let email = params.email.as_str();
+    let today = time::today(timezone).format("%Y-%m-%d").to_string();
+    let tasks_query = format!("deadline:<={} -status:done -status:canceled", today);
+
+    let email_params = [("email", email)];
+    let tasks_params = [
+        ("query", tasks_query.as_str()),
+        ("include_similarity", "false"),
+    ];
+    let events_params = [("email", email), ("days_ahead", "1")];
+
+    let (emails, tasks, events) = tokio::join!(
+        get_json::<Vec<EmailThread>>(&api_base_url, "/api/email/unread", &email_params),
+        get_json::<SearchResponse>(&api_base_url, "/api/notes/search", &tasks_params),
+        get_json::<Vec<CalendarResponse>>(&api_base_url, "/api/calendar", &events_params),
+    );
+    let tasks = tasks.map(|resp| resp.results);
+
+    let mut errors = Vec::new();
+    let emails = section("emails", emails, &mut errors);
+    let tasks = section("tasks", tasks, &mut errors);
+    let events = section("events", events, &mut errors);
+
+    Json(public::InboxResponse {
+        emails,
+        tasks,
+        events,
+        errors,
+    })
+}
+
+/// Create the inbox router
+pub fn router() -> Router<SharedState> {
+    Router::new().route("/", axum::routing::get(inbox_handler))
+}
@@ -0,0 +1,101 @@
+//! Database queries for metrics
+
+use tokio_rusqlite::Connection;
+
+use super::public;
+
+pub use crate::core::metrics::{insert_metric_event, insert_metric_event_idempotent};
+
+/// Summarize tool call frequency and latency over the last
+/// `limit_days` days, most frequently called tools first.
+pub async fn get_tool_metrics(
+    db: &Connection,
+    limit_days: i64,
+) -> Result<Vec<public::ToolMetric>, anyhow::Error> {
+    let results = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                r#"
+            SELECT label,
+            COUNT(*) AS count,
+            AVG(value) AS avg_ms,
+            MAX(value) AS max_ms
+            FROM metric_event
+            WHERE name = ?
+            AND label IS NOT NULL
+            AND timestamp >= datetime('now', '-' || ? || ' days')
+            GROUP BY label
+            ORDER BY count DESC, label
+            "#,
+            )?;
+
+            let tools = stmt
+                .query_map(
+                    tokio_rusqlite::params![public::MetricName::ToolLatency, limit_days],
+                    |row| {
+                        Ok(public::ToolMetric {
+                            name: row.get(0)?,
+                            count: row.get(1)?,
+                            avg_ms: row.get(2)?,
+                            max_ms: row.get(3)?,
+                        })
+                    },
+                )?
+                .filter_map(Result::ok)
+                .collect::<Vec<public::ToolMetric>>();
+
+            Ok(tools)
+        })
+        .await?;
+
+    Ok(results)
+}
+
+/// Count and sum of all the events of a metric for each label
+pub async fn get_metric_totals(
+    db: &Connection,
+    name: public::MetricName,
+) -> Result<Vec<public::MetricTotal>, anyhow::Error> {
+    let results = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                r#"
+            SELECT label, COUNT(*) AS count, SUM(value) AS total
+            FROM metric_event
+            WHERE name = ?
+            GROUP BY label
+            ORDER BY label
+            "#,
+            )?;
+
+            let totals = stmt
+                .query_map([name], |row| {
+                    Ok(public::MetricTotal {
+                        label: row.get(0)?,
+                        count: row.get(1)?,
+                        sum: row.get(2)?,
+                    })
+                })?
+                .filter_map(Result::ok)
+                .collect::<Vec<public::MetricTotal>>();
+
+            Ok(totals)
+        })
+        .await?;
+
+    Ok(results)
+}
+
+/// Number of chat sessions and the number of messages across them
+pub async fn get_chat_totals(db: &Connection) -> Result<(i64, i64), anyhow::Error> {
+    let totals = db
+        .call(|conn| {
+            let sessions = conn.query_row("SELECT COUNT(*) FROM session", [], |row| row.get(0))?;
+            let messages =
+                conn.query_row("SELECT COUNT(*) FROM chat_message", [], |row| row.get(0))?;
+            Ok((sessions, messages))
+        })
+        .await?;
+
+    Ok(totals)
+}
@@ -1,5 +1,7 @@
 //! Metrics API routes
 
+pub mod db;
+pub mod prometheus;
 pub mod public;
 mod router;
 
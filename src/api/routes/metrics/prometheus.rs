@@ -0,0 +1,167 @@
+//! Metrics in the Prometheus text exposition format for scraping by
+//! ops monitoring
+
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use tokio_rusqlite::Connection;
+
+use super::{db, public::MetricName};
+use crate::api::state::AppState;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Number of HTTP requests handled by the server by status class
+/// since it started
+#[derive(Clone, Default)]
+pub struct RequestCounts(Arc<[AtomicU64; 5]>);
+
+impl RequestCounts {
+    pub fn record(&self, status: StatusCode) {
+        let class = (status.as_u16() / 100).clamp(1, 5) as usize;
+        self.0[class - 1].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count of requests for each status class e.g. "2xx"
+    fn counts(&self) -> Vec<(String, u64)> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, count)| (format!("{}xx", i + 1), count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// Middleware that counts every request handled by the server
+pub async fn count_requests(
+    State(state): State<Arc<RwLock<AppState>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let request_counts = state.read().unwrap().request_counts.clone();
+    let response = next.run(request).await;
+    request_counts.record(response.status());
+    response
+}
+
+/// Escape a label value so it can be put in double quotes
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Builds the text exposition format one metric family at a time
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.0, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.0, "# TYPE {} {}", name, kind).unwrap();
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        if labels.is_empty() {
+            writeln!(self.0, "{} {}", name, value).unwrap();
+            return;
+        }
+        let labels = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(self.0, "{}{{{}}} {}", name, labels, value).unwrap();
+    }
+}
+
+/// Render request counts, chat counts, token totals, and tool calls
+/// in the text exposition format
+pub async fn render(db: &Connection, request_counts: &RequestCounts) -> anyhow::Result<String> {
+    let mut out = Exposition::default();
+
+    out.family(
+        "hq_http_requests_total",
+        "counter",
+        "HTTP requests handled since the server started by status class.",
+    );
+    for (class, count) in request_counts.counts() {
+        out.sample("hq_http_requests_total", &[("status", &class)], count);
+    }
+
+    let (sessions, messages) = db::get_chat_totals(db).await?;
+    out.family("hq_chat_sessions", "gauge", "Chat sessions stored.");
+    out.sample("hq_chat_sessions", &[], sessions);
+    out.family("hq_chat_messages", "gauge", "Chat messages stored.");
+    out.sample("hq_chat_messages", &[], messages);
+
+    out.family(
+        "hq_llm_tokens_total",
+        "counter",
+        "Tokens used by LLM completions by model.",
+    );
+    for total in db::get_metric_totals(db, MetricName::TokenCount).await? {
+        let model = total.label.unwrap_or_default();
+        out.sample("hq_llm_tokens_total", &[("model", &model)], total.sum);
+    }
+
+    let tool_totals = db::get_metric_totals(db, MetricName::ToolLatency).await?;
+    out.family(
+        "hq_tool_calls_total",
+        "counter",
+        "Tool calls made by chats by tool.",
+    );
+    for total in &tool_totals {
+        let tool = total.label.clone().unwrap_or_default();
+        out.sample("hq_tool_calls_total", &[("tool", &tool)], total.count);
+    }
+    out.family(
+        "hq_tool_call_duration_milliseconds_total",
+        "counter",
+        "Time spent in tool calls by tool.",
+    );
+    for total in &tool_totals {
+        let tool = total.label.clone().unwrap_or_default();
+        out.sample(
+            "hq_tool_call_duration_milliseconds_total",
+            &[("tool", &tool)],
+            total.sum,
+        );
+    }
+
+    Ok(out.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_requests_by_status_class() {
+        let counts = RequestCounts::default();
+        counts.record(StatusCode::OK);
+        counts.record(StatusCode::CREATED);
+        counts.record(StatusCode::NOT_FOUND);
+
+        let counts = counts.counts();
+        assert_eq!(counts[1], (String::from("2xx"), 2));
+        assert_eq!(counts[3], (String::from("4xx"), 1));
+        assert_eq!(counts[4], (String::from("5xx"), 0));
+    }
+
+    #[test]
+    fn it_escapes_label_values() {
+        let mut out = Exposition::default();
+        out.sample("hq_test", &[("model", "a \"quoted\"\\name")], 1);
+        assert_eq!(out.0, "hq_test{model=\"a \\\"quoted\\\"\\\\name\"} 1\n");
+    }
+}
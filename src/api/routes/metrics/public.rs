@@ -1,17 +1,16 @@
 //! Public types for the metrics API
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum MetricName {
-    #[serde(rename = "token-count")]
-    TokenCount,
-}
+pub use crate::core::metrics::MetricName;
 
 /// Request to record a metric event
 #[derive(Deserialize)]
 pub struct MetricRequest {
     pub name: MetricName,
     pub value: i64,
+    /// Key identifying this event so that retried requests with the
+    /// same key aren't recorded more than once
+    pub idempotency_key: Option<String>,
 }
 
 /// Query parameters for getting metric events
@@ -33,3 +32,27 @@ pub struct MetricEvent {
 pub struct MetricsResponse {
     pub events: Vec<MetricEvent>,
 }
+
+/// Usage summary for a single tool
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ToolMetric {
+    pub name: String,
+    pub count: i64,
+    pub avg_ms: f64,
+    pub max_ms: i64,
+}
+
+/// Number of events of a metric and the sum of their values for a
+/// label
+#[derive(Debug)]
+pub struct MetricTotal {
+    pub label: Option<String>,
+    pub count: i64,
+    pub sum: i64,
+}
+
+/// Response containing usage summaries for each tool
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ToolMetricsResponse {
+    pub tools: Vec<ToolMetric>,
+}
@@ -2,38 +2,19 @@
 
 use std::sync::{Arc, RwLock};
 
-use axum::{Router, extract::State, http::StatusCode, response::Json};
-use axum_extra::extract::Query;
-use rusqlite::{
-    ToSql,
-    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+use axum::{
+    Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Json},
 };
+use axum_extra::extract::Query;
 
-use super::public;
+use super::{db, prometheus, public};
 use crate::api::state::AppState;
 
 type SharedState = Arc<RwLock<AppState>>;
 
-impl ToSql for public::MetricName {
-    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
-        // Use serde serialization to convert the enum back into a
-        // string to save to the database while still enforcing metric
-        // names can only be a `MetricName` variant.
-        let name = serde_json::to_string(self).expect("Failed to parse enum into string");
-        let value: String = serde_json::from_str(&name).expect("Failed to parse string from enum");
-        Ok(value.into())
-    }
-}
-
-impl FromSql for public::MetricName {
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        // Serde deserialization can only parse an enum from string if
-        // it's double quoted.
-        serde_json::from_str(&format!("\"{}\"", value.as_str()?))
-            .map_err(|e| FromSqlError::Other(Box::new(e)))
-    }
-}
-
 /// Record a metric event
 async fn record_metric(
     State(state): State<SharedState>,
@@ -41,17 +22,14 @@ async fn record_metric(
 ) -> Result<StatusCode, crate::api::public::ApiError> {
     let db = state.read().unwrap().db.clone();
 
-    let name = payload.name;
-    let value = payload.value;
-
-    // Insert the metric event into the database
-    db.call(move |conn| {
-        conn.execute(
-            "INSERT INTO metric_event (name, value) VALUES (?, ?)",
-            tokio_rusqlite::params![&name, &value],
-        )?;
-        Ok(())
-    })
+    // Retried requests with the same idempotency key are ignored
+    db::insert_metric_event_idempotent(
+        &db,
+        payload.name,
+        payload.value,
+        None,
+        payload.idempotency_key,
+    )
     .await?;
 
     Ok(StatusCode::OK)
@@ -100,7 +78,37 @@ async fn get_metrics(
     Ok(Json(public::MetricsResponse { events: results }))
 }
 
+/// Summarize tool call frequency and latency
+async fn get_tool_metrics(
+    State(state): State<SharedState>,
+    Query(params): Query<public::MetricsQuery>,
+) -> Result<Json<public::ToolMetricsResponse>, crate::api::public::ApiError> {
+    let db = state.read().unwrap().db.clone();
+
+    // Default to last 30 days if not specified
+    let limit_days = params.limit_days.unwrap_or(30);
+    let tools = db::get_tool_metrics(&db, limit_days).await?;
+
+    Ok(Json(public::ToolMetricsResponse { tools }))
+}
+
+/// Export metrics in the Prometheus text format for ops monitoring
+async fn get_prometheus_metrics(
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, crate::api::public::ApiError> {
+    let (db, request_counts) = {
+        let shared_state = state.read().unwrap();
+        (shared_state.db.clone(), shared_state.request_counts.clone())
+    };
+    let body = prometheus::render(&db, &request_counts).await?;
+
+    Ok(([(header::CONTENT_TYPE, prometheus::CONTENT_TYPE)], body))
+}
+
 /// Create the metrics router
 pub fn router() -> Router<SharedState> {
-    Router::new().route("/", axum::routing::post(record_metric).get(get_metrics))
+    Router::new()
+        .route("/", axum::routing::post(record_metric).get(get_metrics))
+        .route("/tools", axum::routing::get(get_tool_metrics))
+        .route("/prometheus", axum::routing::get(get_prometheus_metrics))
 }
@@ -2,7 +2,9 @@
 
 pub mod calendar;
 pub mod chat;
+pub mod config;
 pub mod email;
+pub mod inbox;
 mod kv;
 pub mod metrics;
 pub mod notes;
@@ -38,4 +40,8 @@ pub fn router() -> Router<SharedState> {
         .nest("/metrics", metrics::router())
         // Webhook routes
         .nest("/webhook", webhook::router())
+        // Config routes
+        .nest("/config", config::router())
+        // Inbox routes
+        .nest("/inbox", inbox::router())
 }
@@ -1,6 +1,17 @@
 //! Database queries for the notes API
-use super::public::ViewNoteResponse;
-use tokio_rusqlite::Connection;
+use std::collections::HashMap;
+
+use super::public::{NoteSuggestion, ViewNoteResponse};
+use serde_json::json;
+use tokio_rusqlite::{Connection, OptionalExtension};
+
+/// Parse the JSON array of attachments stored in note meta. Headings,
+/// tasks, and meetings don't have attachments.
+fn parse_attachments(value: Option<String>) -> Vec<String> {
+    value
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
 
 /// Get a note by ID from the database
 pub async fn get_note_by_id(
@@ -15,7 +26,9 @@ pub async fn get_note_by_id(
             id,
             title,
             body,
-            tags
+            tags,
+            last_indexed_at,
+            attachments
           FROM note_meta
           WHERE id = ?
           LIMIT 1
@@ -28,6 +41,8 @@ pub async fn get_note_by_id(
                     title: i.get(1)?,
                     body: i.get(2)?,
                     tags: i.get(3)?,
+                    last_indexed_at: i.get(4)?,
+                    attachments: parse_attachments(i.get(5)?),
                 })
             })
             .unwrap()
@@ -39,3 +54,149 @@ pub async fn get_note_by_id(
     .await
     .map_err(|e| e.into())
 }
+
+/// Get the source file name of a note by ID
+pub async fn get_note_file_name(
+    db: &Connection,
+    id: String,
+) -> Result<Option<String>, anyhow::Error> {
+    let file_name = db
+        .call(move |conn| {
+            let result = conn
+                .query_row(
+                    "SELECT file_name FROM note_meta WHERE id = ? LIMIT 1",
+                    [id],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            Ok(result)
+        })
+        .await?;
+    Ok(file_name)
+}
+
+/// Get the IDs of all indexed notes, headings, and tasks
+pub async fn get_note_ids(db: &Connection) -> Result<Vec<String>, anyhow::Error> {
+    let ids = db
+        .call(|conn| {
+            let mut stmt = conn.prepare("SELECT id FROM note_meta")?;
+            let ids = stmt
+                .query_map([], |r| r.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(ids)
+        })
+        .await?;
+    Ok(ids)
+}
+
+/// Get multiple notes by ID from the database. Results are returned
+/// in the same order as `ids` with `None` for any ID that was not
+/// found.
+pub async fn get_notes_by_ids(
+    db: &Connection,
+    ids: Vec<String>,
+) -> Result<Vec<Option<ViewNoteResponse>>, anyhow::Error> {
+    let ids_json = json!(ids).to_string();
+    let found: HashMap<String, ViewNoteResponse> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                r"
+          SELECT
+            id,
+            title,
+            body,
+            tags,
+            last_indexed_at,
+            attachments
+          FROM note_meta
+          WHERE id IN (SELECT value FROM json_each(?))
+        ",
+            )?;
+            let rows = stmt
+                .query_map([ids_json], |i| {
+                    Ok(ViewNoteResponse {
+                        id: i.get(0)?,
+                        title: i.get(1)?,
+                        body: i.get(2)?,
+                        tags: i.get(3)?,
+                        last_indexed_at: i.get(4)?,
+                        attachments: parse_attachments(i.get(5)?),
+                    })
+                })?
+                .map(|r| r.map(|note| (note.id.clone(), note)))
+                .collect::<Result<HashMap<_, _>, _>>()?;
+            Ok(rows)
+        })
+        .await?;
+
+    // Duplicate IDs in the request each get their own copy of the note
+    Ok(ids.iter().map(|id| found.get(id).cloned()).collect())
+}
+
+/// Escape `%` and `_` so they match literally in a `LIKE` pattern
+/// with `ESCAPE '\'`
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Find notes whose title, or a word in the title, starts with
+/// `prefix` ignoring case. Titles that start with the prefix come
+/// first followed by shorter titles since they are closer matches.
+/// Only the title is read so this stays fast enough for type-ahead.
+pub async fn suggest_notes(
+    db: &Connection,
+    prefix: &str,
+    limit: usize,
+) -> Result<Vec<NoteSuggestion>, anyhow::Error> {
+    let prefix = escape_like(prefix);
+    let suggestions = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                r"
+          SELECT id, title
+          FROM note_meta
+          WHERE type = 'note'
+            AND (title LIKE ?1 || '%' ESCAPE '\' OR title LIKE '% ' || ?1 || '%' ESCAPE '\')
+          ORDER BY
+            title LIKE ?1 || '%' ESCAPE '\' DESC,
+            length(title),
+            title
+          LIMIT ?2
+        ",
+            )?;
+            let rows = stmt
+                .query_map((prefix, limit), |i| {
+                    Ok(NoteSuggestion {
+                        id: i.get(0)?,
+                        title: i.get(1)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })
+        .await?;
+    Ok(suggestions)
+}
+
+/// Get the ID, title, file name, and last indexed timestamp of every
+/// note file. Headings, tasks, and meetings share the note's file so
+/// they are not included.
+pub async fn get_indexed_notes(
+    db: &Connection,
+) -> Result<Vec<(String, String, String, Option<String>)>, anyhow::Error> {
+    let notes = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, title, file_name, last_indexed_at FROM note_meta WHERE type = 'note'",
+            )?;
+            let rows = stmt
+                .query_map([], |i| Ok((i.get(0)?, i.get(1)?, i.get(2)?, i.get(3)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })
+        .await?;
+    Ok(notes)
+}
@@ -1,11 +1,11 @@
 //! Public types for the notes API
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-// Search
+use serde::{Deserialize, Deserializer, Serialize};
 
-fn default_limit() -> usize {
-    20
-}
+use crate::search::{FacetCount, SearchFacet, SearchMode, SnippetStrategy};
+
+// Search
 
 fn default_as_true() -> bool {
     true
@@ -15,15 +15,48 @@ fn default_as_false() -> bool {
     false
 }
 
+fn default_snippet_chars() -> usize {
+    crate::search::DEFAULT_SNIPPET_CHARS
+}
+
+/// Comma separated list of facets like `tags,status`
+fn deserialize_facets<'de, D>(deserializer: D) -> Result<Vec<SearchFacet>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let facets = String::deserialize(deserializer)?;
+    facets
+        .split(',')
+        .filter(|facet| !facet.trim().is_empty())
+        .map(|facet| facet.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
 #[derive(Deserialize)]
 pub struct SearchRequest {
     pub query: String,
     #[serde(default = "default_as_false")]
     pub include_similarity: bool,
-    #[serde(default = "default_limit")]
-    pub limit: usize,
+    /// Defaults to the configured search limit and is clamped to the
+    /// configured max
+    pub limit: Option<usize>,
+    /// Number of results to skip for paging, defaults to 0
+    pub offset: Option<usize>,
     #[serde(default = "default_as_true")]
     pub truncate: bool,
+    /// Maximum length of the highlighted snippet of each result, 0
+    /// disables snippets
+    #[serde(default = "default_snippet_chars")]
+    pub snippet_chars: usize,
+    /// Part of the body used for snippets, `best_passage` or
+    /// `leading`. Defaults to the configured strategy.
+    pub snippet_strategy: Option<SnippetStrategy>,
+    /// How full-text and similarity results are combined, `full_text`
+    /// or `hybrid`. Defaults to the configured mode.
+    pub mode: Option<SearchMode>,
+    /// Fields to count across all matching notes e.g. `tags,status`
+    #[serde(default, deserialize_with = "deserialize_facets")]
+    pub facets: Vec<SearchFacet>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -41,6 +74,17 @@ pub struct SearchResult {
     pub task_closed: Option<String>,
     pub meeting_date: Option<String>,
     pub body: String,
+    pub last_indexed_at: Option<String>,
+    /// Part of the body, or title if the body didn't match, with
+    /// matched terms wrapped in `<mark>`
+    #[serde(default)]
+    pub snippet: Option<String>,
+    /// Rank of the note in the full-text results starting at 1
+    #[serde(default)]
+    pub lexical_rank: Option<usize>,
+    /// Rank of the note in the similarity results starting at 1
+    #[serde(default)]
+    pub vector_rank: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -48,12 +92,184 @@ pub struct SearchResponse {
     pub raw_query: String,
     pub parsed_query: String,
     pub results: Vec<SearchResult>,
+    /// Number of notes matching the query across all pages, not
+    /// counting similar notes
+    #[serde(default)]
+    pub total_hits: usize,
+    /// Set when similarity search was requested but unavailable so
+    /// only full-text results are included
+    #[serde(default)]
+    pub degraded: bool,
+    /// Counts of each requested facet's values across all matching
+    /// notes, most common first
+    #[serde(default)]
+    pub facets: BTreeMap<SearchFacet, Vec<FacetCount>>,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize)]
+pub struct ViewNoteRequest {
+    /// Return the unprocessed org source of the note's file
+    #[serde(default = "default_as_false")]
+    pub raw: bool,
+}
+
+#[derive(Serialize, Clone)]
 pub struct ViewNoteResponse {
     pub id: String,
     pub title: String,
     pub body: String,
     pub tags: Option<String>,
+    /// Paths of files linked from the note
+    pub attachments: Vec<String>,
+    pub last_indexed_at: Option<String>,
+}
+
+pub use crate::search::OutlineHeading;
+
+#[derive(Serialize, Deserialize)]
+pub struct NoteOutlineResponse {
+    pub id: String,
+    pub outline: Vec<OutlineHeading>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchViewNoteRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchViewNoteResponse {
+    /// Notes in the same order as the requested IDs with `null` for
+    /// any ID that wasn't found
+    pub notes: Vec<Option<ViewNoteResponse>>,
+}
+
+#[derive(Deserialize)]
+pub struct NoteSuggestRequest {
+    /// Prefix of the title to match
+    pub q: String,
+    /// Defaults to 10 and is clamped to 50
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NoteSuggestion {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NoteSuggestResponse {
+    pub suggestions: Vec<NoteSuggestion>,
+}
+
+#[derive(Deserialize)]
+pub struct StaleNotesRequest {
+    /// Only include notes whose file was modified at least this many
+    /// seconds after they were last indexed
+    #[serde(default)]
+    pub older_than: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StaleNote {
+    pub id: String,
+    pub title: String,
+    pub file_name: String,
+    /// `None` if the note was indexed before timestamps were tracked
+    pub last_indexed_at: Option<String>,
+    pub modified_at: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StaleNotesResponse {
+    pub notes: Vec<StaleNote>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnoozeTaskRequest {
+    /// New date as `YYYY-MM-DD`, `today`, `tomorrow`, or relative to
+    /// today like `+3d`, `+2w`, or `+1m`
+    pub date: String,
+    /// Which date to change, `scheduled` or `deadline`. Defaults to
+    /// `scheduled`.
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnoozeTaskResponse {
+    pub id: String,
+    pub kind: String,
+    /// The resolved date as `YYYY-MM-DD`
+    pub date: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct CompleteTaskRequest {
+    /// Title of the task to complete when the ID is for a note with
+    /// multiple tasks. Defaults to the note's first open task.
+    #[serde(default)]
+    pub heading: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompleteTaskResponse {
+    pub id: String,
+    pub title: String,
+}
+
+fn default_history_limit() -> usize {
+    20
+}
+
+#[derive(Deserialize)]
+pub struct NoteHistoryRequest {
+    /// Maximum number of commits to return, newest first
+    #[serde(default = "default_history_limit")]
+    pub limit: usize,
+}
+
+/// A commit that changed the note's file
+#[derive(Serialize, Deserialize)]
+pub struct NoteRevision {
+    pub sha: String,
+    pub author: String,
+    /// Author date in RFC 3339 format
+    pub date: String,
+    /// First line of the commit message
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NoteHistoryResponse {
+    pub id: String,
+    pub file_name: String,
+    pub revisions: Vec<NoteRevision>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateNoteRequest {
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateNoteResponse {
+    /// ID generated for the note using the configured scheme
+    pub id: String,
+    /// Path of the new note relative to the notes directory
+    pub file_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AttachmentResponse {
+    pub id: String,
+    /// Path of the saved file relative to the notes directory
+    pub file_name: String,
+    /// Org link to the file that was added to the note
+    pub link: String,
 }
@@ -4,18 +4,36 @@ use std::sync::{Arc, RwLock};
 
 use axum::{
     Router,
-    extract::{Path, State},
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response, sse::Event},
     routing::{get, post},
 };
 use axum_extra::extract::Query;
-use serde_json::{Value, json};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt as _;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use super::public;
 use crate::api::routes::notes::db as notes_db;
 use crate::api::state::AppState;
+use crate::api::utils::sse_response;
+use crate::core::fs::{
+    ATTACHMENTS_DIR, attachment_extension_type, attachment_file_name, attachment_link,
+    note_file_name, resolve_note_path, sniff_attachment_type,
+};
+use crate::core::git;
+use crate::core::time;
 use crate::search::aql;
-use crate::search::index_all;
-use crate::search::search_notes;
+use crate::search::index_all_with_embedder;
+use crate::search::parse_outline;
+use crate::search::remove_notes;
+use crate::search::rerank::{HttpReranker, Reranker};
+use crate::search::{FieldBoosts, SearchOptions, search_notes};
+use crate::search::{IndexBusy, IndexProgress};
+use crate::search::{PlanningKind, complete_task, set_planning_date};
 
 type SharedState = Arc<RwLock<AppState>>;
 
@@ -25,76 +43,834 @@ async fn note_search(
     Query(params): Query<public::SearchRequest>,
 ) -> Result<axum::Json<public::SearchResponse>, crate::api::public::ApiError> {
     let raw_query = params.query;
-    let query = aql::parse_query(&raw_query).expect("Parsing AQL failed");
-    let (db, index_path) = {
+    let query = aql::parse_query(&raw_query)?;
+    let (db, index_path, limit, boosts, snippet_strategy, mode, rerank_url, today, embedder) = {
         let shared_state = state.read().unwrap();
         (
             shared_state.db.clone(),
             shared_state.config.index_path.clone(),
+            shared_state.config.search_limit(params.limit),
+            FieldBoosts::from(&shared_state.config),
+            params
+                .snippet_strategy
+                .unwrap_or(shared_state.config.snippet_strategy),
+            params.mode.unwrap_or(shared_state.config.search_mode),
+            shared_state.config.rerank_url.clone(),
+            time::today(shared_state.config.timezone),
+            shared_state.embedder.clone(),
         )
     };
+    let query = aql::resolve_relative_dates(query, today);
+    let reranker = rerank_url.as_deref().map(HttpReranker::new);
 
-    let results = search_notes(
+    let search = search_notes(
         &index_path,
         &db,
-        params.include_similarity,
-        params.truncate,
+        embedder.as_ref(),
         &query,
-        params.limit,
+        &SearchOptions {
+            include_similarity: params.include_similarity,
+            truncate: params.truncate,
+            limit,
+            offset: params.offset.unwrap_or(0),
+            snippet_chars: Some(params.snippet_chars).filter(|chars| *chars > 0),
+            snippet_strategy,
+            boosts,
+            mode,
+            facets: &params.facets,
+            reranker: reranker.as_ref().map(|r| r as &dyn Reranker),
+        },
     )
     .await?;
 
     let resp = public::SearchResponse {
         raw_query: raw_query.to_string(),
         parsed_query: format!("{:?}", query),
-        results,
+        results: search.results,
+        total_hits: search.total_hits,
+        degraded: search.degraded,
+        facets: search.facets,
     };
 
     Ok(axum::Json(resp))
 }
 
+/// Pull the latest notes and remove notes deleted in the last commit
+/// from the index. Returns the paths of the notes that changed to
+/// index or `None` to index all notes.
+async fn pull_notes(
+    db: &tokio_rusqlite::Connection,
+    index_path: &str,
+    notes_path: &str,
+    deploy_key_path: &str,
+) -> Option<Vec<std::path::PathBuf>> {
+    crate::core::git::maybe_pull_and_reset_repo(deploy_key_path, notes_path).await;
+    let diff = crate::core::git::diff_last_commit_files(deploy_key_path, notes_path).await;
+    let paths: Vec<std::path::PathBuf> = diff
+        .iter()
+        .filter(|f| !f.is_empty())
+        .map(|f| std::path::PathBuf::from(format!("{}/{}", notes_path, f)))
+        .collect();
+
+    // Files in the diff that no longer exist were deleted so they
+    // need to be removed from the index
+    let deleted: Vec<String> = paths
+        .iter()
+        .filter(|p| !p.exists())
+        .filter_map(|p| p.file_name())
+        .map(|f| f.to_string_lossy().to_string())
+        .collect();
+    if !deleted.is_empty() {
+        match remove_notes(db, index_path, deleted).await {
+            Ok(ids) => tracing::info!("Removed {} deleted notes from the index", ids.len()),
+            Err(e) => tracing::error!("Failed to remove deleted notes: {}", e),
+        }
+    }
+
+    if paths.is_empty() { None } else { Some(paths) }
+}
+
 // Index notes endpoint
 async fn index_notes(
     State(state): State<SharedState>,
-) -> Result<axum::Json<Value>, crate::api::public::ApiError> {
-    let (a_db, index_path, notes_path, deploy_key_path) = {
+) -> Result<Response, crate::api::public::ApiError> {
+    let (a_db, index_path, notes_path, deploy_key_path, indexing, embedder) = {
         let shared_state = state.read().expect("Unable to read share state");
         (
             shared_state.db.clone(),
             shared_state.config.index_path.clone(),
             shared_state.config.notes_path.clone(),
             shared_state.config.deploy_key_path.clone(),
+            shared_state.indexing.clone(),
+            shared_state.embedder.clone(),
         )
     };
+    let Ok(guard) = indexing.try_lock_owned() else {
+        return Ok((StatusCode::CONFLICT, IndexBusy.to_string()).into_response());
+    };
     tokio::spawn(async move {
-        crate::core::git::maybe_pull_and_reset_repo(&deploy_key_path, &notes_path).await;
-        let diff = crate::core::git::diff_last_commit_files(&deploy_key_path, &notes_path).await;
-        let paths: Vec<std::path::PathBuf> = diff
-            .iter()
-            .map(|f| std::path::PathBuf::from(format!("{}/{}", &notes_path, f)))
-            .collect();
-        let filter_paths = if paths.is_empty() { None } else { Some(paths) };
-        index_all(&a_db, &index_path, &notes_path, true, true, filter_paths)
+        let _guard = guard;
+        let filter_paths = pull_notes(&a_db, &index_path, &notes_path, &deploy_key_path).await;
+        if let Err(e) = index_all_with_embedder(
+            &a_db,
+            &index_path,
+            &notes_path,
+            true,
+            Some(embedder.as_ref()),
+            filter_paths,
+            None,
+        )
+        .await
+        {
+            tracing::error!("Indexing notes failed: {}", e);
+        }
+    });
+    Ok(axum::Json(json!({ "success": true })).into_response())
+}
+
+/// Index notes like the index endpoint but stream the progress as SSE
+/// events ending with a summary when indexing is completed or the
+/// error if it failed
+async fn index_notes_stream(State(state): State<SharedState>) -> Response {
+    let (db, index_path, notes_path, deploy_key_path, indexing, embedder) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.index_path.clone(),
+            shared_state.config.notes_path.clone(),
+            shared_state.config.deploy_key_path.clone(),
+            shared_state.indexing.clone(),
+            shared_state.embedder.clone(),
+        )
+    };
+    let Ok(guard) = indexing.try_lock_owned() else {
+        return (StatusCode::CONFLICT, IndexBusy.to_string()).into_response();
+    };
+    let (tx, rx) = mpsc::unbounded_channel::<IndexProgress>();
+    tokio::spawn(async move {
+        let _guard = guard;
+        let filter_paths = pull_notes(&db, &index_path, &notes_path, &deploy_key_path).await;
+        // Index in a separate task so a panic is reported to the
+        // client instead of only closing the stream
+        let progress = tx.clone();
+        let indexed = tokio::spawn(async move {
+            index_all_with_embedder(
+                &db,
+                &index_path,
+                &notes_path,
+                true,
+                Some(embedder.as_ref()),
+                filter_paths,
+                Some(&progress),
+            )
             .await
-            .unwrap();
+        })
+        .await;
+        let error = match indexed {
+            Ok(Ok(())) => return,
+            // Report the error rather than the db error wrapping it
+            Ok(Err(tokio_rusqlite::Error::Other(e))) => e.to_string(),
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        tracing::error!("Indexing notes failed: {}", error);
+        let _ = tx.send(IndexProgress::Failed { error });
+    });
+
+    let events = UnboundedReceiverStream::new(rx).map(|progress| {
+        Ok(Event::default()
+            .data(serde_json::to_string(&progress).expect("Failed to serialize index progress")))
     });
-    Ok(axum::Json(json!({ "success": true })))
+    sse_response(events)
+}
+
+// Re-index a single note endpoint
+async fn reindex_note(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, index_path, notes_path, embedder) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.index_path.clone(),
+            shared_state.config.notes_path.clone(),
+            shared_state.embedder.clone(),
+        )
+    };
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    if resolve_note_path(&notes_path, &file_name).is_err() {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    }
+
+    // Use the same path format as the notes source so the filter
+    // matches the note
+    let path = std::path::Path::new(&notes_path).join(&file_name);
+    index_all_with_embedder(
+        &db,
+        &index_path,
+        &notes_path,
+        true,
+        Some(embedder.as_ref()),
+        Some(vec![path]),
+        None,
+    )
+    .await?;
+
+    Ok(axum::Json(json!({ "success": true })).into_response())
+}
+
+/// Change the scheduled date or deadline of a task and re-index its
+/// note
+async fn snooze_task(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    axum::Json(payload): axum::Json<public::SnoozeTaskRequest>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, index_path, notes_path, timezone, note_locks, embedder) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.index_path.clone(),
+            shared_state.config.notes_path.clone(),
+            shared_state.config.timezone,
+            shared_state.note_locks.clone(),
+            shared_state.embedder.clone(),
+        )
+    };
+
+    let kind = payload.kind.as_deref().unwrap_or("scheduled");
+    let kind = match kind.parse::<PlanningKind>() {
+        Ok(kind) => kind,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+    let date = match time::parse_date(&payload.date, time::today(timezone)) {
+        Ok(date) => date,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id.clone()).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    let Ok(note_path) = resolve_note_path(&notes_path, &file_name) else {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    };
+
+    let _guard = note_locks.lock(&note_path).await;
+    let content = tokio::fs::read_to_string(&note_path).await?;
+    let Some(updated) = set_planning_date(&content, &id, kind, date) else {
+        return Ok((StatusCode::NOT_FOUND, "Task not found in note").into_response());
+    };
+    tokio::fs::write(&note_path, updated).await?;
+
+    // Use the same path format as the notes source so the filter
+    // matches the note
+    let path = std::path::Path::new(&notes_path).join(&file_name);
+    index_all_with_embedder(
+        &db,
+        &index_path,
+        &notes_path,
+        true,
+        Some(embedder.as_ref()),
+        Some(vec![path]),
+        None,
+    )
+    .await?;
+
+    Ok(axum::Json(public::SnoozeTaskResponse {
+        id,
+        kind: kind.to_string(),
+        date: date.format("%Y-%m-%d").to_string(),
+    })
+    .into_response())
+}
+
+/// Mark a task as done and re-index its note
+async fn complete_note_task(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    payload: Option<axum::Json<public::CompleteTaskRequest>>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, index_path, notes_path, timezone, note_locks, embedder) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.index_path.clone(),
+            shared_state.config.notes_path.clone(),
+            shared_state.config.timezone,
+            shared_state.note_locks.clone(),
+            shared_state.embedder.clone(),
+        )
+    };
+    let axum::Json(payload) = payload.unwrap_or_default();
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id.clone()).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    let Ok(note_path) = resolve_note_path(&notes_path, &file_name) else {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    };
+
+    let _guard = note_locks.lock(&note_path).await;
+    let content = tokio::fs::read_to_string(&note_path).await?;
+    let closed = Utc::now().with_timezone(&timezone).naive_local();
+    let Some(completed) = complete_task(&content, &id, payload.heading.as_deref(), closed) else {
+        return Ok((StatusCode::NOT_FOUND, "Open task not found in note").into_response());
+    };
+    tokio::fs::write(&note_path, completed.content).await?;
+
+    // Use the same path format as the notes source so the filter
+    // matches the note
+    let path = std::path::Path::new(&notes_path).join(&file_name);
+    index_all_with_embedder(
+        &db,
+        &index_path,
+        &notes_path,
+        true,
+        Some(embedder.as_ref()),
+        Some(vec![path]),
+        None,
+    )
+    .await?;
+
+    Ok(axum::Json(public::CompleteTaskResponse {
+        id: completed.id,
+        title: completed.title,
+    })
+    .into_response())
+}
+
+/// Contents of a new org note with its ID in a properties drawer
+fn new_note_content(id: &str, title: &str, tags: &[String], body: &str) -> String {
+    let mut content = format!(
+        ":PROPERTIES:\n:ID:       {}\n:END:\n#+TITLE: {}\n",
+        id, title
+    );
+    if !tags.is_empty() {
+        content.push_str(&format!("#+FILETAGS: :{}:\n", tags.join(":")));
+    }
+    let body = body.trim();
+    if !body.is_empty() {
+        content.push('\n');
+        content.push_str(body);
+        content.push('\n');
+    }
+    content
+}
+
+/// Create a note in the root of the notes directory with an ID from
+/// the configured scheme and index it
+async fn create_note(
+    State(state): State<SharedState>,
+    axum::Json(payload): axum::Json<public::CreateNoteRequest>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, index_path, notes_path, note_locks, note_ids, embedder) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.index_path.clone(),
+            shared_state.config.notes_path.clone(),
+            shared_state.note_locks.clone(),
+            shared_state.note_ids.clone(),
+            shared_state.embedder.clone(),
+        )
+    };
+
+    let title = payload.title.trim();
+    if title.is_empty() || title.contains('\n') {
+        return Ok((StatusCode::BAD_REQUEST, "Note title must be a single line").into_response());
+    }
+    if let Some(tag) = payload
+        .tags
+        .iter()
+        .find(|tag| tag.is_empty() || tag.contains(|c: char| c == ':' || c.is_whitespace()))
+    {
+        return Ok((StatusCode::BAD_REQUEST, format!("Invalid tag: {:?}", tag)).into_response());
+    }
+
+    let file_name = note_file_name(title);
+    let Ok(note_path) = resolve_note_path(&notes_path, &file_name) else {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    };
+
+    if note_ids.uses_counter() {
+        let existing = notes_db::get_note_ids(&db).await?;
+        note_ids.skip_existing(existing.iter().map(String::as_str));
+    }
+    let id = note_ids.next_id();
+    let content = new_note_content(&id, title, &payload.tags, &payload.body);
+
+    {
+        let _guard = note_locks.lock(&note_path).await;
+        // Never overwrite a note that already has this file name
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&note_path)
+            .await;
+        let mut file = match file {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Ok((
+                    StatusCode::CONFLICT,
+                    format!("A note already exists at {}", file_name),
+                )
+                    .into_response());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        tokio::io::AsyncWriteExt::write_all(&mut file, content.as_bytes()).await?;
+    }
+
+    // Use the same path format as the notes source so the filter
+    // matches the note
+    let path = std::path::Path::new(&notes_path).join(&file_name);
+    index_all_with_embedder(
+        &db,
+        &index_path,
+        &notes_path,
+        true,
+        Some(embedder.as_ref()),
+        Some(vec![path]),
+        None,
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        axum::Json(public::CreateNoteResponse { id, file_name }),
+    )
+        .into_response())
+}
+
+/// Save a file uploaded as the `file` field of a multipart form to
+/// the attachments directory, link it at the end of the note, and
+/// re-index the note. The file's contents and extension must match
+/// its declared type.
+async fn upload_attachment(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    mut multipart: Multipart,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, index_path, notes_path, note_locks, max_bytes, allowed_types, embedder) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.index_path.clone(),
+            shared_state.config.notes_path.clone(),
+            shared_state.note_locks.clone(),
+            shared_state.config.attachments_max_bytes,
+            shared_state.config.attachments_allowed_types.clone(),
+            shared_state.embedder.clone(),
+        )
+    };
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id.clone()).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    let Ok(note_path) = resolve_note_path(&notes_path, &file_name) else {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    };
+
+    let mut field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return Ok((StatusCode::BAD_REQUEST, "Missing file field").into_response());
+            }
+            Err(e) => return Ok(e.into_response()),
+        }
+    };
+
+    let content_type = field.content_type().unwrap_or_default().to_string();
+    if !allowed_types.contains(&content_type) {
+        return Ok((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Attachment type not allowed: {}", content_type),
+        )
+            .into_response());
+    }
+
+    // Read the file in chunks so an upload over the limit is
+    // rejected without buffering all of it
+    let mut data = Vec::new();
+    loop {
+        match field.chunk().await {
+            Ok(Some(chunk)) => {
+                if data.len() + chunk.len() > max_bytes {
+                    return Ok((
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        format!("Attachment is larger than {} bytes", max_bytes),
+                    )
+                        .into_response());
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => return Ok(e.into_response()),
+        }
+    }
+
+    // Don't trust the declared type since it's whatever the client
+    // says it is
+    let upload_name = field.file_name().unwrap_or_default();
+    if sniff_attachment_type(&data) != Some(content_type.as_str())
+        || attachment_extension_type(upload_name) != Some(content_type.as_str())
+    {
+        return Ok((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Attachment doesn't match its type: {}", content_type),
+        )
+            .into_response());
+    }
+
+    let attachment_name = attachment_file_name(upload_name);
+    let attachments_path = std::path::Path::new(&notes_path).join(ATTACHMENTS_DIR);
+    tokio::fs::create_dir_all(&attachments_path).await?;
+    // Write to a temporary file that's only moved into place once
+    // the note links to it so a failed update doesn't leave an
+    // orphaned attachment behind
+    let attachment_path = attachments_path.join(&attachment_name);
+    let tmp_path = attachments_path.join(format!(".{}.tmp", attachment_name));
+    tokio::fs::write(&tmp_path, data).await?;
+
+    let link = attachment_link(&file_name, &attachment_name);
+    let linked = async {
+        let _guard = note_locks.lock(&note_path).await;
+        let mut content = tokio::fs::read_to_string(&note_path).await?;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&link);
+        content.push('\n');
+        tokio::fs::write(&note_path, content).await
+    }
+    .await;
+    if let Err(e) = linked {
+        if let Err(e) = tokio::fs::remove_file(&tmp_path).await {
+            tracing::warn!("Failed to remove attachment {}: {}", tmp_path.display(), e);
+        }
+        return Err(e.into());
+    }
+    tokio::fs::rename(&tmp_path, &attachment_path).await?;
+
+    // Use the same path format as the notes source so the filter
+    // matches the note
+    let path = std::path::Path::new(&notes_path).join(&file_name);
+    index_all_with_embedder(
+        &db,
+        &index_path,
+        &notes_path,
+        true,
+        Some(embedder.as_ref()),
+        Some(vec![path]),
+        None,
+    )
+    .await?;
+
+    Ok(axum::Json(public::AttachmentResponse {
+        id,
+        file_name: format!("{}/{}", ATTACHMENTS_DIR, attachment_name),
+        link,
+    })
+    .into_response())
+}
+
+/// List the commits that changed a note's file in the notes repo
+async fn note_history(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    Query(params): Query<public::NoteHistoryRequest>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, notes_path) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.notes_path.clone(),
+        )
+    };
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id.clone()).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    if resolve_note_path(&notes_path, &file_name).is_err() {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    }
+
+    let revisions = git::file_history(&notes_path, &file_name, params.limit)
+        .await?
+        .into_iter()
+        .map(|commit| public::NoteRevision {
+            sha: commit.sha,
+            author: commit.author,
+            date: commit.date,
+            message: commit.message,
+        })
+        .collect();
+
+    Ok(axum::Json(public::NoteHistoryResponse {
+        id,
+        file_name,
+        revisions,
+    })
+    .into_response())
+}
+
+/// Download the org source of a note as of a commit in the notes repo
+async fn note_revision(
+    State(state): State<SharedState>,
+    Path((id, sha)): Path<(String, String)>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, notes_path) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.notes_path.clone(),
+        )
+    };
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    if resolve_note_path(&notes_path, &file_name).is_err() {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    }
+
+    if !git::is_commit_sha(&sha) {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid commit SHA").into_response());
+    }
+
+    let Some(content) = git::file_at_revision(&notes_path, &sha, &file_name).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Revision not found").into_response());
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/x-org; charset=utf-8")],
+        content,
+    )
+        .into_response())
+}
+
+// Stale notes endpoint
+async fn stale_notes(
+    State(state): State<SharedState>,
+    Query(params): Query<public::StaleNotesRequest>,
+) -> Result<axum::Json<public::StaleNotesResponse>, crate::api::public::ApiError> {
+    let (db, notes_path) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.db.clone(),
+            shared_state.config.notes_path.clone(),
+        )
+    };
+    let older_than = chrono::Duration::seconds(params.older_than as i64);
+
+    let mut notes = Vec::new();
+    for (id, title, file_name, last_indexed_at) in notes_db::get_indexed_notes(&db).await? {
+        // Skip notes whose file no longer exists, there's nothing to
+        // re-index
+        let Ok(note_path) = resolve_note_path(&notes_path, &file_name) else {
+            continue;
+        };
+        let Ok(modified) = tokio::fs::metadata(&note_path)
+            .await
+            .and_then(|m| m.modified())
+        else {
+            continue;
+        };
+        let modified_at: DateTime<Utc> = modified.into();
+
+        let indexed_at = last_indexed_at
+            .as_deref()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok());
+        let is_stale = match indexed_at {
+            Some(indexed_at) => modified_at - indexed_at.with_timezone(&Utc) > older_than,
+            None => true,
+        };
+
+        if is_stale {
+            notes.push(public::StaleNote {
+                id,
+                title,
+                file_name,
+                last_indexed_at,
+                modified_at: modified_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            });
+        }
+    }
+
+    Ok(axum::Json(public::StaleNotesResponse { notes }))
 }
 
 // View note endpoint
 async fn view_note(
     State(state): State<SharedState>,
     Path(id): Path<String>,
-) -> Result<axum::Json<public::ViewNoteResponse>, crate::api::public::ApiError> {
+    Query(params): Query<public::ViewNoteRequest>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, notes_path) = {
+        let shared_state = state.read().unwrap();
+        (
+            shared_state.db.clone(),
+            shared_state.config.notes_path.clone(),
+        )
+    };
+
+    if !params.raw {
+        let note_result = notes_db::get_note_by_id(&db, id).await?;
+        return Ok(axum::Json(note_result).into_response());
+    }
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    let Ok(note_path) = resolve_note_path(&notes_path, &file_name) else {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    };
+
+    let content = tokio::fs::read_to_string(note_path).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/x-org; charset=utf-8")],
+        content,
+    )
+        .into_response())
+}
+
+/// Get the heading hierarchy of a note parsed from its org source
+async fn note_outline(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Response, crate::api::public::ApiError> {
+    let (db, notes_path) = {
+        let shared_state = state.read().unwrap();
+        (
+            shared_state.db.clone(),
+            shared_state.config.notes_path.clone(),
+        )
+    };
+
+    let Some(file_name) = notes_db::get_note_file_name(&db, id.clone()).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Note not found").into_response());
+    };
+
+    let Ok(note_path) = resolve_note_path(&notes_path, &file_name) else {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid note file name").into_response());
+    };
+
+    let content = tokio::fs::read_to_string(note_path).await?;
+    let outline = parse_outline(&content);
+    Ok(axum::Json(public::NoteOutlineResponse { id, outline }).into_response())
+}
+
+// Batch view notes endpoint
+async fn batch_view_notes(
+    State(state): State<SharedState>,
+    axum::Json(payload): axum::Json<public::BatchViewNoteRequest>,
+) -> Result<axum::Json<public::BatchViewNoteResponse>, crate::api::public::ApiError> {
     let db = state.read().unwrap().db.clone();
-    let note_result = notes_db::get_note_by_id(&db, id).await?;
-    Ok(axum::Json(note_result))
+    let notes = notes_db::get_notes_by_ids(&db, payload.ids).await?;
+    Ok(axum::Json(public::BatchViewNoteResponse { notes }))
+}
+
+/// Default number of suggestions when the request doesn't say
+const DEFAULT_SUGGEST_LIMIT: usize = 10;
+
+/// Most suggestions returned no matter what the request asks for
+const MAX_SUGGEST_LIMIT: usize = 50;
+
+/// Suggest notes whose title matches a prefix for type-ahead. Only
+/// IDs and titles are returned to keep it fast.
+async fn suggest_notes(
+    State(state): State<SharedState>,
+    Query(params): Query<public::NoteSuggestRequest>,
+) -> Result<axum::Json<public::NoteSuggestResponse>, crate::api::public::ApiError> {
+    let db = state.read().unwrap().db.clone();
+    let prefix = params.q.trim();
+    if prefix.is_empty() {
+        return Ok(axum::Json(public::NoteSuggestResponse {
+            suggestions: Vec::new(),
+        }));
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SUGGEST_LIMIT)
+        .min(MAX_SUGGEST_LIMIT);
+    let suggestions = notes_db::suggest_notes(&db, prefix, limit).await?;
+    Ok(axum::Json(public::NoteSuggestResponse { suggestions }))
 }
 
 /// Create the notes router
 pub fn router() -> Router<SharedState> {
     Router::new()
+        .route("/", post(create_note))
         .route("/search", get(note_search))
+        .route("/suggest", get(suggest_notes))
         .route("/index", post(index_notes))
+        .route("/index/stream", post(index_notes_stream))
+        .route("/view", post(batch_view_notes))
+        .route("/stale", get(stale_notes))
         .route("/{id}/view", get(view_note))
+        .route("/{id}/outline", get(note_outline))
+        .route("/{id}/reindex", post(reindex_note))
+        .route("/{id}/snooze", post(snooze_task))
+        .route("/{id}/complete", post(complete_note_task))
+        .route("/{id}/history", get(note_history))
+        .route("/{id}/history/{sha}", get(note_revision))
+        // The attachment size limit is configurable so it's enforced
+        // by the handler instead of the default body limit
+        .route(
+            "/{id}/attachments",
+            post(upload_attachment).layer(DefaultBodyLimit::disable()),
+        )
 }
@@ -52,12 +52,13 @@ async fn send_notification(
     State(state): State<SharedState>,
     Json(payload): Json<public::NotificationRequest>,
 ) -> Result<Json<Value>, crate::api::public::ApiError> {
-    let vapid_key_path = state
-        .read()
-        .expect("Unable to read share state")
-        .config
-        .vapid_key_path
-        .clone();
+    let (vapid_key_path, push_max_concurrency) = {
+        let shared_state = state.read().expect("Unable to read share state");
+        (
+            shared_state.config.vapid_key_path.clone(),
+            shared_state.config.push_max_concurrency,
+        )
+    };
 
     let subscriptions = {
         let db = state.read().unwrap().db.clone();
@@ -85,7 +86,13 @@ async fn send_notification(
         None,
         Some("index_updated"),
     );
-    broadcast_push_notification(subscriptions, vapid_key_path, notification_payload).await;
+    broadcast_push_notification(
+        subscriptions,
+        vapid_key_path,
+        notification_payload,
+        push_max_concurrency,
+    )
+    .await;
 
     Ok(Json(serde_json::json!({ "success": true })))
 }
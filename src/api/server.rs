@@ -10,10 +10,12 @@ use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use super::routes;
+use super::routes::metrics::prometheus::count_requests;
 use crate::api::state::AppState;
-use crate::core::{AppConfig, db::async_db};
+use crate::core::{AppConfig, db::async_db, http::HttpOptions};
 use crate::jobs::{
-    DailyAgenda, GenerateSessionTitles, ResearchMeetingAttendees, spawn_periodic_job,
+    DailyAgenda, EvictChatSessions, GenerateSessionTitles, ResearchMeetingAttendees,
+    spawn_periodic_job,
 };
 
 async fn set_static_cache_control(request: Request, next: middleware::Next) -> Response {
@@ -40,6 +42,10 @@ pub fn app(shared_state: Arc<RwLock<AppState>>) -> Router {
                         .precompressed_gzip(),
                 ),
         )
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&shared_state),
+            count_requests,
+        ))
         .layer(TraceLayer::new_for_http())
         .layer(cors)
         .with_state(Arc::clone(&shared_state))
@@ -63,6 +69,15 @@ pub async fn serve(host: String, port: String, config: AppConfig) {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    crate::core::http::configure(HttpOptions::from(&config));
+    if let Some(path) = &config.llm_log_path {
+        crate::openai::request_log::configure(path);
+    }
+
+    // Warn about integrations that won't work rather than failing at
+    // first use
+    crate::core::selfcheck::log_disabled_integrations(&config);
+
     let db = async_db(&config.vec_db_path)
         .await
         .expect("Failed to connect to async db");
@@ -84,7 +99,8 @@ pub async fn serve(host: String, port: String, config: AppConfig) {
     // in a loop.
     spawn_periodic_job(config.clone(), db.clone(), DailyAgenda);
     spawn_periodic_job(config.clone(), db.clone(), ResearchMeetingAttendees);
-    spawn_periodic_job(config, db, GenerateSessionTitles);
+    spawn_periodic_job(config.clone(), db.clone(), GenerateSessionTitles);
+    spawn_periodic_job(config, db, EvictChatSessions);
 
     axum::serve(listener, app).await.unwrap();
 }
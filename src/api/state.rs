@@ -1,7 +1,14 @@
+use std::sync::Arc;
+
 use serde::Deserialize;
 use tokio_rusqlite::Connection;
 
+use crate::api::routes::chat::ChatStreams;
+use crate::api::routes::metrics::prometheus::RequestCounts;
 use crate::core::AppConfig;
+use crate::core::fs::NoteLocks;
+use crate::core::note_id::NoteIdGenerator;
+use crate::search::embedding::{CachedEmbedder, Embedder, LocalEmbedder, RetryEmbedder};
 
 #[derive(Debug, Deserialize)]
 pub struct LastSelection {
@@ -15,14 +22,38 @@ pub struct AppState {
     pub latest_selection: Option<LastSelection>,
     pub db: Connection,
     pub config: AppConfig,
+    // Streamed events of the latest response in each chat session
+    pub chat_streams: ChatStreams,
+    // Serializes edits to each note file
+    pub note_locks: NoteLocks,
+    // IDs for notes created through the API
+    pub note_ids: Arc<NoteIdGenerator>,
+    // Number of HTTP requests handled for the Prometheus metrics
+    pub request_counts: RequestCounts,
+    // Held while all notes are being indexed so runs don't overlap
+    pub indexing: Arc<tokio::sync::Mutex<()>>,
+    // Embeds notes when indexing and queries when searching
+    pub embedder: Arc<dyn Embedder>,
 }
 
 impl AppState {
     pub fn new(db: Connection, config: AppConfig) -> Self {
+        let note_ids = Arc::new(NoteIdGenerator::new(config.note_id_scheme.clone()));
+        let embedder = Arc::new(CachedEmbedder::new(
+            db.clone(),
+            LocalEmbedder::MODEL_ID,
+            RetryEmbedder::new(LocalEmbedder),
+        ));
         Self {
             latest_selection: None,
             db,
             config,
+            chat_streams: ChatStreams::default(),
+            note_locks: NoteLocks::default(),
+            note_ids,
+            request_counts: RequestCounts::default(),
+            indexing: Arc::default(),
+            embedder,
         }
     }
 }
@@ -1,9 +1,12 @@
-use axum::response::sse::Event;
+use axum::http::{HeaderValue, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
 use futures_util::Stream;
 use std::{
     convert::Infallible,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use tokio::sync::broadcast;
@@ -57,3 +60,22 @@ impl<S> Drop for DetectDisconnect<S> {
         tracing::info!("SSE client disconnected");
     }
 }
+
+/// Respond with a stream of SSE events. Disables caching and proxy
+/// buffering so that chunks reach the client as soon as they're sent.
+pub fn sse_response<S>(stream: S) -> Response
+where
+    S: Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    let mut resp = Sse::new(stream)
+        .keep_alive(
+            KeepAlive::default()
+                .text("keep-alive")
+                .interval(Duration::from_millis(100)),
+        )
+        .into_response();
+    let headers = resp.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
+    resp
+}
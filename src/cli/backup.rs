@@ -0,0 +1,14 @@
+use crate::core::backup::backup;
+use anyhow::Result;
+
+pub async fn run(out: &str, index_path: &str, vec_db_path: &str) -> Result<()> {
+    let db = crate::core::db::async_db(vec_db_path)
+        .await
+        .expect("Failed to connect to async db");
+
+    println!("Backing up db and index to {}...", out);
+    let db_backup_path = backup(&db, index_path, out).await?;
+    println!("Finished backup: {}", db_backup_path.display());
+
+    Ok(())
+}
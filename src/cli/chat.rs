@@ -83,7 +83,7 @@ pub async fn run(vec_db_path: &str) -> Result<()> {
                 let user_msg = Message::new(Role::User, line.as_str());
                 let resp = chat.next_msg(user_msg).await?;
                 let msg = resp.last().unwrap();
-                println!("{}", msg.content.clone().unwrap());
+                println!("{}", msg.content().unwrap());
             }
             Err(ReadlineError::Interrupted) => break,
             Err(ReadlineError::Eof) => break,
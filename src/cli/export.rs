@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+use crate::search::{export_note, find_note_path};
+
+/// Parse the note with the ID in the notes directory or the note at
+/// the path the same way it's parsed for indexing
+pub async fn export(id: Option<&str>, path: Option<&str>, notes_path: &str) -> Result<Value> {
+    let path = match (id, path) {
+        (Some(id), None) => find_note_path(notes_path, id)
+            .await
+            .ok_or_else(|| anyhow!("No note with ID {} found in {}", id, notes_path))?,
+        (None, Some(path)) => PathBuf::from(path),
+        _ => return Err(anyhow!("Specify one of --id or --path")),
+    };
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| anyhow!("Failed to read note {}: {}", path.display(), e))?;
+    Ok(export_note(&content))
+}
+
+pub async fn run(id: Option<String>, path: Option<String>, notes_path: &str) -> Result<()> {
+    let note = export(id.as_deref(), path.as_deref(), notes_path).await?;
+    println!("{}", serde_json::to_string_pretty(&note)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const NOTE: &str = ":PROPERTIES:\n:ID:       export-note\n:END:\n#+TITLE: Export me\n#+FILETAGS: :work:\n\nSome body text.\n\n* TODO [#B] Send report :finance:\nDEADLINE: <2025-04-15 Tue> SCHEDULED: <2025-04-10 Thu>\n:PROPERTIES:\n:ID:       export-task\n:END:\n";
+
+    #[tokio::test]
+    async fn it_exports_a_parsed_note() {
+        let dir = TempDir::new().unwrap();
+        let notes_path = dir.path().to_str().unwrap();
+        std::fs::write(dir.path().join("export.org"), NOTE).unwrap();
+
+        let note = export(Some("export-note"), None, notes_path).await.unwrap();
+        assert_eq!(note["id"], "export-note");
+        assert_eq!(note["title"], "Export me");
+        assert_eq!(note["tags"], "work");
+        assert!(note["body"].as_str().unwrap().contains("Some body text."));
+
+        let task = &note["tasks"][0];
+        assert_eq!(task["id"], "export-task");
+        assert_eq!(task["title"], "Send report");
+        assert_eq!(task["status"], "todo");
+        assert_eq!(task["priority"], "B");
+        assert_eq!(task["tags"], "finance");
+        assert_eq!(task["deadline"], "2025-04-15");
+        assert_eq!(task["scheduled"], "2025-04-10");
+
+        // The same note can be exported by path
+        let path = dir.path().join("export.org");
+        let by_path = export(None, Some(path.to_str().unwrap()), notes_path)
+            .await
+            .unwrap();
+        assert_eq!(by_path, note);
+    }
+
+    #[tokio::test]
+    async fn it_fails_to_export_an_unknown_note() {
+        let dir = TempDir::new().unwrap();
+        let notes_path = dir.path().to_str().unwrap();
+        std::fs::write(dir.path().join("export.org"), NOTE).unwrap();
+
+        let err = export(Some("missing-note"), None, notes_path)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No note with ID missing-note"));
+    }
+}
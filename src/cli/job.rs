@@ -4,8 +4,10 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::core::AppConfig;
 use crate::core::db::async_db;
+use crate::core::http::{self, HttpOptions};
 use crate::jobs::{
-    DailyAgenda, GenerateSessionTitles, PeriodicJob, ProcessEmail, ResearchMeetingAttendees,
+    DailyAgenda, EvictChatSessions, GenerateSessionTitles, PeriodicJob, ProcessEmail,
+    ResearchMeetingAttendees,
 };
 
 #[derive(clap::ValueEnum, Clone, Copy, Debug)]
@@ -14,6 +16,7 @@ pub enum JobId {
     ResearchMeetingAttendees,
     GenerateSessionTitles,
     DailyAgenda,
+    EvictChatSessions,
 }
 
 pub async fn run(id: JobId) -> Result<()> {
@@ -26,6 +29,10 @@ pub async fn run(id: JobId) -> Result<()> {
         .init();
 
     let config = AppConfig::default();
+    http::configure(HttpOptions::from(&config));
+    if let Some(path) = &config.llm_log_path {
+        crate::openai::request_log::configure(path);
+    }
     let db = async_db(&config.vec_db_path)
         .await
         .expect("Failed to connect to db");
@@ -35,6 +42,7 @@ pub async fn run(id: JobId) -> Result<()> {
         JobId::ResearchMeetingAttendees => Box::new(ResearchMeetingAttendees),
         JobId::GenerateSessionTitles => Box::new(GenerateSessionTitles),
         JobId::DailyAgenda => Box::new(DailyAgenda),
+        JobId::EvictChatSessions => Box::new(EvictChatSessions),
     };
 
     println!("Running job: {:?}", id);
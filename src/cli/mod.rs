@@ -3,14 +3,19 @@ use clap::{Parser, Subcommand};
 use std::env;
 
 pub mod auth;
+pub mod backup;
 pub mod chat;
+pub mod export;
 pub mod index;
 pub mod init;
 pub mod job;
 pub mod migrate;
 pub mod query;
 pub mod rebuild;
+pub mod replay;
+pub mod restore;
 pub mod serve;
+pub mod verify;
 
 use auth::ServiceKind;
 use job::JobId;
@@ -54,6 +59,25 @@ enum Command {
     },
     /// Rebuild all indices from source
     Rebuild {},
+    /// Check the notes directory, db, and indices are consistent
+    Verify {
+        /// Re-index or remove notes that are inconsistent
+        #[arg(long, action, default_value = "false")]
+        fix: bool,
+    },
+    /// Snapshot the db and search index to a directory
+    Backup {
+        #[arg(long)]
+        out: String,
+    },
+    /// Restore the db and search index from a backup
+    Restore {
+        #[arg(long)]
+        from: String,
+        /// Replace the existing db and index
+        #[arg(long, action, default_value = "false")]
+        force: bool,
+    },
     /// Query the search index
     Query {
         #[arg(long)]
@@ -61,8 +85,32 @@ enum Command {
         #[arg(long, default_value = "false")]
         vector: bool,
     },
+    /// Print a note as it's parsed for indexing as JSON
+    Export {
+        /// ID of a note in the notes directory
+        #[arg(long, conflicts_with = "path", required_unless_present = "path")]
+        id: Option<String>,
+        /// Path to an org file
+        #[arg(long)]
+        path: Option<String>,
+    },
     /// Start a chat bot session
     Chat {},
+    /// Re-send a request from the LLM request log
+    Replay {
+        /// Path to the log, defaults to HQ_LLM_LOG_PATH
+        #[arg(long)]
+        log: Option<String>,
+        /// Line number of the entry starting at 1
+        #[arg(long, conflicts_with = "id")]
+        log_line: Option<usize>,
+        /// ID of the entry
+        #[arg(long)]
+        id: Option<String>,
+        /// Send the request to this model instead of the logged one
+        #[arg(long)]
+        model: Option<String>,
+    },
     /// Perform oauth and store credentials
     Auth {
         #[arg(long, value_enum)]
@@ -121,12 +169,32 @@ pub async fn run() -> Result<()> {
         Some(Command::Rebuild {}) => {
             rebuild::run(&index_path, &notes_path, &vec_db_path).await?;
         }
+        Some(Command::Verify { fix }) => {
+            verify::run(fix, &index_path, &notes_path, &vec_db_path).await?;
+        }
+        Some(Command::Backup { out }) => {
+            backup::run(&out, &index_path, &vec_db_path).await?;
+        }
+        Some(Command::Restore { from, force }) => {
+            restore::run(&from, force, &index_path, &vec_db_path).await?;
+        }
         Some(Command::Query { term, vector }) => {
             query::run(term, vector, &index_path, &vec_db_path).await?;
         }
+        Some(Command::Export { id, path }) => {
+            export::run(id, path, &notes_path).await?;
+        }
         Some(Command::Chat {}) => {
             chat::run(&vec_db_path).await?;
         }
+        Some(Command::Replay {
+            log,
+            log_line,
+            id,
+            model,
+        }) => {
+            replay::run(log, log_line, id, model).await?;
+        }
         Some(Command::Auth { service }) => {
             auth::run(service, &vec_db_path).await?;
         }
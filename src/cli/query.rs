@@ -1,20 +1,45 @@
+use crate::core::AppConfig;
 use crate::core::db::async_db;
+use crate::core::time;
 use crate::search::aql;
-use crate::search::search_notes;
+use crate::search::embedding::LocalEmbedder;
+use crate::search::rerank::{HttpReranker, Reranker};
+use crate::search::{DEFAULT_SNIPPET_CHARS, FieldBoosts, SearchOptions, search_notes};
 use anyhow::Result;
 use serde_json::json;
 
 pub async fn run(term: String, vector: bool, index_path: &str, vec_db_path: &str) -> Result<()> {
+    let config = AppConfig::default();
     let db = async_db(&vec_db_path)
         .await
         .expect("Failed to connect to async db");
-    let query = aql::parse_query(&term).expect("Parsing AQL failed");
-    let results = search_notes(&index_path, &db, vector, false, &query, 20).await?;
+    let today = time::today(config.timezone);
+    let query = aql::resolve_relative_dates(aql::parse_query(&term)?, today);
+    let reranker = config.rerank_url.as_deref().map(HttpReranker::new);
+    let search = search_notes(
+        &index_path,
+        &db,
+        &LocalEmbedder,
+        &query,
+        &SearchOptions {
+            include_similarity: vector,
+            limit: config.search_limit(None),
+            snippet_chars: Some(DEFAULT_SNIPPET_CHARS),
+            snippet_strategy: config.snippet_strategy,
+            boosts: FieldBoosts::from(&config),
+            mode: config.search_mode,
+            reranker: reranker.as_ref().map(|r| r as &dyn Reranker),
+            ..Default::default()
+        },
+    )
+    .await?;
     println!(
         "{}",
         json!({
             "query": term,
-            "results": results,
+            "results": search.results,
+            "total_hits": search.total_hits,
+            "degraded": search.degraded,
         })
     );
     Ok(())
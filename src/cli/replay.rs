@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+
+use crate::core::AppConfig;
+use crate::core::http::{self, HttpOptions};
+use crate::openai::CompletionOptions;
+use crate::openai::request_log::{EntrySelector, find_entry, replay};
+
+pub async fn run(
+    log: Option<String>,
+    line: Option<usize>,
+    id: Option<String>,
+    model: Option<String>,
+) -> Result<()> {
+    // Use the current API settings rather than anything in the log
+    let config = AppConfig::default();
+    http::configure(HttpOptions::from(&config));
+
+    let log_path = log
+        .or_else(|| config.llm_log_path.clone())
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("Missing --log or env var HQ_LLM_LOG_PATH"))?;
+    let selector = match (line, id) {
+        (Some(line), None) => EntrySelector::Line(line),
+        (None, Some(id)) => EntrySelector::Id(id),
+        _ => return Err(anyhow!("Specify one of --log-line or --id")),
+    };
+
+    let entry = find_entry(&log_path, &selector).await?;
+    let response = replay(
+        &entry,
+        &config.openai_api_hostname,
+        &config.openai_api_key,
+        model.as_deref(),
+        &CompletionOptions::default(),
+    )
+    .await?;
+    println!("{}", serde_json::to_string_pretty(&response)?);
+    Ok(())
+}
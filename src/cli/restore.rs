@@ -0,0 +1,10 @@
+use crate::core::backup::restore;
+use anyhow::Result;
+
+pub async fn run(from: &str, force: bool, index_path: &str, vec_db_path: &str) -> Result<()> {
+    println!("Restoring db and index from {}...", from);
+    restore(from, vec_db_path, index_path, force).await?;
+    println!("Finished restore");
+
+    Ok(())
+}
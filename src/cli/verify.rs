@@ -0,0 +1,46 @@
+use crate::search::{fix_indices, verify_indices};
+use anyhow::Result;
+
+fn yes_no(found: bool) -> &'static str {
+    if found { "yes" } else { "no" }
+}
+
+pub async fn run(fix: bool, index_path: &str, notes_path: &str, vec_db_path: &str) -> Result<()> {
+    let db = crate::core::db::async_db(vec_db_path)
+        .await
+        .expect("Failed to connect to async db");
+
+    let report = verify_indices(&db, index_path, notes_path).await?;
+    println!(
+        "Checked {} notes, found {} mismatches",
+        report.total,
+        report.mismatches.len()
+    );
+
+    if report.is_ok() {
+        return Ok(());
+    }
+
+    println!("id\tfile\tnotes\tdb\tfull_text\tvector");
+    for m in report.mismatches.iter() {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            m.id,
+            m.file_name.as_deref().unwrap_or("-"),
+            yes_no(m.in_notes),
+            yes_no(m.in_db),
+            yes_no(m.in_full_text),
+            yes_no(m.in_vector),
+        );
+    }
+
+    if fix {
+        println!("Fixing mismatches...");
+        fix_indices(&db, index_path, notes_path, &report.mismatches).await?;
+        println!("Finished fixing mismatches");
+    } else {
+        println!("Run with --fix to re-index or remove mismatched notes");
+    }
+
+    Ok(())
+}
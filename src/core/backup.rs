@@ -0,0 +1,350 @@
+//! Snapshots of the db and search index that can be taken while the
+//! server is running and restored later.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow, bail};
+use rusqlite::OpenFlags;
+use tokio_rusqlite::Connection;
+
+use crate::search::SCHEMA_VERSION_FILE_NAME;
+
+/// File name of the db within a backup. Matches the name used by
+/// `async_db` so a backup can be restored by copying it back.
+pub const BACKUP_DB_FILE_NAME: &str = "vector.db";
+
+/// Directory name of the full-text index within a backup
+pub const BACKUP_INDEX_DIR_NAME: &str = "index";
+
+// Tantivy's list of segments that make up the index
+const INDEX_META_FILE_NAME: &str = "meta.json";
+
+// Extensions of the files every segment has
+const SEGMENT_FILE_EXTENSIONS: [&str; 6] = ["idx", "pos", "term", "store", "fast", "fieldnorm"];
+
+/// File names of the segments listed in the index meta. Segment files
+/// are named after the segment ID and deleted documents are tracked
+/// in a file for the opstamp of the latest delete.
+fn segment_files(meta: &[u8]) -> Result<Vec<String>> {
+    let meta: serde_json::Value = serde_json::from_slice(meta)?;
+    let segments = meta["segments"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Index meta is missing its segments"))?;
+    let mut files = Vec::new();
+    for segment in segments {
+        let id = segment["segment_id"]
+            .as_str()
+            .and_then(|id| uuid::Uuid::parse_str(id).ok())
+            .ok_or_else(|| anyhow!("Invalid segment ID in index meta: {}", segment))?
+            .simple()
+            .to_string();
+        files.extend(
+            SEGMENT_FILE_EXTENSIONS
+                .iter()
+                .map(|extension| format!("{}.{}", id, extension)),
+        );
+        if let Some(opstamp) = segment["deletes"]["opstamp"].as_u64() {
+            files.push(format!("{}.{}.del", id, opstamp));
+        }
+    }
+    Ok(files)
+}
+
+/// Copy the full-text index to `out_dir`. Segment files are never
+/// modified once written so the meta file is read first, only the
+/// segments it lists are copied, and it's written last to get a
+/// consistent snapshot even if the index is committed to during the
+/// copy. A listed segment can still be garbage collected after a
+/// merge so the copy fails instead of writing a meta that points to a
+/// missing file. The schema version is copied too so the copy isn't
+/// seen as outdated and rebuilt.
+async fn copy_index(index_path: &Path, out_dir: &Path) -> Result<()> {
+    tokio::fs::create_dir_all(out_dir).await?;
+    let meta = tokio::fs::read(index_path.join(INDEX_META_FILE_NAME)).await?;
+
+    // Indexes from before the version was recorded don't have one
+    match tokio::fs::copy(
+        index_path.join(SCHEMA_VERSION_FILE_NAME),
+        out_dir.join(SCHEMA_VERSION_FILE_NAME),
+    )
+    .await
+    {
+        Ok(_) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    for file_name in segment_files(&meta)? {
+        match tokio::fs::copy(index_path.join(&file_name), out_dir.join(&file_name)).await {
+            Ok(_) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                bail!(
+                    "Index segment {} was removed during the copy, try again",
+                    file_name
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    tokio::fs::write(out_dir.join(INDEX_META_FILE_NAME), meta).await?;
+    Ok(())
+}
+
+/// Write a consistent snapshot of the db and full-text index to
+/// `out_dir`. The db is copied using `VACUUM INTO` so it's safe to run
+/// while other connections are writing to it. Returns the path to the
+/// backed up db.
+pub async fn backup(db: &Connection, index_path: &str, out_dir: &str) -> Result<PathBuf> {
+    let out_dir = Path::new(out_dir);
+    let db_backup_path = out_dir.join(BACKUP_DB_FILE_NAME);
+    let index_backup_path = out_dir.join(BACKUP_INDEX_DIR_NAME);
+    if db_backup_path.exists() || index_backup_path.exists() {
+        bail!("Backup already exists in {}", out_dir.display());
+    }
+    tokio::fs::create_dir_all(out_dir).await?;
+
+    let db_backup_path_str = db_backup_path.to_string_lossy().to_string();
+    db.call(move |conn| {
+        conn.execute("VACUUM INTO ?", [db_backup_path_str])?;
+        Ok(())
+    })
+    .await?;
+
+    copy_index(Path::new(index_path), &index_backup_path).await?;
+
+    Ok(db_backup_path)
+}
+
+/// Check that `from_dir` contains a usable snapshot
+fn validate_backup(from_dir: &Path) -> Result<()> {
+    let db_path = from_dir.join(BACKUP_DB_FILE_NAME);
+    if !db_path.is_file() {
+        bail!("Backup is missing the db: {}", db_path.display());
+    }
+    let db = rusqlite::Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let integrity: String = db.query_row("PRAGMA integrity_check", [], |r| r.get(0))?;
+    if integrity != "ok" {
+        bail!("Backup db failed integrity check: {}", integrity);
+    }
+
+    let index_path = from_dir.join(BACKUP_INDEX_DIR_NAME);
+    tantivy::Index::open_in_dir(&index_path)
+        .map_err(|e| anyhow!("Backup index is invalid {}: {}", index_path.display(), e))?;
+
+    Ok(())
+}
+
+/// Restore a snapshot created by `backup` replacing the db in
+/// `vec_db_path` and the full-text index in `index_path`. Refuses to
+/// replace existing data unless `force` is set. The server should not
+/// be running during a restore.
+///
+/// Everything is copied next to the target first and then renamed
+/// into place so a failed restore never leaves a partial db or index.
+pub async fn restore(
+    from_dir: &str,
+    vec_db_path: &str,
+    index_path: &str,
+    force: bool,
+) -> Result<()> {
+    let from_dir = Path::new(from_dir);
+    let db_path = Path::new(vec_db_path).join(BACKUP_DB_FILE_NAME);
+    let index_path = Path::new(index_path);
+
+    let from_dir_inner = from_dir.to_path_buf();
+    tokio::task::spawn_blocking(move || validate_backup(&from_dir_inner)).await??;
+
+    let index_exists = index_path.join(INDEX_META_FILE_NAME).exists();
+    if !force && (db_path.exists() || index_exists) {
+        bail!("Refusing to overwrite existing db or index without --force");
+    }
+
+    // Stage the db and index next to the targets so the rename is
+    // on the same filesystem
+    tokio::fs::create_dir_all(vec_db_path).await?;
+    let db_tmp_path = db_path.with_extension("db.restore");
+    tokio::fs::copy(from_dir.join(BACKUP_DB_FILE_NAME), &db_tmp_path).await?;
+
+    let index_tmp_path = index_path.with_extension("restore");
+    if index_tmp_path.exists() {
+        tokio::fs::remove_dir_all(&index_tmp_path).await?;
+    }
+    copy_index(&from_dir.join(BACKUP_INDEX_DIR_NAME), &index_tmp_path).await?;
+
+    // Swap the db into place. Journal files belong to the old db so
+    // they need to go too.
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let journal_path = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+        if journal_path.exists() {
+            tokio::fs::remove_file(journal_path).await?;
+        }
+    }
+    tokio::fs::rename(&db_tmp_path, &db_path).await?;
+
+    // Swap the index into place
+    let index_old_path = index_path.with_extension("old");
+    if index_path.exists() {
+        tokio::fs::rename(index_path, &index_old_path).await?;
+    }
+    tokio::fs::rename(&index_tmp_path, index_path).await?;
+    if index_old_path.exists() {
+        tokio::fs::remove_dir_all(&index_old_path).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::db::async_db;
+    use crate::core::testing::TestNotes;
+    use crate::search::index_is_outdated;
+    use tempfile::TempDir;
+
+    async fn setup(dir: &TempDir) -> (Connection, String, String) {
+        let notes = TestNotes::new(dir.path()).await;
+        notes.write_note(
+            "test.org",
+            ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: this is a test\n",
+        );
+        notes.index(None).await.unwrap();
+        (notes.db, notes.db_path, notes.index_path)
+    }
+
+    async fn note_titles(db: &Connection) -> Vec<String> {
+        db.call(|conn| {
+            let mut stmt = conn.prepare("SELECT title FROM note_meta ORDER BY id")?;
+            let titles = stmt
+                .query_map([], |r| r.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(titles)
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_backup() {
+        let dir = TempDir::new().unwrap();
+        let (db, _db_path, index_path) = setup(&dir).await;
+
+        let out_dir = dir.path().join("backup");
+        let out_dir = out_dir.to_str().unwrap();
+        let db_backup_path = backup(&db, &index_path, out_dir).await.unwrap();
+
+        // The backup is a valid db with the same data
+        let backup_db = rusqlite::Connection::open(&db_backup_path).unwrap();
+        let integrity: String = backup_db
+            .query_row("PRAGMA integrity_check", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(integrity, "ok");
+        let title: String = backup_db
+            .query_row(
+                "SELECT title FROM note_meta WHERE id = 'test-note-id'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(title, "this is a test");
+
+        // The index can be opened and has the same documents
+        let backup_index =
+            tantivy::Index::open_in_dir(Path::new(out_dir).join(BACKUP_INDEX_DIR_NAME)).unwrap();
+        let num_docs = backup_index.reader().unwrap().searcher().num_docs();
+        assert_eq!(num_docs, 1);
+        let backup_index_path = Path::new(out_dir).join(BACKUP_INDEX_DIR_NAME);
+        assert!(!index_is_outdated(backup_index_path.to_str().unwrap()));
+
+        // Backups are never overwritten
+        assert!(backup(&db, &index_path, out_dir).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backup_only_copies_listed_segments() {
+        let dir = TempDir::new().unwrap();
+        let (_db, _db_path, index_path) = setup(&dir).await;
+        let index_path = Path::new(&index_path);
+
+        // Files of segments that aren't in the meta are left behind
+        std::fs::write(index_path.join("stale.idx"), "stale").unwrap();
+        let out_dir = dir.path().join("index-copy");
+        copy_index(index_path, &out_dir).await.unwrap();
+        assert!(!out_dir.join("stale.idx").exists());
+        let meta = std::fs::read(index_path.join(INDEX_META_FILE_NAME)).unwrap();
+        let files = segment_files(&meta).unwrap();
+        assert_eq!(files.len(), SEGMENT_FILE_EXTENSIONS.len());
+        for file_name in &files {
+            assert!(out_dir.join(file_name).is_file(), "{}", file_name);
+        }
+
+        // A listed segment that was garbage collected fails the copy
+        std::fs::remove_file(index_path.join(&files[0])).unwrap();
+        let out_dir = dir.path().join("index-copy-missing");
+        let err = copy_index(index_path, &out_dir).await.unwrap_err();
+        assert!(err.to_string().contains(&files[0]), "{}", err);
+        assert!(!out_dir.join(INDEX_META_FILE_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn test_restore() {
+        let dir = TempDir::new().unwrap();
+        let (db, db_path, index_path) = setup(&dir).await;
+
+        let out_dir = dir.path().join("backup");
+        let out_dir = out_dir.to_str().unwrap();
+        backup(&db, &index_path, out_dir).await.unwrap();
+        let backed_up_titles = note_titles(&db).await;
+
+        // Change the live db after the backup was taken
+        db.call(|conn| {
+            conn.execute("UPDATE note_meta SET title = 'changed'", [])?;
+            conn.execute(
+                "INSERT INTO note_meta (id, type, title) VALUES ('new-note-id', 'note', 'new')",
+                [],
+            )?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_ne!(note_titles(&db).await, backed_up_titles);
+        db.close().await.unwrap();
+
+        // Existing data is not overwritten unless forced
+        let err = restore(out_dir, &db_path, &index_path, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("--force"));
+
+        restore(out_dir, &db_path, &index_path, true).await.unwrap();
+
+        let db = async_db(&db_path).await.unwrap();
+        assert_eq!(note_titles(&db).await, backed_up_titles);
+        let num_docs = tantivy::Index::open_in_dir(&index_path)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs();
+        assert_eq!(num_docs, 1);
+        assert!(!index_is_outdated(&index_path));
+    }
+
+    #[tokio::test]
+    async fn test_restore_invalid_backup() {
+        let dir = TempDir::new().unwrap();
+        let (db, db_path, index_path) = setup(&dir).await;
+        db.close().await.unwrap();
+
+        let out_dir = dir.path().join("backup");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        std::fs::write(out_dir.join(BACKUP_DB_FILE_NAME), "not a db").unwrap();
+
+        let result = restore(out_dir.to_str().unwrap(), &db_path, &index_path, true).await;
+        assert!(result.is_err());
+
+        // The live db is untouched
+        let db = async_db(&db_path).await.unwrap();
+        assert_eq!(note_titles(&db).await, vec!["this is a test".to_string()]);
+    }
+}
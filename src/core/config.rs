@@ -1,4 +1,229 @@
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// API key used when `OPENAI_API_KEY` isn't set. Local LLM servers
+/// ignore the key but OpenAI will reject it.
+pub(crate) const PLACEHOLDER_OPENAI_API_KEY: &str = "thiswontworkforopenai";
+
+/// Default maximum size of a note attachment (10 MiB)
+pub const DEFAULT_ATTACHMENTS_MAX_BYTES: usize = 10 * 1024 * 1024;
+
+/// MIME types of note attachments allowed by default
+pub const DEFAULT_ATTACHMENTS_ALLOWED_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+];
+
+/// Default number of times a completion request is retried after a
+/// rate limit or server error
+pub const DEFAULT_COMPLETION_MAX_RETRIES: usize = 3;
+
+/// Default delay before retrying a completion request, doubled for
+/// each retry after the first
+pub const DEFAULT_COMPLETION_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Maximum number of tool calls that run at once when the model
+/// requests several in the same turn
+pub const DEFAULT_MAX_CONCURRENT_TOOLS: usize = 4;
+
+/// Maximum time a tool call can take before the model is told it
+/// timed out
+pub const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of retries in a chat turn, counting completion
+/// retries and failed tool calls, before the turn fails
+pub const DEFAULT_RETRY_BUDGET: usize = 5;
+
+/// Default relevance multiplier for note search matches in the title
+pub const DEFAULT_TITLE_BOOST: f32 = 3.0;
+
+/// Default relevance multiplier for note search matches in the tags
+pub const DEFAULT_TAGS_BOOST: f32 = 2.0;
+
+/// Default relevance multiplier for note search matches in the body
+pub const DEFAULT_BODY_BOOST: f32 = 1.0;
+
+/// How much reasoning models do before responding. Higher effort
+/// gives better answers to hard problems but is slower and uses more
+/// tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl FromStr for ReasoningEffort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            other => Err(anyhow!("Unknown reasoning effort: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for ReasoningEffort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+/// An OpenAI compatible API and the model to send completion
+/// requests to
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Provider {
+    pub api_hostname: String,
+    pub api_key: String,
+    pub model: String,
+}
+
+impl Provider {
+    pub fn new(api_hostname: &str, api_key: &str, model: &str) -> Self {
+        Self {
+            api_hostname: api_hostname.to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+        }
+    }
+}
+
+/// Which part of a search result's body is used for its snippet
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnippetStrategy {
+    /// The passage of the body with the most matches
+    #[default]
+    BestPassage,
+    /// The start of the body with any matches in it highlighted
+    Leading,
+}
+
+impl FromStr for SnippetStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "best_passage" => Ok(Self::BestPassage),
+            "leading" => Ok(Self::Leading),
+            other => Err(anyhow!("Unknown snippet strategy: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for SnippetStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BestPassage => write!(f, "best_passage"),
+            Self::Leading => write!(f, "leading"),
+        }
+    }
+}
+
+/// How full-text and similarity search results are combined
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// Full-text results followed by similar notes when similarity is
+    /// included
+    #[default]
+    FullText,
+    /// Full-text and similarity results ranked together using
+    /// Reciprocal Rank Fusion
+    Hybrid,
+}
+
+impl FromStr for SearchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "full_text" => Ok(Self::FullText),
+            "hybrid" => Ok(Self::Hybrid),
+            other => Err(anyhow!("Unknown search mode: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for SearchMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FullText => write!(f, "full_text"),
+            Self::Hybrid => write!(f, "hybrid"),
+        }
+    }
+}
+
+/// How IDs are generated for new notes
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum NoteIdScheme {
+    /// Random UUIDv4 e.g. `0b3c6c5e-5d7a-4a36-9c39-0e6f5a8e2f11`
+    #[default]
+    Uuid,
+    /// Creation time in UTC to the millisecond e.g. `20250101T093000123`
+    Timestamp,
+    /// Prefix followed by an incrementing counter e.g. `note-42`
+    Prefix(String),
+}
+
+impl FromStr for NoteIdScheme {
+    type Err = anyhow::Error;
+
+    /// Parses `uuid`, `timestamp`, or `prefix:<prefix>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "uuid" => Ok(Self::Uuid),
+            "timestamp" => Ok(Self::Timestamp),
+            other => match other.strip_prefix("prefix:") {
+                Some(prefix) if !prefix.is_empty() => Ok(Self::Prefix(prefix.to_string())),
+                _ => Err(anyhow!("Unknown note ID scheme: {}", other)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for NoteIdScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Uuid => write!(f, "uuid"),
+            Self::Timestamp => write!(f, "timestamp"),
+            Self::Prefix(prefix) => write!(f, "prefix:{}", prefix),
+        }
+    }
+}
+
+/// A named assistant persona that can be selected for a chat
+#[derive(Clone, Debug, Deserialize)]
+pub struct Persona {
+    /// System message used when starting a chat with this persona
+    pub system_message: String,
+    /// Names of the tools available to this persona. All tools are
+    /// available when not set.
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    /// Add the tasks due today to the system message of new chats.
+    /// Uses the server setting when not set.
+    #[serde(default)]
+    pub include_tasks: Option<bool>,
+}
 
 #[derive(Clone, Debug)]
 pub struct AppConfig {
@@ -17,6 +242,120 @@ pub struct AppConfig {
     pub openai_api_hostname: String,
     pub openai_api_key: String,
     pub system_message: String,
+    /// Number of times a request to the LLM is retried after a rate
+    /// limit or server error
+    pub openai_max_retries: usize,
+    /// Delay in milliseconds before retrying a request to the LLM,
+    /// doubled for each retry after the first
+    pub openai_retry_base_delay_ms: u64,
+    /// Providers to fall back to in order when the LLM is unavailable
+    pub openai_fallbacks: Vec<Provider>,
+    /// Reasoning effort for chats with reasoning models
+    pub openai_reasoning_effort: Option<ReasoningEffort>,
+    /// Number of note search results returned when a request doesn't
+    /// specify a limit
+    pub search_default_limit: usize,
+    /// Upper bound on the number of note search results, larger
+    /// requested limits are clamped to this
+    pub search_max_limit: usize,
+    /// Relevance multiplier for note search matches in the title
+    pub title_boost: f32,
+    /// Relevance multiplier for note search matches in the tags
+    pub tags_boost: f32,
+    /// Relevance multiplier for note search matches in the body
+    pub body_boost: f32,
+    /// Part of the body used for note search snippets when a request
+    /// doesn't specify one
+    pub snippet_strategy: SnippetStrategy,
+    /// How note search combines full-text and similarity results
+    /// when a request doesn't specify a mode
+    pub search_mode: SearchMode,
+    /// Endpoint of a reranking model used to reorder the top hybrid
+    /// note search results. Reranking is skipped when not set.
+    pub rerank_url: Option<String>,
+    /// User agent sent with outbound HTTP requests
+    pub http_user_agent: String,
+    /// Proxy URL for outbound HTTP requests
+    pub http_proxy: Option<String>,
+    /// Maximum number of tokens allowed in a chat message
+    pub chat_max_message_tokens: usize,
+    /// Maximum number of tool calls run at once in a chat turn
+    pub chat_max_concurrent_tools: usize,
+    /// Maximum seconds a tool call can take before the model is told
+    /// it timed out
+    pub chat_tool_timeout_secs: u64,
+    /// Combine streamed chat deltas that arrive within this many
+    /// milliseconds into one event. Every delta is its own event when
+    /// not set.
+    pub chat_stream_coalesce_ms: Option<u64>,
+    /// Maximum number of retries in a chat turn, counting completion
+    /// retries and failed tool calls, before the turn fails
+    pub chat_retry_budget: usize,
+    /// Add the tasks due today to the system message of new chat
+    /// sessions. Off by default since it delays the first message.
+    pub chat_include_tasks: bool,
+    /// Store the reasoning of reasoning models with chat messages for
+    /// later review. It's never sent back to the model.
+    pub chat_store_reasoning: bool,
+    /// Maximum number of tokens of a chat session sent to the LLM,
+    /// older messages are left out to stay within it
+    pub chat_max_context_tokens: Option<usize>,
+    /// Maximum number of chat sessions stored. The least recently
+    /// used sessions that aren't pinned are deleted to stay within it.
+    pub chat_max_sessions: Option<usize>,
+    /// Maximum number of push notifications sent at once
+    pub push_max_concurrency: usize,
+    /// Maximum size in bytes of a file attached to a note
+    pub attachments_max_bytes: usize,
+    /// MIME types of files that can be attached to a note
+    pub attachments_allowed_types: Vec<String>,
+    /// How IDs are generated for new notes
+    pub note_id_scheme: NoteIdScheme,
+    /// Assistant personas by name
+    pub personas: HashMap<String, Persona>,
+    /// Names of the tools the assistant is allowed to use. Takes
+    /// precedence over tools selected by a persona. All tools are
+    /// enabled when not set.
+    pub enabled_tools: Option<Vec<String>>,
+    /// Append every LLM request and response to this JSON lines file.
+    /// Disabled when not set.
+    pub llm_log_path: Option<String>,
+    /// Timezone used to decide what day it is for date based queries
+    /// like tasks due today
+    pub timezone: Tz,
+}
+
+impl AppConfig {
+    /// Returns the search limit to use for a request, falling back to
+    /// the default and clamping to the max.
+    pub fn search_limit(&self, requested: Option<usize>) -> usize {
+        requested
+            .unwrap_or(self.search_default_limit)
+            .min(self.search_max_limit)
+    }
+
+    /// Returns whether the tool with the given function name is
+    /// enabled server-wide.
+    pub fn tool_enabled(&self, name: &str) -> bool {
+        self.enabled_tools
+            .as_ref()
+            .is_none_or(|names| names.iter().any(|n| n == name))
+    }
+}
+
+/// A search field boost, which has to be a non-negative number
+fn parse_boost(value: &str) -> anyhow::Result<f32> {
+    let boost: f32 = value.trim().parse()?;
+    if boost.is_nan() || boost < 0.0 {
+        return Err(anyhow!("Boost must be a non-negative number: {}", value));
+    }
+    Ok(boost)
+}
+
+fn boost_from_env(name: &str, default: f32) -> f32 {
+    env::var(name)
+        .map(|v| parse_boost(&v).unwrap_or_else(|e| panic!("Invalid env var {}: {}", name, e)))
+        .unwrap_or(default)
 }
 
 impl Default for AppConfig {
@@ -40,15 +379,123 @@ impl Default for AppConfig {
         let openai_api_hostname =
             env::var("HQ_LOCAL_LLM_HOST").unwrap_or_else(|_| "https://api.openai.com".to_string());
         let openai_api_key =
-            env::var("OPENAI_API_KEY").unwrap_or_else(|_| "thiswontworkforopenai".to_string());
+            env::var("OPENAI_API_KEY").unwrap_or_else(|_| PLACEHOLDER_OPENAI_API_KEY.to_string());
         let openai_model =
             env::var("HQ_LOCAL_LLM_MODEL").unwrap_or_else(|_| "gpt-4.1-mini".to_string());
         let system_message = env::var("HQ_SYSTEM_MESSAGE")
             .unwrap_or_else(|_| "You are a helpful assistant.".to_string());
+        let openai_max_retries = env::var("HQ_LLM_MAX_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_COMPLETION_MAX_RETRIES);
+        let openai_retry_base_delay_ms = env::var("HQ_LLM_RETRY_BASE_DELAY_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_COMPLETION_RETRY_BASE_DELAY.as_millis() as u64);
+        let openai_fallbacks = env::var("HQ_LLM_FALLBACKS")
+            .map(|v| serde_json::from_str(&v).expect("Invalid JSON in env var HQ_LLM_FALLBACKS"))
+            .unwrap_or_default();
+        let openai_reasoning_effort = env::var("HQ_LLM_REASONING_EFFORT")
+            .map(|v| v.parse().expect("Invalid env var HQ_LLM_REASONING_EFFORT"))
+            .ok();
         let google_search_api_key = std::env::var("HQ_GOOGLE_SEARCH_API_KEY")
             .expect("Missing env var HQ_GOOGLE_SEARCH_API_KEY");
         let google_search_cx_id = std::env::var("HQ_GOOGLE_SEARCH_CX_ID")
             .expect("Missing env var HQ_GOOGLE_SEARCH_CX_ID");
+        let search_default_limit = env::var("HQ_SEARCH_DEFAULT_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(20);
+        let search_max_limit = env::var("HQ_SEARCH_MAX_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(100);
+        let title_boost = boost_from_env("HQ_SEARCH_TITLE_BOOST", DEFAULT_TITLE_BOOST);
+        let tags_boost = boost_from_env("HQ_SEARCH_TAGS_BOOST", DEFAULT_TAGS_BOOST);
+        let body_boost = boost_from_env("HQ_SEARCH_BODY_BOOST", DEFAULT_BODY_BOOST);
+        let snippet_strategy = env::var("HQ_SEARCH_SNIPPET_STRATEGY")
+            .map(|v| {
+                v.parse()
+                    .expect("Invalid env var HQ_SEARCH_SNIPPET_STRATEGY")
+            })
+            .unwrap_or_default();
+        let search_mode = env::var("HQ_SEARCH_MODE")
+            .map(|v| v.parse().expect("Invalid env var HQ_SEARCH_MODE"))
+            .unwrap_or_default();
+        let http_user_agent = env::var("HQ_HTTP_USER_AGENT")
+            .unwrap_or_else(|_| crate::core::http::DEFAULT_USER_AGENT.to_string());
+        let rerank_url = env::var("HQ_RERANK_URL").ok();
+        let http_proxy = env::var("HQ_HTTP_PROXY").ok();
+        let chat_max_message_tokens = env::var("HQ_CHAT_MAX_MESSAGE_TOKENS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(8000);
+        let chat_max_concurrent_tools = env::var("HQ_CHAT_MAX_CONCURRENT_TOOLS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_TOOLS);
+        let chat_tool_timeout_secs = env::var("HQ_CHAT_TOOL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TOOL_TIMEOUT.as_secs());
+        let chat_stream_coalesce_ms = env::var("HQ_CHAT_STREAM_COALESCE_MS")
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let chat_retry_budget = env::var("HQ_CHAT_RETRY_BUDGET")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_RETRY_BUDGET);
+        let chat_include_tasks = env::var("HQ_CHAT_INCLUDE_TASKS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(false);
+        let chat_store_reasoning = env::var("HQ_CHAT_STORE_REASONING")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(false);
+        let chat_max_context_tokens = env::var("HQ_CHAT_MAX_CONTEXT_TOKENS")
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let chat_max_sessions = env::var("HQ_CHAT_MAX_SESSIONS")
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let push_max_concurrency = env::var("HQ_PUSH_MAX_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+        let attachments_max_bytes = env::var("HQ_ATTACHMENTS_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_ATTACHMENTS_MAX_BYTES);
+        let attachments_allowed_types = env::var("HQ_ATTACHMENTS_ALLOWED_TYPES")
+            .map(|v| {
+                v.split(',')
+                    .map(|mime| mime.trim().to_string())
+                    .filter(|mime| !mime.is_empty())
+                    .collect()
+            })
+            .unwrap_or_else(|_| {
+                DEFAULT_ATTACHMENTS_ALLOWED_TYPES
+                    .iter()
+                    .map(|mime| mime.to_string())
+                    .collect()
+            });
+        let note_id_scheme = env::var("HQ_NOTE_ID_SCHEME")
+            .map(|v| v.parse().expect("Invalid env var HQ_NOTE_ID_SCHEME"))
+            .unwrap_or_default();
+        let personas = env::var("HQ_PERSONAS")
+            .map(|v| serde_json::from_str(&v).expect("Invalid JSON in env var HQ_PERSONAS"))
+            .unwrap_or_default();
+        let enabled_tools = env::var("HQ_ENABLED_TOOLS").ok().map(|v| {
+            v.split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect()
+        });
+        let llm_log_path = env::var("HQ_LLM_LOG_PATH").ok();
+        let timezone = env::var("HQ_TIMEZONE")
+            .map(|v| v.parse().expect("Invalid env var HQ_TIMEZONE"))
+            .unwrap_or(Tz::UTC);
 
         Self {
             notes_path: notes_path.clone(),
@@ -66,6 +513,168 @@ impl Default for AppConfig {
             openai_api_key,
             openai_model,
             system_message,
+            openai_max_retries,
+            openai_retry_base_delay_ms,
+            openai_fallbacks,
+            openai_reasoning_effort,
+            search_default_limit,
+            search_max_limit,
+            title_boost,
+            tags_boost,
+            body_boost,
+            snippet_strategy,
+            search_mode,
+            rerank_url,
+            http_user_agent,
+            http_proxy,
+            chat_max_message_tokens,
+            chat_max_concurrent_tools,
+            chat_tool_timeout_secs,
+            chat_stream_coalesce_ms,
+            chat_retry_budget,
+            chat_include_tasks,
+            chat_store_reasoning,
+            chat_max_context_tokens,
+            chat_max_sessions,
+            push_max_concurrency,
+            attachments_max_bytes,
+            attachments_allowed_types,
+            note_id_scheme,
+            personas,
+            enabled_tools,
+            llm_log_path,
+            timezone,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn test_config() -> AppConfig {
+        AppConfig {
+            notes_path: String::from("notes"),
+            index_path: String::from("index"),
+            vec_db_path: String::from("db"),
+            storage_path: String::from("."),
+            deploy_key_path: String::from("test_deploy_key_path"),
+            vapid_key_path: String::from("test_vapid_key_path"),
+            note_search_api_url: String::from("http://localhost:2222"),
+            gmail_api_client_id: String::from("test_client_id"),
+            gmail_api_client_secret: String::from("test_client_secret"),
+            google_search_api_key: String::from("test_google_search_key"),
+            google_search_cx_id: String::from("test_cx_id"),
+            openai_model: String::from("gpt-4o"),
+            openai_api_hostname: String::from("https://api.openai.com"),
+            openai_api_key: String::from("test-api-key"),
+            system_message: String::from("You are a helpful assistant."),
+            openai_max_retries: 3,
+            openai_retry_base_delay_ms: 500,
+            openai_fallbacks: Vec::new(),
+            openai_reasoning_effort: None,
+            search_default_limit: 20,
+            search_max_limit: 100,
+            title_boost: 3.0,
+            tags_boost: 2.0,
+            body_boost: 1.0,
+            snippet_strategy: SnippetStrategy::BestPassage,
+            search_mode: SearchMode::FullText,
+            rerank_url: None,
+            http_user_agent: String::from("hq-test"),
+            http_proxy: None,
+            chat_max_message_tokens: 8000,
+            chat_max_concurrent_tools: 4,
+            chat_tool_timeout_secs: 30,
+            chat_stream_coalesce_ms: None,
+            chat_retry_budget: 5,
+            chat_include_tasks: false,
+            chat_store_reasoning: false,
+            chat_max_context_tokens: None,
+            chat_max_sessions: None,
+            push_max_concurrency: 10,
+            attachments_max_bytes: DEFAULT_ATTACHMENTS_MAX_BYTES,
+            attachments_allowed_types: vec![String::from("image/png")],
+            note_id_scheme: NoteIdScheme::Uuid,
+            personas: HashMap::new(),
+            enabled_tools: None,
+            llm_log_path: None,
+            timezone: Tz::UTC,
         }
     }
+
+    #[test]
+    fn it_parses_personas() {
+        let personas: HashMap<String, Persona> = serde_json::from_str(
+            r#"{
+                "coding": {"system_message": "You are a coding assistant.", "tools": ["web_search"]},
+                "journal": {"system_message": "You are a journaling companion."}
+            }"#,
+        )
+        .unwrap();
+
+        let coding = &personas["coding"];
+        assert_eq!(coding.system_message, "You are a coding assistant.");
+        assert_eq!(coding.tools, Some(vec![String::from("web_search")]));
+        assert!(personas["journal"].tools.is_none());
+    }
+
+    #[test]
+    fn it_enables_all_tools_by_default() {
+        let config = test_config();
+        assert!(config.tool_enabled("web_search"));
+        assert!(config.tool_enabled("website_view"));
+    }
+
+    #[test]
+    fn it_only_enables_allowed_tools() {
+        let config = AppConfig {
+            enabled_tools: Some(vec![String::from("web_search")]),
+            ..test_config()
+        };
+        assert!(config.tool_enabled("web_search"));
+        assert!(!config.tool_enabled("website_view"));
+    }
+
+    #[test]
+    fn it_parses_note_id_schemes() {
+        assert_eq!("uuid".parse::<NoteIdScheme>().unwrap(), NoteIdScheme::Uuid);
+        assert_eq!(
+            "timestamp".parse::<NoteIdScheme>().unwrap(),
+            NoteIdScheme::Timestamp
+        );
+        assert_eq!(
+            "prefix:note-".parse::<NoteIdScheme>().unwrap(),
+            NoteIdScheme::Prefix(String::from("note-"))
+        );
+        assert_eq!(
+            NoteIdScheme::Prefix(String::from("note-")).to_string(),
+            "prefix:note-"
+        );
+        assert!("prefix:".parse::<NoteIdScheme>().is_err());
+        assert!("sequential".parse::<NoteIdScheme>().is_err());
+    }
+
+    #[test]
+    fn it_uses_default_search_limit() {
+        let config = test_config();
+        assert_eq!(config.search_limit(None), 20);
+    }
+
+    #[test]
+    fn it_rejects_invalid_boosts() {
+        assert_eq!(parse_boost("2.5").unwrap(), 2.5);
+        assert_eq!(parse_boost("0").unwrap(), 0.0);
+        assert!(parse_boost("NaN").is_err());
+        assert!(parse_boost("-1").is_err());
+        assert!(parse_boost("high").is_err());
+    }
+
+    #[test]
+    fn it_clamps_search_limit_to_max() {
+        let config = test_config();
+        assert_eq!(config.search_limit(Some(5)), 5);
+        assert_eq!(config.search_limit(Some(100)), 100);
+        assert_eq!(config.search_limit(Some(10_000)), 100);
+    }
 }
@@ -32,7 +32,13 @@ pub fn initialize_db(db: &rusqlite::Connection) -> Result<()> {
     -- Task closed date yyyy-mm-dd
     closed TEXT NULLABLE,
     -- Meeting date yyyy-mm-dd
-    date TEXT NULLABLE
+    date TEXT NULLABLE,
+    -- Note created date yyyy-mm-dd from its #+DATE keyword
+    created TEXT NULLABLE,
+    -- JSON array of file paths linked from the note
+    attachments TEXT NULLABLE,
+    -- Timestamp of when the note was last indexed (ISO 8601 format)
+    last_indexed_at TEXT NULLABLE
 );",
         [],
     );
@@ -55,6 +61,23 @@ embedding float[384]
         Err(e) => println!("Create note vec table failed: {}", e),
     };
 
+    // Create table for caching embeddings so unchanged text isn't
+    // embedded again when re-indexing
+    let create_embedding_cache_table = db.execute(
+        "CREATE TABLE IF NOT EXISTS embedding_cache (
+    -- SHA-256 hash of the embedding model and the embedded text
+    hash TEXT PRIMARY KEY,
+    -- Embedding vector of the text
+    embedding BLOB NOT NULL
+);",
+        [],
+    );
+
+    match create_embedding_cache_table {
+        Ok(_) => (),
+        Err(e) => println!("Create embedding cache table failed: {}", e),
+    };
+
     // Create vector virtual table for similarity search
     let create_auth_table = db.execute(
         "CREATE TABLE IF NOT EXISTS auth (
@@ -97,7 +120,15 @@ embedding float[384]
     -- Session ID is a UUID generated by the client
     session_id TEXT,
     -- JSON encoded message data
-    data TEXT NOT NULL
+    data TEXT NOT NULL,
+    -- Name of the tool for tool call requests and responses
+    tool_name TEXT NULLABLE,
+    -- JSON encoded arguments of the tool call
+    tool_args TEXT NULLABLE,
+    -- JSON encoded structured output of the tool call
+    tool_output TEXT NULLABLE,
+    -- Reasoning of the model for assistant messages
+    reasoning TEXT NULLABLE
 );",
         [],
     );
@@ -168,7 +199,11 @@ embedding float[384]
     -- Timestamp when the event was received (ISO 8601 format)
     timestamp TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
     -- Numeric value for the event (e.g., increment amount)
-    value INTEGER NOT NULL
+    value INTEGER NOT NULL,
+    -- Optional label for the event (e.g., the name of a tool)
+    label TEXT NULLABLE,
+    -- Optional client provided key used to dedupe retried events
+    idempotency_key TEXT NULLABLE
 );",
         [],
     );
@@ -189,6 +224,17 @@ embedding float[384]
         Err(e) => println!("Create metric event index failed: {}", e),
     };
 
+    // Create index on metric_event for deduping by idempotency key
+    let create_metric_event_idempotency_key_index = db.execute(
+        "CREATE INDEX IF NOT EXISTS metric_event_idempotency_key_idx ON metric_event(idempotency_key);",
+        [],
+    );
+
+    match create_metric_event_idempotency_key_index {
+        Ok(_) => (),
+        Err(e) => println!("Create metric event idempotency key index failed: {}", e),
+    };
+
     Ok(())
 }
 
@@ -337,6 +383,104 @@ COMMIT;",
         Err(e) => println!("Migrate chat message table failed: {}", e),
     };
 
+    // 2026-10-17 Add last indexed timestamp column to note_meta
+    let add_note_meta_last_indexed_at =
+        db.execute_batch(r"ALTER TABLE note_meta ADD COLUMN last_indexed_at TEXT NULLABLE;");
+
+    match add_note_meta_last_indexed_at {
+        Ok(_) => (),
+        Err(e) => println!("Add last indexed column to note meta table failed: {}", e),
+    };
+
+    // 2026-10-17 Add tool name and arguments columns to chat_message
+    let add_chat_message_tool_columns = db.execute_batch(
+        r"ALTER TABLE chat_message ADD COLUMN tool_name TEXT NULLABLE;
+        ALTER TABLE chat_message ADD COLUMN tool_args TEXT NULLABLE;",
+    );
+
+    match add_chat_message_tool_columns {
+        Ok(_) => (),
+        Err(e) => println!(
+            "Add tool name and args columns to chat message table failed: {}",
+            e
+        ),
+    };
+
+    // 2026-10-17 Add label column to metric_event
+    let add_metric_event_label =
+        db.execute_batch(r"ALTER TABLE metric_event ADD COLUMN label TEXT NULLABLE;");
+
+    match add_metric_event_label {
+        Ok(_) => (),
+        Err(e) => println!("Add label column to metric event table failed: {}", e),
+    };
+
+    // 2026-10-17 Add idempotency key column and index to metric_event
+    let add_metric_event_idempotency_key = db.execute_batch(
+        r"ALTER TABLE metric_event ADD COLUMN idempotency_key TEXT NULLABLE;
+        CREATE INDEX IF NOT EXISTS metric_event_idempotency_key_idx ON metric_event(idempotency_key);",
+    );
+
+    match add_metric_event_idempotency_key {
+        Ok(_) => (),
+        Err(e) => println!(
+            "Add idempotency key column to metric event table failed: {}",
+            e
+        ),
+    };
+
+    // 2026-10-17 Add attachments column to note_meta
+    let add_note_meta_attachments =
+        db.execute_batch(r"ALTER TABLE note_meta ADD COLUMN attachments TEXT NULLABLE;");
+
+    match add_note_meta_attachments {
+        Ok(_) => (),
+        Err(e) => println!("Add attachments column to note meta table failed: {}", e),
+    };
+
+    // 2026-10-18 Add structured tool output column to chat_message
+    let add_chat_message_tool_output =
+        db.execute_batch(r"ALTER TABLE chat_message ADD COLUMN tool_output TEXT NULLABLE;");
+
+    match add_chat_message_tool_output {
+        Ok(_) => (),
+        Err(e) => println!("Add tool output column to chat message table failed: {}", e),
+    };
+
+    // 2026-10-18 Add reasoning column to chat_message
+    let add_chat_message_reasoning =
+        db.execute_batch(r"ALTER TABLE chat_message ADD COLUMN reasoning TEXT NULLABLE;");
+
+    match add_chat_message_reasoning {
+        Ok(_) => (),
+        Err(e) => println!("Add reasoning column to chat message table failed: {}", e),
+    };
+
+    // 2026-10-18 Add created date column to note_meta
+    let add_note_meta_created =
+        db.execute_batch(r"ALTER TABLE note_meta ADD COLUMN created TEXT NULLABLE;");
+
+    match add_note_meta_created {
+        Ok(_) => (),
+        Err(e) => println!("Add created column to note meta table failed: {}", e),
+    };
+
+    // 2026-10-18 Add embedding cache table
+    let create_embedding_cache_table = db.execute(
+        "CREATE TABLE IF NOT EXISTS embedding_cache (
+    -- SHA-256 hash of the embedding model and the embedded text
+    hash TEXT PRIMARY KEY,
+    -- Embedding vector of the text
+    embedding BLOB NOT NULL
+);",
+        [],
+    );
+
+    match create_embedding_cache_table {
+        Ok(_) => (),
+        Err(e) => println!("Create embedding cache table failed: {}", e),
+    };
+
     Ok(())
 }
 
@@ -0,0 +1,326 @@
+//! Filesystem helpers for working with the notes directory
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, anyhow, bail};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Resolve a note's file name to a path within `notes_path`. The
+/// result is canonicalized and verified to stay inside of the notes
+/// directory so a crafted file name (e.g. `../`, an absolute path, or
+/// a symlink) can't be used to read or write files elsewhere. The file
+/// itself does not need to exist yet.
+pub fn resolve_note_path(notes_path: &str, file_name: &str) -> Result<PathBuf> {
+    if file_name.is_empty() {
+        bail!("Empty note file name");
+    }
+    if Path::new(file_name).is_absolute() {
+        bail!("Note file name must be relative: {}", file_name);
+    }
+
+    let base = Path::new(notes_path)
+        .canonicalize()
+        .map_err(|e| anyhow!("Invalid notes path {}: {}", notes_path, e))?;
+    let joined = base.join(file_name);
+
+    // Canonicalize the file if it exists, otherwise canonicalize the
+    // parent directory so new files can be resolved too
+    let resolved = if joined.exists() {
+        joined.canonicalize()?
+    } else {
+        let parent = joined
+            .parent()
+            .ok_or_else(|| anyhow!("Invalid note file name: {}", file_name))?
+            .canonicalize()?;
+        let name = joined
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid note file name: {}", file_name))?;
+        parent.join(name)
+    };
+
+    if !resolved.starts_with(&base) || resolved == base {
+        bail!("Note file name escapes the notes directory: {}", file_name);
+    }
+
+    Ok(resolved)
+}
+
+/// Directory within the notes directory where note attachments are
+/// saved
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// A unique file name for an uploaded attachment that keeps the
+/// original name readable. Anything other than letters, digits, `.`,
+/// `-`, and `_` is replaced so the name is safe to use as a path and
+/// in an org link.
+pub fn attachment_file_name(original: &str) -> String {
+    let name = Path::new(original)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+    let name = if name.is_empty() { "attachment" } else { name };
+    format!("{}-{}", uuid::Uuid::new_v4(), name)
+}
+
+/// MIME type of an attachment detected from the magic bytes at the
+/// start of `data` or `None` if it isn't a type that can be detected
+pub fn sniff_attachment_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else if data.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
+
+/// MIME type of an attachment based on the extension of `file_name`
+pub fn attachment_extension_type(file_name: &str) -> Option<&'static str> {
+    let extension = Path::new(file_name)
+        .extension()?
+        .to_str()?
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "pdf" => Some("application/pdf"),
+        _ => None,
+    }
+}
+
+/// Org link to an attachment relative to the note at `note_file_name`
+/// e.g. `[[file:../attachments/image.png]]` for a note in a
+/// subdirectory
+pub fn attachment_link(note_file_name: &str, attachment_file_name: &str) -> String {
+    let depth = Path::new(note_file_name)
+        .parent()
+        .map(|parent| parent.components().count())
+        .unwrap_or(0);
+    format!(
+        "[[file:{}{}/{}]]",
+        "../".repeat(depth),
+        ATTACHMENTS_DIR,
+        attachment_file_name
+    )
+}
+
+/// File name for a new note in the root of the notes directory based
+/// on its title e.g. `weekly_planning.org` for "Weekly Planning".
+/// Runs of anything other than letters and digits become a single
+/// `_`.
+pub fn note_file_name(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    let slug = slug.trim_end_matches('_');
+    let slug = if slug.is_empty() { "note" } else { slug };
+    format!("{}.org", slug)
+}
+
+/// Locks for each note file so edits that read, change, and write a
+/// file back don't overwrite each other's changes
+#[derive(Clone, Default)]
+pub struct NoteLocks {
+    locks: Arc<Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>>,
+}
+
+impl NoteLocks {
+    /// Wait for exclusive access to the note at `path`. Other edits to
+    /// the same path wait until the returned guard is dropped. Use
+    /// the path from `resolve_note_path` so the same file always has
+    /// the same key.
+    pub async fn lock(&self, path: &Path) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            // Forget locks no one is holding or waiting on
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(path.to_path_buf()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn notes_dir() -> (TempDir, String) {
+        let dir = TempDir::new().unwrap();
+        let notes_path = dir.path().join("notes");
+        std::fs::create_dir_all(&notes_path).unwrap();
+        std::fs::write(notes_path.join("test.org"), "#+TITLE: test").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        let notes_path = notes_path.to_str().unwrap().to_string();
+        (dir, notes_path)
+    }
+
+    #[test]
+    fn it_resolves_existing_note() {
+        let (_dir, notes_path) = notes_dir();
+        let path = resolve_note_path(&notes_path, "test.org").unwrap();
+        let expected = Path::new(&notes_path)
+            .join("test.org")
+            .canonicalize()
+            .unwrap();
+        assert_eq!(path, expected);
+    }
+
+    #[test]
+    fn it_resolves_new_note() {
+        let (_dir, notes_path) = notes_dir();
+        let path = resolve_note_path(&notes_path, "new.org").unwrap();
+        assert!(path.ends_with("new.org"));
+        assert!(path.starts_with(Path::new(&notes_path).canonicalize().unwrap()));
+    }
+
+    #[test]
+    fn it_rejects_parent_traversal() {
+        let (_dir, notes_path) = notes_dir();
+        assert!(resolve_note_path(&notes_path, "../secret.txt").is_err());
+        assert!(resolve_note_path(&notes_path, "..").is_err());
+        assert!(resolve_note_path(&notes_path, "./../secret.txt").is_err());
+        assert!(resolve_note_path(&notes_path, "test.org/../../secret.txt").is_err());
+    }
+
+    #[test]
+    fn it_rejects_absolute_paths() {
+        let (dir, notes_path) = notes_dir();
+        let secret = dir.path().join("secret.txt");
+        assert!(resolve_note_path(&notes_path, secret.to_str().unwrap()).is_err());
+        assert!(resolve_note_path(&notes_path, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn it_rejects_empty_and_current_dir() {
+        let (_dir, notes_path) = notes_dir();
+        assert!(resolve_note_path(&notes_path, "").is_err());
+        assert!(resolve_note_path(&notes_path, ".").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_rejects_symlinks_outside_notes() {
+        let (dir, notes_path) = notes_dir();
+        let link = Path::new(&notes_path).join("link.org");
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), &link).unwrap();
+        assert!(resolve_note_path(&notes_path, "link.org").is_err());
+    }
+
+    #[test]
+    fn it_sanitizes_attachment_file_names() {
+        let name = attachment_file_name("../Screen Shot 1.png");
+        assert!(name.ends_with("-Screen-Shot-1.png"), "{}", name);
+        assert!(!name.contains('/'));
+
+        let name = attachment_file_name("");
+        assert!(name.ends_with("-attachment"), "{}", name);
+        assert_ne!(attachment_file_name("a.png"), attachment_file_name("a.png"));
+    }
+
+    #[test]
+    fn it_names_new_notes_after_their_title() {
+        assert_eq!(note_file_name("Weekly Planning"), "weekly_planning.org");
+        assert_eq!(
+            note_file_name("  Q3: Goals & Risks! "),
+            "q3_goals_risks.org"
+        );
+        assert_eq!(note_file_name("../../etc/passwd"), "etc_passwd.org");
+        assert_eq!(note_file_name("Café"), "café.org");
+        assert_eq!(note_file_name("???"), "note.org");
+    }
+
+    #[test]
+    fn it_detects_attachment_types() {
+        assert_eq!(
+            sniff_attachment_type(b"\x89PNG\r\n\x1a\n\0\0"),
+            Some("image/png")
+        );
+        assert_eq!(
+            sniff_attachment_type(b"\xff\xd8\xff\xe0"),
+            Some("image/jpeg")
+        );
+        assert_eq!(sniff_attachment_type(b"GIF89a"), Some("image/gif"));
+        assert_eq!(
+            sniff_attachment_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_attachment_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff_attachment_type(b"RIFF\0\0\0\0WAVE"), None);
+        assert_eq!(sniff_attachment_type(b"hello"), None);
+
+        assert_eq!(attachment_extension_type("Photo.JPG"), Some("image/jpeg"));
+        assert_eq!(
+            attachment_extension_type("doc.pdf"),
+            Some("application/pdf")
+        );
+        assert_eq!(attachment_extension_type("notes.txt"), None);
+        assert_eq!(attachment_extension_type("png"), None);
+    }
+
+    #[test]
+    fn it_links_attachments_relative_to_note() {
+        assert_eq!(
+            attachment_link("test.org", "image.png"),
+            "[[file:attachments/image.png]]"
+        );
+        assert_eq!(
+            attachment_link("journal/2025/test.org", "image.png"),
+            "[[file:../../attachments/image.png]]"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_serializes_concurrent_note_edits() {
+        let (_dir, notes_path) = notes_dir();
+        let path = resolve_note_path(&notes_path, "test.org").unwrap();
+        let locks = NoteLocks::default();
+
+        // Each append reads the file and yields before writing so
+        // without the lock both would write back the original content
+        let append = |line: &'static str| {
+            let locks = locks.clone();
+            let path = path.clone();
+            tokio::spawn(async move {
+                let _guard = locks.lock(&path).await;
+                let content = tokio::fs::read_to_string(&path).await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                tokio::fs::write(&path, format!("{}\n{}", content, line))
+                    .await
+                    .unwrap();
+            })
+        };
+        let (first, second) = tokio::join!(append("* First"), append("* Second"));
+        first.unwrap();
+        second.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("* First"));
+        assert!(content.contains("* Second"));
+    }
+}
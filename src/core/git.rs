@@ -1,3 +1,4 @@
+use anyhow::{Result, bail};
 use tokio::process::Command;
 
 /// Clone a repo if it doesn't already exist
@@ -17,8 +18,22 @@ pub async fn maybe_clone_repo(deploy_key_path: &str, url: &str, storage_path: &s
     println!("stdout: {}\nstderr: {}", stdout, stderr);
 }
 
-/// Pull and reset to origin main branch
+/// Pull and reset to origin main branch. Skipped while the repo has
+/// uncommitted changes, e.g. notes edited through the API, so that
+/// the reset doesn't discard them.
 pub async fn maybe_pull_and_reset_repo(deploy_key_path: &str, path: &str) {
+    match has_uncommitted_changes(path).await {
+        Ok(true) => {
+            tracing::warn!(
+                "Not pulling notes in {}, there are uncommitted changes",
+                path
+            );
+            return;
+        }
+        Ok(false) => {}
+        Err(e) => tracing::debug!("Failed to check for uncommitted changes: {}", e),
+    }
+
     let git_clone = Command::new("sh")
         .arg("-c")
         .arg(format!("cd {} && GIT_SSH_COMMAND='ssh -i {} -o IdentitiesOnly=yes' git fetch origin && git reset --hard origin/main", path, deploy_key_path))
@@ -31,6 +46,24 @@ pub async fn maybe_pull_and_reset_repo(deploy_key_path: &str, path: &str) {
     tracing::debug!("stdout: {}\nstderr: {}", stdout, stderr);
 }
 
+/// Returns true if the repo at `path` has modified or untracked files
+pub async fn has_uncommitted_changes(path: &str) -> Result<bool> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("status")
+        .arg("--porcelain")
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(
+            "Git status failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(!output.stdout.is_empty())
+}
+
 /// Return a list of files that have changed between the last two
 /// commits.  Run `maybe_pull_and_reset_repo` before hand if you want
 /// to get a list of files that changed on origin.
@@ -56,3 +89,108 @@ pub async fn diff_last_commit_files(deploy_key_path: &str, path: &str) -> Vec<St
 
     stdout.trim().split("\n").map(|s| s.to_string()).collect()
 }
+
+/// A commit that changed a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCommit {
+    pub sha: String,
+    pub author: String,
+    /// Author date in RFC 3339 format
+    pub date: String,
+    /// First line of the commit message
+    pub message: String,
+    /// Path of the file in the commit which differs from the current
+    /// path if the file was renamed since
+    pub file_name: String,
+}
+
+/// Returns true if `sha` looks like a full or abbreviated commit hash.
+/// Checked before passing it to git so it can't be read as an option
+/// or a revision expression.
+pub fn is_commit_sha(sha: &str) -> bool {
+    (4..=40).contains(&sha.len()) && sha.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Return the most recent commits, newest first, that changed
+/// `file_name` in the repo at `path`. Renames are followed.
+pub async fn file_history(path: &str, file_name: &str, limit: usize) -> Result<Vec<FileCommit>> {
+    log_file(path, file_name, Some(limit)).await
+}
+
+async fn log_file(path: &str, file_name: &str, limit: Option<usize>) -> Result<Vec<FileCommit>> {
+    // Fields are separated by the unit separator character since it
+    // won't appear in commit messages. Each commit is followed by the
+    // path of the file in that commit.
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(path)
+        .arg("log")
+        .arg("--follow")
+        .arg("--name-only")
+        .arg("--format=%H%x1f%an%x1f%aI%x1f%s");
+    if let Some(limit) = limit {
+        command.arg(format!("--max-count={}", limit));
+    }
+    let output = command.arg("--").arg(file_name).output().await?;
+    if !output.status.success() {
+        bail!(
+            "Git log failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut commits: Vec<FileCommit> = Vec::new();
+    for line in stdout.lines().filter(|line| !line.is_empty()) {
+        if line.contains('\x1f') {
+            let mut fields = line.split('\x1f');
+            let (Some(sha), Some(author), Some(date)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            commits.push(FileCommit {
+                sha: sha.to_string(),
+                author: author.to_string(),
+                date: date.to_string(),
+                message: fields.next().unwrap_or_default().to_string(),
+                file_name: file_name.to_string(),
+            });
+        } else if let Some(commit) = commits.last_mut() {
+            commit.file_name = line.to_string();
+        }
+    }
+    Ok(commits)
+}
+
+/// Return the content of `file_name` at commit `sha` in the repo at
+/// `path` or `None` if the commit doesn't exist or didn't change the
+/// file. The file is read from the path it had in that commit so
+/// revisions from before a rename are found.
+pub async fn file_at_revision(path: &str, sha: &str, file_name: &str) -> Result<Option<String>> {
+    if !is_commit_sha(sha) {
+        return Ok(None);
+    }
+    let history = log_file(path, file_name, None).await?;
+    let Some(commit) = history.iter().find(|commit| commit.sha.starts_with(sha)) else {
+        return Ok(None);
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("show")
+        .arg(format!("{}:{}", commit.sha, commit.file_name))
+        .output()
+        .await?;
+    if !output.status.success() {
+        tracing::debug!(
+            "Git show failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
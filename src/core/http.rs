@@ -0,0 +1,153 @@
+//! Shared construction of outbound HTTP clients so that every request
+//! made by tools and API clients uses the same user agent and proxy
+//! settings.
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use reqwest::{Client, ClientBuilder, Proxy};
+
+use super::AppConfig;
+
+/// User agent sent when none is configured. Some sites block requests
+/// without a user agent.
+pub const DEFAULT_USER_AGENT: &str = concat!("hq/", env!("CARGO_PKG_VERSION"));
+
+static OPTIONS: OnceLock<HttpOptions> = OnceLock::new();
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Settings applied to every outbound HTTP client
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+    pub user_agent: String,
+    /// Proxy URL used for all requests e.g. `http://proxy:8080`
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+        }
+    }
+}
+
+impl From<&AppConfig> for HttpOptions {
+    fn from(config: &AppConfig) -> Self {
+        Self {
+            user_agent: config.http_user_agent.clone(),
+            proxy: config.http_proxy.clone(),
+        }
+    }
+}
+
+/// Set the options used by `client_builder` for the lifetime of the
+/// process. Only the first call takes effect and the shared client is
+/// built on first use so this should be called once at startup before
+/// any requests are made.
+pub fn configure(options: HttpOptions) {
+    if OPTIONS.set(options).is_err() {
+        tracing::warn!("HTTP options already configured, ignoring");
+    }
+}
+
+/// Returns a client builder using the configured options or the
+/// defaults if `configure` was never called.
+pub fn client_builder() -> Result<ClientBuilder> {
+    let default_options = HttpOptions::default();
+    client_builder_with(OPTIONS.get().unwrap_or(&default_options))
+}
+
+/// Returns the client shared by the whole process. It's built once on
+/// first use so that connection pools and TLS configuration are
+/// reused across calls. Timeouts should be set per request using
+/// `RequestBuilder::timeout`.
+pub fn shared_client() -> Result<&'static Client> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = client_builder()?.build()?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// Returns a client builder with the user agent and proxy from
+/// `options` applied.
+pub fn client_builder_with(options: &HttpOptions) -> Result<ClientBuilder> {
+    let mut builder = Client::builder().user_agent(&options.user_agent);
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_sends_configured_user_agent() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/echo")
+            .match_header("user-agent", "hq-test-agent/1.0")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let options = HttpOptions {
+            user_agent: String::from("hq-test-agent/1.0"),
+            proxy: None,
+        };
+        let client = client_builder_with(&options).unwrap().build().unwrap();
+        let resp = client
+            .get(format!("{}/echo", server.url()))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), 200);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn it_sends_requests_through_the_proxy() {
+        // The mock server stands in for the proxy so any request to
+        // an unreachable host should arrive at it instead
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", mockito::Matcher::Any)
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let options = HttpOptions {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: Some(server.url()),
+        };
+        let client = client_builder_with(&options).unwrap().build().unwrap();
+        let resp = client
+            .get("http://example.invalid/page")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), 200);
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn it_reuses_the_shared_client() {
+        let first = shared_client().unwrap();
+        let second = shared_client().unwrap();
+        assert!(std::ptr::eq(first, second));
+    }
+
+    #[test]
+    fn it_rejects_an_invalid_proxy() {
+        let options = HttpOptions {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: Some(String::from("not a url")),
+        };
+        assert!(client_builder_with(&options).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! Metric events recorded while handling requests and running chats.
+//! The metrics API reports on them.
+
+use rusqlite::{
+    ToSql,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+};
+use serde::{Deserialize, Serialize};
+use tokio_rusqlite::Connection;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MetricName {
+    #[serde(rename = "token-count")]
+    TokenCount,
+    /// Duration of a tool call in milliseconds, labeled with the
+    /// tool's name
+    #[serde(rename = "tool-latency")]
+    ToolLatency,
+}
+
+impl ToSql for MetricName {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        // Use serde serialization to convert the enum back into a
+        // string to save to the database while still enforcing metric
+        // names can only be a `MetricName` variant.
+        let name = serde_json::to_string(self).expect("Failed to parse enum into string");
+        let value: String = serde_json::from_str(&name).expect("Failed to parse string from enum");
+        Ok(value.into())
+    }
+}
+
+impl FromSql for MetricName {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        // Serde deserialization can only parse an enum from string if
+        // it's double quoted.
+        serde_json::from_str(&format!("\"{}\"", value.as_str()?))
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+/// How long an idempotency key is remembered for deduping events
+const IDEMPOTENCY_WINDOW_HOURS: i64 = 24;
+
+/// Insert a metric event. The optional `label` distinguishes events
+/// of the same metric e.g. the name of the tool that was called.
+pub async fn insert_metric_event(
+    db: &Connection,
+    name: MetricName,
+    value: i64,
+    label: Option<String>,
+) -> Result<(), anyhow::Error> {
+    insert_metric_event_idempotent(db, name, value, label, None).await?;
+    Ok(())
+}
+
+/// Insert a metric event unless an event with the same
+/// `idempotency_key` was already recorded within the idempotency
+/// window. Returns whether the event was inserted.
+pub async fn insert_metric_event_idempotent(
+    db: &Connection,
+    name: MetricName,
+    value: i64,
+    label: Option<String>,
+    idempotency_key: Option<String>,
+) -> Result<bool, anyhow::Error> {
+    let inserted = db
+        .call(move |conn| {
+            let count = conn.execute(
+                r#"
+            INSERT INTO metric_event (name, value, label, idempotency_key)
+            SELECT ?1, ?2, ?3, ?4
+            WHERE ?4 IS NULL
+            OR NOT EXISTS (
+                SELECT 1 FROM metric_event
+                WHERE idempotency_key = ?4
+                AND timestamp >= strftime('%Y-%m-%dT%H:%M:%fZ', 'now', '-' || ?5 || ' hours')
+            )
+            "#,
+                tokio_rusqlite::params![
+                    &name,
+                    &value,
+                    &label,
+                    &idempotency_key,
+                    IDEMPOTENCY_WINDOW_HOURS
+                ],
+            )?;
+            Ok(count > 0)
+        })
+        .await?;
+
+    Ok(inserted)
+}
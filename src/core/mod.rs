@@ -1,4 +1,19 @@
 mod config;
-pub use config::AppConfig;
+pub use config::{
+    AppConfig, DEFAULT_BODY_BOOST, DEFAULT_COMPLETION_MAX_RETRIES,
+    DEFAULT_COMPLETION_RETRY_BASE_DELAY, DEFAULT_MAX_CONCURRENT_TOOLS, DEFAULT_RETRY_BUDGET,
+    DEFAULT_TAGS_BOOST, DEFAULT_TITLE_BOOST, DEFAULT_TOOL_TIMEOUT, NoteIdScheme, Persona, Provider,
+    ReasoningEffort, SearchMode, SnippetStrategy,
+};
+pub mod backup;
 pub mod db;
+pub mod fs;
 pub mod git;
+pub mod http;
+pub mod metrics;
+pub mod note_id;
+pub mod redact;
+pub mod selfcheck;
+#[cfg(test)]
+pub mod testing;
+pub mod time;
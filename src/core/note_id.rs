@@ -0,0 +1,136 @@
+//! Generate IDs for new notes using the configured scheme.
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::NoteIdScheme;
+
+/// Format of timestamp IDs, sortable by creation time
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3f";
+
+/// Generates unique note IDs for a scheme. Share a single generator
+/// so that timestamps and counters never repeat.
+#[derive(Debug)]
+pub struct NoteIdGenerator {
+    scheme: NoteIdScheme,
+    /// Last counter value used for prefixed IDs
+    counter: AtomicU64,
+    /// Last timestamp in milliseconds used for timestamp IDs
+    last_timestamp_ms: AtomicI64,
+}
+
+impl NoteIdGenerator {
+    pub fn new(scheme: NoteIdScheme) -> Self {
+        Self {
+            scheme,
+            counter: AtomicU64::new(0),
+            last_timestamp_ms: AtomicI64::new(0),
+        }
+    }
+
+    /// Whether the generator needs the IDs of existing notes to
+    /// avoid reusing them. See `skip_existing`.
+    pub fn uses_counter(&self) -> bool {
+        matches!(self.scheme, NoteIdScheme::Prefix(_))
+    }
+
+    /// Continue after the highest counter in `existing_ids` so
+    /// prefixed IDs of notes created elsewhere or before a restart
+    /// aren't reused
+    pub fn skip_existing<'a>(&self, existing_ids: impl IntoIterator<Item = &'a str>) {
+        let NoteIdScheme::Prefix(prefix) = &self.scheme else {
+            return;
+        };
+        let highest = existing_ids
+            .into_iter()
+            .filter_map(|id| id.strip_prefix(prefix.as_str())?.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0);
+        self.counter.fetch_max(highest, Ordering::SeqCst);
+    }
+
+    /// Returns the next note ID
+    pub fn next_id(&self) -> String {
+        match &self.scheme {
+            NoteIdScheme::Uuid => Uuid::new_v4().to_string(),
+            NoteIdScheme::Timestamp => {
+                // Notes created in the same millisecond get the next
+                // unused millisecond so IDs stay unique and ordered
+                let now_ms = Utc::now().timestamp_millis();
+                let prev = self
+                    .last_timestamp_ms
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                        Some(now_ms.max(last + 1))
+                    })
+                    .expect("Timestamp update always succeeds");
+                let ms = now_ms.max(prev + 1);
+                DateTime::from_timestamp_millis(ms)
+                    .expect("Timestamp out of range")
+                    .format(TIMESTAMP_FORMAT)
+                    .to_string()
+            }
+            NoteIdScheme::Prefix(prefix) => {
+                let n = self.counter.fetch_add(1, Ordering::SeqCst) + 1;
+                format!("{}{}", prefix, n)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use regex::Regex;
+
+    use super::*;
+
+    fn generate(generator: &NoteIdGenerator, count: usize) -> Vec<String> {
+        let ids: Vec<String> = (0..count).map(|_| generator.next_id()).collect();
+        let unique: HashSet<&String> = ids.iter().collect();
+        assert_eq!(unique.len(), count, "IDs must be unique");
+        ids
+    }
+
+    #[test]
+    fn it_generates_uuids() {
+        let generator = NoteIdGenerator::new(NoteIdScheme::Uuid);
+        for id in generate(&generator, 100) {
+            let uuid = Uuid::parse_str(&id).unwrap();
+            assert_eq!(uuid.get_version_num(), 4);
+        }
+    }
+
+    #[test]
+    fn it_generates_timestamps() {
+        let generator = NoteIdGenerator::new(NoteIdScheme::Timestamp);
+        let re = Regex::new(r"^\d{8}T\d{9}$").unwrap();
+        let ids = generate(&generator, 100);
+        for id in &ids {
+            assert!(re.is_match(id), "Unexpected timestamp ID: {}", id);
+        }
+        // Timestamp IDs sort in creation order
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(sorted, ids);
+    }
+
+    #[test]
+    fn it_generates_prefixed_ids() {
+        let generator = NoteIdGenerator::new(NoteIdScheme::Prefix(String::from("note-")));
+        assert_eq!(generate(&generator, 3), vec!["note-1", "note-2", "note-3"]);
+    }
+
+    #[test]
+    fn it_continues_prefixed_ids_after_existing() {
+        let generator = NoteIdGenerator::new(NoteIdScheme::Prefix(String::from("note-")));
+        assert!(generator.uses_counter());
+        generator.skip_existing(["note-7", "note-12", "other-99", "note-abc"]);
+        assert_eq!(generator.next_id(), "note-13");
+
+        // Seeing lower counters again doesn't rewind
+        generator.skip_existing(["note-3"]);
+        assert_eq!(generator.next_id(), "note-14");
+    }
+}
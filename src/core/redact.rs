@@ -0,0 +1,93 @@
+//! Mask secrets in text before it is shown to an LLM or logged.
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+static PATTERNS: OnceLock<Vec<(Regex, String)>> = OnceLock::new();
+
+fn patterns() -> &'static [(Regex, String)] {
+    PATTERNS.get_or_init(|| {
+        let secret_names = "api[_-]?key|key|access[_-]?token|refresh[_-]?token|id[_-]?token|token|client[_-]?secret|secret|password|passwd|sig|signature|auth";
+        [
+            // Query string params e.g. `?key=abc123&q=foo`
+            (
+                format!(r"(?i)([?&](?:{secret_names})=)[^&\s#'\x22]+"),
+                format!("${{1}}{REDACTED}"),
+            ),
+            // Key value pairs e.g. `"api_key": "abc123"` or `token=abc123`
+            (
+                format!(r#"(?i)(\b(?:{secret_names})["']?\s*[:=]\s*["']?)[^\s"'&,}}]+"#),
+                format!("${{1}}{REDACTED}"),
+            ),
+            // Authorization headers
+            (
+                String::from(r"(?i)(\b(?:bearer|basic)\s+)[A-Za-z0-9._~+/=-]{8,}"),
+                format!("${{1}}{REDACTED}"),
+            ),
+            // Well known key formats (OpenAI, Google API keys and
+            // OAuth access tokens)
+            (
+                String::from(r"\b(?:sk-[A-Za-z0-9_-]{16,}|AIza[0-9A-Za-z_-]{35}|ya29\.[0-9A-Za-z._-]+)"),
+                String::from(REDACTED),
+            ),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| (Regex::new(&pattern).unwrap(), replacement))
+        .collect()
+    })
+}
+
+/// Replaces anything that looks like a secret (API keys, tokens,
+/// passwords) in `text` with `[REDACTED]`.
+pub fn redact_secrets(text: &str) -> String {
+    patterns()
+        .iter()
+        .fold(text.to_string(), |acc, (pattern, replacement)| {
+            pattern.replace_all(&acc, replacement.as_str()).into_owned()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacts_query_params() {
+        let err = "error sending request for url (https://www.googleapis.com/customsearch/v1?key=AbCdEf123456&cx=abc&q=rust)";
+        let redacted = redact_secrets(err);
+        assert!(!redacted.contains("AbCdEf123456"));
+        assert_eq!(
+            redacted,
+            "error sending request for url (https://www.googleapis.com/customsearch/v1?key=[REDACTED]&cx=abc&q=rust)"
+        );
+    }
+
+    #[test]
+    fn test_redacts_tokens() {
+        let redacted = redact_secrets("https://example.com/cb?code=1&access_token=secret-value");
+        assert_eq!(
+            redacted,
+            "https://example.com/cb?code=1&access_token=[REDACTED]"
+        );
+
+        let redacted = redact_secrets(r#"{"api_key": "abc123", "q": "rust"}"#);
+        assert_eq!(redacted, r#"{"api_key": "[REDACTED]", "q": "rust"}"#);
+
+        let redacted = redact_secrets("Authorization: Bearer abcdefghijklmnop");
+        assert_eq!(redacted, "Authorization: Bearer [REDACTED]");
+    }
+
+    #[test]
+    fn test_redacts_known_key_formats() {
+        let redacted = redact_secrets("Invalid key sk-proj-abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(redacted, "Invalid key [REDACTED]");
+    }
+
+    #[test]
+    fn test_leaves_other_text_alone() {
+        let text = "Request timed out for https://example.com/search?q=keyboards&page=2";
+        assert_eq!(redact_secrets(text), text);
+    }
+}
@@ -0,0 +1,120 @@
+//! Startup checks for optional integrations so that misconfiguration
+//! is reported when the server starts rather than at first use.
+use std::fmt;
+use std::path::Path;
+
+use super::AppConfig;
+use super::config::PLACEHOLDER_OPENAI_API_KEY;
+
+/// An optional integration that won't work with the current config
+#[derive(Debug, PartialEq)]
+pub struct DisabledIntegration {
+    pub name: &'static str,
+    pub reason: String,
+}
+
+impl fmt::Display for DisabledIntegration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} disabled: {}", self.name, self.reason)
+    }
+}
+
+/// Returns each optional integration that is missing config it needs
+pub fn check_integrations(config: &AppConfig) -> Vec<DisabledIntegration> {
+    let mut disabled = Vec::new();
+    let mut disable = |name: &'static str, reason: &str| {
+        disabled.push(DisabledIntegration {
+            name,
+            reason: reason.to_string(),
+        })
+    };
+
+    if config.gmail_api_client_id.trim().is_empty() {
+        disable("Gmail", "client_id not set");
+    } else if config.gmail_api_client_secret.trim().is_empty() {
+        disable("Gmail", "client_secret not set");
+    }
+
+    if config.google_search_api_key.trim().is_empty() {
+        disable("Google search", "api_key not set");
+    } else if config.google_search_cx_id.trim().is_empty() {
+        disable("Google search", "cx_id not set");
+    }
+
+    if !Path::new(&config.vapid_key_path).is_file() {
+        disable(
+            "Push notifications",
+            &format!("vapid key not found at {}", config.vapid_key_path),
+        );
+    }
+
+    if !Path::new(&config.deploy_key_path).is_file() {
+        disable(
+            "Notes sync",
+            &format!("deploy key not found at {}", config.deploy_key_path),
+        );
+    }
+
+    // A local LLM server doesn't need an API key
+    if config.openai_api_hostname.contains("api.openai.com")
+        && config.openai_api_key == PLACEHOLDER_OPENAI_API_KEY
+    {
+        disable("OpenAI", "OPENAI_API_KEY not set");
+    }
+
+    disabled
+}
+
+/// Log a warning for each disabled integration
+pub fn log_disabled_integrations(config: &AppConfig) {
+    for integration in check_integrations(config) {
+        tracing::warn!("{}", integration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::tests::test_config;
+
+    #[test]
+    fn it_reports_disabled_integrations() {
+        let config = AppConfig {
+            gmail_api_client_id: String::new(),
+            openai_api_key: PLACEHOLDER_OPENAI_API_KEY.to_string(),
+            ..test_config()
+        };
+
+        let names: Vec<String> = check_integrations(&config)
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "Gmail disabled: client_id not set",
+                "Push notifications disabled: vapid key not found at test_vapid_key_path",
+                "Notes sync disabled: deploy key not found at test_deploy_key_path",
+                "OpenAI disabled: OPENAI_API_KEY not set",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_nothing_when_fully_configured() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let vapid_key_path = dir.path().join("vapid.pem");
+        let deploy_key_path = dir.path().join("deploy_key");
+        std::fs::write(&vapid_key_path, "key").unwrap();
+        std::fs::write(&deploy_key_path, "key").unwrap();
+
+        let config = AppConfig {
+            vapid_key_path: vapid_key_path.display().to_string(),
+            deploy_key_path: deploy_key_path.display().to_string(),
+            ..test_config()
+        };
+
+        assert!(check_integrations(&config).is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+//! Fixtures shared by unit tests
+use std::path::{Path, PathBuf};
+
+use tokio_rusqlite::Connection;
+
+use crate::core::db::{async_db, initialize_db};
+use crate::search::embedding::Embedder;
+use crate::search::index_all_with_embedder;
+
+/// Open the db in `db_path` with every table created
+pub async fn test_db(db_path: &Path) -> Connection {
+    let db = async_db(db_path.to_str().unwrap()).await.unwrap();
+    db.call(|conn| {
+        initialize_db(conn).unwrap();
+        Ok(())
+    })
+    .await
+    .unwrap();
+    db
+}
+
+/// Empty notes and index directories and an initialized db
+pub struct TestNotes {
+    pub notes_path: String,
+    pub index_path: String,
+    pub db_path: String,
+    pub db: Connection,
+}
+
+impl TestNotes {
+    /// Create the notes, index, and db directories in `dir` which is
+    /// usually a `TempDir` that outlives the fixture
+    pub async fn new(dir: &Path) -> Self {
+        let notes_path = dir.join("notes");
+        let index_path = dir.join("index");
+        let db_path = dir.join("db");
+        for p in [&notes_path, &index_path, &db_path] {
+            std::fs::create_dir_all(p).unwrap();
+        }
+        let db = test_db(&db_path).await;
+        Self {
+            notes_path: notes_path.to_str().unwrap().to_string(),
+            index_path: index_path.to_str().unwrap().to_string(),
+            db_path: db_path.to_str().unwrap().to_string(),
+            db,
+        }
+    }
+
+    /// Write a note to the notes directory and return its path
+    pub fn write_note(&self, file_name: &str, content: &str) -> PathBuf {
+        let path = Path::new(&self.notes_path).join(file_name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Index every note in full-text search and, if there is an
+    /// `embedder`, in vector storage
+    pub async fn index(&self, embedder: Option<&dyn Embedder>) -> tokio_rusqlite::Result<()> {
+        index_all_with_embedder(
+            &self.db,
+            &self.index_path,
+            &self.notes_path,
+            true,
+            embedder,
+            None,
+            None,
+        )
+        .await
+    }
+}
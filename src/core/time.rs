@@ -0,0 +1,124 @@
+//! Date helpers that respect the configured timezone
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Days, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+/// The calendar date at `now` in `timezone`.
+pub fn date_in(now: DateTime<Utc>, timezone: Tz) -> NaiveDate {
+    now.with_timezone(&timezone).date_naive()
+}
+
+/// Today's date in `timezone`.
+pub fn today(timezone: Tz) -> NaiveDate {
+    date_in(Utc::now(), timezone)
+}
+
+/// Shifts `today` by a signed offset in days (`d`), weeks (`w`), or
+/// months (`m`) e.g. `+3d`, `-2w`, or `+1m`. Returns `None` if the
+/// offset is malformed or the date is out of range.
+pub fn offset_date(offset: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let (earlier, offset) = match (offset.strip_prefix('-'), offset.strip_prefix('+')) {
+        (Some(offset), _) => (true, offset),
+        (_, Some(offset)) => (false, offset),
+        _ => return None,
+    };
+    let unit = offset.chars().last()?;
+    let amount = &offset[..offset.len() - unit.len_utf8()];
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u32 = amount.parse().ok()?;
+    let days = match unit {
+        'd' => Days::new(amount.into()),
+        'w' => Days::new(u64::from(amount) * 7),
+        'm' if earlier => return today.checked_sub_months(Months::new(amount)),
+        'm' => return today.checked_add_months(Months::new(amount)),
+        _ => return None,
+    };
+    if earlier {
+        today.checked_sub_days(days)
+    } else {
+        today.checked_add_days(days)
+    }
+}
+
+/// Parses a date relative to `today`. Accepts `YYYY-MM-DD`, `today`,
+/// `tomorrow`, or an offset like `+3d`, `+2w`, or `+1m`.
+pub fn parse_date(input: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let input = input.trim();
+    match input {
+        "today" => return Ok(today),
+        "tomorrow" => return Ok(today + Days::new(1)),
+        _ => {}
+    }
+
+    if input.starts_with('+') {
+        return offset_date(input, today)
+            .ok_or_else(|| anyhow!("Invalid relative date: {}", input));
+    }
+
+    NaiveDate::parse_from_str(input, "%Y-%m-%d").map_err(|_| anyhow!("Invalid date: {}", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_uses_the_timezone_around_midnight() {
+        // 2025-01-02 07:30 UTC is still the evening of 2025-01-01 in
+        // Los Angeles and already 2025-01-02 in Tokyo
+        let now = DateTime::parse_from_rfc3339("2025-01-02T07:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(date_in(now, Tz::UTC).to_string(), "2025-01-02");
+        assert_eq!(
+            date_in(now, Tz::America__Los_Angeles).to_string(),
+            "2025-01-01"
+        );
+        assert_eq!(date_in(now, Tz::Asia__Tokyo).to_string(), "2025-01-02");
+
+        // Just before midnight UTC it's already tomorrow in Tokyo
+        let now = DateTime::parse_from_rfc3339("2025-01-01T23:59:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(date_in(now, Tz::UTC).to_string(), "2025-01-01");
+        assert_eq!(date_in(now, Tz::Asia__Tokyo).to_string(), "2025-01-02");
+    }
+
+    #[test]
+    fn it_parses_absolute_and_relative_dates() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 31).unwrap();
+        let parse = |input| parse_date(input, today).unwrap().to_string();
+
+        assert_eq!(parse("2025-03-04"), "2025-03-04");
+        assert_eq!(parse("today"), "2025-01-31");
+        assert_eq!(parse("tomorrow"), "2025-02-01");
+        assert_eq!(parse("+3d"), "2025-02-03");
+        assert_eq!(parse("+2w"), "2025-02-14");
+        assert_eq!(parse("+1m"), "2025-02-28");
+
+        assert!(parse_date("+3y", today).is_err());
+        assert!(parse_date("+d", today).is_err());
+        assert!(parse_date("++3d", today).is_err());
+        assert!(parse_date("+1é", today).is_err());
+        assert!(parse_date("+é", today).is_err());
+        assert!(parse_date("next week", today).is_err());
+    }
+
+    #[test]
+    fn it_offsets_dates() {
+        let today = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+        let offset = |input| offset_date(input, today).map(|d| d.to_string());
+
+        assert_eq!(offset("+3d").as_deref(), Some("2025-04-03"));
+        assert_eq!(offset("-1w").as_deref(), Some("2025-03-24"));
+        assert_eq!(offset("-1m").as_deref(), Some("2025-02-28"));
+        assert_eq!(offset("3d"), None);
+        assert_eq!(offset("-"), None);
+        assert_eq!(offset("+3é"), None);
+        assert_eq!(offset("+99999999999d"), None);
+    }
+}
@@ -2,6 +2,9 @@ use anyhow::{Error, Result};
 use reqwest;
 use serde::Deserialize;
 
+use super::check_response;
+use crate::core::http;
+
 #[derive(Deserialize)]
 struct GoogleSearchResponse {
     // When there are no results, Google responds without the
@@ -27,7 +30,7 @@ pub async fn search_google(
     let mut collected: Vec<SearchItem> = Vec::new();
     let mut start_index: u32 = 1; // Google Custom Search uses 1‑based start index
     let base_url = base_url.unwrap_or("https://www.googleapis.com/customsearch/v1");
-    let client = reqwest::Client::new();
+    let client = http::shared_client()?;
 
     while collected.len() < desired {
         // Number of items to request this page (max 10, but not exceeding remaining needed)
@@ -40,7 +43,7 @@ pub async fn search_google(
             .append_pair("num", &per_page.to_string())
             .append_pair("start", &start_index.to_string());
 
-        let resp = client.get(url).send().await?.error_for_status()?;
+        let resp = check_response(client.get(url).send().await?).await?;
         let body: GoogleSearchResponse = resp.json().await?;
         let items = body.items.unwrap_or_default();
         let count = items.len();
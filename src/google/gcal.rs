@@ -2,9 +2,11 @@
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use super::check_response;
+use crate::core::http;
+
 /// Represents a Google Calendar event (meeting)
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Event {
@@ -103,7 +105,7 @@ pub async fn list_events(
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
 ) -> Result<Vec<Event>> {
-    let client = Client::new();
+    let client = http::shared_client()?;
     let url = format!(
         "https://www.googleapis.com/calendar/v3/calendars/{}/events",
         calendar_id
@@ -119,6 +121,8 @@ pub async fn list_events(
             ("orderBy", "startTime".to_string()),
         ])
         .send()
+        .await?;
+    let response = check_response(response)
         .await?
         .json::<ListEventsResponse>()
         .await?;
@@ -4,13 +4,24 @@
 //! API fairly well for my purposes. Best to let AI update this
 //! as it's super bespoke and edge-case-y.
 
-use base64::{Engine as _, engine::general_purpose::URL_SAFE};
+use base64::{
+    Engine as _,
+    engine::general_purpose::{STANDARD, URL_SAFE, URL_SAFE_NO_PAD},
+};
 use chrono::{Duration, Utc};
 use htmd::HtmlToMarkdown;
 use regex::Regex;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use super::check_response;
+use crate::core::http;
+
+const GMAIL_API_URL: &str = "https://gmail.googleapis.com/gmail/v1";
+
+/// Maximum number of pages of unread messages to fetch so a huge
+/// inbox doesn't page forever
+const MAX_UNREAD_PAGES: usize = 10;
+
 /// Message and thread structures from Gmail API documentation
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MessageResponse {
@@ -417,28 +428,51 @@ fn clean_and_strip_body(content: String) -> String {
     strip_signature(&without_quotes)
 }
 
-/// List unread messages from the last N days
+/// List unread messages from the last N days. Follows the next page
+/// token up to `MAX_UNREAD_PAGES` pages.
 /// curl: see spec
 pub async fn list_unread_messages(
     access_token: &str,
     n_days: i64,
 ) -> Result<Vec<MessageResponse>, anyhow::Error> {
-    let client = Client::new();
+    list_unread_messages_from(GMAIL_API_URL, access_token, n_days).await
+}
+
+async fn list_unread_messages_from(
+    base_url: &str,
+    access_token: &str,
+    n_days: i64,
+) -> Result<Vec<MessageResponse>, anyhow::Error> {
+    let client = http::shared_client()?;
     let after_date = (Utc::now() - Duration::days(n_days))
         .format("%Y/%m/%d")
         .to_string();
     let url = format!(
-        "https://gmail.googleapis.com/gmail/v1/users/me/messages?labelIds=UNREAD&q=is:unread%20after:{}%20in:inbox",
-        after_date
+        "{}/users/me/messages?labelIds=UNREAD&q=is:unread%20after:{}%20in:inbox",
+        base_url, after_date
     );
-    let res = client.get(&url).bearer_auth(access_token).send().await?;
-    let status = res.status();
-    let text = res.text().await.unwrap_or_default();
-    if !status.is_success() {
-        anyhow::bail!("Unread fetch failed: {} ({})", status, text);
+
+    let mut messages = Vec::new();
+    let mut page_token: Option<String> = None;
+    for _ in 0..MAX_UNREAD_PAGES {
+        let mut req = client.get(&url).bearer_auth(access_token);
+        if let Some(token) = &page_token {
+            req = req.query(&[("pageToken", token)]);
+        }
+        let res = req.send().await?;
+        let text = check_response(res).await?.text().await?;
+        let msgs: ListMessagesResponse = serde_json::from_str(&text)?;
+        messages.extend(msgs.messages.unwrap_or_default());
+        page_token = msgs.next_page_token;
+        if page_token.is_none() {
+            return Ok(messages);
+        }
     }
-    let msgs: ListMessagesResponse = serde_json::from_str(&text)?;
-    Ok(msgs.messages.unwrap_or_default())
+    tracing::warn!(
+        "Stopped listing unread messages after {} pages",
+        MAX_UNREAD_PAGES
+    );
+    Ok(messages)
 }
 
 /// Fetch full thread for a given threadId
@@ -447,21 +481,139 @@ pub async fn fetch_thread(
     access_token: String,
     thread_id: String,
 ) -> Result<Thread, anyhow::Error> {
-    let client = Client::new();
-    let url = format!(
-        "https://gmail.googleapis.com/gmail/v1/users/me/threads/{}?format=full",
-        thread_id
-    );
+    fetch_thread_from(GMAIL_API_URL, &access_token, &thread_id).await
+}
+
+async fn fetch_thread_from(
+    base_url: &str,
+    access_token: &str,
+    thread_id: &str,
+) -> Result<Thread, anyhow::Error> {
+    let client = http::shared_client()?;
+    let url = format!("{}/users/me/threads/{}?format=full", base_url, thread_id);
     let res = client.get(&url).bearer_auth(access_token).send().await?;
-    let status = res.status();
-    let text = res.text().await.unwrap_or_default();
-    if !status.is_success() {
-        anyhow::bail!("Thread fetch failed: {} ({})", status, text);
-    }
+    let text = check_response(res).await?.text().await?;
     let thread: Thread = serde_json::from_str(&text)?;
     Ok(thread)
 }
 
+/// Mark every message in the thread `thread_id` as read by removing
+/// the `UNREAD` label
+/// curl: see spec
+pub async fn mark_thread_read(access_token: &str, thread_id: &str) -> Result<(), anyhow::Error> {
+    mark_thread_read_at(GMAIL_API_URL, access_token, thread_id).await
+}
+
+async fn mark_thread_read_at(
+    base_url: &str,
+    access_token: &str,
+    thread_id: &str,
+) -> Result<(), anyhow::Error> {
+    let client = http::shared_client()?;
+    let url = format!("{}/users/me/threads/{}/modify", base_url, thread_id);
+    let res = client
+        .post(&url)
+        .bearer_auth(access_token)
+        .json(&serde_json::json!({"removeLabelIds": ["UNREAD"]}))
+        .send()
+        .await?;
+    check_response(res).await?;
+    Ok(())
+}
+
+/// Get the value of the header `name` ignoring case
+fn find_header<'a>(message: &'a Message, name: &str) -> Option<&'a str> {
+    message
+        .payload
+        .as_ref()?
+        .headers
+        .as_ref()?
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str())
+}
+
+/// Remove line breaks so a value can't add headers of its own
+fn header_value(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Encode non-ASCII header values as an RFC 2047 encoded word
+fn encode_header_value(value: &str) -> String {
+    let value = header_value(value);
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(value))
+    }
+}
+
+/// Build an RFC 822 reply to the last message of `thread`. The
+/// In-Reply-To and References headers are set from that message so
+/// mail clients thread the reply with the conversation.
+fn build_reply(thread: &Thread, to: &str, subject: &str, body: &str) -> String {
+    let subject = if subject.to_lowercase().starts_with("re:") {
+        subject.to_string()
+    } else {
+        format!("Re: {}", subject)
+    };
+    let mut headers = vec![
+        format!("To: {}", header_value(to)),
+        format!("Subject: {}", encode_header_value(&subject)),
+    ];
+    let last_message = thread.messages.last();
+    if let Some(message_id) = last_message.and_then(|m| find_header(m, "Message-ID")) {
+        let references = match last_message.and_then(|m| find_header(m, "References")) {
+            Some(references) => format!("{} {}", references, message_id),
+            None => message_id.to_string(),
+        };
+        headers.push(format!("In-Reply-To: {}", header_value(message_id)));
+        headers.push(format!("References: {}", header_value(&references)));
+    }
+    headers.push(String::from("MIME-Version: 1.0"));
+    headers.push(String::from("Content-Type: text/plain; charset=\"UTF-8\""));
+    format!("{}\r\n\r\n{}", headers.join("\r\n"), body)
+}
+
+/// Send a plain text reply in the thread `thread_id`
+/// curl: see spec
+pub async fn send_reply(
+    access_token: &str,
+    thread_id: &str,
+    to: &str,
+    subject: &str,
+    body: &str,
+) -> Result<MessageResponse, anyhow::Error> {
+    send_reply_to(GMAIL_API_URL, access_token, thread_id, to, subject, body).await
+}
+
+async fn send_reply_to(
+    base_url: &str,
+    access_token: &str,
+    thread_id: &str,
+    to: &str,
+    subject: &str,
+    body: &str,
+) -> Result<MessageResponse, anyhow::Error> {
+    let thread = fetch_thread_from(base_url, access_token, thread_id).await?;
+    let raw = URL_SAFE_NO_PAD.encode(build_reply(&thread, to, subject, body));
+
+    let client = http::shared_client()?;
+    let url = format!("{}/users/me/messages/send", base_url);
+    let res = client
+        .post(&url)
+        .bearer_auth(access_token)
+        .json(&serde_json::json!({
+            "raw": raw,
+            "threadId": thread_id,
+        }))
+        .send()
+        .await?;
+    let text = check_response(res).await?.text().await?;
+    let message: MessageResponse = serde_json::from_str(&text)?;
+    Ok(message)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -939,6 +1091,46 @@ mod tests {
         assert_eq!(msgs.messages.unwrap().len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_list_unread_messages_follows_next_page_token() {
+        let mut server = mockito::Server::new_async().await;
+
+        // The first page also matches the second request so expect it
+        // once to have the second request go to the second page
+        let first_page = server
+            .mock("GET", "/gmail/v1/users/me/messages")
+            .match_query(mockito::Matcher::Regex(r"labelIds=UNREAD".to_string()))
+            .expect(1)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"messages": [{"id": "msg_001", "threadId": "thr_001"}, {"id": "msg_002", "threadId": "thr_002"}], "nextPageToken": "page_2"}"#,
+            )
+            .create_async()
+            .await;
+        let second_page = server
+            .mock("GET", "/gmail/v1/users/me/messages")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "pageToken".to_string(),
+                "page_2".to_string(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"messages": [{"id": "msg_003", "threadId": "thr_003"}]}"#)
+            .create_async()
+            .await;
+
+        let base_url = format!("{}/gmail/v1", server.url());
+        let messages = list_unread_messages_from(&base_url, "test_token", 1)
+            .await
+            .unwrap();
+
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+        let ids: Vec<&str> = messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["msg_001", "msg_002", "msg_003"]);
+    }
+
     #[tokio::test]
     async fn test_fetch_thread() {
         let mut server = mockito::Server::new_async().await;
@@ -994,6 +1186,129 @@ mod tests {
         assert_eq!(thread.messages.len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_send_reply() {
+        let mut server = mockito::Server::new_async().await;
+
+        let thread_resp = r#"{
+            "id": "thr_001",
+            "messages": [
+                {
+                    "id": "msg_001a",
+                    "threadId": "thr_001",
+                    "internalDate": "1731401723000",
+                    "payload": {
+                        "mimeType": "text/plain",
+                        "headers": [
+                            {"name": "From", "value": "test@example.com"},
+                            {"name": "Subject", "value": "Lunch"},
+                            {"name": "Message-ID", "value": "<first@example.com>"}
+                        ]
+                    }
+                },
+                {
+                    "id": "msg_001b",
+                    "threadId": "thr_001",
+                    "internalDate": "1731401724000",
+                    "payload": {
+                        "mimeType": "text/plain",
+                        "headers": [
+                            {"name": "From", "value": "test@example.com"},
+                            {"name": "Subject", "value": "Re: Lunch"},
+                            {"name": "Message-Id", "value": "<second@example.com>"},
+                            {"name": "References", "value": "<first@example.com>"}
+                        ]
+                    }
+                }
+            ]
+        }"#;
+        let _thread_mock = server
+            .mock("GET", "/gmail/v1/users/me/threads/thr_001?format=full")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(thread_resp)
+            .create_async()
+            .await;
+
+        let expected_raw = URL_SAFE_NO_PAD.encode(
+            "To: test@example.com\r\n\
+             Subject: Re: Lunch\r\n\
+             In-Reply-To: <second@example.com>\r\n\
+             References: <first@example.com> <second@example.com>\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=\"UTF-8\"\r\n\
+             \r\n\
+             Noon works for me.",
+        );
+        let send_mock = server
+            .mock("POST", "/gmail/v1/users/me/messages/send")
+            .match_header("authorization", "Bearer test_token")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "raw": expected_raw,
+                "threadId": "thr_001",
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "msg_002", "threadId": "thr_001", "labelIds": ["SENT"]}"#)
+            .create_async()
+            .await;
+
+        let base_url = format!("{}/gmail/v1", server.url());
+        let message = send_reply_to(
+            &base_url,
+            "test_token",
+            "thr_001",
+            "test@example.com",
+            "Lunch",
+            "Noon works for me.",
+        )
+        .await
+        .unwrap();
+
+        send_mock.assert_async().await;
+        assert_eq!(message.id, "msg_002");
+        assert_eq!(message.thread_id, "thr_001");
+    }
+
+    #[tokio::test]
+    async fn test_mark_thread_read() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/gmail/v1/users/me/threads/thr_001/modify")
+            .match_header("authorization", "Bearer test_token")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "removeLabelIds": ["UNREAD"],
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "thr_001", "messages": []}"#)
+            .create_async()
+            .await;
+
+        let base_url = format!("{}/gmail/v1", server.url());
+        mark_thread_read_at(&base_url, "test_token", "thr_001")
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_build_reply_strips_header_line_breaks() {
+        let thread = Thread {
+            id: String::from("thr_001"),
+            messages: vec![],
+        };
+        let reply = build_reply(
+            &thread,
+            "a@example.com\r\nBcc: b@example.com",
+            "Héllo",
+            "Hi",
+        );
+        assert!(reply.starts_with("To: a@example.com  Bcc: b@example.com\r\n"));
+        assert!(reply.contains("Subject: =?UTF-8?B?"));
+        assert!(!reply.contains("In-Reply-To"));
+    }
+
     #[tokio::test]
     async fn test_list_unread_messages_error() {
         let mut server = mockito::Server::new_async().await;
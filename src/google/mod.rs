@@ -2,3 +2,67 @@ pub mod custom_search;
 pub mod gcal;
 pub mod gmail;
 pub mod oauth;
+
+use std::time::Duration;
+
+use anyhow::Error;
+use serde::Deserialize;
+
+/// Error body returned by Google APIs e.g.
+/// `{"error": {"code": 401, "message": "...", "status": "UNAUTHENTICATED"}}`
+#[derive(Debug, Deserialize)]
+struct GoogleErrorResponse {
+    error: GoogleErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoogleErrorDetail {
+    message: String,
+}
+
+/// A non-2xx response from a Google API
+#[derive(Debug)]
+pub struct GoogleApiError {
+    pub status: reqwest::StatusCode,
+    pub message: String,
+    /// How long the API asked to wait before sending the request
+    /// again from the `Retry-After` header
+    pub retry_after: Option<Duration>,
+}
+
+impl std::fmt::Display for GoogleApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Google API error ({}): {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for GoogleApiError {}
+
+/// Return the response if it was successful otherwise an error with
+/// the message from Google. Falls back to the raw body when it isn't
+/// a Google style error.
+pub(crate) async fn check_response(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let body = response.text().await.unwrap_or_default();
+    let message = match serde_json::from_str::<GoogleErrorResponse>(&body) {
+        Ok(GoogleErrorResponse { error }) => error.message,
+        Err(_) => body,
+    };
+    Err(GoogleApiError {
+        status,
+        message,
+        retry_after,
+    }
+    .into())
+}
@@ -1,10 +1,11 @@
 //! OAuth 2.0 token exchange & refresh for Gmail API
 
 use anyhow::{Error, Result};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tokio_rusqlite::Connection;
 
+use crate::core::http;
+
 /// Response from Google's token endpoint
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TokenResponse {
@@ -24,7 +25,7 @@ pub async fn exchange_code_for_token(
     code: &str,
     redirect_uri: &str,
 ) -> Result<TokenResponse, anyhow::Error> {
-    let client = Client::new();
+    let client = http::shared_client()?;
 
     let params = [
         ("code", code),
@@ -54,7 +55,7 @@ pub async fn refresh_access_token(
     client_secret: &str,
     refresh_token: &str,
 ) -> Result<TokenResponse, anyhow::Error> {
-    let client = Client::new();
+    let client = http::shared_client()?;
 
     let params = [
         ("client_id", client_id),
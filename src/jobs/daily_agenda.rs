@@ -25,7 +25,9 @@ impl PeriodicJob for DailyAgenda {
     async fn run_job(&self, config: &AppConfig, db: &Connection) {
         let AppConfig {
             note_search_api_url,
+            timezone,
             vapid_key_path,
+            push_max_concurrency,
             openai_api_hostname,
             openai_api_key,
             openai_model,
@@ -39,6 +41,7 @@ impl PeriodicJob for DailyAgenda {
         let (session_id, messages) = agenda::daily_agenda_response(
             db,
             note_search_api_url,
+            *timezone,
             calendar_emails,
             openai_api_hostname,
             openai_api_key,
@@ -47,7 +50,7 @@ impl PeriodicJob for DailyAgenda {
         .await;
 
         let last_msg = messages.last().unwrap();
-        let summary = last_msg.content.clone().unwrap();
+        let summary = last_msg.content().unwrap().to_string();
 
         // Broadcast push notification to all subscribers with a link
         // to the chat session
@@ -68,6 +71,12 @@ impl PeriodicJob for DailyAgenda {
             }
         };
 
-        broadcast_push_notification(subscriptions, vapid_key_path.to_string(), payload).await;
+        broadcast_push_notification(
+            subscriptions,
+            vapid_key_path.to_string(),
+            payload,
+            *push_max_concurrency,
+        )
+        .await;
     }
 }
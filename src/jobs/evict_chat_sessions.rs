@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio_rusqlite::Connection;
+
+use crate::ai::chat::db::evict_chat_sessions;
+use crate::core::AppConfig;
+
+/// Deletes the least recently used chat sessions when there are more
+/// than `AppConfig::chat_max_sessions`
+#[derive(Debug)]
+pub struct EvictChatSessions;
+
+#[async_trait]
+impl crate::jobs::PeriodicJob for EvictChatSessions {
+    fn interval(&self) -> Duration {
+        // Run every hour
+        Duration::from_secs(60 * 60)
+    }
+
+    async fn run_job(&self, config: &AppConfig, db_conn: &Connection) {
+        let Some(max_sessions) = config.chat_max_sessions else {
+            return;
+        };
+        match evict_chat_sessions(db_conn, max_sessions).await {
+            Ok(evicted) => tracing::info!(
+                "Evicted {} chat sessions to stay within {}",
+                evicted,
+                max_sessions
+            ),
+            Err(e) => tracing::error!("Failed to evict chat sessions: {}", e),
+        }
+    }
+}
@@ -1,11 +1,12 @@
 use async_trait::async_trait;
+use serde_json::json;
 use std::time::Duration;
 use tokio_rusqlite::Connection;
 
 use crate::ai::chat::ChatBuilder;
 use crate::ai::chat::db::find_chat_session_by_id;
 use crate::core::AppConfig;
-use crate::openai::{Message, Role};
+use crate::openai::{CompletionParams, Message, Role};
 
 #[derive(Debug)]
 pub struct GenerateSessionTitles;
@@ -105,15 +106,24 @@ async fn generate_and_update_session_info(
         &config.openai_model,
     )
     .transcript(vec![Message::new(Role::System, system_prompt)])
+    // Titles are short so don't wait long on a request that hangs
+    .timeout(Duration::from_secs(60))
+    // Models sometimes add prose around the JSON object otherwise
+    .response_format(json!({"type": "json_object"}))
+    // The same conversation should get the same title
+    .params(CompletionParams {
+        temperature: Some(0.0),
+        ..CompletionParams::default()
+    })
     .build();
 
     let response = chat.next_msg(Message::new(Role::User, &prompt)).await?;
     let last_msg = response.last().expect("No messages").to_owned();
-    let content = last_msg.content.expect("No content");
+    let content = last_msg.content().expect("No content");
 
     // Extract the generated title and summary from the response
     // Try to parse the JSON response
-    match serde_json::from_str::<serde_json::Value>(&content) {
+    match serde_json::from_str::<serde_json::Value>(content) {
         Ok(json_response) => {
             if let (Some(title), Some(summary)) = (
                 json_response["title"].as_str(),
@@ -156,7 +166,7 @@ fn create_session_prompt(transcript: &[Message]) -> String {
 
     // We'll just use a simple format without role distinction for now
     for message in transcript {
-        if let Some(content) = &message.content {
+        if let Some(content) = message.content() {
             conversation.push_str(&format!("{}\n", content));
         }
     }
@@ -11,6 +11,8 @@ pub mod research_meeting_attendees;
 pub use research_meeting_attendees::ResearchMeetingAttendees;
 pub mod generate_session_titles;
 pub use generate_session_titles::GenerateSessionTitles;
+pub mod evict_chat_sessions;
+pub use evict_chat_sessions::EvictChatSessions;
 
 #[async_trait]
 pub trait PeriodicJob: Send + Sync + 'static {
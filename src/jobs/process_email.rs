@@ -25,6 +25,7 @@ impl PeriodicJob for ProcessEmail {
         let AppConfig {
             note_search_api_url,
             vapid_key_path,
+            push_max_concurrency,
             openai_api_hostname,
             openai_api_key,
             openai_model,
@@ -42,7 +43,7 @@ impl PeriodicJob for ProcessEmail {
         )
         .await;
         let last_msg = messages.last().unwrap();
-        let summary = last_msg.content.clone().unwrap();
+        let summary = last_msg.content().unwrap().to_string();
 
         // Broadcast push notification to all subscribers, using a new read lock for DB/config each time
         let chat_url = format!("/chat?session_id={}", session_id);
@@ -54,6 +55,12 @@ impl PeriodicJob for ProcessEmail {
             None,
         );
         let subscriptions = find_all_notification_subscriptions(db).await.unwrap();
-        broadcast_push_notification(subscriptions, vapid_key_path.to_string(), payload).await;
+        broadcast_push_notification(
+            subscriptions,
+            vapid_key_path.to_string(),
+            payload,
+            *push_max_concurrency,
+        )
+        .await;
     }
 }
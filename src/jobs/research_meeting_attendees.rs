@@ -29,6 +29,7 @@ impl PeriodicJob for ResearchMeetingAttendees {
         let AppConfig {
             note_search_api_url,
             vapid_key_path,
+            push_max_concurrency,
             openai_api_hostname,
             openai_api_key,
             openai_model,
@@ -100,9 +101,9 @@ Frank is the VP of People at Acme. He was previously HR Manager at Acme and befo
         // Get the final response from the chat
         let summary = if let Some(last_msg) = messages.last() {
             last_msg
-                .content
-                .clone()
-                .unwrap_or_else(|| "No summary available".to_string())
+                .content()
+                .unwrap_or("No summary available")
+                .to_string()
         } else {
             "No response from chat".to_string()
         };
@@ -117,6 +118,12 @@ Frank is the VP of People at Acme. He was previously HR Manager at Acme and befo
 
         // Broadcast push notification to all subscribers
         let subscriptions = find_all_notification_subscriptions(db).await.unwrap();
-        broadcast_push_notification(subscriptions, vapid_key_path.to_string(), payload).await;
+        broadcast_push_notification(
+            subscriptions,
+            vapid_key_path.to_string(),
+            payload,
+            *push_max_concurrency,
+        )
+        .await;
     }
 }
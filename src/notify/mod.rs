@@ -3,7 +3,10 @@ pub mod models;
 pub use db::*;
 pub use models::*;
 
+use std::sync::Arc;
+
 use anyhow::{Error, Result};
+use tokio::sync::Semaphore;
 use web_push::{
     ContentEncoding, HyperWebPushClient, SubscriptionInfo, VapidSignatureBuilder, WebPushClient,
     WebPushMessageBuilder,
@@ -41,21 +44,90 @@ pub async fn send_push_notification(
     Ok(())
 }
 
+/// Send a push notification to each subscription with at most
+/// `max_concurrency` notifications in flight at once.
 pub async fn broadcast_push_notification(
     subscriptions: Vec<PushSubscription>,
     vapid_key_path: String,
     payload: PushNotificationPayload,
+    max_concurrency: usize,
 ) {
-    let mut tasks = tokio::task::JoinSet::new();
-    for sub in subscriptions {
-        let vapid = vapid_key_path.clone();
-        tasks.spawn(send_push_notification(
-            vapid,
+    for_each_subscription(subscriptions, max_concurrency, |sub| {
+        send_push_notification(
+            vapid_key_path.clone(),
             sub.endpoint,
             sub.p256dh,
             sub.auth,
             payload.clone(),
-        ));
+        )
+    })
+    .await
+}
+
+async fn for_each_subscription<F, Fut>(
+    subscriptions: Vec<PushSubscription>,
+    max_concurrency: usize,
+    send: F,
+) where
+    F: Fn(PushSubscription) -> Fut,
+    Fut: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(max_concurrency.max(1)));
+    let mut tasks = tokio::task::JoinSet::new();
+    for sub in subscriptions {
+        // Wait for a free slot before spawning so a large number of
+        // subscriptions doesn't open thousands of connections at once
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore should never be closed");
+        let fut = send(sub);
+        tasks.spawn(async move {
+            let result = fut.await;
+            drop(permit);
+            result
+        });
     }
     while let Some(_res) = tasks.join_next().await {}
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_limits_concurrent_sends() {
+        let subscriptions = (0..50)
+            .map(|i| PushSubscription {
+                endpoint: format!("https://push.example.com/{}", i),
+                p256dh: String::from("p256dh"),
+                auth: String::from("auth"),
+            })
+            .collect();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let sent = Arc::new(AtomicUsize::new(0));
+
+        for_each_subscription(subscriptions, 4, |_sub| {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            let sent = sent.clone();
+            async move {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                sent.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })
+        .await;
+
+        assert_eq!(sent.load(Ordering::SeqCst), 50);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 4);
+    }
+}
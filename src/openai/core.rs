@@ -1,13 +1,23 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, time::Duration};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-use anyhow::{Error, Result};
+use anyhow::{Error, Result, anyhow};
 use async_trait::async_trait;
 use erased_serde;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 
+use super::request_log;
+use crate::core::http;
+pub use crate::core::{
+    DEFAULT_COMPLETION_MAX_RETRIES, DEFAULT_COMPLETION_RETRY_BASE_DELAY, Provider, ReasoningEffort,
+};
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum Role {
     #[serde(rename = "system")]
@@ -48,27 +58,152 @@ pub struct FunctionCall {
     pub r#type: String,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct Message {
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ImageUrl {
+    /// URL of the image or a base64 encoded data URL
+    pub url: String,
+    /// One of `low`, `high`, or `auto`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Part of a multimodal message e.g. text and images
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+impl ContentPart {
+    pub fn text(text: &str) -> Self {
+        ContentPart::Text {
+            text: text.to_string(),
+        }
+    }
+    pub fn image_url(url: &str) -> Self {
+        ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: url.to_string(),
+                detail: None,
+            },
+        }
+    }
+}
+
+/// Message content is a plain string for text only messages or a
+/// list of content parts for multimodal messages
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// The shape of a message sent to and received from the API
+#[derive(Clone, Serialize, Deserialize)]
+struct MessageWire {
     role: Role,
     #[serde(skip_serializing_if = "Option::is_none")]
     refusal: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
+    content: Option<MessageContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_call_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_calls: Option<Vec<FunctionCall>>,
 }
 
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(into = "MessageWire", from = "MessageWire")]
+pub struct Message {
+    role: Role,
+    refusal: Option<bool>,
+    /// Text of the message. For multimodal messages this is the text
+    /// of all the text parts.
+    content: Option<String>,
+    content_parts: Option<Vec<ContentPart>>,
+    tool_call_id: Option<String>,
+    tool_calls: Option<Vec<FunctionCall>>,
+    /// Structured output of a tool call for clients to render. Never
+    /// sent to the model which only sees the text content.
+    structured_content: Option<Value>,
+    /// Reasoning of a reasoning model before it responded. Never sent
+    /// back to the model.
+    reasoning: Option<String>,
+}
+
+impl From<Message> for MessageWire {
+    fn from(msg: Message) -> Self {
+        let content = match msg.content_parts {
+            Some(parts) => Some(MessageContent::Parts(parts)),
+            None => msg.content.map(MessageContent::Text),
+        };
+        MessageWire {
+            role: msg.role,
+            refusal: msg.refusal,
+            content,
+            tool_call_id: msg.tool_call_id,
+            tool_calls: msg.tool_calls,
+        }
+    }
+}
+
+impl From<MessageWire> for Message {
+    fn from(wire: MessageWire) -> Self {
+        let (content, content_parts) = match wire.content {
+            Some(MessageContent::Text(text)) => (Some(text), None),
+            Some(MessageContent::Parts(parts)) => (Some(parts_text(&parts)), Some(parts)),
+            None => (None, None),
+        };
+        Message {
+            role: wire.role,
+            refusal: wire.refusal,
+            content,
+            content_parts,
+            tool_call_id: wire.tool_call_id,
+            tool_calls: wire.tool_calls,
+            structured_content: None,
+            reasoning: None,
+        }
+    }
+}
+
+fn parts_text(parts: &[ContentPart]) -> String {
+    parts
+        .iter()
+        .filter_map(|part| match part {
+            ContentPart::Text { text } => Some(text.as_str()),
+            ContentPart::ImageUrl { .. } => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl Message {
     pub fn new(role: Role, content: &str) -> Self {
         Message {
             role,
             refusal: None,
             content: Some(content.to_string()),
+            content_parts: None,
+            tool_call_id: None,
+            tool_calls: None,
+            structured_content: None,
+            reasoning: None,
+        }
+    }
+    /// A multimodal message made up of text and image parts
+    pub fn new_with_parts(role: Role, parts: Vec<ContentPart>) -> Self {
+        Message {
+            role,
+            refusal: None,
+            content: Some(parts_text(&parts)),
+            content_parts: Some(parts),
             tool_call_id: None,
             tool_calls: None,
+            structured_content: None,
+            reasoning: None,
         }
     }
     pub fn new_tool_call_request(tool_calls: Vec<FunctionCall>) -> Self {
@@ -76,8 +211,11 @@ impl Message {
             role: Role::Assistant,
             refusal: None,
             content: None,
+            content_parts: None,
             tool_call_id: None,
             tool_calls: Some(tool_calls),
+            structured_content: None,
+            reasoning: None,
         }
     }
     pub fn new_tool_call_response(content: &str, tool_call_id: &str) -> Self {
@@ -85,10 +223,58 @@ impl Message {
             role: Role::Tool,
             refusal: None,
             content: Some(content.to_string()),
+            content_parts: None,
             tool_call_id: Some(tool_call_id.to_string()),
             tool_calls: None,
+            structured_content: None,
+            reasoning: None,
         }
     }
+    /// Attach structured output to a tool call response
+    pub fn with_structured_content(mut self, structured_content: Option<Value>) -> Self {
+        self.structured_content = structured_content;
+        self
+    }
+    /// Attach the model's reasoning to an assistant message
+    pub fn with_reasoning(mut self, reasoning: Option<String>) -> Self {
+        self.reasoning = reasoning;
+        self
+    }
+    /// Append text to the message separated from any existing text by
+    /// a blank line
+    pub fn push_content(&mut self, text: &str) {
+        if let Some(parts) = &mut self.content_parts {
+            parts.push(ContentPart::text(text));
+            self.content = Some(parts_text(parts));
+            return;
+        }
+        let content = self.content.get_or_insert_default();
+        if !content.is_empty() {
+            content.push_str("\n\n");
+        }
+        content.push_str(text);
+    }
+    pub fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+    pub fn content_parts(&self) -> Option<&[ContentPart]> {
+        self.content_parts.as_deref()
+    }
+    pub fn role(&self) -> &Role {
+        &self.role
+    }
+    pub fn tool_call_id(&self) -> Option<&str> {
+        self.tool_call_id.as_deref()
+    }
+    pub fn tool_calls(&self) -> Option<&[FunctionCall]> {
+        self.tool_calls.as_deref()
+    }
+    pub fn structured_content(&self) -> Option<&Value> {
+        self.structured_content.as_ref()
+    }
+    pub fn reasoning(&self) -> Option<&str> {
+        self.reasoning.as_deref()
+    }
 }
 
 #[derive(Serialize, Default)]
@@ -152,18 +338,155 @@ pub enum ToolType {
 pub trait ToolCall: erased_serde::Serialize {
     async fn call(&self, args: &str) -> Result<String, Error>;
     fn function_name(&self) -> String;
+
+    /// Call the tool returning structured output alongside the text
+    /// the model sees e.g. calendar events as JSON a client can
+    /// render. Defaults to only the text from `call`.
+    async fn call_structured(&self, args: &str) -> Result<ToolOutput, Error> {
+        Ok(ToolOutput::from(self.call(args).await?))
+    }
 }
 erased_serde::serialize_trait_object!(ToolCall);
 
 pub type BoxedToolCall = Box<dyn ToolCall + Send + Sync + 'static>;
 
-pub async fn completion(
+/// Result of a tool call
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolOutput {
+    /// Rendered output that is sent back to the model
+    pub text: String,
+    /// Structured output stored with the tool call response for
+    /// clients to render
+    pub structured: Option<Value>,
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            structured: None,
+        }
+    }
+}
+
+/// Default timeout for a non-streaming completion request
+pub const DEFAULT_COMPLETION_TIMEOUT: Duration = Duration::from_secs(60 * 10);
+
+/// Default timeout for a streaming completion request
+pub const DEFAULT_COMPLETION_STREAM_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+
+/// Sampling parameters sent with a completion request. Anything not
+/// set is left out of the payload so the provider's default is used.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CompletionParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+}
+
+impl CompletionParams {
+    /// Add the parameters that are set to the request `payload`
+    fn merge_into(&self, payload: &mut Value) {
+        if let (Value::Object(params), Some(payload)) = (json!(self), payload.as_object_mut()) {
+            payload.extend(params);
+        }
+    }
+}
+
+/// Optional settings for a completion request. Anything not set uses
+/// the default.
+#[derive(Clone, Debug, Default)]
+pub struct CompletionOptions {
+    /// Timeout for the whole request including reading the response
+    pub timeout: Option<Duration>,
+    /// Return the constructed payload instead of sending the request
+    pub dry_run: bool,
+    /// Log the request and response to this JSON lines file. Falls
+    /// back to the path set by `request_log::configure`.
+    pub log_path: Option<PathBuf>,
+    /// Number of times the request is retried after a rate limit or
+    /// server error
+    pub max_retries: Option<usize>,
+    /// Delay before the first retry, doubled for each retry after that
+    pub retry_base_delay: Option<Duration>,
+    /// Called before each retry, stopping the retries if it returns an
+    /// error e.g. when the caller's retry budget runs out
+    pub on_retry: Option<RetryHook>,
+    /// Constrain the response format for models that support it e.g.
+    /// `{"type": "json_object"}` to always respond with valid JSON
+    pub response_format: Option<Value>,
+    /// Sampling parameters such as the temperature
+    pub params: CompletionParams,
+    /// Reasoning effort for models that support it. Other models
+    /// reject requests that set it.
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Providers to try in order when the request can't connect or
+    /// fails with a server error after retries. Streaming completions
+    /// only fall back before the first chunk is forwarded.
+    pub fallbacks: Vec<Provider>,
+    /// Stop a streaming completion when cancelled e.g. the client
+    /// disconnected. The response is dropped rather than read to the
+    /// end so the upstream connection is freed right away.
+    pub cancel: Option<CancellationToken>,
+    /// Combine content and reasoning deltas of a streaming completion
+    /// that arrive within this window into one chunk. Every delta is
+    /// forwarded as it arrives when not set.
+    pub coalesce_window: Option<Duration>,
+}
+
+type RetryFn = dyn Fn(&Error) -> Result<(), Error> + Send + Sync;
+
+/// Called with the error that failed a request before it's retried.
+/// Returning an error stops retrying and fails the request with it.
+#[derive(Clone)]
+pub struct RetryHook(Arc<RetryFn>);
+
+impl RetryHook {
+    pub fn new(hook: impl Fn(&Error) -> Result<(), Error> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+impl std::fmt::Debug for RetryHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RetryHook")
+    }
+}
+
+impl CompletionOptions {
+    pub(crate) fn log_path(&self) -> Option<&Path> {
+        self.log_path
+            .as_deref()
+            .or_else(|| request_log::configured_path())
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|t| t.is_cancelled())
+    }
+
+    /// Resolves when the completion is cancelled or never if there is
+    /// no cancellation token
+    async fn cancelled(&self) {
+        match &self.cancel {
+            Some(token) => token.cancelled().await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// Build the request body for a chat completion of `messages` with
+/// the optional `tools` and settings from `options`
+fn build_payload(
     messages: &Vec<Message>,
     tools: &Option<Vec<BoxedToolCall>>,
-    api_hostname: &str,
-    api_key: &str,
     model: &str,
-) -> Result<Value, Error> {
+    options: &CompletionOptions,
+) -> Value {
     let mut payload = json!({
         "model": model,
         "messages": messages,
@@ -171,17 +494,324 @@ pub async fn completion(
     if let Some(tools) = tools {
         payload["tools"] = json!(tools);
     }
+    if let Some(response_format) = &options.response_format {
+        payload["response_format"] = response_format.clone();
+    }
+    options.params.merge_into(&mut payload);
+    if let Some(reasoning_effort) = options.reasoning_effort {
+        payload["reasoning_effort"] = json!(reasoning_effort);
+    }
+    payload
+}
+
+pub async fn completion(
+    messages: &Vec<Message>,
+    tools: &Option<Vec<BoxedToolCall>>,
+    api_hostname: &str,
+    api_key: &str,
+    model: &str,
+    options: &CompletionOptions,
+) -> Result<Value, Error> {
+    let payload = build_payload(messages, tools, model, options);
+    if options.dry_run {
+        return Ok(payload);
+    }
+    let primary = Provider::new(api_hostname, api_key, model);
+    let (provider, result) = send_with_fallbacks(primary, &options.fallbacks, |provider| {
+        let payload = provider_payload(&payload, &provider);
+        async move {
+            send_completion(&payload, &provider.api_hostname, &provider.api_key, options).await
+        }
+    })
+    .await;
+    if let Some(log_path) = options.log_path() {
+        request_log::record(log_path, &provider_payload(&payload, &provider), &result).await;
+    }
+    result
+}
+
+/// Error body returned by OpenAI compatible APIs e.g.
+/// `{"error": {"message": "...", "type": "...", "code": "..."}}`
+#[derive(Debug, Deserialize)]
+pub struct OpenAiErrorResponse {
+    pub error: OpenAiErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiErrorDetail {
+    pub message: String,
+    #[serde(default)]
+    pub r#type: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// A non-2xx response from the API
+#[derive(Debug)]
+pub struct OpenAiApiError {
+    pub status: reqwest::StatusCode,
+    pub message: String,
+    pub r#type: Option<String>,
+    pub code: Option<String>,
+    /// How long the API asked to wait before sending the request
+    /// again from the `Retry-After` header
+    pub retry_after: Option<Duration>,
+}
+
+impl std::fmt::Display for OpenAiApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OpenAI API error ({}): {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for OpenAiApiError {}
+
+/// A streaming completion was cancelled before it finished
+#[derive(Debug)]
+pub struct CompletionCancelled;
+
+impl std::fmt::Display for CompletionCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Completion was cancelled")
+    }
+}
+
+impl std::error::Error for CompletionCancelled {}
+
+/// A streaming completion failed after chunks were already forwarded.
+/// It's never sent to a fallback provider since the chunks from the
+/// new response would be appended to the ones already forwarded.
+#[derive(Debug)]
+pub struct CompletionStreamInterrupted(Error);
+
+impl std::fmt::Display for CompletionStreamInterrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Completion stream was interrupted: {:#}", self.0)
+    }
+}
+
+impl std::error::Error for CompletionStreamInterrupted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+impl OpenAiApiError {
+    /// Whether the request was rejected for having more tokens than
+    /// the model's context window
+    pub fn is_context_length_exceeded(&self) -> bool {
+        self.code.as_deref() == Some("context_length_exceeded")
+            || self.message.contains("maximum context length")
+    }
+
+    /// Whether the request might succeed if it's sent again e.g. rate
+    /// limits and temporary server errors
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.status.as_u16(), 429 | 500 | 502 | 503)
+    }
+}
+
+/// Return the response if it was successful otherwise an error with
+/// the provider's error message. Falls back to the raw body when it
+/// isn't an OpenAI style error.
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    // Only the delay in seconds form is supported, not HTTP dates
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let body = response.text().await.unwrap_or_default();
+    let err = match serde_json::from_str::<OpenAiErrorResponse>(&body) {
+        Ok(OpenAiErrorResponse { error }) => OpenAiApiError {
+            status,
+            message: error.message,
+            r#type: error.r#type,
+            code: error.code,
+            retry_after,
+        },
+        Err(_) => OpenAiApiError {
+            status,
+            message: body,
+            r#type: None,
+            code: None,
+            retry_after,
+        },
+    };
+    Err(err.into())
+}
+
+/// Random delay up to `max` so that clients retrying at the same time
+/// don't all hit the API again at once
+fn jitter(max: Duration) -> Duration {
+    let max_millis = max.as_millis() as u64;
+    if max_millis == 0 {
+        return Duration::ZERO;
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or_default();
+    Duration::from_millis(nanos % max_millis)
+}
+
+/// The longest a `Retry-After` header can make a request wait before
+/// it's retried
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How long to wait before the `attempt`th retry. Uses the delay the
+/// API asked for if there is one, up to `MAX_RETRY_AFTER`, otherwise
+/// backs off exponentially from `base_delay` with jitter.
+fn retry_delay(base_delay: Duration, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    if let Some(retry_after) = retry_after {
+        return retry_after.min(MAX_RETRY_AFTER);
+    }
+    base_delay.saturating_mul(2u32.saturating_pow(attempt - 1)) + jitter(base_delay)
+}
+
+/// Send the request made by `request` and check the response,
+/// retrying dropped connections, rate limits, and temporary server
+/// errors with backoff up to the number of retries in `options`
+async fn send_with_retries<F>(
+    request: F,
+    options: &CompletionOptions,
+) -> Result<reqwest::Response, Error>
+where
+    F: Fn() -> Result<reqwest::RequestBuilder, Error>,
+{
+    let max_retries = options
+        .max_retries
+        .unwrap_or(DEFAULT_COMPLETION_MAX_RETRIES);
+    let base_delay = options
+        .retry_base_delay
+        .unwrap_or(DEFAULT_COMPLETION_RETRY_BASE_DELAY);
+    let mut attempt = 0;
+    loop {
+        let err = match request()?.send().await {
+            Ok(response) => match check_response(response).await {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            },
+            Err(err) => err.into(),
+        };
+        let retryable = match err.downcast_ref::<OpenAiApiError>() {
+            Some(api_err) => api_err.is_retryable(),
+            None => err
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_connect()),
+        };
+        if !retryable || attempt >= max_retries {
+            return Err(err);
+        }
+        if let Some(on_retry) = &options.on_retry {
+            (on_retry.0)(&err)?;
+        }
+        attempt += 1;
+        let retry_after = err
+            .downcast_ref::<OpenAiApiError>()
+            .and_then(|e| e.retry_after);
+        let delay = retry_delay(base_delay, attempt as u32, retry_after);
+        tracing::warn!(
+            "Completion request failed, retrying in {:?} ({}/{}): {:#}",
+            delay,
+            attempt,
+            max_retries,
+            err
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Whether a request that failed with `err` should be sent to the
+/// next provider. Only errors from the provider being unavailable fall
+/// back since a different provider would reject a bad request too.
+fn should_fall_back(err: &Error) -> bool {
+    if let Some(api_err) = err.downcast_ref::<OpenAiApiError>() {
+        return api_err.status.is_server_error();
+    }
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+/// The payload to send to `provider` which only differs by the model
+fn provider_payload(payload: &Value, provider: &Provider) -> Value {
+    let mut payload = payload.clone();
+    payload["model"] = json!(provider.model);
+    payload
+}
+
+/// Send the request to the `primary` provider using `send`, trying
+/// each of the `fallbacks` in order if the previous one is
+/// unavailable. The first successful response wins and its `model` is
+/// set to the model of the provider that answered. Returns the last
+/// provider tried along with its result.
+async fn send_with_fallbacks<F, Fut>(
+    primary: Provider,
+    fallbacks: &[Provider],
+    send: F,
+) -> (Provider, Result<Value, Error>)
+where
+    F: Fn(Provider) -> Fut,
+    Fut: Future<Output = Result<Value, Error>>,
+{
+    let mut providers = std::iter::once(primary)
+        .chain(fallbacks.iter().cloned())
+        .enumerate()
+        .peekable();
+    loop {
+        // There is always at least the primary provider
+        let (idx, provider) = providers.next().expect("No providers to send to");
+        let (api_hostname, model) = (provider.api_hostname.clone(), provider.model.clone());
+        match send(provider.clone()).await {
+            Ok(mut resp) => {
+                if let Some(resp) = resp.as_object_mut() {
+                    resp.insert("model".to_string(), json!(model));
+                }
+                if idx > 0 {
+                    tracing::info!(
+                        "Completion sent to fallback {} using {}",
+                        api_hostname,
+                        model
+                    );
+                } else {
+                    tracing::debug!("Completion sent to {} using {}", api_hostname, model);
+                }
+                return (provider, Ok(resp));
+            }
+            Err(err) if should_fall_back(&err) && providers.peek().is_some() => {
+                tracing::warn!(
+                    "Completion failed for {} using {}, trying the next provider: {:#}",
+                    api_hostname,
+                    model,
+                    err
+                );
+            }
+            Err(err) => return (provider, Err(err)),
+        }
+    }
+}
+
+pub(crate) async fn send_completion(
+    payload: &Value,
+    api_hostname: &str,
+    api_key: &str,
+    options: &CompletionOptions,
+) -> Result<Value, Error> {
     let url = format!("{}/v1/chat/completions", api_hostname.trim_end_matches("/"));
-    let response = reqwest::Client::new()
-        .post(url)
-        .bearer_auth(api_key)
-        .header("Content-Type", "application/json")
-        .timeout(Duration::from_secs(60 * 10))
-        .json(&payload)
-        .send()
-        .await?
-        .json()
-        .await?;
+    let request = || {
+        Ok(http::shared_client()?
+            .post(&url)
+            .bearer_auth(api_key)
+            .header("Content-Type", "application/json")
+            .timeout(options.timeout.unwrap_or(DEFAULT_COMPLETION_TIMEOUT))
+            .json(payload))
+    };
+    let response = send_with_retries(request, options).await?.json().await?;
 
     Ok(response)
 }
@@ -246,6 +876,14 @@ enum Delta {
     Stop {},
 }
 
+/// Number of tokens used by a completion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+}
+
 #[derive(Debug, Deserialize)]
 struct CompletionChunkChoice {
     #[allow(dead_code)]
@@ -266,7 +904,84 @@ struct CompletionChunk {
     model: String,
     #[allow(dead_code)]
     system_fingerprint: String,
+    #[serde(default)]
     choices: Vec<CompletionChunkChoice>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+/// Combines content and reasoning deltas of a streaming completion
+/// that arrive within a window into one chunk so clients receive
+/// fewer, larger events. Pending text is sent when the window passes
+/// or any other kind of chunk arrives so the order is preserved.
+struct ChunkCoalescer {
+    window: Option<Duration>,
+    /// First chunk of the pending deltas, the field of its delta that
+    /// is being combined, and the combined text
+    pending: Option<(Value, &'static str, String)>,
+    deadline: Option<tokio::time::Instant>,
+}
+
+impl ChunkCoalescer {
+    fn new(window: Option<Duration>) -> Self {
+        Self {
+            window,
+            pending: None,
+            deadline: None,
+        }
+    }
+
+    /// Forward the chunk in `data` to `tx`. Text deltas are held until
+    /// the window passes so they can be combined.
+    fn send(
+        &mut self,
+        tx: &mpsc::UnboundedSender<String>,
+        data: &str,
+        choice: &CompletionChunkChoice,
+    ) {
+        let text = match (&choice.delta, &choice.finish_reason) {
+            (Delta::Content { content }, None) => Some(("content", content)),
+            (Delta::Reasoning { reasoning }, None) => Some(("reasoning", reasoning)),
+            _ => None,
+        };
+        let (Some(window), Some((field, text))) = (self.window, text) else {
+            self.flush(tx);
+            let _ = tx.send(data.to_string());
+            return;
+        };
+        match &mut self.pending {
+            Some((_, pending_field, buf)) if *pending_field == field => buf.push_str(text),
+            _ => {
+                self.flush(tx);
+                // Already parsed as a completion chunk so this is valid
+                let chunk: Value =
+                    serde_json::from_str(data).expect("Failed to parse completion chunk");
+                self.pending = Some((chunk, field, text.clone()));
+                self.deadline = Some(tokio::time::Instant::now() + window);
+            }
+        }
+    }
+
+    /// Send any pending text as one chunk
+    fn flush(&mut self, tx: &mpsc::UnboundedSender<String>) {
+        self.deadline = None;
+        if let Some((mut chunk, field, text)) = self.pending.take() {
+            chunk["choices"][0]["delta"][field] = json!(text);
+            let _ = tx.send(chunk.to_string());
+        }
+    }
+
+    /// Resolves when the pending text needs to be sent or never if
+    /// there is nothing pending
+    fn deadline(&self) -> impl Future<Output = ()> + use<> {
+        let deadline = self.deadline;
+        async move {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        }
+    }
 }
 
 pub async fn completion_stream(
@@ -276,35 +991,95 @@ pub async fn completion_stream(
     api_hostname: &str,
     api_key: &str,
     model: &str,
+    options: &CompletionOptions,
 ) -> Result<Value, Error> {
-    let mut payload = json!({
-        "model": model,
-        "messages": messages,
-        "stream": true,
-        "stream_options": {"include_usage": true}
-    });
-    if let Some(tools) = tools {
-        payload["tools"] = json!(tools);
+    let mut payload = build_payload(messages, tools, model, options);
+    payload["stream"] = json!(true);
+    payload["stream_options"] = json!({"include_usage": true});
+    if options.dry_run {
+        return Ok(payload);
+    }
+    let primary = Provider::new(api_hostname, api_key, model);
+    let (provider, result) = send_with_fallbacks(primary, &options.fallbacks, |provider| {
+        let tx = tx.clone();
+        let payload = provider_payload(&payload, &provider);
+        async move {
+            send_completion_stream(
+                tx,
+                &payload,
+                &provider.api_hostname,
+                &provider.api_key,
+                options,
+            )
+            .await
+        }
+    })
+    .await;
+    if let Some(log_path) = options.log_path() {
+        request_log::record(log_path, &provider_payload(&payload, &provider), &result).await;
     }
+    result
+}
+
+async fn send_completion_stream(
+    tx: mpsc::UnboundedSender<String>,
+    payload: &Value,
+    api_hostname: &str,
+    api_key: &str,
+    options: &CompletionOptions,
+) -> Result<Value, Error> {
     let url = format!("{}/v1/chat/completions", api_hostname.trim_end_matches("/"));
-    let response = reqwest::Client::new()
-        .post(url)
-        .bearer_auth(api_key)
-        .header("Content-Type", "application/json")
-        .timeout(Duration::from_secs(60 * 5))
-        .json(&payload)
-        .send()
-        .await?;
+    let request = || {
+        Ok(http::shared_client()?
+            .post(&url)
+            .bearer_auth(api_key)
+            .header("Content-Type", "application/json")
+            .timeout(options.timeout.unwrap_or(DEFAULT_COMPLETION_STREAM_TIMEOUT))
+            .json(payload))
+    };
+    // Only the request is retried, errors after the response starts
+    // streaming are returned since chunks were already sent
+    let response = tokio::select! {
+        biased;
+        _ = options.cancelled() => return Err(CompletionCancelled.into()),
+        response = send_with_retries(request, options) => response?,
+    };
 
     let mut stream = response.bytes_stream();
 
     let mut content_buf = String::from("");
     let mut reasoning_buf: String = String::from("");
     let mut tool_calls: HashMap<usize, ToolCallFinal> = HashMap::new();
+    let mut usage: Option<Usage> = None;
     let mut buffer = String::new();
-
-    'outer: while let Some(chunk) = stream.next().await {
-        let chunk = chunk.expect("Invalid chunk");
+    let mut coalescer = ChunkCoalescer::new(options.coalesce_window);
+    // Errors after the first chunk is forwarded can't fall back to
+    // another provider
+    let mut forwarded = false;
+
+    'outer: loop {
+        // Returning drops the response which closes the connection
+        // instead of waiting for the rest of the stream
+        let chunk = tokio::select! {
+            biased;
+            _ = options.cancelled() => {
+                tracing::info!("Completion stream cancelled");
+                return Err(CompletionCancelled.into());
+            }
+            _ = coalescer.deadline() => {
+                coalescer.flush(&tx);
+                continue;
+            }
+            chunk = stream.next() => chunk,
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) if forwarded => return Err(CompletionStreamInterrupted(e.into()).into()),
+            Err(e) => return Err(e.into()),
+        };
         let chunk_str = std::str::from_utf8(&chunk)?;
 
         // Append new data to buffer. This is necessary to handle SSE
@@ -335,13 +1110,10 @@ pub async fn completion_stream(
                 continue;
             }
 
-            // Forward the chunk to the receiver channel
-            // (The result is ignored here because we want to complete
-            // processing the response)
-            let _ = tx.send(data.to_string());
-
             // Handle the end of the stream
             if data == "[DONE]" {
+                coalescer.flush(&tx);
+                let _ = tx.send(data.to_string());
                 break 'outer;
             }
 
@@ -349,18 +1121,33 @@ pub async fn completion_stream(
             let chunk = serde_json::from_str::<CompletionChunk>(data).inspect_err(|e| {
                 tracing::error!("Parsing completion chunk failed for {}\nError:{}", data, e)
             })?;
-            let choice = chunk.choices.first().expect("Missing choices field");
+
+            // Token usage comes in a chunk of its own without any
+            // choices after the message is finished. It's kept out of
+            // the forwarded chunks since it has no delta.
+            if let Some(chunk_usage) = chunk.usage {
+                usage = Some(chunk_usage);
+            }
+            let Some(choice) = chunk.choices.first() else {
+                continue;
+            };
+
+            // Forward the chunk to the receiver channel
+            // (The result is ignored here because we want to complete
+            // processing the response)
+            coalescer.send(&tx, data, choice);
+            forwarded = true;
 
             match &choice.delta {
                 Delta::Reasoning { reasoning } => {
                     if choice.finish_reason.is_some() {
-                        break 'outer;
+                        continue;
                     }
                     reasoning_buf += &reasoning.clone();
                 }
                 Delta::Content { content } => {
                     if choice.finish_reason.is_some() {
-                        break 'outer;
+                        continue;
                     }
 
                     content_buf += &content.clone();
@@ -369,7 +1156,7 @@ pub async fn completion_stream(
                     tool_calls: tool_call_deltas,
                 } => {
                     if choice.finish_reason.is_some() {
-                        break 'outer;
+                        continue;
                     }
                     for tool_call_delta in tool_call_deltas.iter() {
                         match tool_call_delta {
@@ -401,38 +1188,102 @@ pub async fn completion_stream(
                         }
                     }
                 }
-                Delta::Stop {} => {
-                    break 'outer;
-                }
+                // Keep reading after the message is finished for the
+                // usage chunk that comes before the end of the stream
+                Delta::Stop {} => {}
             }
         }
     }
 
+    coalescer.flush(&tx);
+
     // Handle if this is a tool call or a content message
-    if !tool_calls.is_empty() {
+    let mut message = if !tool_calls.is_empty() {
         let tool_call_message = tool_calls.values().collect::<Vec<_>>();
-        let out = json!({
-            "choices": [{"message": {"tool_calls": tool_call_message}}]
-        });
-        return Ok(out);
+        json!({"tool_calls": tool_call_message})
+    } else {
+        json!({"content": content_buf})
+    };
+    if !reasoning_buf.is_empty() {
+        message["reasoning"] = json!(reasoning_buf);
     }
-
-    let out = json!({
+    let mut out = json!({
         "choices": [
-            {"message": {"content": content_buf}}
+            {"message": message}
         ]
     });
+    if let Some(usage) = usage {
+        out["usage"] = json!(usage);
+    }
     Ok(out)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Maximum number of texts sent in each embeddings request
+pub const EMBEDDINGS_BATCH_SIZE: usize = 100;
 
-    #[test]
-    fn test_role_serialization() {
-        assert_eq!(serde_json::to_string(&Role::System).unwrap(), r#""system""#);
-        assert_eq!(
+/// Timeout for each embeddings request
+const EMBEDDINGS_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+/// Embed `texts` using an OpenAI compatible `/v1/embeddings` endpoint.
+/// Texts are sent in batches of `EMBEDDINGS_BATCH_SIZE` and the
+/// vectors are returned in the same order as `texts`.
+pub async fn embeddings(
+    texts: &[String],
+    api_hostname: &str,
+    api_key: &str,
+    model: &str,
+) -> Result<Vec<Vec<f32>>, Error> {
+    let url = format!("{}/v1/embeddings", api_hostname.trim_end_matches("/"));
+    let options = CompletionOptions::default();
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBEDDINGS_BATCH_SIZE) {
+        let payload = json!({
+            "model": model,
+            "input": batch,
+        });
+        let request = || {
+            Ok(http::shared_client()?
+                .post(&url)
+                .bearer_auth(api_key)
+                .timeout(EMBEDDINGS_TIMEOUT)
+                .json(&payload))
+        };
+        let mut resp: EmbeddingsResponse =
+            send_with_retries(request, &options).await?.json().await?;
+        if resp.data.len() != batch.len() {
+            return Err(anyhow!(
+                "Expected {} embeddings but received {}",
+                batch.len(),
+                resp.data.len()
+            ));
+        }
+        // The API doesn't guarantee the order of the data so sort by
+        // the index of the input
+        resp.data.sort_by_key(|d| d.index);
+        vectors.extend(resp.data.into_iter().map(|d| d.embedding));
+    }
+    Ok(vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_serialization() {
+        assert_eq!(serde_json::to_string(&Role::System).unwrap(), r#""system""#);
+        assert_eq!(
             serde_json::to_string(&Role::Assistant).unwrap(),
             r#""assistant""#
         );
@@ -470,6 +1321,69 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_message_text_only_serializes_content_as_string() {
+        let msg = Message::new(Role::User, "Describe this");
+        let value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"role": "user", "content": "Describe this"})
+        );
+
+        let roundtrip: Message = serde_json::from_value(value).unwrap();
+        assert_eq!(roundtrip.content(), Some("Describe this"));
+        assert!(roundtrip.content_parts().is_none());
+    }
+
+    #[test]
+    fn test_message_new_with_parts() {
+        let msg = Message::new_with_parts(
+            Role::User,
+            vec![
+                ContentPart::text("What's in this image?"),
+                ContentPart::image_url("https://example.com/cat.png"),
+            ],
+        );
+        let value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "What's in this image?"},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+                ]
+            })
+        );
+
+        // Text parts are still available as plain content
+        assert_eq!(msg.content(), Some("What's in this image?"));
+
+        let roundtrip: Message = serde_json::from_value(value).unwrap();
+        assert_eq!(roundtrip.content_parts(), msg.content_parts());
+        assert_eq!(roundtrip.content(), Some("What's in this image?"));
+    }
+
+    #[test]
+    fn test_message_push_content() {
+        let mut msg = Message::new(Role::System, "Be brief.");
+        msg.push_content("Cite your sources.");
+        assert_eq!(msg.content(), Some("Be brief.\n\nCite your sources."));
+
+        // Appending to a multimodal message adds a text part so the
+        // parts sent to the model match the content
+        let mut msg = Message::new_with_parts(
+            Role::User,
+            vec![ContentPart::image_url("https://example.com/cat.png")],
+        );
+        msg.push_content("What's in this image?");
+        assert_eq!(msg.content(), Some("What's in this image?"));
+        assert_eq!(
+            msg.content_parts().unwrap().last(),
+            Some(&ContentPart::text("What's in this image?"))
+        );
+    }
+
     #[test]
     fn test_message_new_tool_call_request() {
         let tool_calls = vec![FunctionCall {
@@ -746,7 +1660,15 @@ mod tests {
             .create();
 
         let messages = vec![Message::new(Role::User, "Hi")];
-        let result = completion(&messages, &None, server.url().as_str(), "test-key", "gpt-4").await;
+        let result = completion(
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions::default(),
+        )
+        .await;
 
         mock.assert();
         assert!(result.is_ok());
@@ -755,6 +1677,236 @@ mod tests {
         assert_eq!(json["choices"][0]["message"]["content"], "Hello!");
     }
 
+    #[tokio::test]
+    async fn test_embeddings() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "model": "text-embedding-3-small",
+                "input": ["first", "second"],
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "object": "list",
+                    "data": [
+                        {"object": "embedding", "index": 1, "embedding": [0.4, 0.5, 0.6]},
+                        {"object": "embedding", "index": 0, "embedding": [0.1, 0.2, 0.3]},
+                    ],
+                    "model": "text-embedding-3-small",
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let vectors = embeddings(
+            &texts,
+            server.url().as_str(),
+            "test-key",
+            "text-embedding-3-small",
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(vectors.len(), 2);
+        assert!(vectors.iter().all(|v| v.len() == 3));
+        assert_eq!(vectors[0], vec![0.1, 0.2, 0.3]);
+        assert_eq!(vectors[1], vec![0.4, 0.5, 0.6]);
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_errors_on_missing_vectors() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/embeddings")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({"data": [{"index": 0, "embedding": [0.1]}]}).to_string())
+            .create_async()
+            .await;
+
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let err = embeddings(&texts, server.url().as_str(), "test-key", "model")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Expected 2 embeddings but received 1");
+    }
+
+    #[tokio::test]
+    async fn test_completion_writes_request_log() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"choices": [{"message": {"role": "assistant", "content": "Hello!"}}]}"#)
+            .create();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let log_path = dir.path().join("llm.jsonl");
+        let options = CompletionOptions {
+            log_path: Some(log_path.clone()),
+            params: CompletionParams {
+                temperature: Some(0.5),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let messages = vec![Message::new(
+            Role::User,
+            "Use api_key=sk-live-abcdefghijklmnopqrstuvwxyz to call it",
+        )];
+        for _ in 0..2 {
+            completion(
+                &messages,
+                &None,
+                server.url().as_str(),
+                "test-key",
+                "gpt-4",
+                &options,
+            )
+            .await
+            .unwrap();
+        }
+
+        mock.expect(2).assert();
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let entry: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(entry["request"]["model"], "gpt-4");
+        assert_eq!(entry["request"]["temperature"], 0.5);
+        assert_eq!(entry["request"]["messages"][0]["role"], "user");
+        assert_eq!(
+            entry["request"]["messages"][0]["content"],
+            "Use api_key=[REDACTED] to call it"
+        );
+        assert_eq!(
+            entry["response"]["choices"][0]["message"]["content"],
+            "Hello!"
+        );
+        assert!(entry["timestamp"].is_string());
+        assert!(!log.contains("sk-live-abcdefghijklmnopqrstuvwxyz"));
+        assert!(!log.contains("test-key"));
+    }
+
+    #[tokio::test]
+    async fn test_completion_timeout() {
+        let mut server = mockito::Server::new_async().await;
+
+        // Respond slower than the configured timeout
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_chunked_body(|w| {
+                std::thread::sleep(std::time::Duration::from_secs(3));
+                w.write_all(b"{}")
+            })
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Hi")];
+        let options = CompletionOptions {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        let result = completion(
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &options,
+        )
+        .await;
+
+        mock.assert();
+        let err = result.expect_err("Expected a timeout error");
+        let err = err
+            .downcast_ref::<reqwest::Error>()
+            .expect("Expected a reqwest error");
+        assert!(err.is_timeout());
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_completion_dry_run() {
+        let mut server = mockito::Server::new_async().await;
+
+        // Dry run should never hit the API
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(0)
+            .create();
+
+        #[derive(serde::Serialize)]
+        struct MockTool;
+        #[async_trait]
+        impl ToolCall for MockTool {
+            async fn call(&self, _args: &str) -> Result<String, Error> {
+                Ok("mock result".to_string())
+            }
+            fn function_name(&self) -> String {
+                "search_notes".to_string()
+            }
+        }
+
+        let messages = vec![
+            Message::new(Role::System, "You are a helpful assistant"),
+            Message::new(Role::User, "Search for test"),
+        ];
+        let tools = Some(vec![Box::new(MockTool) as BoxedToolCall]);
+        let options = CompletionOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let payload = completion(
+            &messages,
+            &tools,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &options,
+        )
+        .await
+        .unwrap();
+
+        mock.assert();
+        assert_eq!(payload["model"], "gpt-4");
+        assert_eq!(payload["messages"], json!(messages));
+        assert_eq!(payload["tools"], json!(tools));
+        assert!(payload.get("stream").is_none());
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let payload = completion_stream(
+            tx,
+            &messages,
+            &tools,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &options,
+        )
+        .await
+        .unwrap();
+
+        mock.assert();
+        assert_eq!(payload["model"], "gpt-4");
+        assert_eq!(payload["messages"], json!(messages));
+        assert_eq!(payload["tools"], json!(tools));
+        assert_eq!(payload["stream"], true);
+        assert!(rx.try_recv().is_err());
+    }
+
     #[tokio::test]
     async fn test_completion_with_tools() {
         let mut server = mockito::Server::new_async().await;
@@ -811,6 +1963,7 @@ mod tests {
             server.url().as_str(),
             "test-key",
             "gpt-4",
+            &CompletionOptions::default(),
         )
         .await;
 
@@ -822,82 +1975,828 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_completion_stream_content() {
+    async fn test_completion_with_response_format() {
         let mut server = mockito::Server::new_async().await;
 
-        // SSE response with content chunks
-        let sse_response = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}
-
-data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":" World"},"finish_reason":null}]}
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "response_format": {"type": "json_object"}
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"choices": [{"message": {"role": "assistant", "content": "{\"title\": \"Hi\"}"}}]}"#,
+            )
+            .create();
 
-data: {"id":"chunk3","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"!"},"finish_reason":"stop"}]}
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let result = completion(
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                response_format: Some(json!({"type": "json_object"})),
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
 
-data: [DONE]
+        mock.assert();
+        assert_eq!(
+            result["choices"][0]["message"]["content"],
+            r#"{"title": "Hi"}"#
+        );
 
-"#;
+        // Not included unless requested
+        let payload = completion(
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                dry_run: true,
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(payload.get("response_format").is_none());
+    }
 
+    #[tokio::test]
+    async fn test_completion_falls_back_to_next_provider() {
+        let mut server = mockito::Server::new_async().await;
         let mock = server
             .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({"model": "fallback-model"})))
             .with_status(200)
-            .with_header("content-type", "text/event-stream")
-            .with_body(sse_response)
-            .create();
-
-        let messages = vec![Message::new(Role::User, "Say hello")];
-        let (tx, mut rx) = mpsc::unbounded_channel();
-        let server_url = server.url();
-
-        // Run completion_stream in a separate task
-        let handle = tokio::spawn(async move {
-            completion_stream(
-                tx,
-                &messages,
-                &None,
-                server_url.as_str(),
-                "test-key",
-                "gpt-4",
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"choices": [{"message": {"role": "assistant", "content": "From the fallback"}}]}"#,
             )
-            .await
-        });
+            .create();
 
-        // Wait for the task to complete
-        let result = tokio::time::timeout(tokio::time::Duration::from_secs(5), handle).await;
+        // Nothing is listening on the primary provider's port
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let result = completion(
+            &messages,
+            &None,
+            "http://127.0.0.1:1",
+            "test-key",
+            "local-model",
+            &CompletionOptions {
+                max_retries: Some(0),
+                fallbacks: vec![Provider::new(
+                    server.url().as_str(),
+                    "fallback-key",
+                    "fallback-model",
+                )],
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
 
         mock.assert();
-        assert!(result.is_ok());
-        assert!(result.unwrap().unwrap().is_ok());
-
-        // The channel should have received the raw JSON chunks
-        let mut chunk_count = 0;
-        while let Ok(_) = rx.try_recv() {
-            chunk_count += 1;
-        }
-        assert!(chunk_count >= 3);
+        assert_eq!(
+            result["choices"][0]["message"]["content"],
+            "From the fallback"
+        );
     }
 
     #[tokio::test]
-    async fn test_completion_stream_tool_call() {
+    async fn test_completion_logs_the_fallback_that_answered() {
         let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"choices": [{"message": {"role": "assistant", "content": "From the fallback"}}]}"#,
+            )
+            .create();
 
-        // SSE response with tool call chunks
-        let sse_response = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"id":"call_abc123","index":0,"function":{"name":"search_notes","arguments":"{\"query\":"},"type":"function"}]},"finish_reason":null}]}
-
-data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"test\"}"}}]},"finish_reason":null}]}
-
-data: {"id":"chunk3","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":""}}]},"finish_reason":"stop"}]}
+        let dir = tempfile::TempDir::new().unwrap();
+        let log_path = dir.path().join("llm.jsonl");
+        let messages = vec![Message::new(Role::User, "Hello")];
+        completion(
+            &messages,
+            &None,
+            "http://127.0.0.1:1",
+            "test-key",
+            "local-model",
+            &CompletionOptions {
+                max_retries: Some(0),
+                log_path: Some(log_path.clone()),
+                fallbacks: vec![Provider::new(
+                    server.url().as_str(),
+                    "fallback-key",
+                    "fallback-model",
+                )],
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
 
-data: [DONE]
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let entry: Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+        assert_eq!(entry["request"]["model"], "fallback-model");
+        assert_eq!(entry["response"]["model"], "fallback-model");
+    }
 
-"#;
+    #[tokio::test]
+    async fn test_completion_stream_falls_back_on_server_error() {
+        let mut primary = mockito::Server::new_async().await;
+        let primary_mock = primary
+            .mock("POST", "/v1/chat/completions")
+            .with_status(502)
+            .with_body("Bad gateway")
+            .create();
 
-        let mock = server
+        let mut fallback = mockito::Server::new_async().await;
+        let fallback_mock = fallback
             .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", "Bearer fallback-key")
             .with_status(200)
             .with_header("content-type", "text/event-stream")
-            .with_body(sse_response)
+            .with_body(
+                "data: {\"id\":\"chunk1\",\"created\":1234567890,\"model\":\"gpt-4.1-mini\",\"system_fingerprint\":\"fp1\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n",
+            )
             .create();
 
-        let messages = vec![Message::new(Role::User, "Search for test")];
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let result = completion_stream(
+            tx,
+            &messages,
+            &None,
+            primary.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                max_retries: Some(0),
+                fallbacks: vec![Provider::new(
+                    fallback.url().as_str(),
+                    "fallback-key",
+                    "gpt-4.1-mini",
+                )],
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        primary_mock.assert();
+        fallback_mock.assert();
+        assert_eq!(result["choices"][0]["message"]["content"], "Hi");
+        // Labeled with the model that answered
+        assert_eq!(result["model"], "gpt-4.1-mini");
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_does_not_fall_back_after_forwarding_chunks() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // The primary sends one chunk and then stalls until the
+        // request times out
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let primary_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let _ = socket.read(&mut buf).await;
+            let chunk = "data: {\"id\":\"chunk1\",\"created\":1234567890,\"model\":\"gpt-4\",\"system_fingerprint\":\"fp1\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+                chunk.len(),
+                chunk
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let mut fallback = mockito::Server::new_async().await;
+        let fallback_mock = fallback
+            .mock("POST", "/v1/chat/completions")
+            .expect(0)
+            .create();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let err = completion_stream(
+            tx,
+            &messages,
+            &None,
+            &primary_url,
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                timeout: Some(Duration::from_millis(500)),
+                fallbacks: vec![Provider::new(
+                    fallback.url().as_str(),
+                    "fallback-key",
+                    "gpt-4.1-mini",
+                )],
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap_err();
+
+        fallback_mock.assert();
+        assert!(err.downcast_ref::<CompletionStreamInterrupted>().is_some());
+        assert!(rx.recv().await.unwrap().contains("Hi"));
+    }
+
+    #[tokio::test]
+    async fn test_completion_does_not_fall_back_on_client_error() {
+        let mut primary = mockito::Server::new_async().await;
+        let primary_mock = primary
+            .mock("POST", "/v1/chat/completions")
+            .with_status(400)
+            .with_body(r#"{"error": {"message": "Invalid request"}}"#)
+            .create();
+
+        let mut fallback = mockito::Server::new_async().await;
+        let fallback_mock = fallback
+            .mock("POST", "/v1/chat/completions")
+            .expect(0)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let result = completion(
+            &messages,
+            &None,
+            primary.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                fallbacks: vec![Provider::new(
+                    fallback.url().as_str(),
+                    "fallback-key",
+                    "gpt-4.1-mini",
+                )],
+                ..CompletionOptions::default()
+            },
+        )
+        .await;
+
+        assert!(result.is_err());
+        primary_mock.assert();
+        fallback_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_reasoning_effort() {
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let options = CompletionOptions {
+            dry_run: true,
+            reasoning_effort: Some(ReasoningEffort::High),
+            ..CompletionOptions::default()
+        };
+
+        let payload = completion(
+            &messages,
+            &None,
+            "http://localhost",
+            "test-key",
+            "o3",
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(payload["reasoning_effort"], "high");
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let payload = completion_stream(
+            tx,
+            &messages,
+            &None,
+            "http://localhost",
+            "test-key",
+            "o3",
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(payload["reasoning_effort"], "high");
+
+        // Left out unless configured
+        let payload = completion(
+            &messages,
+            &None,
+            "http://localhost",
+            "test-key",
+            "o3",
+            &CompletionOptions {
+                dry_run: true,
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(payload.get("reasoning_effort").is_none());
+    }
+
+    #[test]
+    fn test_parse_reasoning_effort() {
+        assert_eq!(
+            "medium".parse::<ReasoningEffort>().unwrap(),
+            ReasoningEffort::Medium
+        );
+        assert_eq!(ReasoningEffort::Low.to_string(), "low");
+        assert!("extreme".parse::<ReasoningEffort>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_params() {
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let options = CompletionOptions {
+            dry_run: true,
+            params: CompletionParams {
+                temperature: Some(0.2),
+                max_tokens: Some(256),
+                ..CompletionParams::default()
+            },
+            ..CompletionOptions::default()
+        };
+
+        let payload = completion(
+            &messages,
+            &None,
+            "http://localhost",
+            "test-key",
+            "gpt-4",
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "model": "gpt-4",
+                "messages": messages,
+                "temperature": 0.2,
+                "max_tokens": 256,
+            })
+        );
+
+        // Streaming requests include the same params
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let payload = completion_stream(
+            tx,
+            &messages,
+            &None,
+            "http://localhost",
+            "test-key",
+            "gpt-4",
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(payload["temperature"], json!(0.2));
+        assert_eq!(payload["max_tokens"], 256);
+        assert!(payload.get("top_p").is_none());
+        assert!(payload.get("presence_penalty").is_none());
+
+        // Nothing is added unless set
+        let payload = completion(
+            &messages,
+            &None,
+            "http://localhost",
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                dry_run: true,
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(payload, json!({"model": "gpt-4", "messages": messages}));
+    }
+
+    #[tokio::test]
+    async fn test_completion_surfaces_provider_error_message() {
+        let mut server = mockito::Server::new_async().await;
+
+        let response_body = r#"{
+            "error": {
+                "message": "This model's maximum context length is 128000 tokens.",
+                "type": "invalid_request_error",
+                "param": "messages",
+                "code": "context_length_exceeded"
+            }
+        }"#;
+
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(response_body)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let result = completion(
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions::default(),
+        )
+        .await;
+
+        mock.assert();
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("This model's maximum context length is 128000 tokens."),
+            "{}",
+            err
+        );
+        let err = err.downcast::<OpenAiApiError>().unwrap();
+        assert_eq!(err.status, reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(err.code.as_deref(), Some("context_length_exceeded"));
+    }
+
+    #[tokio::test]
+    async fn test_completion_retries_server_errors() {
+        let mut server = mockito::Server::new_async().await;
+
+        let unavailable = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .with_body("Service Unavailable")
+            .expect(2)
+            .create();
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"choices": [{"message": {"role": "assistant", "content": "Hi"}}]}"#)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let result = completion(
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                retry_base_delay: Some(Duration::from_millis(1)),
+                ..CompletionOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        unavailable.assert();
+        ok.assert();
+        assert_eq!(result["choices"][0]["message"]["content"], "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_retries_rate_limits() {
+        let mut server = mockito::Server::new_async().await;
+
+        let rate_limited = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .with_body(r#"{"error": {"message": "Rate limit reached", "type": "requests"}}"#)
+            .expect(2)
+            .create();
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(
+                "data: {\"id\":\"chunk1\",\"created\":1234567890,\"model\":\"gpt-4\",\"system_fingerprint\":\"fp1\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n",
+            )
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let result = completion_stream(
+            tx,
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        rate_limited.assert();
+        ok.assert();
+        assert_eq!(result["choices"][0]["message"]["content"], "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_completion_gives_up_after_max_retries() {
+        let mut server = mockito::Server::new_async().await;
+
+        // The first attempt plus two retries
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .expect(3)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let result = completion(
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                max_retries: Some(2),
+                retry_base_delay: Some(Duration::from_millis(1)),
+                ..CompletionOptions::default()
+            },
+        )
+        .await;
+
+        mock.assert();
+        let err = result.unwrap_err().downcast::<OpenAiApiError>().unwrap();
+        assert_eq!(err.status, reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let base = Duration::from_millis(100);
+        let first = retry_delay(base, 1, None);
+        assert!(first >= base && first < base * 2, "{:?}", first);
+        let third = retry_delay(base, 3, None);
+        assert!(third >= base * 4 && third < base * 5, "{:?}", third);
+        assert_eq!(
+            retry_delay(base, 3, Some(Duration::from_secs(2))),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            retry_delay(base, 1, Some(Duration::from_secs(3600))),
+            MAX_RETRY_AFTER
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_surfaces_non_json_error_body() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(502)
+            .with_body("Bad Gateway")
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Hello")];
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let result = completion_stream(
+            tx,
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions {
+                max_retries: Some(0),
+                ..CompletionOptions::default()
+            },
+        )
+        .await;
+
+        mock.assert();
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("502"), "{}", err);
+        assert!(err.contains("Bad Gateway"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_content() {
+        let mut server = mockito::Server::new_async().await;
+
+        // SSE response with content chunks
+        let sse_response = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}
+
+data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":" World"},"finish_reason":null}]}
+
+data: {"id":"chunk3","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"!"},"finish_reason":"stop"}]}
+
+data: [DONE]
+
+"#;
+
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(sse_response)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Say hello")];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server_url = server.url();
+
+        // Run completion_stream in a separate task
+        let handle = tokio::spawn(async move {
+            completion_stream(
+                tx,
+                &messages,
+                &None,
+                server_url.as_str(),
+                "test-key",
+                "gpt-4",
+                &CompletionOptions::default(),
+            )
+            .await
+        });
+
+        // Wait for the task to complete
+        let result = tokio::time::timeout(tokio::time::Duration::from_secs(5), handle).await;
+
+        mock.assert();
+        assert!(result.is_ok());
+        assert!(result.unwrap().unwrap().is_ok());
+
+        // The channel should have received the raw JSON chunks
+        let mut chunk_count = 0;
+        while let Ok(_) = rx.try_recv() {
+            chunk_count += 1;
+        }
+        assert!(chunk_count >= 3);
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_coalesces_chunks() {
+        let mut server = mockito::Server::new_async().await;
+
+        let words = [
+            "The", " quick", " brown", " fox", " jumps", " over", " the", " dog",
+        ];
+        let mut body = String::new();
+        for word in words {
+            body.push_str(&format!(
+                "data: {}\n\n",
+                json!({
+                    "id": "chunk",
+                    "created": 1234567890,
+                    "model": "gpt-4",
+                    "system_fingerprint": "fp1",
+                    "choices": [{"index": 0, "delta": {"content": word}, "finish_reason": null}]
+                })
+            ));
+        }
+        body.push_str("data: {\"id\":\"chunk\",\"created\":1234567890,\"model\":\"gpt-4\",\"system_fingerprint\":\"fp1\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n");
+        body.push_str("data: [DONE]\n\n");
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Say something")];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let options = CompletionOptions {
+            coalesce_window: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let result = completion_stream(
+            tx,
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &options,
+        )
+        .await
+        .unwrap();
+        mock.assert();
+        assert_eq!(
+            result["choices"][0]["message"]["content"],
+            "The quick brown fox jumps over the dog"
+        );
+
+        let mut content_events = 0;
+        let mut content = String::new();
+        while let Ok(event) = rx.try_recv() {
+            if event == "[DONE]" {
+                continue;
+            }
+            let chunk: Value = serde_json::from_str(&event).unwrap();
+            if let Some(text) = chunk["choices"][0]["delta"]["content"].as_str() {
+                content_events += 1;
+                content.push_str(text);
+            }
+        }
+        assert!(
+            content_events < words.len(),
+            "Received {} content events",
+            content_events
+        );
+        assert_eq!(content, "The quick brown fox jumps over the dog");
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_cancel() {
+        let mut server = mockito::Server::new_async().await;
+
+        // Stream a chunk every 100ms so the whole response takes a
+        // second to read
+        let chunk = "data: {\"id\":\"chunk\",\"created\":1234567890,\"model\":\"gpt-4\",\"system_fingerprint\":\"fp1\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\n";
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_chunked_body(move |w| {
+                for _ in 0..10 {
+                    w.write_all(chunk.as_bytes())?;
+                    w.flush()?;
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                w.write_all(b"data: [DONE]\n\n")
+            })
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Say hello")];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let options = CompletionOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        let server_url = server.url();
+        let start = std::time::Instant::now();
+        let handle = tokio::spawn(async move {
+            completion_stream(
+                tx,
+                &messages,
+                &None,
+                server_url.as_str(),
+                "test-key",
+                "gpt-4",
+                &options,
+            )
+            .await
+        });
+
+        // Cancel as soon as the first chunk arrives
+        rx.recv().await.expect("Expected a chunk");
+        cancel.cancel();
+
+        let result = tokio::time::timeout(Duration::from_millis(500), handle)
+            .await
+            .expect("Expected the stream to stop when cancelled")
+            .unwrap();
+        mock.assert();
+        let err = result.expect_err("Expected the completion to be cancelled");
+        assert!(err.is::<CompletionCancelled>());
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+
+        // Returned without reading the rest of the chunks
+        let mut chunk_count = 1;
+        while rx.try_recv().is_ok() {
+            chunk_count += 1;
+        }
+        assert!(chunk_count < 10, "Received {} chunks", chunk_count);
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_tool_call() {
+        let mut server = mockito::Server::new_async().await;
+
+        // SSE response with tool call chunks
+        let sse_response = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"id":"call_abc123","index":0,"function":{"name":"search_notes","arguments":"{\"query\":"},"type":"function"}]},"finish_reason":null}]}
+
+data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"test\"}"}}]},"finish_reason":null}]}
+
+data: {"id":"chunk3","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":""}}]},"finish_reason":"stop"}]}
+
+data: [DONE]
+
+"#;
+
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(sse_response)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Search for test")];
         let (tx, _rx) = mpsc::unbounded_channel();
         let server_url = server.url();
 
@@ -910,6 +2809,7 @@ data: [DONE]
                 server_url.as_str(),
                 "test-key",
                 "gpt-4",
+                &CompletionOptions::default(),
             )
             .await
         });
@@ -922,6 +2822,64 @@ data: [DONE]
         assert!(result.unwrap().unwrap().is_ok());
     }
 
+    #[tokio::test]
+    async fn test_completion_stream_usage() {
+        let mut server = mockito::Server::new_async().await;
+
+        // The usage chunk comes after the finish reason and has no
+        // choices
+        let sse_response = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}
+
+data: {"id":"chunk2","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}
+
+data: {"id":"chunk3","created":1234567890,"model":"gpt-4","system_fingerprint":"fp1","choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}
+
+data: [DONE]
+
+"#;
+
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(sse_response)
+            .create();
+
+        let messages = vec![Message::new(Role::User, "Say hello")];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let result = completion_stream(
+            tx,
+            &messages,
+            &None,
+            server.url().as_str(),
+            "test-key",
+            "gpt-4",
+            &CompletionOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        mock.assert();
+        assert_eq!(result["choices"][0]["message"]["content"], "Hello");
+        let usage: Usage = serde_json::from_value(result["usage"].clone()).unwrap();
+        assert_eq!(
+            usage,
+            Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+                total_tokens: 15,
+            }
+        );
+
+        // The usage chunk isn't forwarded since it has no delta
+        let mut chunks = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.last().unwrap(), "[DONE]");
+    }
+
     #[tokio::test]
     async fn test_completion_stream_reasoning() {
         let mut server = mockito::Server::new_async().await;
@@ -957,6 +2915,7 @@ data: [DONE]
                 server_url.as_str(),
                 "test-key",
                 "gpt-4",
+                &CompletionOptions::default(),
             )
             .await
         });
@@ -1,2 +1,3 @@
 pub mod core;
+pub mod request_log;
 pub use core::*;
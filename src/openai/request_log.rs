@@ -0,0 +1,204 @@
+//! Optional append-only JSON lines log of every LLM request and
+//! response for prompt engineering and auditing.
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Error, Result, anyhow};
+use serde_json::{Value, json};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::core::{CompletionOptions, send_completion};
+use crate::core::redact::redact_secrets;
+
+static LOG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Serializes writes so that concurrent requests don't interleave
+/// lines in the log
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Log every LLM request and response to the file at `path` for the
+/// lifetime of the process. Only the first call takes effect.
+pub fn configure(path: impl Into<PathBuf>) {
+    if LOG_PATH.set(path.into()).is_err() {
+        tracing::warn!("LLM request log already configured, ignoring");
+    }
+}
+
+/// Returns the log path set by `configure` if logging is enabled
+pub fn configured_path() -> Option<&'static Path> {
+    LOG_PATH.get().map(PathBuf::as_path)
+}
+
+/// Append a line to the log at `path` with the whole request payload
+/// sent to the provider and the response or error. Secrets are
+/// redacted before writing. Failing to write is logged rather than
+/// failing the request.
+pub async fn record(path: &Path, payload: &Value, result: &Result<Value, Error>) {
+    let mut entry = json!({
+        "id": Uuid::new_v4().to_string(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "request": payload,
+    });
+    match result {
+        Ok(response) => entry["response"] = response.clone(),
+        Err(e) => entry["error"] = json!(format!("{:#}", e)),
+    }
+    let line = format!("{}\n", redact_secrets(&entry.to_string()));
+
+    if let Err(e) = append(path, &line).await {
+        tracing::warn!("Failed to write LLM request log {}: {}", path.display(), e);
+    }
+}
+
+async fn append(path: &Path, line: &str) -> Result<()> {
+    let _guard = WRITE_LOCK.lock().await;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Identifies an entry in the log
+pub enum EntrySelector {
+    /// Line number starting at 1
+    Line(usize),
+    /// The `id` field of the entry
+    Id(String),
+}
+
+/// Find an entry in the log at `path`
+pub async fn find_entry(path: &Path, selector: &EntrySelector) -> Result<Value> {
+    let log = tokio::fs::read_to_string(path).await?;
+    let line = match selector {
+        EntrySelector::Line(n) => log
+            .lines()
+            .nth(n.saturating_sub(1))
+            .filter(|_| *n > 0)
+            .ok_or_else(|| anyhow!("No entry at line {} in {}", n, path.display()))?,
+        EntrySelector::Id(id) => log
+            .lines()
+            .find(|line| {
+                serde_json::from_str::<Value>(line).is_ok_and(|entry| entry["id"] == id.as_str())
+            })
+            .ok_or_else(|| anyhow!("No entry with ID {} in {}", id, path.display()))?,
+    };
+    Ok(serde_json::from_str(line)?)
+}
+
+/// Re-send the request from a log entry as it was logged using the
+/// current API credentials, optionally with a different model.
+/// Streaming requests are replayed without streaming so the whole
+/// response is returned.
+pub async fn replay(
+    entry: &Value,
+    api_hostname: &str,
+    api_key: &str,
+    model: Option<&str>,
+    options: &CompletionOptions,
+) -> Result<Value> {
+    let mut payload = entry["request"].clone();
+    let Some(request) = payload.as_object_mut() else {
+        return Err(anyhow!("Log entry is missing the request"));
+    };
+    if let Some(model) = model {
+        request.insert(String::from("model"), json!(model));
+    }
+    request.remove("stream");
+    request.remove("stream_options");
+    let result = send_completion(&payload, api_hostname, api_key, options).await;
+    if let Some(log_path) = options.log_path() {
+        record(log_path, &payload, &result).await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const LOG: &str = r#"{"id":"first","request":{"model":"gpt-4","messages":[{"role":"user","content":"Hi"}]},"response":{}}
+{"id":"second","request":{"model":"gpt-4","messages":[{"role":"system","content":"Be brief"},{"role":"user","content":"Weather?"}],"tools":[{"type":"function","function":{"name":"web_search"}}],"temperature":0.2,"reasoning_effort":"low","stream":true,"stream_options":{"include_usage":true}},"response":{}}
+"#;
+
+    #[tokio::test]
+    async fn it_finds_entries_by_line_and_id() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("llm.jsonl");
+        std::fs::write(&path, LOG).unwrap();
+
+        let entry = find_entry(&path, &EntrySelector::Line(2)).await.unwrap();
+        assert_eq!(entry["id"], "second");
+
+        let entry = find_entry(&path, &EntrySelector::Id(String::from("first")))
+            .await
+            .unwrap();
+        assert_eq!(entry["request"]["messages"][0]["content"], "Hi");
+
+        assert!(find_entry(&path, &EntrySelector::Line(0)).await.is_err());
+        assert!(find_entry(&path, &EntrySelector::Line(3)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_replays_a_logged_request() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("llm.jsonl");
+        std::fs::write(&path, LOG).unwrap();
+        let entry = find_entry(&path, &EntrySelector::Line(2)).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", "Bearer current-key")
+            .match_body(Matcher::Json(json!({
+                "model": "gpt-4.1",
+                "messages": [
+                    {"role": "system", "content": "Be brief"},
+                    {"role": "user", "content": "Weather?"}
+                ],
+                "tools": [{"type": "function", "function": {"name": "web_search"}}],
+                "temperature": 0.2,
+                "reasoning_effort": "low",
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"choices": [{"message": {"content": "Sunny"}}]}"#)
+            .create_async()
+            .await;
+
+        let response = replay(
+            &entry,
+            &server.url(),
+            "current-key",
+            Some("gpt-4.1"),
+            &CompletionOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(response["choices"][0]["message"]["content"], "Sunny");
+    }
+
+    #[tokio::test]
+    async fn it_rejects_entries_without_a_request() {
+        let entry = json!({"id": "old", "model": "gpt-4", "messages": []});
+        let result = replay(
+            &entry,
+            "http://127.0.0.1:1",
+            "current-key",
+            None,
+            &CompletionOptions::default(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
@@ -1,9 +1,12 @@
-use winnow::ascii::{alphanumeric1, space0};
+use chrono::NaiveDate;
+use winnow::ascii::{alphanumeric1, digit1, float, space0, space1};
 use winnow::combinator::*;
 use winnow::error::{ErrMode, InputError};
 use winnow::prelude::*;
 use winnow::token::{literal, take_while};
 
+use crate::core::time;
+
 #[derive(Debug, PartialEq)]
 pub enum RangeOp {
     Lt,
@@ -26,13 +29,330 @@ pub enum Expr {
         value: String,
         negated: bool,
     },
+    Exists {
+        field: String,
+        negated: bool,
+    },
+    /// Matches terms within a Levenshtein distance of the value
+    /// e.g. `kubernetes~2`
+    Fuzzy {
+        field: Option<String>,
+        value: String,
+        distance: u8,
+        negated: bool,
+    },
+    /// Multiplies the relevance score of matches e.g. `title:rust^2`
+    Boost {
+        expr: Box<Expr>,
+        boost: f32,
+    },
     And(Box<Expr>, Box<Expr>),
     Or(Box<Expr>, Box<Expr>),
 }
 
-pub fn parse_query(input: &str) -> Result<Expr, ErrMode<InputError<&str>>> {
-    let mut input = input;
-    parse_expr(&mut input)
+/// Why an AQL query could not be parsed.
+#[derive(Debug, PartialEq)]
+pub enum AqlError {
+    /// The query is empty or only whitespace
+    EmptyQuery,
+    /// A `"` was opened but never closed
+    UnterminatedQuote { position: usize },
+    /// A `field:` prefix that isn't in the index
+    UnknownField(String),
+    /// Anything else the parser couldn't make sense of like a
+    /// dangling `-` or an unmatched `)`
+    Syntax { position: usize },
+    /// A `field:[LOW TO HIGH]` range where LOW comes after HIGH
+    InvalidRange { low: String, high: String },
+    /// A date starting with `today` that isn't a valid relative date
+    /// e.g. `today-7x`
+    InvalidDate(String),
+    /// An `OR` between a date field and an indexed field e.g.
+    /// `tags:work OR has:deadline`. Date fields are filtered in the
+    /// database after searching the index so they can only be ORed
+    /// with other date fields.
+    MixedOr,
+}
+
+impl AqlError {
+    /// Short machine readable name for the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AqlError::EmptyQuery => "empty_query",
+            AqlError::UnterminatedQuote { .. } => "unterminated_quote",
+            AqlError::UnknownField(_) => "unknown_field",
+            AqlError::Syntax { .. } => "syntax",
+            AqlError::InvalidRange { .. } => "invalid_range",
+            AqlError::InvalidDate(_) => "invalid_date",
+            AqlError::MixedOr => "mixed_or",
+        }
+    }
+}
+
+impl std::fmt::Display for AqlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AqlError::EmptyQuery => write!(f, "Query is empty"),
+            AqlError::UnterminatedQuote { position } => {
+                write!(f, "Unterminated quote starting at position {position}")
+            }
+            AqlError::UnknownField(field) => write!(f, "Unknown field '{field}'"),
+            AqlError::Syntax { position } => write!(f, "Invalid query at position {position}"),
+            AqlError::InvalidRange { low, high } => {
+                write!(f, "Range start '{low}' is after range end '{high}'")
+            }
+            AqlError::InvalidDate(value) => write!(
+                f,
+                "Invalid relative date '{value}', expected today with an optional offset e.g. today-7d"
+            ),
+            AqlError::MixedOr => write!(
+                f,
+                "OR can't combine the date fields {} with other fields",
+                DATE_FIELDS.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AqlError {}
+
+/// Date fields that are only stored in the database. These are the
+/// only fields that accept a `field:[LOW TO HIGH]` range.
+const DATE_FIELDS: [&str; 5] = ["scheduled", "deadline", "closed", "date", "created"];
+
+/// Fields that can be used in `field:value`, ranges, and `has:`.
+/// Everything in the index schema plus the planning dates that are
+/// only stored in the database.
+fn is_known_field(field: &str) -> bool {
+    DATE_FIELDS.contains(&field)
+        || crate::search::fts::schema::note_schema()
+            .get_field(field)
+            .is_ok()
+}
+
+/// Position of a `"` that is never closed. Quotes escaped with `\`
+/// inside a phrase don't count.
+fn unterminated_quote(query: &str) -> Option<usize> {
+    let mut open = None;
+    let mut chars = query.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if open.is_some() => {
+                chars.next();
+            }
+            '"' => open = if open.is_some() { None } else { Some(i) },
+            _ => {}
+        }
+    }
+    open
+}
+
+/// Whether a range's LOW comes after its HIGH. Dates are compared as
+/// strings, the same way the database compares them, which orders
+/// `YYYY-MM-DD` dates correctly.
+fn is_backwards(low: &str, high: &str) -> bool {
+    // Relative dates can only be compared to each other until they're
+    // resolved so any day works for today
+    let today = NaiveDate::default();
+    match (relative_date(low, today), relative_date(high, today)) {
+        (Some(low_date), Some(high_date)) => low_date > high_date,
+        (None, None) => low > high,
+        _ => false,
+    }
+}
+
+/// Bounds of the `[LOW TO HIGH]` range at the start of `input` if LOW
+/// comes after HIGH
+fn invalid_range(mut input: &str) -> Option<(String, String)> {
+    let Ok((Some(low), Some(high))) = parse_range_bounds(&mut input) else {
+        return None;
+    };
+    is_backwards(&low, &high).then_some((low, high))
+}
+
+/// A date relative to `today` e.g. `today`, `today-7d`, or `today+2w`.
+/// The offset is in days (`d`), weeks (`w`), or months (`m`).
+fn relative_date(value: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let offset = value.strip_prefix("today")?;
+    if offset.is_empty() {
+        return Some(today);
+    }
+    time::offset_date(offset, today)
+}
+
+/// Replace relative dates like `today-7d` in date filters with the
+/// actual date. Parsed queries keep relative dates as is so this
+/// needs to be called at query time with today's date in the
+/// configured timezone.
+pub fn resolve_relative_dates(expr: Expr, today: NaiveDate) -> Expr {
+    let resolve = |field: &str, value: String| {
+        if !DATE_FIELDS.contains(&field) {
+            return value;
+        }
+        relative_date(&value, today)
+            .map(|date| date.to_string())
+            .unwrap_or(value)
+    };
+    match expr {
+        Expr::Term {
+            field: Some(field),
+            value,
+            phrase: false,
+            negated,
+        } => Expr::Term {
+            value: resolve(&field, value),
+            field: Some(field),
+            phrase: false,
+            negated,
+        },
+        Expr::Range {
+            field,
+            op,
+            value,
+            negated,
+        } => Expr::Range {
+            value: resolve(&field, value),
+            field,
+            op,
+            negated,
+        },
+        Expr::Boost { expr, boost } => Expr::Boost {
+            expr: Box::new(resolve_relative_dates(*expr, today)),
+            boost,
+        },
+        Expr::And(lhs, rhs) => Expr::And(
+            Box::new(resolve_relative_dates(*lhs, today)),
+            Box::new(resolve_relative_dates(*rhs, today)),
+        ),
+        Expr::Or(lhs, rhs) => Expr::Or(
+            Box::new(resolve_relative_dates(*lhs, today)),
+            Box::new(resolve_relative_dates(*rhs, today)),
+        ),
+        expr => expr,
+    }
+}
+
+/// Relative dates in date fields are resolved at query time so make
+/// sure they're valid up front instead of searching for the literal
+/// value and quietly matching nothing
+fn validate_date(field: &str, value: &str) -> Result<(), AqlError> {
+    // Any day works for today since only the format is checked
+    if DATE_FIELDS.contains(&field)
+        && value.starts_with("today")
+        && relative_date(value, NaiveDate::default()).is_none()
+    {
+        return Err(AqlError::InvalidDate(value.to_string()));
+    }
+    Ok(())
+}
+
+fn validate_fields(expr: &Expr) -> Result<(), AqlError> {
+    let field = match expr {
+        Expr::Term { field: None, .. } => return Ok(()),
+        Expr::Term {
+            field: Some(field),
+            value,
+            phrase: false,
+            ..
+        }
+        | Expr::Range { field, value, .. } => {
+            validate_date(field, value)?;
+            field
+        }
+        Expr::Term {
+            field: Some(field), ..
+        }
+        | Expr::Fuzzy {
+            field: Some(field), ..
+        } => field,
+        Expr::Fuzzy { field: None, .. } => return Ok(()),
+        Expr::Exists { field, .. } => field,
+        Expr::Boost { expr, .. } => return validate_fields(expr),
+        Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => {
+            validate_fields(lhs)?;
+            return validate_fields(rhs);
+        }
+    };
+    if is_known_field(field) {
+        Ok(())
+    } else {
+        Err(AqlError::UnknownField(field.clone()))
+    }
+}
+
+/// Whether an expression filters on indexed fields and whether it
+/// filters on date fields that are only in the database
+fn field_sources(expr: &Expr) -> (bool, bool) {
+    let field = match expr {
+        Expr::Term { field, .. } | Expr::Fuzzy { field, .. } => field.as_deref(),
+        Expr::Range { field, .. } | Expr::Exists { field, .. } => Some(field.as_str()),
+        Expr::Boost { expr, .. } => return field_sources(expr),
+        Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => {
+            let (lhs_index, lhs_db) = field_sources(lhs);
+            let (rhs_index, rhs_db) = field_sources(rhs);
+            return (lhs_index || rhs_index, lhs_db || rhs_db);
+        }
+    };
+    let db = field.is_some_and(|field| DATE_FIELDS.contains(&field));
+    (!db, db)
+}
+
+/// The index and the database are searched separately and their
+/// results intersected, so an OR across both can't be evaluated
+fn validate_or(expr: &Expr) -> Result<(), AqlError> {
+    match expr {
+        Expr::Or(..) => match field_sources(expr) {
+            (true, true) => Err(AqlError::MixedOr),
+            _ => Ok(()),
+        },
+        Expr::Boost { expr, .. } => validate_or(expr),
+        Expr::And(lhs, rhs) => {
+            validate_or(lhs)?;
+            validate_or(rhs)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parse an AQL query. Terms next to each other are combined with AND
+/// which binds tighter than `OR`, and parentheses group expressions
+/// e.g. `(tags:meeting OR tags:standup) status:todo`.
+pub fn parse_query(query: &str) -> Result<Expr, AqlError> {
+    if query.trim().is_empty() {
+        return Err(AqlError::EmptyQuery);
+    }
+
+    // Phrases fall back to plain terms when the closing quote is
+    // missing so catch that up front instead of silently searching
+    // for a term with a stray `"` in it
+    if let Some(position) = unterminated_quote(query) {
+        return Err(AqlError::UnterminatedQuote { position });
+    }
+
+    let syntax_error = |remaining: &str| AqlError::Syntax {
+        position: query.len() - remaining.len(),
+    };
+
+    let mut input = query;
+    let expr = parse_expr(&mut input).map_err(|e| match e.into_inner() {
+        // Parsing stops at a backwards range
+        Ok(e) => match invalid_range(e.input) {
+            Some((low, high)) => AqlError::InvalidRange { low, high },
+            None => syntax_error(e.input),
+        },
+        Err(_) => syntax_error(input),
+    })?;
+
+    // Anything left over like a trailing `OR` or an unmatched `)`
+    // means the query is invalid
+    let _ = space0::<_, InputError<&str>>.parse_next(&mut input);
+    if !input.is_empty() {
+        return Err(syntax_error(input));
+    }
+
+    validate_fields(&expr)?;
+    validate_or(&expr)?;
+    Ok(expr)
 }
 
 fn parse_expr<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
@@ -41,11 +361,15 @@ fn parse_expr<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a st
 
 fn parse_or<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
     let mut lhs = parse_and(input)?;
-    while preceded(space0, tag_no_case("OR"))
-        .parse_next(input)
-        .is_ok()
-    {
-        let rhs = parse_and(input)?;
+    loop {
+        let checkpoint = *input;
+        space0.parse_next(input)?;
+        if keyword("OR").parse_next(input).is_err() {
+            *input = checkpoint;
+            break;
+        }
+        space0.parse_next(input)?;
+        let rhs = cut_err(parse_and).parse_next(input)?;
         lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
     }
     Ok(lhs)
@@ -56,19 +380,32 @@ fn parse_and<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str
 
     loop {
         let checkpoint = *input;
+        space0.parse_next(input)?;
 
-        if preceded(space0, tag_no_case("AND"))
-            .parse_next(input)
-            .is_ok()
-        {
+        // `OR` binds looser so leave it for `parse_or`
+        if keyword("OR").parse_next(input).is_ok() {
             *input = checkpoint;
             break;
         }
 
-        if let Ok(rhs) = parse_not(input) {
-            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        // An explicit `AND` is the same as terms next to each other
+        // but must be followed by another term
+        let explicit = opt(terminated(keyword("AND"), space0))
+            .parse_next(input)?
+            .is_some();
+        let rhs = if explicit {
+            cut_err(parse_not).parse_next(input)
         } else {
-            break;
+            parse_not(input)
+        };
+
+        match rhs {
+            Ok(rhs) => lhs = Expr::And(Box::new(lhs), Box::new(rhs)),
+            Err(ErrMode::Backtrack(_)) => {
+                *input = checkpoint;
+                break;
+            }
+            Err(e) => return Err(e),
         }
     }
 
@@ -76,21 +413,138 @@ fn parse_and<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str
 }
 
 fn parse_not<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
-    let negated = opt(alt((literal("-"), tag_no_case("NOT"))))
+    let negated = opt(alt((literal("-"), terminated(keyword("NOT"), space0))))
         .parse_next(input)?
         .is_some();
-    let mut expr = parse_term(input)?;
-    match &mut expr {
-        Expr::Term { negated: n, .. } => *n = *n || negated,
-        Expr::Range { negated: n, .. } => *n = *n || negated,
-        _ => (),
+    let mut expr = alt((parse_group, parse_term)).parse_next(input)?;
+    if negated {
+        expr = negate(expr);
+    }
+
+    let fuzzy_start = *input;
+    let fuzzy: Option<Option<&str>> = opt(preceded(literal("~"), opt(digit1))).parse_next(input)?;
+    if let Some(distance) = fuzzy {
+        // Phrases, groups, and ranges can't be fuzzy
+        expr =
+            fuzzy_term(expr, distance).ok_or_else(|| ErrMode::Cut(InputError::at(fuzzy_start)))?;
+    }
+
+    let boost: Option<f32> = opt(preceded(literal("^"), float)).parse_next(input)?;
+    if let Some(boost) = boost {
+        expr = Expr::Boost {
+            expr: Box::new(expr),
+            boost,
+        };
     }
 
     Ok(expr)
 }
 
+/// Maximum edit distance for fuzzy terms, anything higher matches too
+/// much and is slow to search
+const MAX_FUZZY_DISTANCE: u8 = 2;
+
+/// Turn a single term into a fuzzy term. A bare `~` defaults to a
+/// distance of 1 and larger distances are clamped.
+fn fuzzy_term(expr: Expr, distance: Option<&str>) -> Option<Expr> {
+    let distance = distance
+        .map(|d| d.parse::<u32>().unwrap_or(u32::MAX))
+        .unwrap_or(1)
+        .min(MAX_FUZZY_DISTANCE as u32) as u8;
+    match expr {
+        Expr::Term {
+            field,
+            value,
+            phrase: false,
+            negated,
+        } => Some(Expr::Fuzzy {
+            field,
+            value,
+            distance,
+            negated,
+        }),
+        _ => None,
+    }
+}
+
+fn parse_group<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
+    delimited(
+        (literal("("), space0),
+        cut_err(parse_expr),
+        (space0, cut_err(literal(")"))),
+    )
+    .parse_next(input)
+}
+
+/// Negate an expression, pushing the negation down to each term of a
+/// group so that `-(a OR b)` is the same as `-a -b`
+fn negate(expr: Expr) -> Expr {
+    match expr {
+        Expr::Term {
+            field,
+            value,
+            phrase,
+            negated,
+        } => Expr::Term {
+            field,
+            value,
+            phrase,
+            negated: !negated,
+        },
+        Expr::Range {
+            field,
+            op,
+            value,
+            negated,
+        } => Expr::Range {
+            field,
+            op,
+            value,
+            negated: !negated,
+        },
+        Expr::Exists { field, negated } => Expr::Exists {
+            field,
+            negated: !negated,
+        },
+        Expr::Fuzzy {
+            field,
+            value,
+            distance,
+            negated,
+        } => Expr::Fuzzy {
+            field,
+            value,
+            distance,
+            negated: !negated,
+        },
+        Expr::Boost { expr, boost } => Expr::Boost {
+            expr: Box::new(negate(*expr)),
+            boost,
+        },
+        Expr::And(lhs, rhs) => Expr::Or(Box::new(negate(*lhs)), Box::new(negate(*rhs))),
+        Expr::Or(lhs, rhs) => Expr::And(Box::new(negate(*lhs)), Box::new(negate(*rhs))),
+    }
+}
+
 fn parse_term<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
-    alt((parse_range_expr, parse_fielded_term, parse_default_term)).parse_next(input)
+    alt((
+        parse_exists,
+        parse_date_range,
+        parse_range_expr,
+        parse_fielded_term,
+        parse_default_term,
+    ))
+    .parse_next(input)
+}
+
+fn parse_exists<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
+    let negated = opt(literal("-")).parse_next(input)?.is_some();
+    literal("has:").parse_next(input)?;
+    let field: &str = alphanumeric1.parse_next(input)?;
+    Ok(Expr::Exists {
+        field: field.to_string(),
+        negated,
+    })
 }
 
 fn parse_range_expr<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
@@ -113,16 +567,80 @@ fn parse_range_expr<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<
     })
 }
 
+/// Low and high bounds of a range, `None` when that side is open
+type RangeBounds = (Option<String>, Option<String>);
+
+/// `[LOW TO HIGH]` where either bound can be `*` to leave that side of
+/// the range open.
+fn parse_range_bounds<'a>(
+    input: &mut &'a str,
+) -> Result<RangeBounds, ErrMode<InputError<&'a str>>> {
+    let bound = |input: &mut &'a str| {
+        take_while(1.., |c: char| !c.is_whitespace() && c != ']')
+            .map(|s: &str| (s != "*").then(|| s.to_string()))
+            .parse_next(input)
+    };
+    literal("[").parse_next(input)?;
+    space0.parse_next(input)?;
+    let low = bound(input)?;
+    (space1, literal("TO"), space1).parse_next(input)?;
+    let high = bound(input)?;
+    space0.parse_next(input)?;
+    literal("]").parse_next(input)?;
+    Ok((low, high))
+}
+
+/// Inclusive date range e.g. `scheduled:[2025-01-01 TO 2025-02-01]`
+/// which is the same as `scheduled:>=2025-01-01 scheduled:<=2025-02-01`.
+/// An open range on both sides matches any note with the field set.
+fn parse_date_range<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
+    let negated = opt(literal("-")).parse_next(input)?.is_some();
+    let field: &str = alphanumeric1
+        .verify(|field: &str| DATE_FIELDS.contains(&field))
+        .parse_next(input)?;
+    literal(":").parse_next(input)?;
+    let range_start = *input;
+    let (low, high) = parse_range_bounds(input)?;
+    // A backwards range would quietly match nothing so stop here and
+    // let `parse_query` report it
+    if let (Some(low), Some(high)) = (&low, &high)
+        && is_backwards(low, high)
+    {
+        return Err(ErrMode::Cut(InputError::at(range_start)));
+    }
+
+    let bound = |op, value| Expr::Range {
+        field: field.to_string(),
+        op,
+        value,
+        negated: false,
+    };
+    let expr = match (low, high) {
+        (Some(low), Some(high)) => Expr::And(
+            Box::new(bound(RangeOp::Gte, low)),
+            Box::new(bound(RangeOp::Lte, high)),
+        ),
+        (Some(low), None) => bound(RangeOp::Gte, low),
+        (None, Some(high)) => bound(RangeOp::Lte, high),
+        (None, None) => Expr::Exists {
+            field: field.to_string(),
+            negated: false,
+        },
+    };
+    Ok(if negated { negate(expr) } else { expr })
+}
+
 fn parse_fielded_term<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
     let negated = opt(literal("-")).parse_next(input)?.is_some();
     let field: &str = alphanumeric1.parse_next(input)?;
     literal(":").parse_next(input)?;
 
     let term_parser = alt((
-        delimited(literal("\""), take_while(1.., |c| c != '"'), literal("\""))
-            .map(|s: &str| (s.to_string(), true)),
-        take_while(1.., |c: char| !c.is_whitespace() && c != ')' && c != ',')
-            .map(|s: &str| (s.to_string(), false)),
+        parse_quoted.map(|s| (s, true)),
+        take_while(1.., |c: char| {
+            !c.is_whitespace() && c != ')' && c != ',' && c != '^' && c != '~'
+        })
+        .map(|s: &str| (s.to_string(), false)),
     ));
 
     let values: Vec<(String, bool)> =
@@ -149,10 +667,11 @@ fn parse_fielded_term<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputErro
 
 fn parse_default_term<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputError<&'a str>>> {
     let value = alt((
-        delimited(literal("\""), take_while(1.., |c| c != '"'), literal("\""))
-            .map(|s: &str| (s.to_string(), true)),
-        take_while(1.., |c: char| !c.is_whitespace() && c != ')')
-            .map(|s: &str| (s.to_string(), false)),
+        parse_quoted.map(|s| (s, true)),
+        take_while(1.., |c: char| {
+            !c.is_whitespace() && c != ')' && c != '^' && c != '~'
+        })
+        .map(|s: &str| (s.to_string(), false)),
     ))
     .parse_next(input)?;
     Ok(Expr::Term {
@@ -163,16 +682,57 @@ fn parse_default_term<'a>(input: &mut &'a str) -> Result<Expr, ErrMode<InputErro
     })
 }
 
+/// A non-empty phrase in double quotes. Use `\"` for a quote and `\\`
+/// for a backslash inside the phrase.
+fn parse_quoted<'a>(input: &mut &'a str) -> Result<String, ErrMode<InputError<&'a str>>> {
+    literal("\"").parse_next(input)?;
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if !value.is_empty() => {
+                *input = &input[i + 1..];
+                return Ok(value);
+            }
+            '"' => break,
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                Some((_, other)) => {
+                    value.push('\\');
+                    value.push(other);
+                }
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err(ErrMode::Backtrack(InputError::at(*input)))
+}
+
 fn tag_no_case<'a>(
     tag_str: &'static str,
 ) -> impl Parser<&'a str, &'a str, ErrMode<InputError<&'a str>>> {
     move |input: &mut &'a str| {
         let len = tag_str.len();
-        let (head, tail) = input.split_at(len.min(input.len()));
-        if head.eq_ignore_ascii_case(tag_str) {
-            *input = tail;
-            Ok(head)
+        match input.get(..len) {
+            Some(head) if head.eq_ignore_ascii_case(tag_str) => {
+                *input = &input[len..];
+                Ok(head)
+            }
+            _ => Err(ErrMode::Backtrack(InputError::at(*input))),
+        }
+    }
+}
+
+/// A keyword like `OR` that isn't part of a longer word
+fn keyword<'a>(word: &'static str) -> impl Parser<&'a str, &'a str, ErrMode<InputError<&'a str>>> {
+    move |input: &mut &'a str| {
+        let checkpoint = *input;
+        let matched = tag_no_case(word).parse_next(input)?;
+        if input.is_empty() || input.starts_with(|c: char| c.is_whitespace() || c == '(') {
+            Ok(matched)
         } else {
+            *input = checkpoint;
             Err(ErrMode::Backtrack(InputError::at(*input)))
         }
     }
@@ -198,18 +758,306 @@ mod tests {
 
     #[test]
     fn test_negated_range() {
-        let result = parse_query("-price:<=100").unwrap();
+        let result = parse_query("-date:<=2024-12-31").unwrap();
         assert_eq!(
             result,
             Expr::Range {
-                field: "price".into(),
+                field: "date".into(),
                 op: RangeOp::Lte,
-                value: "100".into(),
+                value: "2024-12-31".into(),
                 negated: true,
             }
         );
     }
 
+    fn date_bound(field: &str, op: RangeOp, value: &str) -> Expr {
+        Expr::Range {
+            field: field.into(),
+            op,
+            value: value.into(),
+            negated: false,
+        }
+    }
+
+    #[test]
+    fn test_inclusive_date_range() {
+        let result = parse_query("scheduled:[2025-01-01 TO 2025-02-01]").unwrap();
+        assert_eq!(
+            result,
+            Expr::And(
+                Box::new(date_bound("scheduled", RangeOp::Gte, "2025-01-01")),
+                Box::new(date_bound("scheduled", RangeOp::Lte, "2025-02-01")),
+            )
+        );
+
+        // The same day on both sides is a valid range
+        assert!(parse_query("date:[2025-01-01 TO 2025-01-01]").is_ok());
+    }
+
+    #[test]
+    fn test_open_ended_date_range() {
+        assert_eq!(
+            parse_query("deadline:[* TO 2025-03-01]").unwrap(),
+            date_bound("deadline", RangeOp::Lte, "2025-03-01")
+        );
+        assert_eq!(
+            parse_query("closed:[2025-01-01 TO *]").unwrap(),
+            date_bound("closed", RangeOp::Gte, "2025-01-01")
+        );
+        assert_eq!(
+            parse_query("date:[* TO *]").unwrap(),
+            Expr::Exists {
+                field: "date".into(),
+                negated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_negated_date_range() {
+        let result = parse_query("-date:[2025-01-01 TO 2025-02-01]").unwrap();
+        assert_eq!(
+            result,
+            Expr::Or(
+                Box::new(Expr::Range {
+                    field: "date".into(),
+                    op: RangeOp::Gte,
+                    value: "2025-01-01".into(),
+                    negated: true,
+                }),
+                Box::new(Expr::Range {
+                    field: "date".into(),
+                    op: RangeOp::Lte,
+                    value: "2025-02-01".into(),
+                    negated: true,
+                }),
+            )
+        );
+    }
+
+    #[test]
+    fn test_backwards_date_range_errors() {
+        let result = parse_query("tags:work date:[2025-02-01 TO 2025-01-01]");
+        assert_eq!(
+            result,
+            Err(AqlError::InvalidRange {
+                low: "2025-02-01".into(),
+                high: "2025-01-01".into(),
+            })
+        );
+        assert_eq!(result.unwrap_err().kind(), "invalid_range");
+    }
+
+    #[test]
+    fn test_created_date_range() {
+        assert_eq!(
+            parse_query("created:[2025-01-01 TO 2025-02-01]").unwrap(),
+            Expr::And(
+                Box::new(date_bound("created", RangeOp::Gte, "2025-01-01")),
+                Box::new(date_bound("created", RangeOp::Lte, "2025-02-01")),
+            )
+        );
+        assert_eq!(
+            parse_query("created:[2025-02-01 TO 2025-01-01]"),
+            Err(AqlError::InvalidRange {
+                low: "2025-02-01".into(),
+                high: "2025-01-01".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_brackets_outside_ranges_are_not_ranges() {
+        // Only ranges on date fields are checked, not brackets in
+        // phrases or other terms
+        assert!(parse_query(r#""foo [bar""#).is_ok());
+        assert!(parse_query(r#""[2025-02-01 TO 2025-01-01]""#).is_ok());
+        assert!(parse_query(r#"title:"[b TO a]" date:[2025-01-01 TO 2025-02-01]"#).is_ok());
+        assert_eq!(
+            parse_query(r#""[b TO a]" date:[2025-02-01 TO 2025-01-01]"#),
+            Err(AqlError::InvalidRange {
+                low: "2025-02-01".into(),
+                high: "2025-01-01".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_relative_dates() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 31).unwrap();
+        let resolve = |query| resolve_relative_dates(parse_query(query).unwrap(), today);
+
+        // Relative dates are kept as is until they're resolved
+        assert_eq!(
+            parse_query("deadline:<=today").unwrap(),
+            date_bound("deadline", RangeOp::Lte, "today")
+        );
+        assert_eq!(
+            resolve("deadline:<=today"),
+            date_bound("deadline", RangeOp::Lte, "2025-01-31")
+        );
+        assert_eq!(
+            resolve("scheduled:>=today-7d"),
+            date_bound("scheduled", RangeOp::Gte, "2025-01-24")
+        );
+        assert_eq!(
+            resolve("scheduled:<today+2w"),
+            Expr::Range {
+                field: "scheduled".into(),
+                op: RangeOp::Lt,
+                value: "2025-02-14".into(),
+                negated: false,
+            }
+        );
+        assert_eq!(
+            resolve("deadline:today+1m"),
+            Expr::Term {
+                field: Some("deadline".into()),
+                value: "2025-02-28".into(),
+                phrase: false,
+                negated: false,
+            }
+        );
+        assert_eq!(
+            resolve("closed:[today-1w TO today]"),
+            Expr::And(
+                Box::new(date_bound("closed", RangeOp::Gte, "2025-01-24")),
+                Box::new(date_bound("closed", RangeOp::Lte, "2025-01-31")),
+            )
+        );
+    }
+
+    #[test]
+    fn test_relative_dates_only_resolve_for_date_fields() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 31).unwrap();
+        let resolve = |query| resolve_relative_dates(parse_query(query).unwrap(), today);
+
+        assert_eq!(
+            resolve("title:today"),
+            Expr::Term {
+                field: Some("title".into()),
+                value: "today".into(),
+                phrase: false,
+                negated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_malformed_relative_date_errors() {
+        for query in [
+            "deadline:<=today-7x",
+            "scheduled:today+",
+            "closed:todayish",
+            "date:[today-1w TO today-d]",
+        ] {
+            let err = parse_query(query).unwrap_err();
+            assert_eq!(err.kind(), "invalid_date", "{}", query);
+        }
+        // Only date fields have relative dates
+        assert!(parse_query("title:todayish").is_ok());
+    }
+
+    #[test]
+    fn test_backwards_relative_date_range_errors() {
+        assert_eq!(
+            parse_query("deadline:[today TO today-7d]"),
+            Err(AqlError::InvalidRange {
+                low: "today".into(),
+                high: "today-7d".into(),
+            })
+        );
+        // Relative dates can't be compared to absolute dates until
+        // they're resolved
+        assert!(parse_query("deadline:[2099-01-01 TO today]").is_ok());
+    }
+
+    #[test]
+    fn test_exists() {
+        let result = parse_query("has:deadline").unwrap();
+        assert_eq!(
+            result,
+            Expr::Exists {
+                field: "deadline".into(),
+                negated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_negated_exists() {
+        let result = parse_query("-has:tags").unwrap();
+        assert_eq!(
+            result,
+            Expr::Exists {
+                field: "tags".into(),
+                negated: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_exists_with_terms() {
+        let result = parse_query("has:deadline status:todo").unwrap();
+        assert_eq!(
+            result,
+            Expr::And(
+                Box::new(Expr::Exists {
+                    field: String::from("deadline"),
+                    negated: false,
+                }),
+                Box::new(Expr::Term {
+                    field: Some(String::from("status")),
+                    value: String::from("todo"),
+                    phrase: false,
+                    negated: false,
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn test_boost() {
+        let result = parse_query("title:meeting^2").unwrap();
+        assert_eq!(
+            result,
+            Expr::Boost {
+                expr: Box::new(Expr::Term {
+                    field: Some(String::from("title")),
+                    value: String::from("meeting"),
+                    phrase: false,
+                    negated: false,
+                }),
+                boost: 2.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_boost_phrase_and_default_term() {
+        let result = parse_query("\"weekly sync\"^1.5 agenda").unwrap();
+        assert_eq!(
+            result,
+            Expr::And(
+                Box::new(Expr::Boost {
+                    expr: Box::new(Expr::Term {
+                        field: None,
+                        value: String::from("weekly sync"),
+                        phrase: true,
+                        negated: false,
+                    }),
+                    boost: 1.5,
+                }),
+                Box::new(Expr::Term {
+                    field: None,
+                    value: String::from("agenda"),
+                    phrase: false,
+                    negated: false,
+                })
+            )
+        );
+    }
+
     #[test]
     fn test_multiple_terms() {
         let result = parse_query("title:testing tags:meeting date:>2025-01-01").unwrap();
@@ -261,4 +1109,241 @@ mod tests {
             ),
         );
     }
+
+    fn term(field: &str, value: &str, negated: bool) -> Expr {
+        Expr::Term {
+            field: Some(String::from(field)),
+            value: String::from(value),
+            phrase: false,
+            negated,
+        }
+    }
+
+    fn and(lhs: Expr, rhs: Expr) -> Expr {
+        Expr::And(Box::new(lhs), Box::new(rhs))
+    }
+
+    fn or(lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Or(Box::new(lhs), Box::new(rhs))
+    }
+
+    #[test]
+    fn test_or_binds_looser_than_and() {
+        let result = parse_query("tags:meeting status:todo OR tags:standup").unwrap();
+        assert_eq!(
+            result,
+            or(
+                and(
+                    term("tags", "meeting", false),
+                    term("status", "todo", false)
+                ),
+                term("tags", "standup", false),
+            )
+        );
+    }
+
+    #[test]
+    fn test_group() {
+        let result = parse_query("(tags:meeting OR tags:standup) status:todo").unwrap();
+        assert_eq!(
+            result,
+            and(
+                or(
+                    term("tags", "meeting", false),
+                    term("tags", "standup", false)
+                ),
+                term("status", "todo", false),
+            )
+        );
+    }
+
+    #[test]
+    fn test_nested_groups() {
+        let result =
+            parse_query("( tags:meeting OR (tags:standup AND title:daily) ) status:todo").unwrap();
+        assert_eq!(
+            result,
+            and(
+                or(
+                    term("tags", "meeting", false),
+                    and(
+                        term("tags", "standup", false),
+                        term("title", "daily", false)
+                    ),
+                ),
+                term("status", "todo", false),
+            )
+        );
+    }
+
+    #[test]
+    fn test_negation_inside_group() {
+        let result = parse_query("(tags:meeting -status:done) OR NOT tags:standup").unwrap();
+        assert_eq!(
+            result,
+            or(
+                and(term("tags", "meeting", false), term("status", "done", true)),
+                term("tags", "standup", true),
+            )
+        );
+    }
+
+    #[test]
+    fn test_negated_group() {
+        let result = parse_query("-(tags:meeting OR -status:done)").unwrap();
+        assert_eq!(
+            result,
+            and(term("tags", "meeting", true), term("status", "done", false))
+        );
+    }
+
+    #[test]
+    fn test_keywords_must_be_whole_words() {
+        let result = parse_query("notes ordering").unwrap();
+        assert_eq!(
+            result,
+            and(
+                Expr::Term {
+                    field: None,
+                    value: String::from("notes"),
+                    phrase: false,
+                    negated: false,
+                },
+                Expr::Term {
+                    field: None,
+                    value: String::from("ordering"),
+                    phrase: false,
+                    negated: false,
+                },
+            )
+        );
+    }
+
+    #[test]
+    fn test_invalid_queries_error() {
+        assert!(parse_query("tags:meeting OR").is_err());
+        assert!(parse_query("tags:meeting AND").is_err());
+        assert!(parse_query("(tags:meeting OR tags:standup").is_err());
+        assert!(parse_query("tags:meeting)").is_err());
+        assert!(parse_query("()").is_err());
+        assert!(parse_query("éé").is_ok());
+    }
+
+    #[test]
+    fn test_or_across_date_and_indexed_fields_errors() {
+        assert_eq!(
+            parse_query("tags:work OR deadline:<=2026-10-01"),
+            Err(AqlError::MixedOr)
+        );
+        assert_eq!(
+            parse_query("tags:work OR has:deadline"),
+            Err(AqlError::MixedOr)
+        );
+        assert_eq!(
+            parse_query("(tags:work deadline:2026-10-01) OR tags:home"),
+            Err(AqlError::MixedOr)
+        );
+        assert_eq!(
+            parse_query("status:todo (meeting OR -has:scheduled)"),
+            Err(AqlError::MixedOr)
+        );
+
+        // ORs on one side or the other are fine
+        assert!(parse_query("deadline:<=2026-10-01 OR has:scheduled").is_ok());
+        assert!(parse_query("tags:work (deadline:<=2026-10-01 OR has:scheduled)").is_ok());
+        assert!(parse_query("(tags:work OR tags:home) has:deadline").is_ok());
+    }
+
+    #[test]
+    fn test_empty_query_errors() {
+        assert_eq!(parse_query(""), Err(AqlError::EmptyQuery));
+        assert_eq!(parse_query("   \t\n"), Err(AqlError::EmptyQuery));
+    }
+
+    #[test]
+    fn test_dangling_negation_errors() {
+        assert_eq!(parse_query("-"), Err(AqlError::Syntax { position: 1 }));
+        assert!(matches!(
+            parse_query("tags:meeting -"),
+            Err(AqlError::Syntax { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unterminated_quote_errors() {
+        assert_eq!(
+            parse_query("title:\"weekly sync"),
+            Err(AqlError::UnterminatedQuote { position: 6 })
+        );
+    }
+
+    #[test]
+    fn test_unknown_field_errors() {
+        assert_eq!(
+            parse_query("tags:meeting effort:high"),
+            Err(AqlError::UnknownField("effort".into()))
+        );
+        assert_eq!(
+            parse_query("has:owner"),
+            Err(AqlError::UnknownField("owner".into()))
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_term() {
+        assert_eq!(
+            parse_query("kubernetes~2").unwrap(),
+            Expr::Fuzzy {
+                field: None,
+                value: "kubernetes".into(),
+                distance: 2,
+                negated: false,
+            }
+        );
+        assert_eq!(
+            parse_query("-title:recieve~^2").unwrap(),
+            Expr::Boost {
+                expr: Box::new(Expr::Fuzzy {
+                    field: Some("title".into()),
+                    value: "recieve".into(),
+                    distance: 1,
+                    negated: true,
+                }),
+                boost: 2.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_distance_is_clamped() {
+        assert!(matches!(
+            parse_query("kubernetes~5").unwrap(),
+            Expr::Fuzzy { distance: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn test_fuzzy_phrase_errors() {
+        assert!(matches!(
+            parse_query("\"weekly sync\"~1"),
+            Err(AqlError::Syntax { .. })
+        ));
+    }
+
+    #[test]
+    fn test_phrase_with_escaped_quotes() {
+        assert_eq!(
+            parse_query(r#"body:"say \"hi\" now""#).unwrap(),
+            Expr::Term {
+                field: Some("body".into()),
+                value: r#"say "hi" now"#.into(),
+                phrase: true,
+                negated: false,
+            }
+        );
+        assert_eq!(
+            parse_query(r#""a \"quote"#),
+            Err(AqlError::UnterminatedQuote { position: 0 })
+        );
+    }
 }
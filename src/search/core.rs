@@ -1,6 +1,9 @@
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
 use itertools::Itertools;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tantivy::collector::TopDocs;
 use tantivy::schema::*;
@@ -9,9 +12,91 @@ use tokio_rusqlite::{Connection, Result};
 use zerocopy::IntoBytes;
 
 use crate::api::public::notes::SearchResult;
+use crate::core::{SearchMode, SnippetStrategy};
 use crate::search::aql::{self};
-use crate::search::fts::schema::note_schema;
-use crate::search::query::{aql_to_index_query, expr_to_sql, query_to_similarity};
+use crate::search::embedding::Embedder;
+use crate::search::fts::schema::register_tokenizers;
+use crate::search::query::{FieldBoosts, aql_to_index_query, expr_to_sql, query_to_similarity};
+use crate::search::rerank::Reranker;
+use crate::search::snippet::Highlighter;
+
+/// Constant used by Reciprocal Rank Fusion to dampen the difference
+/// between the top ranks so that no single list dominates
+const RRF_K: f32 = 60.0;
+
+/// Number of the top hybrid search results that are reranked
+const RERANK_CANDIDATES: usize = 50;
+
+/// Field of the matching notes that can be counted by value
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchFacet {
+    /// Each tag in a note's comma separated tags
+    Tags,
+    /// Task status like `todo` or `done`
+    Status,
+}
+
+impl FromStr for SearchFacet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "tags" => Ok(Self::Tags),
+            "status" => Ok(Self::Status),
+            other => Err(anyhow::anyhow!("Unknown search facet: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for SearchFacet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Tags => write!(f, "tags"),
+            Self::Status => write!(f, "status"),
+        }
+    }
+}
+
+/// Number of matching notes with a value for a facet
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// Count the values of each facet across rows of `(tags, status)`.
+/// Counts are ordered from most to least common.
+fn count_facets(
+    rows: &[(Option<String>, Option<String>)],
+    facets: &[SearchFacet],
+) -> BTreeMap<SearchFacet, Vec<FacetCount>> {
+    facets
+        .iter()
+        .map(|facet| {
+            let values = rows.iter().flat_map(|(tags, status)| match facet {
+                SearchFacet::Tags => tags
+                    .as_deref()
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .collect::<Vec<_>>(),
+                SearchFacet::Status => status.as_deref().into_iter().collect(),
+            });
+            let counts = values
+                .counts()
+                .into_iter()
+                .map(|(value, count)| FacetCount {
+                    value: value.to_string(),
+                    count,
+                })
+                .sorted_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)))
+                .collect();
+            (*facet, counts)
+        })
+        .collect()
+}
 
 #[derive(Serialize)]
 pub enum SearchHitType {
@@ -28,10 +113,18 @@ pub struct SearchHit {
     pub score: f32,
 }
 
-fn fulltext_search(index_path: &str, query: &aql::Expr, limit: usize) -> Result<Vec<SearchHit>> {
-    let schema = note_schema();
+fn fulltext_search(
+    index_path: &str,
+    query: &aql::Expr,
+    boosts: &FieldBoosts,
+    limit: usize,
+) -> Result<Vec<SearchHit>> {
     let index_path = tantivy::directory::MmapDirectory::open(index_path).expect("Index not found");
     let idx = Index::open(index_path).expect("Unable to open index");
+    register_tokenizers(&idx);
+    // Use the schema the index was created with so that queries are
+    // tokenized the same way as the indexed text
+    let schema = idx.schema();
 
     let reader = idx
         .reader_builder()
@@ -42,7 +135,8 @@ fn fulltext_search(index_path: &str, query: &aql::Expr, limit: usize) -> Result<
     let searcher = reader.searcher();
 
     // Parse query using custom parser
-    let index_query = aql_to_index_query(query, &schema);
+    let index_query = aql_to_index_query(query, &schema, boosts)
+        .map_err(|e| tokio_rusqlite::Error::Other(Box::new(e)))?;
 
     if let Some(idx_query) = index_query {
         let results = searcher
@@ -77,32 +171,131 @@ fn fulltext_search(index_path: &str, query: &aql::Expr, limit: usize) -> Result<
     }
 }
 
+/// Rank of each note in `hits` starting at 1
+fn ranks_by_id(hits: &[SearchHit]) -> HashMap<String, usize> {
+    let mut ranks = HashMap::new();
+    for (i, hit) in hits.iter().enumerate() {
+        ranks.entry(hit.id.clone()).or_insert(i + 1);
+    }
+    ranks
+}
+
+/// Merge ranked lists of note IDs into one list using Reciprocal Rank
+/// Fusion. Each note scores `1 / (k + rank)` for every list it's in so
+/// notes ranked highly in several lists come first. Ties keep the
+/// order the notes were first seen in.
+fn reciprocal_rank_fusion(rankings: &[&HashMap<String, usize>], order: &[String]) -> Vec<String> {
+    let score = |id: &String| -> f32 {
+        rankings
+            .iter()
+            .filter_map(|ranks| ranks.get(id))
+            .map(|rank| 1.0 / (RRF_K + *rank as f32))
+            .sum()
+    };
+    order
+        .iter()
+        .unique()
+        .map(|id| (id, score(id)))
+        .sorted_by(|(_, a), (_, b)| b.total_cmp(a))
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Reorder the top candidates in `ids` by how relevant the reranker
+/// scores their title and body to the query, leaving the rest in
+/// place. Reranking is best effort so the original order is kept if it
+/// fails.
+async fn rerank_candidates(
+    db: &Connection,
+    reranker: &dyn Reranker,
+    query: &aql::Expr,
+    mut ids: Vec<String>,
+) -> Vec<String> {
+    let Some(query_text) = query_to_similarity(query) else {
+        return ids;
+    };
+    let num_candidates = ids.len().min(RERANK_CANDIDATES);
+    if num_candidates < 2 {
+        return ids;
+    }
+    let candidates: Vec<String> = ids.drain(..num_candidates).collect();
+
+    let candidate_ids = json!(candidates).to_string();
+    let texts = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, title, body FROM note_meta WHERE id IN (SELECT value FROM json_each(?1))",
+            )?;
+            let texts = stmt
+                .query_map([candidate_ids], |row| {
+                    let title: Option<String> = row.get(1)?;
+                    let body: Option<String> = row.get(2)?;
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        format!("{}\n\n{}", title.unwrap_or_default(), body.unwrap_or_default()),
+                    ))
+                })?
+                .collect::<std::result::Result<HashMap<String, String>, _>>()?;
+            Ok(texts)
+        })
+        .await;
+    let documents = match texts {
+        Ok(mut texts) => candidates
+            .iter()
+            .map(|id| texts.remove(id).unwrap_or_default())
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Failed to load notes to rerank: {}", e);
+            return candidates.into_iter().chain(ids).collect();
+        }
+    };
+
+    match reranker.rerank(&query_text, documents).await {
+        Ok(scores) if scores.len() == candidates.len() => candidates
+            .into_iter()
+            .zip(scores)
+            .sorted_by(|(_, a), (_, b)| b.total_cmp(a))
+            .map(|(id, _)| id)
+            .chain(ids)
+            .collect(),
+        Ok(scores) => {
+            tracing::warn!(
+                "Reranker returned {} scores for {} notes, keeping the original order",
+                scores.len(),
+                candidates.len()
+            );
+            candidates.into_iter().chain(ids).collect()
+        }
+        Err(e) => {
+            tracing::warn!("Reranking failed, keeping the original order: {}", e);
+            candidates.into_iter().chain(ids).collect()
+        }
+    }
+}
+
 /// Returns the note ID and similarity distance for the query. Results
 /// are ordered by ascending distance because sqlite-vec only supports
 /// ascending distance.
 pub async fn search_similar_notes(
     db: &Connection,
+    embedder: &dyn Embedder,
     query: &aql::Expr,
     limit: usize,
-) -> Result<Vec<SearchHit>> {
+) -> anyhow::Result<Vec<SearchHit>> {
     // Extract the relevant text to use for similar search from the
     // AQL query. It's possible there is nothing to use for a
     // similarity search. This can happen when the query is entirely
     // fields that are not valid for similarity like a status field or
     // a date field.
-    let similarity_string = query_to_similarity(query);
-    if similarity_string.is_none() {
+    let Some(similarity_string) = query_to_similarity(query) else {
         return Ok(Vec::new());
-    }
+    };
 
-    let embeddings_model = TextEmbedding::try_new(
-        InitOptions::new(EmbeddingModel::BGESmallENV15).with_show_download_progress(true),
-    )
-    .unwrap();
-    let query_vector = embeddings_model
-        .embed(vec![similarity_string.unwrap()], None)
-        .unwrap();
-    let q = query_vector[0].clone();
+    let query_vector = embedder.embed(vec![similarity_string]).await?;
+    let q = query_vector
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Embedder returned no vectors"))?;
     let result: Vec<SearchHit> = db
         .call(move |conn| {
             let mut stmt = conn.prepare(
@@ -123,13 +316,16 @@ pub async fn search_similar_notes(
         "#,
             )?;
             let found = stmt
-                .query_map([q.as_bytes(), limit.as_bytes(), limit.as_bytes()], |r| {
-                    Ok(SearchHit {
-                        r#type: SearchHitType::Similarity,
-                        id: r.get(0)?,
-                        score: r.get(5)?,
-                    })
-                })?
+                .query_map(
+                    tokio_rusqlite::params![q.as_bytes(), limit as i64, limit as i64],
+                    |r| {
+                        Ok(SearchHit {
+                            r#type: SearchHitType::Similarity,
+                            id: r.get(0)?,
+                            score: r.get(5)?,
+                        })
+                    },
+                )?
                 .collect::<std::result::Result<Vec<SearchHit>, _>>()?;
             Ok(found)
         })
@@ -137,18 +333,98 @@ pub async fn search_similar_notes(
     Ok(result)
 }
 
+/// Returns an error if the vector embeddings can't be queried e.g.
+/// the `vec_items` table is missing after a failed init, the
+/// sqlite-vec extension isn't loaded, or the table is corrupt.
+async fn check_vector_db(db: &Connection) -> anyhow::Result<()> {
+    db.call(|conn| {
+        conn.prepare("SELECT note_meta_id FROM vec_items LIMIT 1")?
+            .exists([])?;
+        Ok(())
+    })
+    .await?;
+    Ok(())
+}
+
+/// Results of a note search along with whether the search had to
+/// fall back to a less capable mode.
+pub struct NoteSearch {
+    pub results: Vec<SearchResult>,
+    /// Number of full-text matches that pass the filters on date
+    /// fields, ignoring the limit and offset so callers can page
+    /// through results. Similar notes aren't counted.
+    pub total_hits: usize,
+    /// True when similarity search was requested but the embedding
+    /// backend failed or the vector db is unavailable so only
+    /// full-text results were returned.
+    pub degraded: bool,
+    /// Counts of each requested facet across all matching notes
+    pub facets: BTreeMap<SearchFacet, Vec<FacetCount>>,
+}
+
+/// Options for `search_notes`
+pub struct SearchOptions<'a> {
+    /// Include notes similar to the query after the full-text matches
+    pub include_similarity: bool,
+    /// Shorten each result's title and body
+    pub truncate: bool,
+    pub limit: usize,
+    pub offset: usize,
+    /// Maximum length of each result's snippet or `None` to skip
+    /// snippets
+    pub snippet_chars: Option<usize>,
+    pub snippet_strategy: SnippetStrategy,
+    pub boosts: FieldBoosts,
+    /// `SearchMode::Hybrid` ranks full-text and similar notes together
+    pub mode: SearchMode,
+    /// Counted across all matching notes, not just the current page
+    pub facets: &'a [SearchFacet],
+    /// Reorders the top hybrid results
+    pub reranker: Option<&'a dyn Reranker>,
+}
+
+impl Default for SearchOptions<'_> {
+    fn default() -> Self {
+        Self {
+            include_similarity: false,
+            truncate: false,
+            limit: 20,
+            offset: 0,
+            snippet_chars: None,
+            snippet_strategy: SnippetStrategy::default(),
+            boosts: FieldBoosts::default(),
+            mode: SearchMode::default(),
+            facets: &[],
+            reranker: None,
+        }
+    }
+}
+
 // Performs a full-text search of all notes for the given query. If
 // `include_similarity`, also includes vector search results appended
 // to the end of the list of results. This way, if there is a keyword
-// search miss, there may be semantically similar results.
+// search miss, there may be semantically similar results. Falls back
+// to full-text only and marks the search degraded when similarity
+// search is unavailable.
 pub async fn search_notes(
     index_path: &str,
     db: &Connection,
-    include_similarity: bool,
-    truncate: bool,
+    embedder: &dyn Embedder,
     query: &aql::Expr,
-    limit: usize,
-) -> anyhow::Result<Vec<SearchResult>> {
+    options: &SearchOptions<'_>,
+) -> anyhow::Result<NoteSearch> {
+    let SearchOptions {
+        include_similarity,
+        truncate,
+        limit,
+        offset,
+        snippet_chars,
+        snippet_strategy,
+        ref boosts,
+        mode,
+        facets,
+        reranker,
+    } = *options;
     // The limit of search hits needs to be high enough here for broad
     // queries like `status:todo deadline:>2025-04-01` otherwise
     // results will be unexpectedly missing
@@ -156,11 +432,38 @@ pub async fn search_notes(
     // because full text results will drown out the similarity search
     // unless we have a really good way of combining results by
     // relevance
-    let mut search_hits = fulltext_search(index_path, query, 10000).unwrap_or_else(|_| Vec::new());
-    if include_similarity {
-        let mut vec_search_result = search_similar_notes(db, query, limit)
-            .await
-            .unwrap_or_default();
+    let mut search_hits = fulltext_search(index_path, query, boosts, 10000).unwrap_or_else(|e| {
+        tracing::warn!("Full-text search failed: {}", e);
+        Vec::new()
+    });
+    // Only full-text matches count towards the total since the number
+    // of similar notes depends on the limit
+    let fulltext_ids_str = json!(search_hits.iter().map(|i| &i.id).collect::<Vec<_>>()).to_string();
+    let mut degraded = false;
+    let lexical_ranks = ranks_by_id(&search_hits);
+    let mut vector_ranks = HashMap::new();
+    if include_similarity || mode == SearchMode::Hybrid {
+        // Hybrid results are ranked before paging so enough similar
+        // notes are needed to fill every page up to this one
+        let similar_limit = match mode {
+            SearchMode::Hybrid => offset + limit,
+            SearchMode::FullText => limit,
+        };
+        // Don't bother embedding the query if there's nothing to
+        // search with it
+        let similar = match check_vector_db(db).await {
+            Ok(()) => search_similar_notes(db, embedder, query, similar_limit).await,
+            Err(e) => Err(e.context("Vector db is unavailable")),
+        };
+        let mut vec_search_result = match similar {
+            Ok(hits) => hits,
+            Err(e) => {
+                tracing::warn!("Similarity search failed, using full-text only: {:#}", e);
+                degraded = true;
+                Vec::new()
+            }
+        };
+        vector_ranks = ranks_by_id(&vec_search_result);
 
         // Combine the results, dedupe, then sort by score
         search_hits.append(&mut vec_search_result);
@@ -171,14 +474,20 @@ pub async fn search_notes(
     }
 
     // Search the db for the metadata and construct results
-    let result_ids: Vec<String> = search_hits.iter().map(|i| i.id.clone()).collect();
+    let mut result_ids: Vec<String> = search_hits.iter().map(|i| i.id.clone()).collect();
+    if mode == SearchMode::Hybrid {
+        result_ids = reciprocal_rank_fusion(&[&lexical_ranks, &vector_ranks], &result_ids);
+        if let Some(reranker) = reranker {
+            result_ids = rerank_candidates(db, reranker, query, result_ids).await;
+        }
+    }
     let result_ids_serialized = json!(result_ids);
     let result_ids_str = result_ids_serialized.to_string();
 
     let mut where_clauses = Vec::new();
 
     if !result_ids.is_empty() {
-        where_clauses.push("note_meta.id in (SELECT value from json_each(?))".to_string());
+        where_clauses.push("note_meta.id in (SELECT value from json_each(?1))".to_string());
     }
 
     if let Some(extra_sql) = expr_to_sql(query) {
@@ -191,6 +500,19 @@ pub async fn search_notes(
         "".to_string()
     };
 
+    // Search hits are ordered by relevance so that order is used
+    // when there's nothing else to order by. Hybrid results are
+    // ordered by relevance only.
+    let order_by = match mode {
+        SearchMode::Hybrid => "(SELECT key FROM json_each(?1) WHERE value = note_meta.id)",
+        SearchMode::FullText => {
+            r#"date DESC,
+          deadline DESC,
+          scheduled DESC,
+          closed DESC,
+          (SELECT key FROM json_each(?1) WHERE value = note_meta.id)"#
+        }
+    };
     let sql = format!(
         r#"
         SELECT
@@ -205,21 +527,53 @@ pub async fn search_notes(
           scheduled,
           deadline,
           closed,
-          date
+          date,
+          last_indexed_at
         FROM note_meta
         {}
-        ORDER BY date DESC, deadline DESC, scheduled DESC, closed DESC
-        LIMIT {}
+        ORDER BY
+          {}
+        LIMIT {} OFFSET {}
     "#,
-        where_clause, limit
+        where_clause, order_by, limit, offset
+    );
+    let count_sql = format!(
+        "SELECT COUNT(*) FROM note_meta WHERE note_meta.id in (SELECT value from json_each(?1)){}",
+        expr_to_sql(query)
+            .map(|sql| format!(" AND {}", sql))
+            .unwrap_or_default()
     );
+    let facet_sql = format!("SELECT tags, status FROM note_meta {}", where_clause);
+    let facets = facets.to_vec();
+
+    // Snippets are nice to have so don't fail the search without them
+    let highlighter = snippet_chars.and_then(|max_num_chars| {
+        Highlighter::new(index_path, query, max_num_chars, snippet_strategy)
+            .inspect_err(|e| tracing::warn!("Failed to create snippet highlighter: {}", e))
+            .ok()
+    });
 
-    let results: Vec<SearchResult> = if !result_ids.is_empty() {
+    let (results, total_hits, facets) = if !result_ids.is_empty() {
         db.call(move |conn| {
+            let total_hits: usize =
+                conn.query_row(&count_sql, [fulltext_ids_str.as_bytes()], |r| {
+                    r.get::<_, usize>(0)
+                })?;
+            let facets = if facets.is_empty() {
+                BTreeMap::new()
+            } else {
+                let rows = conn
+                    .prepare(&facet_sql)?
+                    .query_map([result_ids_str.as_bytes()], |r| Ok((r.get(0)?, r.get(1)?)))?
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                count_facets(&rows, &facets)
+            };
             let mut stmt = conn.prepare(&sql).unwrap();
             let found = stmt
                 .query_map([result_ids_str.as_bytes()], |r| {
-                    let id = r.get(0)?;
+                    let id: String = r.get(0)?;
+                    let lexical_rank = lexical_ranks.get(&id).copied();
+                    let vector_rank = vector_ranks.get(&id).copied();
                     let r#type = r.get(1)?;
                     let category = r.get(2)?;
                     let file_name = r.get(3)?;
@@ -232,6 +586,8 @@ pub async fn search_notes(
                     let task_deadline = r.get(9)?;
                     let task_closed = r.get(10)?;
                     let meeting_date = r.get(11)?;
+                    let last_indexed_at = r.get(12)?;
+                    let snippet = highlighter.as_ref().and_then(|h| h.snippet(&title, &body));
 
                     if truncate {
                         title = title.chars().take(140).collect();
@@ -252,14 +608,567 @@ pub async fn search_notes(
                         task_deadline,
                         task_closed,
                         meeting_date,
+                        last_indexed_at,
+                        snippet,
+                        lexical_rank,
+                        vector_rank,
                     })
                 })?
                 .collect::<std::result::Result<Vec<SearchResult>, _>>()?;
-            Ok(found)
+            Ok((found, total_hits, facets))
         })
         .await?
     } else {
-        Vec::new()
+        (Vec::new(), 0, count_facets(&[], &facets))
     };
-    Ok(results)
+    Ok(NoteSearch {
+        results,
+        total_hits,
+        degraded,
+        facets,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::core::testing::TestNotes;
+    use crate::search::fts::schema::{SearchTokenizer, note_schema_with};
+    use crate::search::fts::utils::create_index;
+    use crate::search::rerank::HttpReranker;
+    use async_trait::async_trait;
+    use tempfile::TempDir;
+
+    struct FailingEmbedder;
+
+    #[async_trait]
+    impl Embedder for FailingEmbedder {
+        async fn embed(&self, _texts: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+            Err(anyhow::anyhow!("embedding backend is down"))
+        }
+    }
+
+    /// Embeds every text as the same vector
+    struct FixedEmbedder(Vec<f32>);
+
+    #[async_trait]
+    impl Embedder for FixedEmbedder {
+        async fn embed(&self, texts: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|_| self.0.clone()).collect())
+        }
+    }
+
+    fn unit_vector(dimension: usize) -> Vec<f32> {
+        let mut vector = vec![0.0f32; 384];
+        vector[dimension] = 1.0;
+        vector
+    }
+
+    const TEST_NOTE: &str =
+        ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: this is a test\n";
+
+    async fn setup_index(dir: &TempDir, note: &str) -> (String, Connection) {
+        setup_index_with_tokenizer(dir, note, SearchTokenizer::Default).await
+    }
+
+    async fn setup_index_with_tokenizer(
+        dir: &TempDir,
+        note: &str,
+        tokenizer: SearchTokenizer,
+    ) -> (String, Connection) {
+        let notes = TestNotes::new(dir.path()).await;
+        create_index(&notes.index_path, note_schema_with(tokenizer)).unwrap();
+        notes.write_note("test.org", note);
+        notes.index(None).await.unwrap();
+        (notes.index_path, notes.db)
+    }
+
+    #[tokio::test]
+    async fn it_falls_back_to_full_text_when_embedding_fails() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_index(&dir, TEST_NOTE).await;
+        let query = aql::parse_query("title:test").unwrap();
+
+        let search = search_notes(
+            &index_path,
+            &db,
+            &FailingEmbedder,
+            &query,
+            &SearchOptions {
+                include_similarity: true,
+                truncate: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(search.degraded);
+        assert_eq!(search.results.len(), 1);
+        assert_eq!(search.results[0].id, "test-note-id");
+    }
+
+    #[tokio::test]
+    async fn it_falls_back_to_full_text_when_the_vector_db_is_missing() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_index(&dir, TEST_NOTE).await;
+        // Same as an init that failed to create the vector table
+        db.call(|conn| {
+            conn.execute("DROP TABLE vec_items", [])?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let query = aql::parse_query("test").unwrap();
+
+        let search = search_notes(
+            &index_path,
+            &db,
+            &FixedEmbedder(unit_vector(0)),
+            &query,
+            &SearchOptions {
+                include_similarity: true,
+                truncate: true,
+                mode: SearchMode::Hybrid,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(search.degraded);
+        assert_eq!(search.results.len(), 1);
+        assert_eq!(search.results[0].id, "test-note-id");
+    }
+
+    #[tokio::test]
+    async fn it_is_not_degraded_without_similarity() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_index(&dir, TEST_NOTE).await;
+        let query = aql::parse_query("test").unwrap();
+
+        let search = search_notes(
+            &index_path,
+            &db,
+            &FailingEmbedder,
+            &query,
+            &SearchOptions {
+                truncate: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(!search.degraded);
+        assert_eq!(search.results.len(), 1);
+    }
+
+    const TASKS_NOTE: &str = ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: Tasks\n\n* TODO Pay taxes :finance:\nDEADLINE: <2025-04-15 Tue>\n:PROPERTIES:\n:ID:       task-with-deadline\n:END:\n* TODO Water plants\n:PROPERTIES:\n:ID:       task-without-deadline\n:END:\n";
+
+    /// IDs of the notes matching `query` using full-text search only
+    pub(crate) async fn search_ids(index_path: &str, db: &Connection, query: &str) -> Vec<String> {
+        let query = aql::parse_query(query).unwrap();
+        let search = search_notes(
+            index_path,
+            db,
+            &FailingEmbedder,
+            &query,
+            &SearchOptions {
+                truncate: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        search.results.into_iter().map(|r| r.id).collect()
+    }
+
+    #[tokio::test]
+    async fn it_counts_only_full_text_matches_in_total_hits() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_index(&dir, TASKS_NOTE).await;
+        db.call(|conn| {
+            for id in [
+                "test-note-id",
+                "task-with-deadline",
+                "task-without-deadline",
+            ] {
+                conn.execute(
+                    "INSERT INTO vec_items(note_meta_id, embedding) VALUES (?, ?)",
+                    tokio_rusqlite::params![id, unit_vector(0).as_bytes()],
+                )?;
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let query = aql::parse_query("title:taxes").unwrap();
+        let search = search_notes(
+            &index_path,
+            &db,
+            &FixedEmbedder(unit_vector(0)),
+            &query,
+            &SearchOptions {
+                include_similarity: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        // The task without a deadline is only similar so it's in the
+        // results but not the total
+        assert!(!search.degraded);
+        assert_eq!(search.results.len(), 3);
+        assert_eq!(
+            search.total_hits,
+            search_ids(&index_path, &db, "title:taxes").await.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_partitions_notes_by_field_existence() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_index(&dir, TASKS_NOTE).await;
+
+        let ids = search_ids(&index_path, &db, "has:deadline").await;
+        assert_eq!(ids, vec!["task-with-deadline"]);
+
+        let ids = search_ids(&index_path, &db, "-has:deadline").await;
+        assert!(ids.contains(&String::from("task-without-deadline")));
+        assert!(!ids.contains(&String::from("task-with-deadline")));
+
+        let ids = search_ids(&index_path, &db, "has:tags").await;
+        assert_eq!(ids, vec!["task-with-deadline"]);
+
+        let ids = search_ids(&index_path, &db, "-has:tags").await;
+        assert!(ids.contains(&String::from("task-without-deadline")));
+        assert!(!ids.contains(&String::from("task-with-deadline")));
+    }
+
+    #[tokio::test]
+    async fn it_matches_either_side_of_an_or_group() {
+        let dir = TempDir::new().unwrap();
+        let note = ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: Tasks\n\n* TODO Plan sprint :work:\n:PROPERTIES:\n:ID:       work-task\n:END:\n* TODO Share update :standup:\n:PROPERTIES:\n:ID:       standup-task\n:END:\n* DONE Share blockers :standup:\n:PROPERTIES:\n:ID:       done-task\n:END:\n* TODO Pay taxes :finance:\n:PROPERTIES:\n:ID:       finance-task\n:END:\n";
+        let (index_path, db) = setup_index(&dir, note).await;
+
+        let mut ids = search_ids(&index_path, &db, "(tags:work OR tags:standup) status:todo").await;
+        ids.sort();
+        assert_eq!(ids, vec!["standup-task", "work-task"]);
+    }
+
+    #[tokio::test]
+    async fn it_matches_misspelled_terms_with_fuzzy_operator() {
+        let dir = TempDir::new().unwrap();
+        let note = ":PROPERTIES:\n:ID:       fuzzy-note\n:END:\n#+TITLE: Mail\n\nWaiting to receive the package.\n";
+        let (index_path, db) = setup_index(&dir, note).await;
+
+        assert!(search_ids(&index_path, &db, "recieve").await.is_empty());
+        assert_eq!(
+            search_ids(&index_path, &db, "recieve~1").await,
+            vec!["fuzzy-note"]
+        );
+
+        // A bare `~` is a distance of 1 so two edits don't match
+        assert_eq!(
+            search_ids(&index_path, &db, "recieve~").await,
+            vec!["fuzzy-note"]
+        );
+        assert!(search_ids(&index_path, &db, "recieved~").await.is_empty());
+        assert_eq!(
+            search_ids(&index_path, &db, "recieved~2").await,
+            vec!["fuzzy-note"]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_matches_quoted_phrases_in_order() {
+        let dir = TempDir::new().unwrap();
+        let note = ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: Tasks\n\n* TODO Prepare the project kickoff\n:PROPERTIES:\n:ID:       phrase-task\n:END:\n* TODO Kickoff planning for the garden side project\n:PROPERTIES:\n:ID:       keywords-task\n:END:\n";
+        let (index_path, db) = setup_index(&dir, note).await;
+
+        // Separate keywords match both tasks
+        let ids = search_ids(&index_path, &db, "title:project title:kickoff").await;
+        assert!(ids.contains(&String::from("phrase-task")));
+        assert!(ids.contains(&String::from("keywords-task")));
+
+        // The phrase only matches where the words are together
+        let ids = search_ids(&index_path, &db, r#"title:"Project Kickoff""#).await;
+        assert!(ids.contains(&String::from("phrase-task")));
+        assert!(!ids.contains(&String::from("keywords-task")));
+
+        let ids = search_ids(&index_path, &db, r#""project kickoff""#).await;
+        assert!(ids.contains(&String::from("phrase-task")));
+        assert!(!ids.contains(&String::from("keywords-task")));
+    }
+
+    #[tokio::test]
+    async fn it_ranks_title_matches_above_body_matches() {
+        let dir = TempDir::new().unwrap();
+        let note = ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: Notes\n\n* Planning\n:PROPERTIES:\n:ID:       body-match\n:END:\nTalked about the roadmap in detail.\n* Roadmap\n:PROPERTIES:\n:ID:       title-match\n:END:\nNext quarter.\n";
+        let (index_path, db) = setup_index(&dir, note).await;
+
+        let ids = search_ids(&index_path, &db, "roadmap").await;
+        let title_rank = ids.iter().position(|id| id == "title-match").unwrap();
+        let body_rank = ids.iter().position(|id| id == "body-match").unwrap();
+        assert!(title_rank < body_rank);
+    }
+
+    #[tokio::test]
+    async fn it_ranks_boosted_terms_higher() {
+        let dir = TempDir::new().unwrap();
+        let note = ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: Notes\n\n* Planning\n:PROPERTIES:\n:ID:       roadmap-match\n:END:\nTalked about the roadmap.\n* Finances\n:PROPERTIES:\n:ID:       budget-match\n:END:\nTalked about the budget.\n";
+        let (index_path, db) = setup_index(&dir, note).await;
+
+        let rank = |ids: &[String], id: &str| ids.iter().position(|i| i == id).unwrap();
+
+        let ids = search_ids(&index_path, &db, "roadmap OR budget^5").await;
+        assert!(rank(&ids, "budget-match") < rank(&ids, "roadmap-match"));
+
+        let ids = search_ids(&index_path, &db, "roadmap^5 OR budget").await;
+        assert!(rank(&ids, "roadmap-match") < rank(&ids, "budget-match"));
+    }
+
+    #[tokio::test]
+    async fn it_ranks_notes_with_configured_field_boosts() {
+        let dir = TempDir::new().unwrap();
+        let title_note =
+            ":PROPERTIES:\n:ID:       title-note\n:END:\n#+TITLE: Roadmap\n\nNext quarter.\n";
+        let notes = TestNotes::new(dir.path()).await;
+        notes.write_note("title.org", title_note);
+        notes.write_note(
+            "body.org",
+            ":PROPERTIES:\n:ID:       body-note\n:END:\n#+TITLE: Planning\n\nTalked about the roadmap.\n",
+        );
+        notes.index(None).await.unwrap();
+        let TestNotes { index_path, db, .. } = notes;
+
+        let query = aql::parse_query("roadmap").unwrap();
+        let search_with = |boosts: FieldBoosts| {
+            let (index_path, db, query) = (&index_path, &db, &query);
+            async move {
+                search_notes(
+                    index_path,
+                    db,
+                    &FailingEmbedder,
+                    query,
+                    &SearchOptions {
+                        truncate: true,
+                        boosts,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap()
+                .results
+                .into_iter()
+                .map(|r| r.id)
+                .collect::<Vec<_>>()
+            }
+        };
+
+        let boosts = FieldBoosts {
+            title: 3.0,
+            body: 1.0,
+            ..FieldBoosts::default()
+        };
+        assert_eq!(search_with(boosts).await, vec!["title-note", "body-note"]);
+
+        // Weighting the body higher flips the order
+        let boosts = FieldBoosts {
+            title: 1.0,
+            body: 10.0,
+            ..FieldBoosts::default()
+        };
+        assert_eq!(search_with(boosts).await, vec!["body-note", "title-note"]);
+    }
+
+    const JAPANESE_NOTE: &str =
+        ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: 日記\n\n私は東京に住んでいます。\n";
+
+    #[tokio::test]
+    async fn it_matches_cjk_substrings_with_cjk_tokenizer() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) =
+            setup_index_with_tokenizer(&dir, JAPANESE_NOTE, SearchTokenizer::Cjk).await;
+
+        assert_eq!(
+            search_ids(&index_path, &db, "東京").await,
+            vec!["test-note-id"]
+        );
+        assert_eq!(
+            search_ids(&index_path, &db, "東京に住んで").await,
+            vec!["test-note-id"]
+        );
+        assert!(search_ids(&index_path, &db, "大阪").await.is_empty());
+    }
+
+    #[test]
+    fn it_fuses_rankings_with_reciprocal_rank_fusion() {
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let ranks = |ids: &[&str]| {
+            ids.iter()
+                .enumerate()
+                .map(|(i, id)| (id.to_string(), i + 1))
+                .collect::<HashMap<_, _>>()
+        };
+        let lexical = ranks(&["a", "b", "c"]);
+        let vector = ranks(&["c", "d", "a"]);
+
+        // `a` and `c` are in both lists so they rank above the rest
+        let fused = reciprocal_rank_fusion(&[&lexical, &vector], &ids(&["a", "b", "c", "d"]));
+        assert_eq!(fused, ids(&["a", "c", "b", "d"]));
+    }
+
+    /// Index notes matching "budget" where only one note without the
+    /// word is close to the query's embedding
+    async fn setup_hybrid_index(dir: &TempDir) -> (String, Connection) {
+        let note = ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: Planning\n\n* Budget review\n:PROPERTIES:\n:ID:       lexical-1\n:END:\nThe budget is on track.\n* Budget meeting\n:PROPERTIES:\n:ID:       lexical-2\n:END:\nBudget questions.\n* Team budget\n:PROPERTIES:\n:ID:       lexical-3\n:END:\n* Budget archive\n:PROPERTIES:\n:ID:       lexical-4\n:END:\n* Spending plan for next year\n:PROPERTIES:\n:ID:       semantic\n:END:\nHow much we expect to spend.\n";
+        let (index_path, db) = setup_index(dir, note).await;
+
+        // Only the semantic note is close to the query's embedding
+        db.call(|conn| {
+            for (id, vector) in [("semantic", unit_vector(0)), ("lexical-4", unit_vector(1))] {
+                conn.execute(
+                    "INSERT INTO vec_items(note_meta_id, embedding) VALUES (?, ?)",
+                    tokio_rusqlite::params![id, vector.as_bytes()],
+                )?;
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        (index_path, db)
+    }
+
+    async fn search_budget(
+        index_path: &str,
+        db: &Connection,
+        mode: SearchMode,
+        reranker: Option<&dyn Reranker>,
+    ) -> Vec<SearchResult> {
+        let query = aql::parse_query("budget").unwrap();
+        search_notes(
+            index_path,
+            db,
+            &FixedEmbedder(unit_vector(0)),
+            &query,
+            &SearchOptions {
+                truncate: true,
+                limit: 3,
+                mode,
+                reranker,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .results
+    }
+
+    #[tokio::test]
+    async fn it_surfaces_semantic_matches_in_hybrid_mode() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_hybrid_index(&dir).await;
+
+        let full_text = search_budget(&index_path, &db, SearchMode::FullText, None).await;
+        assert!(full_text.iter().all(|r| r.id != "semantic"));
+
+        let hybrid = search_budget(&index_path, &db, SearchMode::Hybrid, None).await;
+        assert_eq!(hybrid.len(), 3);
+        let semantic = hybrid.iter().find(|r| r.id == "semantic").unwrap();
+        assert_eq!(semantic.lexical_rank, None);
+        assert_eq!(semantic.vector_rank, Some(1));
+
+        // A note matching both ways ranks above notes only matching one
+        assert_eq!(hybrid[0].id, "lexical-4");
+        assert!(hybrid[0].lexical_rank.is_some());
+        assert_eq!(hybrid[0].vector_rank, Some(2));
+    }
+
+    /// Scores documents titled with the prefix above everything else
+    struct KeywordReranker(&'static str);
+
+    #[async_trait]
+    impl Reranker for KeywordReranker {
+        async fn rerank(&self, _query: &str, documents: Vec<String>) -> anyhow::Result<Vec<f32>> {
+            Ok(documents
+                .iter()
+                .map(|doc| if doc.starts_with(self.0) { 1.0 } else { 0.0 })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reranks_hybrid_results() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_hybrid_index(&dir).await;
+
+        let hybrid = search_budget(&index_path, &db, SearchMode::Hybrid, None).await;
+        assert_ne!(hybrid[0].id, "lexical-3");
+
+        let reranker = KeywordReranker("Team budget");
+        let reranked = search_budget(&index_path, &db, SearchMode::Hybrid, Some(&reranker)).await;
+        assert_eq!(reranked.len(), 3);
+        assert_eq!(reranked[0].id, "lexical-3");
+        assert_eq!(reranked[1].id, hybrid[0].id);
+
+        // Full-text search is left alone
+        let full_text = search_budget(&index_path, &db, SearchMode::FullText, None).await;
+        let full_text_reranked =
+            search_budget(&index_path, &db, SearchMode::FullText, Some(&reranker)).await;
+        assert_eq!(
+            full_text.iter().map(|r| &r.id).collect::<Vec<_>>(),
+            full_text_reranked.iter().map(|r| &r.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_hybrid_order_when_the_reranker_is_down() {
+        let dir = TempDir::new().unwrap();
+        let (index_path, db) = setup_hybrid_index(&dir).await;
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/rerank")
+            .with_status(503)
+            .create_async()
+            .await;
+        let reranker = HttpReranker::new(&format!("{}/rerank", server.url()));
+
+        let hybrid = search_budget(&index_path, &db, SearchMode::Hybrid, None).await;
+        let reranked = search_budget(&index_path, &db, SearchMode::Hybrid, Some(&reranker)).await;
+        mock.assert_async().await;
+        assert_eq!(
+            hybrid.iter().map(|r| &r.id).collect::<Vec<_>>(),
+            reranked.iter().map(|r| &r.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_finds_notes_by_attachment_file_name() {
+        let dir = TempDir::new().unwrap();
+        let note = ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: Architecture\n\nSee [[file:images/diagram.png]] for details.\n";
+        let (index_path, db) = setup_index(&dir, note).await;
+        let query = aql::parse_query("diagram.png").unwrap();
+
+        let search = search_notes(
+            &index_path,
+            &db,
+            &FailingEmbedder,
+            &query,
+            &SearchOptions {
+                truncate: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(search.results.len(), 1);
+        assert_eq!(search.results[0].id, "test-note-id");
+    }
 }
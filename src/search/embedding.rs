@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use sha2::{Digest, Sha256};
+use tokio_rusqlite::{Connection, OptionalExtension};
+use zerocopy::IntoBytes;
+
+/// Turns text into embedding vectors for similarity search. This is
+/// a seam so that search can degrade gracefully when embeddings are
+/// unavailable and so tests can swap in a fake backend.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Embeds text locally using fastembed. The model is loaded on first
+/// use, which may require downloading it, and then shared by every
+/// `LocalEmbedder` in the process.
+pub struct LocalEmbedder;
+
+static LOCAL_MODEL: Mutex<Option<Arc<TextEmbedding>>> = Mutex::new(None);
+
+impl LocalEmbedder {
+    /// Name of the model that cached embeddings were made with
+    pub const MODEL_ID: &str = "bge-small-en-v1.5";
+
+    /// Returns the loaded model, loading it if this is the first use.
+    /// Blocks while the model loads so call it off the async runtime.
+    pub fn model() -> Result<Arc<TextEmbedding>> {
+        let mut model = LOCAL_MODEL.lock().unwrap();
+        if let Some(model) = model.as_ref() {
+            return Ok(Arc::clone(model));
+        }
+        let loaded = Arc::new(
+            TextEmbedding::try_new(
+                InitOptions::new(EmbeddingModel::BGESmallENV15).with_show_download_progress(true),
+            )
+            .map_err(|e| anyhow!("Failed to load embedding model: {}", e))?,
+        );
+        *model = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+}
+
+#[async_trait]
+impl Embedder for LocalEmbedder {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        tokio::task::spawn_blocking(move || {
+            LocalEmbedder::model()?
+                .embed(texts, None)
+                .map_err(|e| anyhow!("Failed to generate embeddings: {}", e))
+        })
+        .await?
+    }
+}
+
+/// Retries failed embedding calls with exponential backoff so that a
+/// transient failure doesn't fail the caller. Used when indexing,
+/// which isn't part of a chat turn, so retries are limited by
+/// `max_retries` instead of a chat turn's retry budget.
+pub struct RetryEmbedder<E> {
+    inner: E,
+    max_retries: usize,
+    initial_backoff: Duration,
+}
+
+impl<E: Embedder> RetryEmbedder<E> {
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<E: Embedder> Embedder for RetryEmbedder<E> {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            match self.inner.embed(texts.clone()).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err(e) if attempt < self.max_retries => {
+                    attempt += 1;
+                    tracing::warn!(
+                        "Embedding failed, retrying in {:?} ({}/{}): {:#}",
+                        backoff,
+                        attempt,
+                        self.max_retries,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Reuses the embeddings of text that was embedded before by caching
+/// them in the db keyed by a hash of the model and the text. Re-indexing
+/// notes that haven't changed then doesn't call the embedding backend
+/// at all.
+pub struct CachedEmbedder<E> {
+    inner: E,
+    db: Connection,
+    /// Identifies the model `inner` embeds with so that changing the
+    /// model doesn't reuse vectors from the old one
+    model_id: String,
+}
+
+impl<E: Embedder> CachedEmbedder<E> {
+    pub fn new(db: Connection, model_id: &str, inner: E) -> Self {
+        Self {
+            inner,
+            db,
+            model_id: model_id.to_string(),
+        }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+fn content_hash(model_id: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model_id.as_bytes());
+    // Separates the model from the text so that different pairs can't
+    // hash the same
+    hasher.update([0]);
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[async_trait]
+impl<E: Embedder> Embedder for CachedEmbedder<E> {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let hashes: Vec<String> = texts
+            .iter()
+            .map(|t| content_hash(&self.model_id, t))
+            .collect();
+        let lookup = hashes.clone();
+        let mut embeddings: HashMap<String, Vec<f32>> = self
+            .db
+            .call(move |conn| {
+                let mut stmt =
+                    conn.prepare("SELECT embedding FROM embedding_cache WHERE hash = ?")?;
+                let mut found = HashMap::new();
+                for hash in lookup {
+                    let embedding = stmt
+                        .query_row([&hash], |r| r.get::<_, Vec<u8>>(0))
+                        .optional()?;
+                    if let Some(bytes) = embedding {
+                        let vector = bytes
+                            .chunks_exact(4)
+                            .map(|b| f32::from_ne_bytes(b.try_into().unwrap()))
+                            .collect();
+                        found.insert(hash, vector);
+                    }
+                }
+                Ok(found)
+            })
+            .await?;
+
+        // Embed everything that isn't cached in one call
+        let (missing_hashes, missing_texts): (Vec<String>, Vec<String>) = hashes
+            .iter()
+            .zip(texts)
+            .filter(|(hash, _)| !embeddings.contains_key(*hash))
+            .map(|(hash, text)| (hash.clone(), text))
+            .unzip();
+        if !missing_texts.is_empty() {
+            let new_embeddings = self.inner.embed(missing_texts).await?;
+            if new_embeddings.len() != missing_hashes.len() {
+                return Err(anyhow!(
+                    "Expected {} embeddings but got {}",
+                    missing_hashes.len(),
+                    new_embeddings.len()
+                ));
+            }
+            let rows: Vec<(String, Vec<f32>)> =
+                missing_hashes.into_iter().zip(new_embeddings).collect();
+            let to_cache = rows.clone();
+            self.db
+                .call(move |conn| {
+                    let tx = conn.transaction()?;
+                    {
+                        let mut stmt = tx.prepare(
+                            "INSERT OR REPLACE INTO embedding_cache (hash, embedding) VALUES (?, ?)",
+                        )?;
+                        for (hash, embedding) in to_cache {
+                            stmt.execute(tokio_rusqlite::params![hash, embedding.as_bytes()])?;
+                        }
+                    }
+                    tx.commit()?;
+                    Ok(())
+                })
+                .await?;
+            embeddings.extend(rows);
+        }
+
+        Ok(hashes.iter().map(|hash| embeddings[hash].clone()).collect())
+    }
+}
@@ -1,7 +1,105 @@
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
 use tantivy;
+use tantivy::Index;
 use tantivy::schema::*;
+use tantivy::tokenizer::{
+    LowerCaser, NgramTokenizer, RemoveLongFilter, SimpleTokenizer, TextAnalyzer,
+};
+
+/// Name of the n-gram tokenizer registered for CJK content
+pub const CJK_TOKENIZER: &str = "cjk_ngram";
+
+/// Tokenizer used for the title and body of notes. Changing it only
+/// applies to a new index so the index needs to be rebuilt.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SearchTokenizer {
+    /// Splits on whitespace and punctuation
+    #[default]
+    Default,
+    /// Overlapping 1 and 2 character n-grams so that text without
+    /// spaces (Chinese, Japanese, Korean) can be matched by substring
+    Cjk,
+}
+
+impl FromStr for SearchTokenizer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "default" => Ok(Self::Default),
+            "cjk" => Ok(Self::Cjk),
+            other => Err(anyhow!("Unknown search tokenizer: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for SearchTokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Cjk => write!(f, "cjk"),
+        }
+    }
+}
+
+impl SearchTokenizer {
+    /// Tokenizer set by the `HQ_SEARCH_TOKENIZER` env var
+    pub fn from_env() -> Self {
+        env::var("HQ_SEARCH_TOKENIZER")
+            .map(|v| v.parse().expect("Invalid env var HQ_SEARCH_TOKENIZER"))
+            .unwrap_or_default()
+    }
+}
 
+/// Analyzer for the `CJK_TOKENIZER`. Also used at query time so that
+/// query terms are split the same way as the indexed text.
+pub fn cjk_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(NgramTokenizer::new(1, 2, false).expect("Invalid n-gram size"))
+        .filter(LowerCaser)
+        .build()
+}
+
+/// Same as tantivy's `default` tokenizer used by `TEXT` fields so
+/// that phrases can be split into terms the way they were indexed.
+pub fn default_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser)
+        .build()
+}
+
+/// Register custom tokenizers used by the schema. This needs to be
+/// called every time the index is opened since tokenizers aren't
+/// persisted with the index.
+pub fn register_tokenizers(idx: &Index) {
+    idx.tokenizers().register(CJK_TOKENIZER, cjk_analyzer());
+}
+
+/// Version of `note_schema`. Bump this when fields are added or
+/// changed so that existing indexes are rebuilt with the new schema.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Schema for a new index using the tokenizer set in the environment
 pub fn note_schema() -> Schema {
+    note_schema_with(SearchTokenizer::from_env())
+}
+
+pub fn note_schema_with(tokenizer: SearchTokenizer) -> Schema {
+    let content_options = match tokenizer {
+        SearchTokenizer::Default => TEXT | STORED,
+        SearchTokenizer::Cjk => TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(CJK_TOKENIZER)
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            )
+            .set_stored(),
+    };
+
     let mut schema_builder = Schema::builder();
     // There is no primary ID concept in tantivy so this needs to be
     // stored as a raw value using the index type `STRING` instead of
@@ -11,10 +109,16 @@ pub fn note_schema() -> Schema {
     schema_builder.add_text_field("id", STRING | STORED);
     schema_builder.add_text_field("type", TEXT | STORED);
     schema_builder.add_text_field("category", TEXT | STORED);
-    schema_builder.add_text_field("title", TEXT | STORED);
+    schema_builder.add_text_field("title", content_options.clone());
     schema_builder.add_text_field("tags", TEXT | STORED);
     schema_builder.add_text_field("status", TEXT | STORED);
-    schema_builder.add_text_field("body", TEXT | STORED);
+    // Priority cookie of a headline like `A` for `[#A]`. Not
+    // tokenized so it's matched exactly.
+    schema_builder.add_text_field("priority", STRING | STORED);
+    schema_builder.add_text_field("body", content_options);
     schema_builder.add_text_field("file_name", TEXT | STORED);
+    // File names of attachments linked from the note. Not tokenized
+    // so that a file name like `diagram.png` is matched exactly.
+    schema_builder.add_text_field("attachments", STRING | STORED);
     schema_builder.build()
 }
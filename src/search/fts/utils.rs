@@ -1,7 +1,13 @@
-use crate::search::fts::schema::note_schema;
+use crate::search::fts::schema::{SCHEMA_VERSION, note_schema, register_tokenizers};
 use std::fs;
+use std::path::Path;
 use tantivy;
 use tantivy::Index;
+use tantivy::schema::Schema;
+
+/// File in the index directory with the `SCHEMA_VERSION` the index
+/// was created with. Tantivy ignores files it doesn't manage.
+pub const SCHEMA_VERSION_FILE_NAME: &str = "hq_schema_version";
 
 /// Resets the index by deleting all data and recreating an empty
 /// index. Useful when rebuilding from scratch or migrating the schema
@@ -9,7 +15,70 @@ use tantivy::Index;
 pub fn recreate_index(index_path: &str) {
     fs::remove_dir_all(index_path).expect("Failed to delete index directory");
     fs::create_dir(index_path).expect("Failed to recreate index directory");
-    let index_path = tantivy::directory::MmapDirectory::open(index_path).expect("Index not found");
-    let schema = note_schema();
-    Index::open_or_create(index_path, schema.clone()).expect("Unable to open or create index");
+    open_or_create_index(index_path).expect("Unable to open or create index");
+}
+
+/// Open the index or create it if it doesn't exist yet. An existing
+/// index keeps the schema it was created with.
+pub fn open_or_create_index(index_path: &str) -> tantivy::Result<Index> {
+    let dir = tantivy::directory::MmapDirectory::open(index_path)?;
+    let idx = if Index::exists(&dir)? {
+        Index::open(dir)?
+    } else {
+        create_index(index_path, note_schema())?
+    };
+    register_tokenizers(&idx);
+    Ok(idx)
+}
+
+/// Create a new index with `schema` and record the current
+/// `SCHEMA_VERSION` alongside it.
+pub fn create_index(index_path: &str, schema: Schema) -> tantivy::Result<Index> {
+    let idx = Index::create_in_dir(index_path, schema)?;
+    fs::write(
+        Path::new(index_path).join(SCHEMA_VERSION_FILE_NAME),
+        SCHEMA_VERSION.to_string(),
+    )?;
+    Ok(idx)
+}
+
+/// Returns true if there is an index that was created with an older
+/// schema than the current `SCHEMA_VERSION`. Indexes from before the
+/// version was recorded are always outdated.
+pub fn index_is_outdated(index_path: &str) -> bool {
+    let Ok(dir) = tantivy::directory::MmapDirectory::open(index_path) else {
+        return false;
+    };
+    if !Index::exists(&dir).unwrap_or(false) {
+        return false;
+    }
+    let version = fs::read_to_string(Path::new(index_path).join(SCHEMA_VERSION_FILE_NAME))
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok());
+    version != Some(SCHEMA_VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn it_detects_outdated_indexes() {
+        let dir = TempDir::new().unwrap();
+        let index_path = dir.path().to_str().unwrap();
+
+        // No index yet
+        assert!(!index_is_outdated(index_path));
+
+        open_or_create_index(index_path).unwrap();
+        assert!(!index_is_outdated(index_path));
+
+        // An index from before the version was recorded
+        fs::remove_file(dir.path().join(SCHEMA_VERSION_FILE_NAME)).unwrap();
+        assert!(index_is_outdated(index_path));
+
+        recreate_index(index_path);
+        assert!(!index_is_outdated(index_path));
+    }
 }
@@ -4,35 +4,41 @@ use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use itertools::Itertools;
 use orgize::ParseConfig;
+use orgize::ast::Headline;
 use orgize::rowan::ast::AstNode;
+use serde::Serialize;
+use tantivy::directory::error::LockError;
 use tantivy::schema::*;
-use tantivy::{Index, IndexWriter, doc};
+use tantivy::{IndexWriter, TantivyError, doc};
 use text_splitter::{ChunkConfig, TextSplitter};
 use tiktoken_rs::{CoreBPE, cl100k_base};
 use tokio::fs;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio_rusqlite::{Connection, Result};
 use zerocopy::IntoBytes;
 
+use super::embedding::{CachedEmbedder, Embedder, LocalEmbedder, RetryEmbedder};
 use super::export::MarkdownExport;
-use super::fts::schema::note_schema;
+use super::fts::utils::{index_is_outdated, open_or_create_index, recreate_index};
 use super::source::{note_filter, notes};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 struct Task {
     id: String,
     title: String,
     category: String,
     body: String,
     status: String,
+    priority: Option<String>,
     tags: Option<String>,
     scheduled: Option<String>,
     deadline: Option<String>,
     closed: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 struct Meeting {
     id: String,
     title: String,
@@ -42,30 +48,51 @@ struct Meeting {
     date: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 struct Heading {
     id: String,
     title: String,
     category: String,
     body: String,
     tags: Option<String>,
+    priority: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 struct Note {
     id: String,
     title: String,
     category: String,
     body: String,
     tags: Option<String>,
+    /// Date from the `#+DATE` keyword
+    created: Option<String>,
+    attachments: Vec<String>,
     tasks: Vec<Task>,
     meetings: Vec<Meeting>,
     headings: Vec<Heading>,
 }
 
-/// Parse the content into a `Note`
-fn parse_note(content: &str) -> Note {
-    let config = ParseConfig {
+/// Find the paths of files linked from the content e.g.
+/// `[[file:images/diagram.png]]` or `[[attachment:notes.pdf][Notes]]`
+/// in the order they first appear.
+fn parse_attachments(content: &str) -> Vec<String> {
+    let link_regex = Regex::new(r"\[\[(?:file|attachment):([^\]\[]+)\](?:\[[^\]]*\])?\]").unwrap();
+    let mut attachments: Vec<String> = Vec::new();
+    for cap in link_regex.captures_iter(content) {
+        // Links can have a search option after the path e.g.
+        // `file:notes.org::*Heading`
+        let path = cap[1].split("::").next().unwrap_or_default().trim();
+        if !path.is_empty() && !attachments.iter().any(|a| a == path) {
+            attachments.push(path.to_string());
+        }
+    }
+    attachments
+}
+
+/// Org mode parser config with the TODO keywords used in notes
+pub(super) fn parse_config() -> ParseConfig {
+    ParseConfig {
         todo_keywords: (
             vec![
                 "TODO".to_string(),
@@ -79,8 +106,30 @@ fn parse_note(content: &str) -> Note {
             ],
         ),
         ..Default::default()
-    };
-    let p = config.parse(content);
+    }
+}
+
+/// Tags in a `#+FILETAGS` value like `:work:meeting:`. Tags separated
+/// by spaces are accepted too.
+fn parse_filetags(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+/// Add each of the comma separated tags as a separate value of the
+/// multi-valued `tags` field
+fn add_tags(doc: &mut TantivyDocument, field: Field, tags: &Option<String>) {
+    for tag in tags.iter().flat_map(|tags| tags.split(',')) {
+        doc.add_text(field, tag);
+    }
+}
+
+/// Parse the content into a `Note`
+fn parse_note(content: &str) -> Note {
+    let p = parse_config().parse(content);
     let d = p.document();
 
     let props = d.properties().expect("Missing property drawer");
@@ -102,27 +151,21 @@ fn parse_note(content: &str) -> Note {
     note_body_md.render(d.syntax());
     let note_body = note_body_md.finish();
 
-    let filetags: Vec<Vec<String>> = p
+    // Tags of the note are its file tags. Headline tags are only
+    // indexed on the headline they're on.
+    let all_tags: Vec<String> = p
         .keywords()
-        .filter_map(|k| match k.key().to_string().as_str() {
-            "FILETAGS" => Some(
-                k.value()
-                    .to_string()
-                    .trim()
-                    .split(" ")
-                    .map(|s| s.to_string())
-                    .collect(),
-            ),
-            _ => None,
-        })
+        .filter(|k| k.key() == "FILETAGS")
+        .flat_map(|k| parse_filetags(k.value().as_ref()))
+        .unique()
         .collect();
 
     // For now, tags are a comma separated string which should
     // allow it to still be searchable
-    let note_tags = if filetags.is_empty() {
+    let note_tags = if all_tags.is_empty() {
         None
     } else {
-        Some(filetags[0].to_owned().join(","))
+        Some(all_tags.join(","))
     };
 
     let mut tasks: Vec<Task> = Vec::new();
@@ -130,37 +173,22 @@ fn parse_note(content: &str) -> Note {
     let mut headings: Vec<Heading> = Vec::new();
 
     let date_regex = Regex::new(r"(\d{4})-(\d{2})-(\d{2})").unwrap();
+    let note_created = p.keywords().find(|k| k.key() == "DATE").and_then(|k| {
+        date_regex
+            .find(k.value().as_ref())
+            .map(|m| m.as_str().to_string())
+    });
+
     for i in p.document().headlines() {
-        let tag_string = i
-            .tags()
-            .map(|j| j.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
+        let tag_string = i.tags().map(|j| j.to_string()).unique().join(",");
         let tags = if tag_string.is_empty() {
             None
         } else {
             Some(tag_string.clone())
         };
         let title = i.title_raw().trim().to_string();
-
-        // Tasks sometimes don't have an org-id.
-        let mut hasher = DefaultHasher::new();
-        title.hash(&mut hasher);
-        let default_id = hasher.finish().to_string();
-
-        // Note: Can't use a question mark operator as that
-        // will cause an early return rather than handling the
-        // case where properties don't exist
-        let task_properties = i.properties();
-        let id = if let Some(task_props) = task_properties {
-            // Properties might exist but the ID might be missing
-            task_props
-                .get("ID")
-                .map(|j| j.to_string())
-                .unwrap_or(default_id)
-        } else {
-            default_id
-        };
+        let id = headline_id(&i);
+        let priority = i.priority().map(|p| p.to_string().to_uppercase());
 
         let mut plain_text = MarkdownExport::default();
         plain_text.render(i.syntax());
@@ -235,6 +263,7 @@ fn parse_note(content: &str) -> Note {
                 body,
                 tags,
                 status,
+                priority,
                 scheduled,
                 deadline,
                 closed,
@@ -250,6 +279,7 @@ fn parse_note(content: &str) -> Note {
             category: note_category.clone(),
             body,
             tags,
+            priority,
         };
         headings.push(heading);
     }
@@ -260,12 +290,57 @@ fn parse_note(content: &str) -> Note {
         category: note_category,
         body: note_body,
         tags: note_tags,
+        created: note_created,
+        attachments: parse_attachments(content),
         tasks,
         meetings,
         headings,
     }
 }
 
+/// The ID of a headline from its `ID` property, falling back to a
+/// hash of the title since tasks sometimes don't have an org-id.
+pub(super) fn headline_id(headline: &Headline) -> String {
+    // Note: Can't use a question mark operator as that will cause an
+    // early return rather than handling the case where properties
+    // don't exist
+    if let Some(id) = headline
+        .properties()
+        .and_then(|props| props.get("ID").map(|j| j.to_string()))
+    {
+        return id;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    headline.title_raw().trim().to_string().hash(&mut hasher);
+    hasher.finish().to_string()
+}
+
+/// The note as it's parsed for indexing including its tasks,
+/// meetings, and headings. Useful for debugging how a note is parsed.
+pub fn export_note(content: &str) -> serde_json::Value {
+    serde_json::to_value(parse_note(content)).expect("Failed to serialize note")
+}
+
+/// Path of the note with the ID in the notes directory. Only notes
+/// that mention the ID are parsed.
+pub async fn find_note_path(notes_dir_path: &str, id: &str) -> Option<PathBuf> {
+    for path in notes(notes_dir_path) {
+        let Ok(content) = fs::read_to_string(&path).await else {
+            continue;
+        };
+        if content.contains(id) && parse_note_id(&content) == id {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Parse the content and return the ID of the note
+pub(super) fn parse_note_id(content: &str) -> String {
+    parse_note(content).id
+}
+
 enum DocType {
     Note,
     Task,
@@ -302,7 +377,9 @@ fn index_note_full_text(
     let body = schema.get_field("body")?;
     let tags = schema.get_field("tags")?;
     let status = schema.get_field("status")?;
+    let priority = schema.get_field("priority")?;
     let file_name = schema.get_field("file_name")?;
+    let attachments = schema.get_field("attachments")?;
 
     let note_type = DocType::Note.to_str();
 
@@ -312,6 +389,9 @@ fn index_note_full_text(
         category: note_category,
         body: note_body,
         tags: note_tags,
+        // Only stored in the database
+        created: _,
+        attachments: note_attachments,
         tasks: note_tasks,
         meetings: note_meetings,
         headings: note_headings,
@@ -327,8 +407,12 @@ fn index_note_full_text(
     );
 
     // This needs to be done outside of the `doc!` macro
-    if let Some(tag_list) = note_tags {
-        doc.add_text(tags, tag_list);
+    add_tags(&mut doc, tags, note_tags);
+    // Index only the file name of each attachment so it can be found
+    // regardless of which directory it's in
+    for attachment in note_attachments.iter() {
+        let attachment_file_name = attachment.rsplit('/').next().unwrap_or(attachment);
+        doc.add_text(attachments, attachment_file_name.to_lowercase());
     }
     index_writer.add_document(doc)?;
 
@@ -347,9 +431,7 @@ fn index_note_full_text(
             body => m.body.clone(),
             file_name => file_name_value,
         );
-        if let Some(tag_list) = m.tags.clone() {
-            doc.add_text(tags, tag_list);
-        }
+        add_tags(&mut doc, tags, &m.tags);
         index_writer.add_document(doc)?;
     }
 
@@ -369,8 +451,9 @@ fn index_note_full_text(
             status => t.status.clone(),
             file_name => file_name_value,
         );
-        if let Some(tag_list) = t.tags.clone() {
-            doc.add_text(tags, tag_list);
+        add_tags(&mut doc, tags, &t.tags);
+        if let Some(p) = t.priority.as_deref() {
+            doc.add_text(priority, p);
         }
         index_writer.add_document(doc)?;
     }
@@ -390,8 +473,9 @@ fn index_note_full_text(
             body => h.body.clone(),
             file_name => file_name_value,
         );
-        if let Some(tag_list) = h.tags.clone() {
-            doc.add_text(tags, tag_list);
+        add_tags(&mut doc, tags, &h.tags);
+        if let Some(p) = h.priority.as_deref() {
+            doc.add_text(priority, p);
         }
         index_writer.add_document(doc)?;
     }
@@ -399,6 +483,13 @@ fn index_note_full_text(
     Ok(())
 }
 
+/// Returns true if the note body has any chunks to embed. Chunks are
+/// trimmed so a body that is empty or only whitespace has none and
+/// the note won't have any vectors.
+pub(super) fn has_embedding_chunks(note_body: &str) -> bool {
+    !note_body.trim().is_empty()
+}
+
 /// Generate embeddings for the note body chunks.
 /// Target model has N tokens or roughly a M sized context window
 ///
@@ -406,19 +497,28 @@ fn index_note_full_text(
 /// 1. If the note text is less than N tokens, embed the whole thing
 /// 2. Otherwise, split the text into N tokens
 /// 3. Calculate the embeddings for each chunk
-fn generate_embeddings(
-    embeddings_model: &TextEmbedding,
+///
+/// Chunks that fail to embed are skipped so that one bad chunk
+/// doesn't fail indexing.
+async fn generate_embeddings(
+    embedder: &dyn Embedder,
     splitter: &TextSplitter<CoreBPE>,
+    note_id: &str,
     note_body: &str,
 ) -> Vec<Vec<f32>> {
-    splitter
-        .chunks(note_body)
-        .flat_map(|chunk| {
-            embeddings_model
-                .embed(vec![chunk], None)
-                .expect("Failed to generate embeddings")
-        })
-        .collect()
+    let chunks: Vec<String> = splitter.chunks(note_body).map(String::from).collect();
+    let mut embeddings = Vec::new();
+    for chunk in chunks {
+        match embedder.embed(vec![chunk]).await {
+            Ok(mut chunk_embeddings) => embeddings.append(&mut chunk_embeddings),
+            Err(e) => tracing::error!(
+                "Skipping chunk of note {} that failed to embed: {:#}",
+                note_id,
+                e
+            ),
+        }
+    }
+    embeddings
 }
 
 /// Store the embedding vector in the sqlite database.
@@ -455,9 +555,13 @@ fn store_embeddings_in_db(
 /// note(s) by ID.
 fn index_note_meta(db: &mut rusqlite::Connection, file_name: &str, note: &Note) -> Result<()> {
     let mut note_meta_stmt = db.prepare(
-        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body, created, attachments, last_indexed_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))",
     )?;
 
+    // Attachments are stored as a JSON array
+    let note_attachments =
+        serde_json::to_string(&note.attachments).expect("Failed to serialize attachments");
+
     // Update the note meta table
     note_meta_stmt
         // TODO: Don't hardcode the note path, save the file name instead
@@ -468,20 +572,22 @@ fn index_note_meta(db: &mut rusqlite::Connection, file_name: &str, note: &Note)
             file_name,
             note.title,
             note.tags,
-            note.body
+            note.body,
+            note.created,
+            note_attachments
         ])
         .expect("Note meta upsert failed");
 
     let mut meeting_meta_stmt = db.prepare(
-        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body, date) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body, date, last_indexed_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))",
     )?;
 
     let mut heading_meta_stmt = db.prepare(
-        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body, last_indexed_at) VALUES (?, ?, ?, ?, ?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))",
     )?;
 
     let mut task_meta_stmt = db.prepare(
-        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body, status, scheduled, deadline, closed) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        "REPLACE INTO note_meta(id, type, category, file_name, title, tags, body, status, scheduled, deadline, closed, last_indexed_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))",
     )?;
 
     for m in note.meetings.iter() {
@@ -521,6 +627,37 @@ fn index_note_meta(db: &mut rusqlite::Connection, file_name: &str, note: &Note)
     Ok(())
 }
 
+/// Progress of an indexing run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IndexProgress {
+    /// Indexing started for `total` notes
+    Started { total: usize },
+    /// A note was indexed
+    Indexed {
+        file_name: String,
+        indexed: usize,
+        total: usize,
+    },
+    /// All notes were indexed and the full text index committed
+    Completed { indexed: usize },
+    /// Indexing stopped because of an error
+    Failed { error: String },
+}
+
+/// Error returned when indexing can't start because another indexing
+/// run is writing to the full-text index
+#[derive(Debug)]
+pub struct IndexBusy;
+
+impl std::fmt::Display for IndexBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Notes are already being indexed")
+    }
+}
+
+impl std::error::Error for IndexBusy {}
+
 /// This is the primary function to call for indexing. Coordinates
 /// saving notes in the db, full text search index, and vector
 /// storage. This needs to be done in one to avoid parsing org mode
@@ -533,37 +670,157 @@ pub async fn index_all(
     index_vector: bool,
     paths: Option<Vec<PathBuf>>,
 ) -> Result<()> {
-    let embeddings_model = Arc::new(
-        TextEmbedding::try_new(
-            InitOptions::new(EmbeddingModel::BGESmallENV15).with_show_download_progress(true),
-        )
-        .unwrap(),
-    );
+    // Only load the embedding model when it's needed so that full
+    // text indexing works without access to the model
+    let embedder = if index_vector {
+        tokio::task::spawn_blocking(LocalEmbedder::model)
+            .await
+            .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?
+            .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+        Some(CachedEmbedder::new(
+            db.clone(),
+            LocalEmbedder::MODEL_ID,
+            RetryEmbedder::new(LocalEmbedder),
+        ))
+    } else {
+        None
+    };
+    index_all_with_embedder(
+        db,
+        index_dir_path,
+        notes_dir_path,
+        index_full_text,
+        embedder.as_ref().map(|e| e as &dyn Embedder),
+        paths,
+        None,
+    )
+    .await
+}
+
+/// Remove notes by ID from the db, vector storage, and full-text
+/// index.
+pub(super) async fn delete_notes(
+    db: &Connection,
+    index_dir_path: &str,
+    ids: Vec<String>,
+) -> anyhow::Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let db_ids = ids.clone();
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        for id in db_ids.iter() {
+            tx.execute("DELETE FROM note_meta WHERE id = ?", [id])?;
+            tx.execute("DELETE FROM vec_items WHERE note_meta_id = ?", [id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    })
+    .await?;
+
+    let index_dir_path = index_dir_path.to_string();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let idx = open_or_create_index(&index_dir_path)?;
+        let id_field = idx.schema().get_field("id")?;
+        let mut index_writer: IndexWriter = idx.writer(50_000_000)?;
+        for id in ids.iter() {
+            index_writer.delete_term(Term::from_field_text(id_field, id));
+        }
+        index_writer.commit()?;
+        Ok(())
+    })
+    .await??;
+
+    Ok(())
+}
+
+/// Remove the notes deleted from the notes directory along with any
+/// headings, tasks, and meetings from the same files. Indexing only
+/// adds and updates notes so this needs to be called with the file
+/// names of deleted notes. Returns the IDs that were removed.
+pub async fn remove_notes(
+    db: &Connection,
+    index_dir_path: &str,
+    file_names: Vec<String>,
+) -> anyhow::Result<Vec<String>> {
+    let ids: Vec<String> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare("SELECT id FROM note_meta WHERE file_name = ?")?;
+            let mut ids = Vec::new();
+            for file_name in file_names {
+                let file_ids = stmt
+                    .query_map([&file_name], |r| r.get(0))?
+                    .collect::<std::result::Result<Vec<String>, _>>()?;
+                ids.extend(file_ids);
+            }
+            Ok(ids)
+        })
+        .await?;
+    delete_notes(db, index_dir_path, ids.clone()).await?;
+    Ok(ids)
+}
+
+/// Same as `index_all` but uses `embedder` for vector indexing
+/// instead of loading the local model and sends an `IndexProgress` to
+/// `progress` as each note is indexed e.g. to show a progress bar.
+/// Vector indexing is skipped when there is no embedder.
+pub async fn index_all_with_embedder(
+    db: &Connection,
+    index_dir_path: &str,
+    notes_dir_path: &str,
+    index_full_text: bool,
+    embedder: Option<&dyn Embedder>,
+    paths: Option<Vec<PathBuf>>,
+    progress: Option<&UnboundedSender<IndexProgress>>,
+) -> Result<()> {
+    // The receiver going away shouldn't stop indexing so send errors
+    // are ignored
+    let report = |event: IndexProgress| {
+        if let Some(tx) = progress {
+            let _ = tx.send(event);
+        }
+    };
     let tokenizer = cl100k_base().unwrap();
     let max_tokens = 1280;
     let splitter = Arc::new(TextSplitter::new(
         ChunkConfig::new(max_tokens).with_sizer(tokenizer),
     ));
 
+    // An index created with an older schema is missing fields so it's
+    // rebuilt from every note instead of only the ones that changed
+    let paths = if index_full_text && index_is_outdated(index_dir_path) {
+        tracing::warn!("Full-text index schema is outdated, rebuilding the index");
+        recreate_index(index_dir_path);
+        None
+    } else {
+        paths
+    };
+
     let note_paths: Vec<PathBuf> = if let Some(path_bufs) = paths {
         note_filter(notes_dir_path, path_bufs)
     } else {
         notes(notes_dir_path)
     };
 
-    let index_path =
-        tantivy::directory::MmapDirectory::open(index_dir_path).expect("Index not found");
-    let schema = note_schema();
-    let idx =
-        Index::open_or_create(index_path, schema.clone()).expect("Unable to open or create index");
-    let mut index_writer: IndexWriter = idx
-        .writer(50_000_000)
-        .expect("Index writer failed to initialize");
+    let idx = open_or_create_index(index_dir_path).expect("Unable to open or create index");
+    let schema = idx.schema();
+    // Only one writer can hold the index at a time
+    let mut index_writer: IndexWriter = idx.writer(50_000_000).map_err(|e| match e {
+        TantivyError::LockFailure(LockError::LockBusy, _) => {
+            tokio_rusqlite::Error::Other(Box::new(IndexBusy))
+        }
+        e => tokio_rusqlite::Error::Other(Box::new(e)),
+    })?;
 
     // Collect all notes for full-text indexing (done in a single blocking task later)
     let mut full_text_notes: Vec<(String, Note)> = Vec::new();
 
-    for p in note_paths.iter() {
+    let total = note_paths.len();
+    report(IndexProgress::Started { total });
+
+    for (i, p) in note_paths.iter().enumerate() {
         tracing::debug!("Indexing note: {:?}", p);
 
         // Arc the shared items so that it can be safely passed to the
@@ -575,7 +832,6 @@ pub async fn index_all(
         let note = Arc::new(parse_note(&content));
         let note_id = note.id.clone();
         let note_body = note.body.clone();
-        let embeddings_model = Arc::clone(&embeddings_model);
         let splitter = Arc::clone(&splitter);
         let note_inner = Arc::clone(&note);
         let file_name_inner = Arc::clone(&file_name);
@@ -589,15 +845,10 @@ pub async fn index_all(
         .await
         .expect("DB work failed");
 
-        // If vector indexing is enabled, generate embeddings asynchronously
-        // and then store them in the database
-        if index_vector {
-            // Spawn a blocking task for the CPU-intensive embedding generation
-            let embeddings = tokio::task::spawn_blocking(move || {
-                generate_embeddings(&embeddings_model, &splitter, &note_body)
-            })
-            .await
-            .expect("Embedding generation task failed");
+        // If vector indexing is enabled, generate embeddings and then
+        // store them in the database
+        if let Some(embedder) = embedder {
+            let embeddings = generate_embeddings(embedder, &splitter, &note_id, &note_body).await;
 
             // Store the pre-generated embeddings in the database
             db.call(move |conn| {
@@ -613,6 +864,12 @@ pub async fn index_all(
         if index_full_text {
             full_text_notes.push(((*file_name).clone(), (*note).clone()));
         }
+
+        report(IndexProgress::Indexed {
+            file_name: (*file_name).clone(),
+            indexed: i + 1,
+            total,
+        });
     }
 
     // Perform all full-text indexing in a single blocking task
@@ -632,5 +889,319 @@ pub async fn index_all(
         .expect("Full-text indexing task failed");
     }
 
+    report(IndexProgress::Completed { indexed: total });
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::core::testing::TestNotes;
+    use crate::search::core::tests::search_ids;
+
+    /// Fails like an overloaded embedding server. Text containing
+    /// "flaky" fails once before succeeding and text containing
+    /// "broken" always fails.
+    #[derive(Default)]
+    struct UnreliableEmbedder {
+        attempts: Mutex<HashMap<String, usize>>,
+    }
+
+    #[async_trait]
+    impl Embedder for UnreliableEmbedder {
+        async fn embed(&self, texts: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+            let text = texts.join(" ");
+            let attempt = {
+                let mut attempts = self.attempts.lock().unwrap();
+                let count = attempts.entry(text.clone()).or_default();
+                *count += 1;
+                *count
+            };
+            if text.contains("broken") || (text.contains("flaky") && attempt == 1) {
+                return Err(anyhow::anyhow!("503 Service Unavailable"));
+            }
+            Ok(texts.iter().map(|_| vec![0.1; 384]).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_retries_embeddings_and_skips_failed_chunks() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        notes.write_note(
+            "flaky.org",
+            ":PROPERTIES:\n:ID:       flaky-note\n:END:\n#+TITLE: Flaky\n\nThis is flaky.\n",
+        );
+        notes.write_note(
+            "broken.org",
+            ":PROPERTIES:\n:ID:       broken-note\n:END:\n#+TITLE: Broken\n\nThis is broken.\n",
+        );
+
+        let embedder = RetryEmbedder::new(UnreliableEmbedder::default())
+            .max_retries(2)
+            .initial_backoff(Duration::from_millis(1));
+        notes.index(Some(&embedder)).await.unwrap();
+
+        let embedded: Vec<String> = notes
+            .db
+            .call(|conn| {
+                let mut stmt = conn.prepare("SELECT note_meta_id FROM vec_items")?;
+                let ids = stmt
+                    .query_map([], |r| r.get(0))?
+                    .collect::<std::result::Result<Vec<String>, _>>()?;
+                Ok(ids)
+            })
+            .await
+            .unwrap();
+        assert_eq!(embedded, vec!["flaky-note"]);
+
+        // Broken chunks are retried before being skipped
+        let attempts = embedder.inner().attempts.lock().unwrap();
+        let broken = attempts
+            .iter()
+            .find(|(text, _)| text.contains("broken"))
+            .unwrap();
+        assert_eq!(*broken.1, 3);
+    }
+
+    #[tokio::test]
+    async fn it_reuses_cached_embeddings_for_unchanged_notes() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        let note_path = notes.write_note(
+            "cached.org",
+            ":PROPERTIES:\n:ID:       cached-note\n:END:\n#+TITLE: Cached\n\nThis is unchanged.\n",
+        );
+        let embedder = CachedEmbedder::new(
+            notes.db.clone(),
+            "test-model",
+            UnreliableEmbedder::default(),
+        );
+        let index = || notes.index(Some(&embedder));
+        let embed_calls = || -> usize { embedder.inner().attempts.lock().unwrap().values().sum() };
+
+        index().await.unwrap();
+        assert_eq!(embed_calls(), 1);
+
+        // Indexing the same content again uses the cache
+        index().await.unwrap();
+        assert_eq!(embed_calls(), 1);
+
+        // Changed content is embedded again
+        std::fs::write(
+            &note_path,
+            ":PROPERTIES:\n:ID:       cached-note\n:END:\n#+TITLE: Cached\n\nThis has changed.\n",
+        )
+        .unwrap();
+        index().await.unwrap();
+        assert_eq!(embed_calls(), 2);
+
+        // Embeddings from another model aren't reused
+        let other_model = CachedEmbedder::new(
+            notes.db.clone(),
+            "other-model",
+            UnreliableEmbedder::default(),
+        );
+        notes.index(Some(&other_model)).await.unwrap();
+        let other_calls: usize = other_model.inner().attempts.lock().unwrap().values().sum();
+        assert_eq!(other_calls, 1);
+    }
+
+    #[tokio::test]
+    async fn it_fails_when_the_index_is_busy() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        let idx = open_or_create_index(&notes.index_path).unwrap();
+        let _writer: IndexWriter = idx.writer(15_000_000).unwrap();
+
+        let err = notes.index(None).await.unwrap_err();
+        assert!(matches!(err, tokio_rusqlite::Error::Other(e) if e.is::<IndexBusy>()));
+    }
+
+    #[tokio::test]
+    async fn it_removes_deleted_notes() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        let note_path = notes.write_note(
+            "doomed.org",
+            ":PROPERTIES:\n:ID:       doomed-note\n:END:\n#+TITLE: Doomed\n\nThis is doomed.\n\n* TODO Doomed task\n:PROPERTIES:\n:ID:       doomed-task\n:END:\n",
+        );
+        notes
+            .index(Some(&UnreliableEmbedder::default()))
+            .await
+            .unwrap();
+        let TestNotes { db, index_path, .. } = &notes;
+        let index_path = index_path.as_str();
+
+        let vector_rows = || {
+            db.call(|conn| {
+                let count: usize =
+                    conn.query_row("SELECT COUNT(*) FROM vec_items", [], |r| r.get(0))?;
+                Ok(count)
+            })
+        };
+        assert_eq!(search_ids(index_path, &db, "doomed").await.len(), 2);
+        assert!(vector_rows().await.unwrap() > 0);
+
+        std::fs::remove_file(&note_path).unwrap();
+        let removed = remove_notes(&db, index_path, vec!["doomed.org".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(removed.len(), 2);
+        assert!(removed.contains(&"doomed-note".to_string()));
+        assert!(removed.contains(&"doomed-task".to_string()));
+        assert!(search_ids(index_path, &db, "doomed").await.is_empty());
+        assert!(search_ids(index_path, &db, "title:doomed").await.is_empty());
+        assert_eq!(vector_rows().await.unwrap(), 0);
+    }
+
+    const TAGGED_NOTE: &str = ":PROPERTIES:\n:ID:       tagged-note\n:END:\n#+TITLE: Tagged\n#+FILETAGS: :work:planning:\n\n* Roadmap :project:work:\n:PROPERTIES:\n:ID:       tagged-heading\n:END:\n";
+
+    #[test]
+    fn it_collects_filetags_and_headline_tags() {
+        let note = parse_note(TAGGED_NOTE);
+        assert_eq!(note.tags, Some("work,planning".to_string()));
+        assert_eq!(note.headings[0].tags, Some("project,work".to_string()));
+    }
+
+    #[tokio::test]
+    async fn it_searches_filetags_and_headline_tags() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        notes.write_note("tagged.org", TAGGED_NOTE);
+        notes.index(None).await.unwrap();
+        let TestNotes { db, index_path, .. } = &notes;
+        let index_path = index_path.as_str();
+
+        // File tags only match the note
+        assert_eq!(
+            search_ids(index_path, &db, "tags:planning").await,
+            vec!["tagged-note"]
+        );
+        // Headline tags only match the heading
+        assert_eq!(
+            search_ids(index_path, &db, "tags:project").await,
+            vec!["tagged-heading"]
+        );
+    }
+
+    const DATED_NOTE: &str =
+        ":PROPERTIES:\n:ID:       dated-note\n:END:\n#+TITLE: Dated\n#+DATE: 2025-01-28\n";
+
+    #[tokio::test]
+    async fn it_searches_created_date_ranges() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        notes.write_note("dated.org", DATED_NOTE);
+        notes.index(None).await.unwrap();
+        let TestNotes { db, index_path, .. } = &notes;
+        let index_path = index_path.as_str();
+
+        assert_eq!(
+            parse_note(DATED_NOTE).created,
+            Some("2025-01-28".to_string())
+        );
+        assert_eq!(
+            search_ids(index_path, &db, "dated created:[2025-01-01 TO 2025-02-01]").await,
+            vec!["dated-note"]
+        );
+        assert!(
+            search_ids(index_path, &db, "dated created:[2025-02-01 TO *]")
+                .await
+                .is_empty()
+        );
+    }
+
+    const PLANNING_NOTE: &str = ":PROPERTIES:\n:ID:       planning-note\n:END:\n#+TITLE: Planning\n\n* WAITING [#a] Hear back from vendor\n:PROPERTIES:\n:ID:       planning-task\n:END:\n* Background reading\n:PROPERTIES:\n:ID:       planning-heading\n:END:\n";
+
+    #[test]
+    fn it_parses_todo_keywords_and_priorities() {
+        let note = parse_note(PLANNING_NOTE);
+        let task = &note.tasks[0];
+        assert_eq!(task.title, "Hear back from vendor");
+        assert_eq!(task.status, "waiting");
+        assert_eq!(task.priority, Some("A".to_string()));
+
+        // A headline without a keyword isn't a task
+        assert_eq!(note.tasks.len(), 1);
+        assert_eq!(note.headings[0].title, "Background reading");
+        assert_eq!(note.headings[0].priority, None);
+    }
+
+    #[tokio::test]
+    async fn it_searches_status_and_priority() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        notes.write_note("planning.org", PLANNING_NOTE);
+        notes.index(None).await.unwrap();
+        let TestNotes { db, index_path, .. } = &notes;
+        let index_path = index_path.as_str();
+
+        assert_eq!(
+            search_ids(index_path, &db, "status:waiting priority:A").await,
+            vec!["planning-task"]
+        );
+        // Priorities match regardless of case
+        assert_eq!(
+            search_ids(index_path, &db, "priority:a").await,
+            vec!["planning-task"]
+        );
+        assert!(search_ids(index_path, &db, "priority:B").await.is_empty());
+        let ids = search_ids(index_path, &db, "-has:priority").await;
+        assert!(ids.contains(&"planning-heading".to_string()));
+        assert!(!ids.contains(&"planning-task".to_string()));
+    }
+
+    #[tokio::test]
+    async fn it_rebuilds_an_index_with_an_outdated_schema() {
+        let dir = TempDir::new().unwrap();
+        let notes = TestNotes::new(dir.path()).await;
+        let planning_path = notes.write_note("planning.org", PLANNING_NOTE);
+        notes.write_note("tagged.org", TAGGED_NOTE);
+        let TestNotes {
+            db,
+            index_path,
+            notes_path,
+            ..
+        } = &notes;
+
+        // An index from before priorities were indexed
+        let mut schema_builder = tantivy::schema::Schema::builder();
+        for field in ["id", "type", "category", "title", "tags", "status", "body"] {
+            schema_builder.add_text_field(field, tantivy::schema::TEXT | tantivy::schema::STORED);
+        }
+        schema_builder.add_text_field("file_name", tantivy::schema::TEXT);
+        tantivy::Index::create_in_dir(&index_path, schema_builder.build()).unwrap();
+
+        // Only one note changed but every note is indexed again
+        index_all_with_embedder(
+            db,
+            index_path,
+            notes_path,
+            true,
+            None,
+            Some(vec![planning_path]),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            search_ids(&index_path, &db, "priority:A").await,
+            vec!["planning-task"]
+        );
+        assert_eq!(
+            search_ids(&index_path, &db, "tags:planning").await,
+            vec!["tagged-note"]
+        );
+    }
+}
@@ -1,10 +1,25 @@
 pub mod aql;
 mod core;
+pub mod embedding;
 mod export;
 mod fts;
-pub use fts::utils::recreate_index;
+pub use fts::utils::{SCHEMA_VERSION_FILE_NAME, index_is_outdated, recreate_index};
 mod indexing;
-pub use indexing::index_all;
+pub use indexing::{
+    IndexBusy, IndexProgress, export_note, find_note_path, index_all, index_all_with_embedder,
+    remove_notes,
+};
+mod outline;
+pub use outline::{OutlineHeading, parse_outline};
+mod planning;
+pub use planning::{CompletedTask, PlanningKind, complete_task, set_planning_date};
 mod query;
+pub use query::FieldBoosts;
+pub mod rerank;
+mod snippet;
+pub use crate::core::{SearchMode, SnippetStrategy};
+pub use snippet::DEFAULT_SNIPPET_CHARS;
 mod source;
-pub use core::search_notes;
+mod verify;
+pub use core::{FacetCount, NoteSearch, SearchFacet, SearchOptions, search_notes};
+pub use verify::{Mismatch, VerifyReport, fix_indices, verify_indices};
@@ -0,0 +1,90 @@
+use orgize::ast::Headline;
+use serde::{Deserialize, Serialize};
+
+use super::indexing::parse_config;
+
+/// A heading in a note's outline along with its subheadings
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OutlineHeading {
+    /// Number of stars in the heading starting at 1
+    pub level: usize,
+    pub text: String,
+    /// Line number of the heading in the note's file starting at 1
+    pub line: usize,
+    pub children: Vec<OutlineHeading>,
+}
+
+/// Build the heading hierarchy of an org note. Line numbers are
+/// 1-based and refer to the line of the heading in `content`.
+pub fn parse_outline(content: &str) -> Vec<OutlineHeading> {
+    let org = parse_config().parse(content);
+    org.document()
+        .headlines()
+        .map(|h| outline_heading(content, &h))
+        .collect()
+}
+
+fn outline_heading(content: &str, headline: &Headline) -> OutlineHeading {
+    let start: usize = headline.start().into();
+    let line = content[..start].matches('\n').count() + 1;
+
+    OutlineHeading {
+        level: headline.level(),
+        text: headline.title_raw().trim().to_string(),
+        line,
+        children: headline
+            .headlines()
+            .map(|h| outline_heading(content, &h))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_nested_headings() {
+        let content = r#":PROPERTIES:
+:ID:       outline-test
+:END:
+#+TITLE: Outline
+
+* Projects
+** TODO Write the outline endpoint
+Some details.
+*** Tests
+** Docs
+* Journal
+"#;
+
+        let outline = parse_outline(content);
+
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0].level, 1);
+        assert_eq!(outline[0].text, "Projects");
+        assert_eq!(outline[0].line, 6);
+        assert_eq!(outline[0].children.len(), 2);
+
+        let task = &outline[0].children[0];
+        assert_eq!(task.level, 2);
+        assert_eq!(task.text, "Write the outline endpoint");
+        assert_eq!(task.line, 7);
+        assert_eq!(task.children.len(), 1);
+        assert_eq!(task.children[0].level, 3);
+        assert_eq!(task.children[0].text, "Tests");
+        assert_eq!(task.children[0].line, 9);
+
+        assert_eq!(outline[0].children[1].text, "Docs");
+        assert_eq!(outline[1].text, "Journal");
+        assert_eq!(outline[1].line, 11);
+        assert!(outline[1].children.is_empty());
+    }
+
+    #[test]
+    fn it_returns_empty_outline_without_headings() {
+        let content =
+            ":PROPERTIES:\n:ID:       outline-test\n:END:\n#+TITLE: Outline\n\nNo headings here.\n";
+        assert!(parse_outline(content).is_empty());
+    }
+}
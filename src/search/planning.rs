@@ -0,0 +1,279 @@
+//! Rewrite the planning line and TODO keyword of tasks in an org note
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use orgize::ast::{Headline, Timestamp};
+use regex::Regex;
+
+use super::indexing::{headline_id, parse_config};
+
+/// Which planning date of a task to change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlanningKind {
+    Scheduled,
+    Deadline,
+}
+
+impl PlanningKind {
+    fn keyword(&self) -> &'static str {
+        match self {
+            Self::Scheduled => "SCHEDULED",
+            Self::Deadline => "DEADLINE",
+        }
+    }
+
+    fn timestamp(&self, headline: &Headline) -> Option<Timestamp> {
+        match self {
+            Self::Scheduled => headline.scheduled(),
+            Self::Deadline => headline.deadline(),
+        }
+    }
+}
+
+impl FromStr for PlanningKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scheduled" => Ok(Self::Scheduled),
+            "deadline" => Ok(Self::Deadline),
+            other => Err(anyhow!("Unknown planning kind: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for PlanningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Scheduled => write!(f, "scheduled"),
+            Self::Deadline => write!(f, "deadline"),
+        }
+    }
+}
+
+/// Set the `SCHEDULED:` or `DEADLINE:` date of the headline with ID
+/// `id`, keeping any time or repeater in the existing timestamp. The
+/// planning line is added if the headline doesn't have one. Returns
+/// `None` if there is no headline with that ID.
+pub fn set_planning_date(
+    content: &str,
+    id: &str,
+    kind: PlanningKind,
+    date: NaiveDate,
+) -> Option<String> {
+    let org = parse_config().parse(content);
+    let headline = org.document().headlines().find(|h| headline_id(h) == id)?;
+    let new_date = date.format("%Y-%m-%d %a").to_string();
+
+    let mut updated = content.to_string();
+    if let Some(timestamp) = kind.timestamp(&headline) {
+        // Only replace the date and day name e.g. `2025-01-05 Sun`
+        let start: usize = timestamp.start().into();
+        let end: usize = timestamp.end().into();
+        let date_regex = Regex::new(r"\d{4}-\d{2}-\d{2}( [^\s\d>\]]+)?").unwrap();
+        let replaced = date_regex.replace(&content[start..end], new_date.as_str());
+        updated.replace_range(start..end, &replaced);
+    } else {
+        let entry = format!("{}: <{}>", kind.keyword(), new_date);
+        insert_planning_entry(&mut updated, &headline, &entry);
+    }
+
+    Some(updated)
+}
+
+/// Add an entry like `CLOSED: [...]` to the start of the headline's
+/// planning line, adding the planning line if there isn't one.
+/// `content` must be unchanged up to the end of the planning line
+/// since the headline's offsets are used.
+fn insert_planning_entry(content: &mut String, headline: &Headline, entry: &str) {
+    if let Some(planning) = headline.planning() {
+        let start: usize = planning.start().into();
+        let indent = content[start..].len() - content[start..].trim_start().len();
+        content.insert_str(start + indent, &format!("{} ", entry));
+    } else {
+        // The planning line goes right after the headline
+        let start: usize = headline.start().into();
+        match content[start..].find('\n') {
+            Some(offset) => content.insert_str(start + offset + 1, &format!("{}\n", entry)),
+            None => content.push_str(&format!("\n{}\n", entry)),
+        }
+    }
+}
+
+/// A task that was marked as done
+pub struct CompletedTask {
+    pub id: String,
+    pub title: String,
+    /// The note's content with the task marked as done
+    pub content: String,
+}
+
+/// Mark a task as `DONE` and add a `CLOSED:` timestamp. The task is
+/// the headline with ID `id` if there is one, otherwise the open task
+/// titled `heading`, otherwise the first open task. Returns `None` if
+/// there is no matching open task.
+pub fn complete_task(
+    content: &str,
+    id: &str,
+    heading: Option<&str>,
+    closed: NaiveDateTime,
+) -> Option<CompletedTask> {
+    let org = parse_config().parse(content);
+    let headline = match org.document().headlines().find(|h| headline_id(h) == id) {
+        Some(headline) => headline,
+        None => org.document().headlines().find(|h| {
+            h.is_todo() && heading.is_none_or(|title| h.title_raw().trim() == title.trim())
+        })?,
+    };
+    if !headline.is_todo() {
+        return None;
+    }
+
+    // Insert the planning entry first since it comes after the
+    // keyword and won't shift the keyword's offsets
+    let mut updated = content.to_string();
+    let entry = format!("CLOSED: [{}]", closed.format("%Y-%m-%d %a %H:%M"));
+    insert_planning_entry(&mut updated, &headline, &entry);
+
+    let keyword = headline.todo_keyword()?;
+    updated.replace_range(
+        usize::from(keyword.start())..usize::from(keyword.end()),
+        "DONE",
+    );
+
+    Some(CompletedTask {
+        id: headline_id(&headline),
+        title: headline.title_raw().trim().to_string(),
+        content: updated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn it_replaces_the_scheduled_date() {
+        let content = r#"#+TITLE: Tasks
+
+* TODO Write report
+SCHEDULED: <2025-01-05 Sun 10:00 +1w> DEADLINE: <2025-01-10 Fri>
+:PROPERTIES:
+:ID:       task-1
+:END:
+Body
+"#;
+        let updated = set_planning_date(
+            content,
+            "task-1",
+            PlanningKind::Scheduled,
+            date("2025-01-08"),
+        )
+        .unwrap();
+        assert!(updated.contains(
+            "SCHEDULED: <2025-01-08 Wed 10:00 +1w> DEADLINE: <2025-01-10 Fri>\n:PROPERTIES:"
+        ));
+    }
+
+    #[test]
+    fn it_adds_a_missing_planning_date() {
+        let content = r#"#+TITLE: Tasks
+
+* TODO Write report
+DEADLINE: <2025-01-10 Fri>
+* TODO Call plumber
+:PROPERTIES:
+:ID:       task-2
+:END:
+"#;
+        // Headlines without an ID are found by the hash of their title
+        let id = {
+            let org = parse_config().parse(content);
+            headline_id(&org.document().headlines().next().unwrap())
+        };
+        let updated =
+            set_planning_date(content, &id, PlanningKind::Scheduled, date("2025-01-08")).unwrap();
+        assert!(updated.contains(
+            "* TODO Write report\nSCHEDULED: <2025-01-08 Wed> DEADLINE: <2025-01-10 Fri>\n"
+        ));
+
+        let updated = set_planning_date(
+            content,
+            "task-2",
+            PlanningKind::Deadline,
+            date("2025-02-01"),
+        )
+        .unwrap();
+        assert!(updated.contains(
+            "* TODO Call plumber\nDEADLINE: <2025-02-01 Sat>\n:PROPERTIES:\n:ID:       task-2\n"
+        ));
+    }
+
+    #[test]
+    fn it_returns_none_for_unknown_headline() {
+        let content = "* TODO Write report\n";
+        assert!(
+            set_planning_date(
+                content,
+                "missing",
+                PlanningKind::Scheduled,
+                date("2025-01-08")
+            )
+            .is_none()
+        );
+    }
+
+    fn closed() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2025-01-08 09:30", "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    const TASKS: &str = r#":PROPERTIES:
+:ID:       note-1
+:END:
+#+TITLE: Tasks
+
+* DONE Buy milk
+* NEXT Write report
+DEADLINE: <2025-01-10 Fri>
+* TODO Call plumber
+:PROPERTIES:
+:ID:       task-2
+:END:
+"#;
+
+    #[test]
+    fn it_completes_a_task_by_id() {
+        let completed = complete_task(TASKS, "task-2", None, closed()).unwrap();
+        assert_eq!(completed.id, "task-2");
+        assert!(completed.content.contains(
+            "* DONE Call plumber\nCLOSED: [2025-01-08 Wed 09:30]\n:PROPERTIES:\n:ID:       task-2\n"
+        ));
+    }
+
+    #[test]
+    fn it_completes_the_first_open_task_of_a_note() {
+        let completed = complete_task(TASKS, "note-1", None, closed()).unwrap();
+        assert_eq!(completed.title, "Write report");
+        assert!(completed.content.contains(
+            "* DONE Write report\nCLOSED: [2025-01-08 Wed 09:30] DEADLINE: <2025-01-10 Fri>\n"
+        ));
+        assert!(completed.content.contains("* TODO Call plumber\n"));
+    }
+
+    #[test]
+    fn it_completes_a_task_by_heading() {
+        let completed = complete_task(TASKS, "note-1", Some("Call plumber"), closed()).unwrap();
+        assert_eq!(completed.id, "task-2");
+        assert!(completed.content.contains("* NEXT Write report\n"));
+
+        // Tasks that are already done can't be completed again
+        assert!(complete_task(TASKS, "note-1", Some("Buy milk"), closed()).is_none());
+    }
+}
@@ -1,9 +1,15 @@
-use crate::search::aql::{Expr, RangeOp};
+use crate::core::{AppConfig, DEFAULT_BODY_BOOST, DEFAULT_TAGS_BOOST, DEFAULT_TITLE_BOOST};
+use crate::search::aql::{AqlError, Expr, RangeOp};
 use std::ops::Bound;
 use tantivy::Term;
-use tantivy::query::{AllQuery, BooleanQuery, FuzzyTermQuery, PhraseQuery, TermQuery};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, EmptyQuery, FuzzyTermQuery, PhraseQuery, RegexQuery,
+    TermQuery,
+};
 use tantivy::query::{Occur, Query};
-use tantivy::schema::{Field, IndexRecordOption, Schema};
+use tantivy::schema::{Field, FieldType, IndexRecordOption, Schema};
+
+use crate::search::fts::schema::{CJK_TOKENIZER, cjk_analyzer, default_analyzer};
 
 fn parse_date_to_timestamp(date_str: &str) -> u64 {
     let parts: Vec<u32> = date_str.split('-').map(|s| s.parse().unwrap()).collect();
@@ -36,9 +42,168 @@ fn parse_date_to_timestamp(date_str: &str) -> u64 {
 
 const DEFAULT_FIELD_NAME: &str = "__default";
 
-pub fn aql_to_index_query(expr: &Expr, schema: &Schema) -> Option<Box<dyn Query>> {
+/// Relevance score multipliers for matches in each field so that a
+/// match in the title ranks above the same match in the body
+#[derive(Clone, Debug)]
+pub struct FieldBoosts {
+    pub title: f32,
+    pub tags: f32,
+    pub body: f32,
+}
+
+impl Default for FieldBoosts {
+    fn default() -> Self {
+        Self {
+            title: DEFAULT_TITLE_BOOST,
+            tags: DEFAULT_TAGS_BOOST,
+            body: DEFAULT_BODY_BOOST,
+        }
+    }
+}
+
+impl From<&AppConfig> for FieldBoosts {
+    fn from(config: &AppConfig) -> Self {
+        Self {
+            title: config.title_boost,
+            tags: config.tags_boost,
+            body: config.body_boost,
+        }
+    }
+}
+
+impl FieldBoosts {
+    /// Boost for the field, fields without a boost aren't changed
+    pub fn get(&self, field: &str) -> f32 {
+        match field {
+            "title" => self.title,
+            "tags" => self.tags,
+            "body" => self.body,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Returns true if the field is indexed with the CJK n-gram tokenizer
+fn is_ngram_field(schema: &Schema, field: Field) -> bool {
+    match schema.get_field_entry(field).field_type() {
+        FieldType::Str(options) => options
+            .get_indexing_options()
+            .is_some_and(|i| i.tokenizer() == CJK_TOKENIZER),
+        _ => false,
+    }
+}
+
+/// Split the value into n-grams the same way the field was indexed
+/// and match documents that contain all of them
+fn ngram_query(field: Field, value: &str) -> Box<dyn Query> {
+    let mut analyzer = cjk_analyzer();
+    let mut stream = analyzer.token_stream(value);
+    let mut terms: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    while stream.advance() {
+        let term = Term::from_field_text(field, &stream.token().text);
+        terms.push((
+            Occur::Must,
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+        ));
+    }
+    Box::new(BooleanQuery::new(terms))
+}
+
+/// Match the words of the phrase near each other and in order. Words
+/// are split the same way the field was indexed so punctuation like
+/// quotes in the phrase is ignored.
+fn phrase_query(field: Field, value: &str) -> Box<dyn Query> {
+    let mut analyzer = default_analyzer();
+    let mut stream = analyzer.token_stream(value);
+    let mut terms: Vec<(usize, Term)> = Vec::new();
+    while stream.advance() {
+        let token = stream.token();
+        terms.push((token.position, Term::from_field_text(field, &token.text)));
+    }
+    match terms.len() {
+        // A phrase query needs more than one term
+        0 => Box::new(EmptyQuery),
+        1 => Box::new(TermQuery::new(
+            terms.pop().unwrap().1,
+            IndexRecordOption::Basic,
+        )),
+        _ => Box::new(PhraseQuery::new_with_offset_and_slop(terms, 2)),
+    }
+}
+
+fn boost_query(query: Box<dyn Query>, boost: f32) -> Box<dyn Query> {
+    if boost == 1.0 {
+        query
+    } else {
+        Box::new(BoostQuery::new(query, boost))
+    }
+}
+
+/// Look up a field in the schema. The query parser only accepts known
+/// fields but an index created with an older schema may not have it.
+fn schema_field(schema: &Schema, field: &str) -> Result<Field, AqlError> {
+    schema
+        .get_field(field)
+        .map_err(|_| AqlError::UnknownField(field.to_string()))
+}
+
+/// Fields to match a term against. Defaults to the title, body, and
+/// attachments when there is no field name specified.
+fn term_fields(
+    schema: &Schema,
+    field: Option<&str>,
+    phrase: bool,
+) -> Result<Vec<(String, Field)>, AqlError> {
+    let field_name = field.unwrap_or(DEFAULT_FIELD_NAME);
+    let field_names = if field_name == DEFAULT_FIELD_NAME {
+        // Attachments aren't tokenized so they can't be
+        // matched by a phrase
+        if phrase {
+            vec!["title", "body"]
+        } else {
+            vec!["title", "body", "attachments"]
+        }
+    } else {
+        vec![field_name]
+    };
+    field_names
+        .into_iter()
+        .map(|name| Ok((name.to_string(), schema_field(schema, name)?)))
+        .collect()
+}
+
+/// Match documents that match any of the queries
+fn any_of(queries: Vec<Box<dyn Query>>) -> Box<dyn Query> {
+    if queries.len() > 1 {
+        Box::new(BooleanQuery::from(
+            queries
+                .into_iter()
+                .map(|q| (Occur::Should, q))
+                .collect::<Vec<(Occur, Box<dyn Query>)>>(),
+        ))
+    } else {
+        queries.into_iter().next().unwrap()
+    }
+}
+
+/// Match every document except those matching the query
+fn exclude(query: Box<dyn Query>) -> Box<dyn Query> {
+    Box::new(BooleanQuery::new(vec![
+        (Occur::Must, Box::new(AllQuery)),
+        (Occur::MustNot, query),
+    ]))
+}
+
+pub fn aql_to_index_query(
+    expr: &Expr,
+    schema: &Schema,
+    boosts: &FieldBoosts,
+) -> Result<Option<Box<dyn Query>>, AqlError> {
     fn is_sql_only_field(field: &str) -> bool {
-        matches!(field, "scheduled" | "deadline" | "closed" | "date")
+        matches!(
+            field,
+            "scheduled" | "deadline" | "closed" | "date" | "created"
+        )
     }
 
     fn is_fuzzy_search_field(field: &str) -> bool {
@@ -50,60 +215,104 @@ pub fn aql_to_index_query(expr: &Expr, schema: &Schema) -> Option<Box<dyn Query>
     match expr {
         Expr::Term {
             field: Some(field), ..
-        } if is_sql_only_field(field) => None,
-        Expr::Range { field, .. } if is_sql_only_field(field) => None,
+        } if is_sql_only_field(field) => Ok(None),
+        Expr::Fuzzy {
+            field: Some(field), ..
+        } if is_sql_only_field(field) => Ok(None),
+        Expr::Range { field, .. } if is_sql_only_field(field) => Ok(None),
+        // Match every note and let the SQL query filter by whether
+        // the field is set
+        Expr::Exists { field, .. } if is_sql_only_field(field) => Ok(Some(Box::new(AllQuery))),
+        Expr::Exists { field, negated } => {
+            // Any indexed term in the field means it has a value
+            let field = schema_field(schema, field)?;
+            let query =
+                Box::new(RegexQuery::from_pattern(".+", field).expect("Invalid exists pattern"))
+                    as Box<dyn Query>;
+            if *negated {
+                Ok(Some(Box::new(BooleanQuery::new(vec![
+                    (Occur::Must, Box::new(AllQuery)),
+                    (Occur::MustNot, query),
+                ]))))
+            } else {
+                Ok(Some(query))
+            }
+        }
         Expr::Term {
             field,
             value,
             phrase,
             negated,
         } => {
-            // Default to title and body when there is no field name specified
-            let field_name = field.clone().unwrap_or_else(|| "__default".into());
-            let fields: Vec<(String, Field)> = if field_name == DEFAULT_FIELD_NAME {
-                vec![
-                    (String::from("title"), schema.get_field("title").unwrap()),
-                    (String::from("body"), schema.get_field("body").unwrap()),
-                ]
-            } else {
-                vec![(field_name.clone(), schema.get_field(&field_name).unwrap())]
+            // Priorities are indexed in uppercase like `A` for `[#a]`
+            let value = match field.as_deref() {
+                Some("priority") => value.to_uppercase(),
+                _ => value.clone(),
             };
+            let value = value.as_str();
+            let fields = term_fields(schema, field.as_deref(), *phrase)?;
             let terms: Vec<Box<dyn Query>> = fields
                 .iter()
                 .map(|(query_field_name, query_field)| {
                     let term = Term::from_field_text(*query_field, value);
-                    if *negated {
-                        Box::new(BooleanQuery::new(vec![
-                            (Occur::Must, Box::new(AllQuery)),
-                            (
-                                Occur::MustNot,
-                                Box::new(TermQuery::new(term, IndexRecordOption::Basic))
-                                    as Box<dyn Query>,
-                            ),
-                        ]))
+                    if is_ngram_field(schema, *query_field) {
+                        let query = ngram_query(*query_field, value);
+                        if *negated {
+                            exclude(query)
+                        } else {
+                            boost_query(query, boosts.get(query_field_name))
+                        }
+                    } else if *negated && *phrase {
+                        exclude(phrase_query(*query_field, value))
+                    } else if *negated {
+                        exclude(Box::new(TermQuery::new(term, IndexRecordOption::Basic)))
                     } else if *phrase {
-                        let terms = value.split(" ").map(|i| Term::from_field_text(*query_field, i)).collect::<Vec<Term>>();
-                        let mut query = PhraseQuery::new(terms);
-                        query.set_slop(2);
-                        Box::new(query)
+                        boost_query(
+                            phrase_query(*query_field, value),
+                            boosts.get(query_field_name),
+                        )
                     } else if is_fuzzy_search_field(query_field_name) {
-                        Box::new(FuzzyTermQuery::new(term, 2, true)) as Box<dyn Query>
+                        boost_query(
+                            Box::new(FuzzyTermQuery::new(term, 2, true)),
+                            boosts.get(query_field_name),
+                        )
                     } else {
-                        Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>
+                        boost_query(
+                            Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+                            boosts.get(query_field_name),
+                        )
                     }
                 })
                 .collect();
 
-            if terms.len() > 1 {
-                Some(Box::new(BooleanQuery::from(
-                    terms
-                        .into_iter()
-                        .map(|q| (Occur::Should, q))
-                        .collect::<Vec<(Occur, Box<dyn Query>)>>(),
-                )))
-            } else {
-                Some(terms.into_iter().next().unwrap())
-            }
+            Ok(Some(any_of(terms)))
+        }
+        Expr::Fuzzy {
+            field,
+            value,
+            distance,
+            negated,
+        } => {
+            let terms: Vec<Box<dyn Query>> = term_fields(schema, field.as_deref(), false)?
+                .iter()
+                .map(|(query_field_name, query_field)| {
+                    // N-grams already tolerate typos and edit
+                    // distance across n-grams isn't meaningful
+                    let query: Box<dyn Query> = if is_ngram_field(schema, *query_field) {
+                        ngram_query(*query_field, value)
+                    } else {
+                        let term = Term::from_field_text(*query_field, value);
+                        Box::new(FuzzyTermQuery::new(term, *distance, true))
+                    };
+                    if *negated {
+                        exclude(query)
+                    } else {
+                        boost_query(query, boosts.get(query_field_name))
+                    }
+                })
+                .collect();
+
+            Ok(Some(any_of(terms)))
         }
         Expr::Range {
             field,
@@ -111,7 +320,7 @@ pub fn aql_to_index_query(expr: &Expr, schema: &Schema) -> Option<Box<dyn Query>
             value,
             negated,
         } => {
-            let field = schema.get_field(field).unwrap();
+            let field = schema_field(schema, field)?;
             let value = parse_date_to_timestamp(value);
             let (lower_bound, upper_bound) = match op {
                 RangeOp::Lt => (
@@ -135,23 +344,26 @@ pub fn aql_to_index_query(expr: &Expr, schema: &Schema) -> Option<Box<dyn Query>
             let range_query = tantivy::query::RangeQuery::new(lower_bound, upper_bound);
 
             if *negated {
-                Some(Box::new(BooleanQuery::from(vec![(
+                Ok(Some(Box::new(BooleanQuery::from(vec![(
                     Occur::MustNot,
                     Box::new(range_query) as Box<dyn Query>,
-                )])))
+                )]))))
             } else {
-                Some(Box::new(range_query))
+                Ok(Some(Box::new(range_query)))
             }
         }
+        Expr::Boost { expr, boost } => {
+            Ok(aql_to_index_query(expr, schema, boosts)?.map(|q| boost_query(q, *boost)))
+        }
         Expr::And(left, right) => {
             // This handles the following cases:
             // - Left and right expressions have a query term
             // - Only the left expression has a query term
             // - Only the right expression has a query term
             // - Neither left or right expressions have a query term
-            let left_query = aql_to_index_query(left, schema);
-            let right_query = aql_to_index_query(right, schema);
-            if let Some(lq) = left_query {
+            let left_query = aql_to_index_query(left, schema, boosts)?;
+            let right_query = aql_to_index_query(right, schema, boosts)?;
+            let query: Option<Box<dyn Query>> = if let Some(lq) = left_query {
                 if let Some(rq) = right_query {
                     Some(Box::new(BooleanQuery::from(vec![
                         (Occur::Must, lq),
@@ -164,16 +376,19 @@ pub fn aql_to_index_query(expr: &Expr, schema: &Schema) -> Option<Box<dyn Query>
                 Some(Box::new(BooleanQuery::from(vec![(Occur::Must, rq)])))
             } else {
                 None
-            }
+            };
+            Ok(query)
         }
+        // The parser rejects an OR of SQL only and indexed fields so
+        // both sides are filtered in the same place
         Expr::Or(left, right) => {
-            let left_query = aql_to_index_query(left, schema);
-            let right_query = aql_to_index_query(right, schema);
-            if let Some(lq) = left_query {
+            let left_query = aql_to_index_query(left, schema, boosts)?;
+            let right_query = aql_to_index_query(right, schema, boosts)?;
+            let query: Option<Box<dyn Query>> = if let Some(lq) = left_query {
                 if let Some(rq) = right_query {
                     Some(Box::new(BooleanQuery::from(vec![
                         (Occur::Should, lq),
-                        (Occur::Must, rq),
+                        (Occur::Should, rq),
                     ])))
                 } else {
                     Some(Box::new(BooleanQuery::from(vec![(Occur::Should, lq)])))
@@ -182,14 +397,18 @@ pub fn aql_to_index_query(expr: &Expr, schema: &Schema) -> Option<Box<dyn Query>
                 Some(Box::new(BooleanQuery::from(vec![(Occur::Should, rq)])))
             } else {
                 None
-            }
+            };
+            Ok(query)
         }
     }
 }
 
 pub fn expr_to_sql(expr: &Expr) -> Option<String> {
     fn is_allowed(field: &str) -> bool {
-        matches!(field, "scheduled" | "deadline" | "closed" | "date")
+        matches!(
+            field,
+            "scheduled" | "deadline" | "closed" | "date" | "created"
+        )
     }
 
     match expr {
@@ -207,6 +426,10 @@ pub fn expr_to_sql(expr: &Expr) -> Option<String> {
                 value.replace('\'', "''")
             ))
         }
+        Expr::Exists { field, negated } if is_allowed(field) => {
+            let cmp = if *negated { "IS NULL" } else { "IS NOT NULL" };
+            Some(format!("{} {}", field, cmp))
+        }
         Expr::Range {
             field,
             op,
@@ -250,6 +473,7 @@ pub fn expr_to_sql(expr: &Expr) -> Option<String> {
                 value.replace('\'', "''")
             ))
         }
+        Expr::Boost { expr, .. } => expr_to_sql(expr),
         Expr::And(left, right) => {
             let l = expr_to_sql(left);
             let r = expr_to_sql(right);
@@ -274,14 +498,51 @@ pub fn expr_to_sql(expr: &Expr) -> Option<String> {
     }
 }
 
+/// Values of the terms in the query that can be highlighted in the
+/// title or body of a result. Negated terms never match a result so
+/// they're skipped.
+pub fn query_to_highlight_terms(expr: &Expr) -> Vec<String> {
+    fn is_allowed(field: &Option<String>) -> bool {
+        field
+            .as_deref()
+            .is_none_or(|field| matches!(field, "title" | "body"))
+    }
+
+    match expr {
+        Expr::Term {
+            field,
+            value,
+            negated: false,
+            ..
+        }
+        | Expr::Fuzzy {
+            field,
+            value,
+            negated: false,
+            ..
+        } if is_allowed(field) => vec![value.to_owned()],
+        Expr::Boost { expr, .. } => query_to_highlight_terms(expr),
+        Expr::And(left, right) | Expr::Or(left, right) => {
+            let mut terms = query_to_highlight_terms(left);
+            terms.extend(query_to_highlight_terms(right));
+            terms
+        }
+        _ => Vec::new(),
+    }
+}
+
 pub fn query_to_similarity(expr: &Expr) -> Option<String> {
-    fn is_allowed(field: &str) -> bool {
-        matches!(field, "title" | "body")
+    // Terms without a field search the title and body so they're
+    // allowed too
+    fn is_allowed(field: &Option<String>) -> bool {
+        field
+            .as_deref()
+            .is_none_or(|field| matches!(field, "title" | "body"))
     }
 
     match expr {
         Expr::Term {
-            field: Some(field),
+            field,
             value,
             negated,
             ..
@@ -292,6 +553,19 @@ pub fn query_to_similarity(expr: &Expr) -> Option<String> {
                 Some(value.to_owned())
             }
         }
+        Expr::Fuzzy {
+            field,
+            value,
+            negated,
+            ..
+        } if is_allowed(field) => {
+            if *negated {
+                None
+            } else {
+                Some(value.to_owned())
+            }
+        }
+        Expr::Boost { expr, .. } => query_to_similarity(expr),
         Expr::And(left, right) => {
             let l = query_to_similarity(left);
             let r = query_to_similarity(right);
@@ -332,11 +606,12 @@ mod tests {
         let expr = parse_query(expr_str).unwrap();
 
         // Convert expression to query
-        let query = aql_to_index_query(&expr, &schema);
+        let query = aql_to_index_query(&expr, &schema, &FieldBoosts::default());
 
         // Assertions
         assert!(
             query
+                .unwrap()
                 .unwrap()
                 .as_any()
                 .downcast_ref::<BooleanQuery>()
@@ -344,6 +619,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_aql_to_index_query_or() {
+        let schema = note_schema();
+        let expr = parse_query("tags:meeting OR tags:standup").unwrap();
+        let query = aql_to_index_query(&expr, &schema, &FieldBoosts::default())
+            .unwrap()
+            .unwrap();
+
+        // Either side can match
+        let query = format!("{:?}", query);
+        assert!(!query.contains("Must"));
+        assert_eq!(query.matches("Should").count(), 2);
+    }
+
+    #[test]
+    fn test_aql_to_index_query_field_missing_from_schema() {
+        // An index created before the field was added to the schema
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", tantivy::schema::TEXT);
+        schema_builder.add_text_field("body", tantivy::schema::TEXT);
+        let schema = schema_builder.build();
+
+        let expr = parse_query("priority:A").unwrap();
+        let result = aql_to_index_query(&expr, &schema, &FieldBoosts::default());
+        assert!(matches!(result, Err(AqlError::UnknownField(field)) if field == "priority"));
+
+        let expr = parse_query("meeting").unwrap();
+        let result = aql_to_index_query(&expr, &schema, &FieldBoosts::default());
+        assert!(matches!(result, Err(AqlError::UnknownField(field)) if field == "attachments"));
+    }
+
     #[test]
     fn test_expr_to_sql_term() {
         let expr = parse_query("scheduled:2025-04-20").unwrap();
@@ -371,14 +677,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_expr_to_sql_date_range() {
+        let expr = parse_query("scheduled:[2025-01-01 TO 2025-02-01]").unwrap();
+        assert_eq!(
+            expr_to_sql(&expr),
+            Some("(scheduled >= '2025-01-01' AND scheduled <= '2025-02-01')".to_string())
+        );
+
+        let expr = parse_query("-date:[2025-01-01 TO *]").unwrap();
+        assert_eq!(expr_to_sql(&expr), Some("date < '2025-01-01'".to_string()));
+    }
+
+    #[test]
+    fn test_expr_to_sql_exists() {
+        let expr = parse_query("has:deadline").unwrap();
+        assert_eq!(expr_to_sql(&expr), Some("deadline IS NOT NULL".to_string()));
+
+        let expr = parse_query("-has:scheduled").unwrap();
+        assert_eq!(expr_to_sql(&expr), Some("scheduled IS NULL".to_string()));
+
+        // Fields in the index aren't filtered by SQL
+        let expr = parse_query("has:tags").unwrap();
+        assert_eq!(expr_to_sql(&expr), None);
+    }
+
     #[test]
     fn test_expr_to_sql_drops_unknown() {
-        // 'priority' is not an allowed field; should yield None when it's alone.
-        let expr = parse_query("priority:high").unwrap();
+        // 'status' is not a SQL field; should yield None when it's alone.
+        let expr = parse_query("status:todo").unwrap();
         assert_eq!(expr_to_sql(&expr), None);
 
         // If mixed with a valid field, only valid one appears in output.
-        let expr = parse_query("priority:high scheduled:2024-12-12").unwrap();
+        let expr = parse_query("status:todo scheduled:2024-12-12").unwrap();
         assert_eq!(
             expr_to_sql(&expr),
             Some("scheduled = '2024-12-12'".to_string())
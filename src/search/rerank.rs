@@ -0,0 +1,143 @@
+//! Reorder search results using a cross-encoder reranking model which
+//! scores each result against the query directly. More accurate than
+//! fusing full-text and vector ranks, but too slow to run on more
+//! than the top results.
+
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::core::http;
+
+/// Timeout for a reranking request. Search falls back to the original
+/// order so don't hold up the search for long.
+const RERANK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Scores how relevant documents are to a query. This is a seam so
+/// that search works without a reranker and so tests can swap in a
+/// fake backend.
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    /// Relevance score for each of `documents` in the same order,
+    /// higher is more relevant
+    async fn rerank(&self, query: &str, documents: Vec<String>) -> Result<Vec<f32>>;
+}
+
+#[derive(Deserialize)]
+struct RerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+#[derive(Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+/// Reranks using an HTTP endpoint compatible with the Cohere and Jina
+/// rerank APIs e.g. a self-hosted model behind llama.cpp or Infinity.
+pub struct HttpReranker {
+    url: String,
+}
+
+impl HttpReranker {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Reranker for HttpReranker {
+    async fn rerank(&self, query: &str, documents: Vec<String>) -> Result<Vec<f32>> {
+        let num_documents = documents.len();
+        let resp: RerankResponse = http::shared_client()?
+            .post(&self.url)
+            .timeout(RERANK_TIMEOUT)
+            .json(&json!({
+                "query": query,
+                "documents": documents,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // Results are sorted by score so put them back in the order
+        // of the documents
+        let mut scores = vec![None; num_documents];
+        for result in resp.results {
+            let score = scores
+                .get_mut(result.index)
+                .ok_or_else(|| anyhow!("Reranker returned unknown index {}", result.index))?;
+            *score = Some(result.relevance_score);
+        }
+        scores
+            .into_iter()
+            .enumerate()
+            .map(|(i, score)| score.ok_or_else(|| anyhow!("Reranker is missing a score for {}", i)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_returns_scores_in_document_order() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/rerank")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "query": "budget",
+                "documents": ["first", "second", "third"],
+            })))
+            .with_status(200)
+            .with_body(
+                json!({
+                    "results": [
+                        {"index": 2, "relevance_score": 0.9},
+                        {"index": 0, "relevance_score": 0.5},
+                        {"index": 1, "relevance_score": 0.1},
+                    ]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let reranker = HttpReranker::new(&format!("{}/rerank", server.url()));
+        let scores = reranker
+            .rerank(
+                "budget",
+                vec!["first".into(), "second".into(), "third".into()],
+            )
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(scores, vec![0.5, 0.1, 0.9]);
+    }
+
+    #[tokio::test]
+    async fn it_errors_when_a_score_is_missing() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/rerank")
+            .with_status(200)
+            .with_body(json!({"results": [{"index": 0, "relevance_score": 0.5}]}).to_string())
+            .create_async()
+            .await;
+
+        let reranker = HttpReranker::new(&format!("{}/rerank", server.url()));
+        let result = reranker
+            .rerank("budget", vec!["first".into(), "second".into()])
+            .await;
+        assert!(result.is_err());
+    }
+}
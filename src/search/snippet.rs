@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use tantivy::Index;
+use tantivy::directory::MmapDirectory;
+use tantivy::snippet::SnippetGenerator;
+
+use crate::core::SnippetStrategy;
+use crate::search::aql::Expr;
+use crate::search::fts::schema::register_tokenizers;
+use crate::search::query::query_to_highlight_terms;
+
+/// Number of characters in a search result snippet when not specified
+pub const DEFAULT_SNIPPET_CHARS: usize = 200;
+
+/// Escape text that goes into a snippet the same way tantivy does
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Highlights where a query matched in the body of a search result,
+/// falling back to the title when the match isn't in the body.
+pub struct Highlighter {
+    body: SnippetGenerator,
+    title: SnippetGenerator,
+    max_num_chars: usize,
+    strategy: SnippetStrategy,
+}
+
+impl Highlighter {
+    pub fn new(
+        index_path: &str,
+        query: &Expr,
+        max_num_chars: usize,
+        strategy: SnippetStrategy,
+    ) -> anyhow::Result<Self> {
+        let idx = Index::open(MmapDirectory::open(index_path)?)?;
+        register_tokenizers(&idx);
+        let schema = idx.schema();
+        let terms = query_to_highlight_terms(query);
+
+        // Terms are split the same way as the indexed text so that
+        // highlights line up with what matched. This can't use
+        // `SnippetGenerator::create` because fuzzy queries don't
+        // report the terms they match.
+        let generator = |name: &str| -> anyhow::Result<SnippetGenerator> {
+            let field = schema.get_field(name)?;
+            let mut tokenizer = idx.tokenizer_for_field(field)?;
+            let mut terms_text = BTreeMap::new();
+            for value in terms.iter() {
+                let mut stream = tokenizer.token_stream(value);
+                while stream.advance() {
+                    terms_text.insert(stream.token().text.clone(), 1.0);
+                }
+            }
+            Ok(SnippetGenerator::new(
+                terms_text,
+                tokenizer,
+                field,
+                max_num_chars,
+            ))
+        };
+
+        Ok(Self {
+            body: generator("body")?,
+            title: generator("title")?,
+            max_num_chars,
+            strategy,
+        })
+    }
+
+    /// HTML snippet with matched terms wrapped in `<mark>` or `None`
+    /// if nothing in the title or body matched. The leading strategy
+    /// always uses the start of the body unless it's empty.
+    pub fn snippet(&self, title: &str, body: &str) -> Option<String> {
+        if self.strategy == SnippetStrategy::Leading && !body.is_empty() {
+            return Some(self.leading_snippet(body));
+        }
+        [(&self.body, body), (&self.title, title)]
+            .into_iter()
+            .map(|(generator, text)| generator.snippet(text))
+            .find(|snippet| !snippet.highlighted().is_empty())
+            .map(|mut snippet| {
+                snippet.set_snippet_prefix_postfix("<mark>", "</mark>");
+                snippet.to_html()
+            })
+    }
+
+    fn leading_snippet(&self, body: &str) -> String {
+        // Fragments are measured in bytes so cut on a character
+        // boundary that fits
+        let end = body
+            .char_indices()
+            .map(|(i, c)| i + c.len_utf8())
+            .take_while(|end| *end <= self.max_num_chars)
+            .last()
+            .unwrap_or(0);
+        let leading = &body[..end];
+        // The first fragment starts at the beginning of the text so
+        // any match in it spans the whole leading text
+        let mut snippet = self.body.snippet(leading);
+        if snippet.highlighted().is_empty() {
+            return escape_html(leading.trim_end());
+        }
+        snippet.set_snippet_prefix_postfix("<mark>", "</mark>");
+        snippet.to_html()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::aql::parse_query;
+    use crate::search::fts::schema::note_schema;
+    use tempfile::TempDir;
+
+    fn highlighter(query: &str) -> (TempDir, Highlighter) {
+        highlighter_with(query, SnippetStrategy::BestPassage)
+    }
+
+    fn highlighter_with(query: &str, strategy: SnippetStrategy) -> (TempDir, Highlighter) {
+        let dir = TempDir::new().unwrap();
+        Index::create_in_dir(dir.path(), note_schema()).unwrap();
+        let query = parse_query(query).unwrap();
+        let highlighter =
+            Highlighter::new(dir.path().to_str().unwrap(), &query, 40, strategy).unwrap();
+        (dir, highlighter)
+    }
+
+    #[test]
+    fn it_highlights_matches_in_the_body() {
+        let (_dir, highlighter) = highlighter("kickoff -status:done");
+        let snippet = highlighter
+            .snippet("Planning", "Agenda for the project Kickoff on Monday")
+            .unwrap();
+        assert!(snippet.contains("<mark>Kickoff</mark>"), "{}", snippet);
+    }
+
+    #[test]
+    fn it_falls_back_to_the_title() {
+        let (_dir, highlighter) = highlighter("title:kickoff~1");
+        let snippet = highlighter.snippet("Project kickoff", "").unwrap();
+        assert_eq!(snippet, "Project <mark>kickoff</mark>");
+        assert!(highlighter.snippet("Planning", "Nothing here").is_none());
+    }
+
+    const LONG_BODY: &str = "Notes from the weekly sync about hiring plans and the roadmap for next quarter. Budget is tight so travel is on hold. We agreed the kickoff moves to Monday.";
+
+    #[test]
+    fn it_uses_the_best_passage_deep_in_the_body() {
+        let (_dir, highlighter) = highlighter_with("kickoff", SnippetStrategy::BestPassage);
+        let snippet = highlighter.snippet("Weekly sync", LONG_BODY).unwrap();
+        assert!(snippet.contains("<mark>kickoff</mark>"), "{}", snippet);
+        assert!(!snippet.starts_with("Notes from"), "{}", snippet);
+    }
+
+    #[test]
+    fn it_uses_the_start_of_the_body_when_leading() {
+        let (_dir, highlighter) = highlighter_with("kickoff", SnippetStrategy::Leading);
+        let snippet = highlighter.snippet("Weekly sync", LONG_BODY).unwrap();
+        assert_eq!(snippet, "Notes from the weekly sync about hiring");
+
+        let (_dir, highlighter) = highlighter_with("weekly", SnippetStrategy::Leading);
+        let snippet = highlighter.snippet("Weekly sync", LONG_BODY).unwrap();
+        assert_eq!(
+            snippet,
+            "Notes from the <mark>weekly</mark> sync about hiring"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! Cross-checks that the notes directory, db, and full-text index
+//! agree on which notes exist. These can drift over time e.g. when a
+//! note is deleted or indexing fails part way through.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+use tantivy::Index;
+use tantivy::collector::DocSetCollector;
+use tantivy::query::AllQuery;
+use tantivy::schema::{TantivyDocument, Value};
+use tokio_rusqlite::Connection;
+
+use super::index_all;
+use super::indexing::{delete_notes, has_embedding_chunks, parse_note_id, remove_notes};
+use super::source::notes;
+
+/// A note that is missing from one or more places it should be
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Mismatch {
+    pub id: String,
+    pub file_name: Option<String>,
+    pub in_notes: bool,
+    pub in_db: bool,
+    pub in_full_text: bool,
+    pub in_vector: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    /// Number of distinct note IDs found across all sources
+    pub total: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Returns the ID and file name of every note in the notes directory
+async fn note_files(notes_path: &str) -> Result<HashMap<String, String>> {
+    let mut found = HashMap::new();
+    for path in notes(notes_path) {
+        let content = tokio::fs::read_to_string(&path).await?;
+        let file_name = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or_default()
+            .to_string();
+        found.insert(parse_note_id(&content), file_name);
+    }
+    Ok(found)
+}
+
+/// Returns the IDs of every note in the full-text index. Headings,
+/// tasks, and meetings are skipped since they aren't in every index.
+fn full_text_note_ids(index_path: &str) -> Result<HashSet<String>> {
+    let dir = tantivy::directory::MmapDirectory::open(index_path)?;
+    let idx = Index::open(dir)?;
+    let schema = idx.schema();
+    let id_field = schema.get_field("id")?;
+    let type_field = schema.get_field("type")?;
+    let searcher = idx.reader()?.searcher();
+
+    let mut ids = HashSet::new();
+    for doc_addr in searcher.search(&AllQuery, &DocSetCollector)? {
+        let doc: TantivyDocument = searcher.doc(doc_addr)?;
+        let doc_type = doc.get_first(type_field).and_then(|v| v.as_str());
+        if doc_type != Some("note") {
+            continue;
+        }
+        if let Some(id) = doc.get_first(id_field).and_then(|v| v.as_str()) {
+            ids.insert(id.to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Check which notes are missing from the notes directory, db,
+/// full-text index, or vector storage. Notes without a body to embed
+/// aren't expected to be in vector storage.
+pub async fn verify_indices(
+    db: &Connection,
+    index_path: &str,
+    notes_path: &str,
+) -> Result<VerifyReport> {
+    let files = note_files(notes_path).await?;
+
+    let (db_notes, unembedded_ids, vector_ids) = db
+        .call(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT id, file_name, body FROM note_meta WHERE type = 'note'")?;
+            let mut db_notes: HashMap<String, String> = HashMap::new();
+            let mut unembedded_ids: HashSet<String> = HashSet::new();
+            let rows = stmt.query_map([], |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    r.get::<_, String>(1)?,
+                    r.get::<_, Option<String>>(2)?,
+                ))
+            })?;
+            for row in rows {
+                let (id, file_name, body) = row?;
+                if !has_embedding_chunks(body.as_deref().unwrap_or_default()) {
+                    unembedded_ids.insert(id.clone());
+                }
+                db_notes.insert(id, file_name);
+            }
+            let mut stmt = conn.prepare("SELECT note_meta_id FROM vec_items")?;
+            let vector_ids: HashSet<String> = stmt
+                .query_map([], |r| r.get(0))?
+                .collect::<Result<HashSet<_>, _>>()?;
+            Ok((db_notes, unembedded_ids, vector_ids))
+        })
+        .await?;
+
+    let index_path = index_path.to_string();
+    let full_text_ids =
+        tokio::task::spawn_blocking(move || full_text_note_ids(&index_path)).await??;
+
+    let all_ids: BTreeSet<&String> = files
+        .keys()
+        .chain(db_notes.keys())
+        .chain(full_text_ids.iter())
+        .chain(vector_ids.iter())
+        .collect();
+
+    let mismatches = all_ids
+        .iter()
+        .map(|id| Mismatch {
+            id: id.to_string(),
+            file_name: files.get(*id).or(db_notes.get(*id)).cloned(),
+            in_notes: files.contains_key(*id),
+            in_db: db_notes.contains_key(*id),
+            in_full_text: full_text_ids.contains(*id),
+            in_vector: vector_ids.contains(*id),
+        })
+        .filter(|m| {
+            let vector_ok = m.in_vector || unembedded_ids.contains(&m.id);
+            !(m.in_notes && m.in_db && m.in_full_text && vector_ok)
+        })
+        .collect();
+
+    Ok(VerifyReport {
+        total: all_ids.len(),
+        mismatches,
+    })
+}
+
+/// Fix mismatches by re-indexing notes that still exist and removing
+/// notes that no longer exist from every index.
+pub async fn fix_indices(
+    db: &Connection,
+    index_path: &str,
+    notes_path: &str,
+    mismatches: &[Mismatch],
+) -> Result<()> {
+    let (existing, removed): (Vec<&Mismatch>, Vec<&Mismatch>) =
+        mismatches.iter().partition(|m| m.in_notes);
+
+    if !existing.is_empty() {
+        let paths = existing
+            .iter()
+            .filter_map(|m| m.file_name.as_ref())
+            .map(|f| Path::new(notes_path).join(f))
+            .collect();
+        // Only load the embedding model if it's needed
+        let index_vector = existing.iter().any(|m| !m.in_vector);
+        index_all(db, index_path, notes_path, true, index_vector, Some(paths)).await?;
+    }
+
+    if removed.is_empty() {
+        return Ok(());
+    }
+
+    // Remove the note along with any headings, tasks, and meetings
+    // from the same file if the file is gone
+    let removed_ids: Vec<String> = removed.iter().map(|m| m.id.clone()).collect();
+    let removed_files: Vec<String> = removed
+        .iter()
+        .filter_map(|m| m.file_name.clone())
+        .filter(|f| !Path::new(notes_path).join(f).exists())
+        .collect();
+    remove_notes(db, index_path, removed_files).await?;
+    delete_notes(db, index_path, removed_ids).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::TestNotes;
+    use tantivy::{IndexWriter, doc};
+    use tempfile::TempDir;
+
+    async fn setup(dir: &TempDir) -> TestNotes {
+        let notes = TestNotes::new(dir.path()).await;
+        notes.write_note(
+            "test.org",
+            ":PROPERTIES:\n:ID:       test-note-id\n:END:\n#+TITLE: this is a test\n",
+        );
+        notes.index(None).await.unwrap();
+        notes
+    }
+
+    /// Add a note to the full-text index that isn't anywhere else
+    fn seed_full_text_only_note(index_path: &str) {
+        let dir = tantivy::directory::MmapDirectory::open(index_path).unwrap();
+        let idx = Index::open(dir).unwrap();
+        let schema = idx.schema();
+        let mut index_writer: IndexWriter = idx.writer(50_000_000).unwrap();
+        index_writer
+            .add_document(doc!(
+                schema.get_field("id").unwrap() => "orphan-note-id",
+                schema.get_field("type").unwrap() => "note",
+                schema.get_field("title").unwrap() => "deleted note",
+                schema.get_field("file_name").unwrap() => "deleted.org",
+            ))
+            .unwrap();
+        index_writer.commit().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_consistent() {
+        let dir = TempDir::new().unwrap();
+        let TestNotes {
+            db,
+            index_path,
+            notes_path,
+            ..
+        } = setup(&dir).await;
+
+        let report = verify_indices(&db, &index_path, &notes_path).await.unwrap();
+
+        assert_eq!(report.total, 1);
+        assert!(report.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verify_detects_note_body_without_vectors() {
+        let dir = TempDir::new().unwrap();
+        let notes = setup(&dir).await;
+        notes.write_note(
+            "body.org",
+            ":PROPERTIES:\n:ID:       body-note-id\n:END:\n#+TITLE: with a body\n\nSome text to embed\n",
+        );
+        notes.index(None).await.unwrap();
+
+        let report = verify_indices(&notes.db, &notes.index_path, &notes.notes_path)
+            .await
+            .unwrap();
+
+        assert_eq!(report.total, 2);
+        assert_eq!(
+            report.mismatches,
+            vec![Mismatch {
+                id: "body-note-id".to_string(),
+                file_name: Some("body.org".to_string()),
+                in_notes: true,
+                in_db: true,
+                in_full_text: true,
+                in_vector: false,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_detects_full_text_only_note() {
+        let dir = TempDir::new().unwrap();
+        let TestNotes {
+            db,
+            index_path,
+            notes_path,
+            ..
+        } = setup(&dir).await;
+        seed_full_text_only_note(&index_path);
+
+        let report = verify_indices(&db, &index_path, &notes_path).await.unwrap();
+
+        assert_eq!(report.total, 2);
+        assert_eq!(
+            report.mismatches,
+            vec![Mismatch {
+                id: "orphan-note-id".to_string(),
+                file_name: None,
+                in_notes: false,
+                in_db: false,
+                in_full_text: true,
+                in_vector: false,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fix_removes_full_text_only_note() {
+        let dir = TempDir::new().unwrap();
+        let TestNotes {
+            db,
+            index_path,
+            notes_path,
+            ..
+        } = setup(&dir).await;
+        seed_full_text_only_note(&index_path);
+
+        let report = verify_indices(&db, &index_path, &notes_path).await.unwrap();
+        fix_indices(&db, &index_path, &notes_path, &report.mismatches)
+            .await
+            .unwrap();
+
+        let report = verify_indices(&db, &index_path, &notes_path).await.unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.total, 1);
+    }
+}
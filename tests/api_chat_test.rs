@@ -11,7 +11,12 @@ mod tests {
     use serial_test::serial;
     use tower::util::ServiceExt;
 
-    use crate::test_utils::{body_to_string, test_app};
+    use hq::ai::chat::{get_or_create_session, insert_chat_message};
+    use hq::openai::{FunctionCall, FunctionCallFn, Message, Role};
+
+    use crate::test_utils::{
+        TestApp, body_to_string, test_app, test_app_fixture, test_app_fixture_with_config,
+    };
 
     /// Tests getting chat sessions returns empty list initially
     #[tokio::test]
@@ -243,4 +248,746 @@ mod tests {
         let body = body_to_string(response.into_body()).await;
         assert!(body.contains("\"sessions\""));
     }
+
+    /// Tests previewing the payload for a stored chat session
+    #[tokio::test]
+    #[serial]
+    async fn it_previews_chat_payload() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        let session_id = "test-session-preview";
+        get_or_create_session(&db, session_id, &[]).await.unwrap();
+        for msg in [
+            Message::new(Role::System, "You are a helpful assistant."),
+            Message::new(Role::User, "What's on my calendar?"),
+            Message::new(Role::Assistant, "You have a meeting at 10am."),
+        ] {
+            insert_chat_message(&db, session_id, &msg, None)
+                .await
+                .unwrap();
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/chat/{session_id}/preview"))
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({"message": "Thanks!"}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let preview: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(preview["model"], "gpt-4o");
+
+        // Stored transcript followed by the new message
+        let messages = preview["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["content"], "What's on my calendar?");
+        assert_eq!(messages[2]["content"], "You have a meeting at 10am.");
+        assert_eq!(messages[3]["role"], "user");
+        assert_eq!(messages[3]["content"], "Thanks!");
+
+        // Configured tools are included
+        let tool_names: Vec<&str> = preview["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["function"]["name"].as_str().unwrap())
+            .collect();
+        assert!(tool_names.contains(&"search_notes"));
+        assert!(tool_names.contains(&"memory"));
+    }
+
+    /// Tests forking a chat session copies the messages up to the index
+    #[tokio::test]
+    #[serial]
+    async fn it_forks_chat_session() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        let session_id = "test-session-fork";
+        get_or_create_session(&db, session_id, &["work"])
+            .await
+            .unwrap();
+        for msg in [
+            Message::new(Role::System, "You are a helpful assistant."),
+            Message::new(Role::User, "What's on my calendar?"),
+            Message::new(Role::Assistant, "You have a meeting at 10am."),
+            Message::new(Role::User, "Cancel it."),
+            Message::new(Role::Assistant, "Done."),
+        ] {
+            insert_chat_message(&db, session_id, &msg, None)
+                .await
+                .unwrap();
+        }
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/chat/{session_id}/fork?upto=2"))
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let fork: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let fork_id = fork["session_id"].as_str().unwrap();
+        assert_ne!(fork_id, session_id);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/chat/{fork_id}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let transcript: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let messages = transcript["transcript"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["content"], "What's on my calendar?");
+        assert_eq!(messages[2]["content"], "You have a meeting at 10am.");
+
+        // The fork keeps the tags of the original session
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat/sessions?tags=work")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = body_to_string(response.into_body()).await;
+        let sessions: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(sessions["total_sessions"], 2);
+
+        // The original session is unchanged
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/chat/{session_id}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = body_to_string(response.into_body()).await;
+        let transcript: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(transcript["transcript"].as_array().unwrap().len(), 5);
+    }
+
+    /// Tests forking past the end of a chat session is rejected
+    #[tokio::test]
+    #[serial]
+    async fn it_rejects_fork_past_end_of_session() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        let session_id = "test-session-fork-range";
+        get_or_create_session(&db, session_id, &[]).await.unwrap();
+        insert_chat_message(&db, session_id, &Message::new(Role::User, "Hello"), None)
+            .await
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/chat/{session_id}/fork?upto=1"))
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat/missing-session/fork?upto=0")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// Tests forking between tool calls and their responses is rejected
+    #[tokio::test]
+    #[serial]
+    async fn it_rejects_fork_that_splits_tool_calls() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        let session_id = "test-session-fork-tools";
+        get_or_create_session(&db, session_id, &[]).await.unwrap();
+        let tool_call = |id: &str| FunctionCall {
+            function: FunctionCallFn {
+                arguments: "{}".to_string(),
+                name: "calendar".to_string(),
+            },
+            id: id.to_string(),
+            r#type: "function".to_string(),
+        };
+        for msg in [
+            Message::new(Role::User, "What's on my calendar?"),
+            Message::new_tool_call_request(vec![tool_call("call_1"), tool_call("call_2")]),
+            Message::new_tool_call_response("No events today", "call_1"),
+            Message::new_tool_call_response("No events tomorrow", "call_2"),
+            Message::new(Role::Assistant, "Your calendar is clear."),
+        ] {
+            insert_chat_message(&db, session_id, &msg, None)
+                .await
+                .unwrap();
+        }
+
+        // On the tool calls or before the last of their responses
+        for upto in [1, 2] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/chat/{session_id}/fork?upto={upto}"))
+                        .method("POST")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "upto={upto}");
+        }
+
+        // After all of the responses
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/chat/{session_id}/fork?upto=3"))
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// Tests previewing a new chat session uses the default system message
+    #[tokio::test]
+    #[serial]
+    async fn it_previews_new_chat_session() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat/test-session-new-preview/preview")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({"message": "Hello"}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let preview: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let messages = preview["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["content"], "You are a helpful assistant.");
+        assert_eq!(messages[1]["content"], "Hello");
+    }
+
+    /// Tests that selecting a persona uses its system message and tools
+    #[tokio::test]
+    #[serial]
+    async fn it_previews_chat_with_persona() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat/test-session-persona/preview")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({"message": "Hello", "persona": "journal"}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let preview: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let messages = preview["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "You are a journaling companion.");
+
+        let tool_names: Vec<&str> = preview["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["function"]["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(tool_names, vec!["search_notes", "memory"]);
+    }
+
+    /// Preview a new chat session with tasks search mocked and return
+    /// the system message
+    async fn preview_system_message_with_tasks(include_tasks: bool) -> String {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/notes/search")
+            .match_query(mockito::Matcher::Regex(
+                r"query=deadline%3A%3C%3D\d{4}-\d{2}-\d{2}".into(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(std::fs::read_to_string("./tests/data/tasks_search_response.json").unwrap())
+            .create_async()
+            .await;
+        let url = server.url();
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.note_search_api_url = url;
+            config.chat_include_tasks = include_tasks;
+        })
+        .await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat/test-session-tasks/preview")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({"message": "Hello"}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let preview: serde_json::Value = serde_json::from_str(&body).unwrap();
+        preview["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Tests that the tasks due today are added to the system message
+    /// of a new chat session when enabled
+    #[tokio::test]
+    #[serial]
+    async fn it_includes_tasks_due_today_in_system_message() {
+        let system_message = preview_system_message_with_tasks(true).await;
+
+        assert!(system_message.starts_with("You are a helpful assistant."));
+        assert!(system_message.contains("# Tasks due today"));
+        assert!(system_message.contains("Complete project report"));
+        assert!(system_message.contains("Review pull requests"));
+    }
+
+    /// Tests that the system message is left alone when tasks aren't
+    /// enabled
+    #[tokio::test]
+    #[serial]
+    async fn it_omits_tasks_from_system_message_when_disabled() {
+        let system_message = preview_system_message_with_tasks(false).await;
+
+        assert_eq!(system_message, "You are a helpful assistant.");
+    }
+
+    /// Tests that a tool disabled server-wide isn't available even when
+    /// the persona selects it
+    #[tokio::test]
+    #[serial]
+    async fn it_excludes_globally_disabled_tools() {
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.enabled_tools = Some(vec![String::from("memory"), String::from("web_search")]);
+        })
+        .await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat/test-session-enabled-tools/preview")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({"message": "Hello", "persona": "journal"}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let preview: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let tool_names: Vec<&str> = preview["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["function"]["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(tool_names, vec!["memory"]);
+    }
+
+    /// Tests that an unknown persona is rejected
+    #[tokio::test]
+    #[serial]
+    async fn it_rejects_unknown_persona() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "session_id": "test-session-unknown-persona",
+                            "message": "Hello",
+                            "persona": "pirate",
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body_to_string(response.into_body()).await;
+        assert!(body.contains("Unknown persona: pirate"));
+    }
+
+    /// Tests chatting over a websocket and cancelling mid-stream
+    #[tokio::test]
+    #[serial]
+    async fn it_streams_and_cancels_chat_over_websocket() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        // Stream the first chunk and then stall so there is time to
+        // cancel before the response finishes
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_chunked_body(|w| {
+                w.write_all(
+                    br#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4o","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}
+
+"#,
+                )?;
+                w.flush()?;
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                w.write_all(b"data: [DONE]\n\n")
+            })
+            .create_async()
+            .await;
+
+        let url = server.url();
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.openai_api_hostname = url;
+        })
+        .await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/api/chat/ws"))
+            .await
+            .unwrap();
+
+        socket
+            .send(WsMessage::text(
+                serde_json::json!({
+                    "type": "chat",
+                    "session_id": "test-session-ws",
+                    "message": "Hi",
+                })
+                .to_string(),
+            ))
+            .await
+            .unwrap();
+
+        // Receives the streamed chunk
+        let msg = socket.next().await.unwrap().unwrap();
+        let chunk: serde_json::Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "Hello");
+
+        socket
+            .send(WsMessage::text(
+                serde_json::json!({"type": "cancel"}).to_string(),
+            ))
+            .await
+            .unwrap();
+
+        // Cancelling stops the response before it's done
+        let msg = socket.next().await.unwrap().unwrap();
+        let event: serde_json::Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+        assert_eq!(event["type"], "cancelled");
+    }
+
+    /// Tests that reconnecting with a `Last-Event-ID` resumes the
+    /// streamed response after that event instead of replaying it
+    #[tokio::test]
+    #[serial]
+    async fn it_resumes_chat_stream_from_last_event_id() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(
+                r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4o","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"One"},"finish_reason":null}]}
+
+data: {"id":"chunk2","created":1234567890,"model":"gpt-4o","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Two"},"finish_reason":null}]}
+
+data: {"id":"chunk3","created":1234567890,"model":"gpt-4o","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Three"},"finish_reason":"stop"}]}
+
+data: [DONE]
+
+"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let url = server.url();
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.openai_api_hostname = url;
+        })
+        .await;
+
+        let chat_request = |last_event_id: Option<&str>| {
+            let mut builder = Request::builder()
+                .uri("/api/chat")
+                .method("POST")
+                .header("content-type", "application/json");
+            if let Some(id) = last_event_id {
+                builder = builder.header("last-event-id", id);
+            }
+            builder
+                .body(Body::from(
+                    serde_json::json!({
+                        "session_id": "test-session-resume",
+                        "message": "Count to three",
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(chat_request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["cache-control"], "no-cache");
+        let body = body_to_string(response.into_body()).await;
+        assert!(body.contains("id: 1\n"));
+        assert!(body.contains("id: 3\n"));
+        assert!(body.contains("One"));
+
+        // Reconnecting after the first event only sends the rest
+        let response = app.oneshot(chat_request(Some("1"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        assert!(!body.contains("id: 1\n"));
+        assert!(!body.contains("One"));
+        assert!(body.contains("id: 2\n"));
+        assert!(body.contains("id: 3\n"));
+        assert!(body.contains("Three"));
+
+        // The model was only called for the first request
+        mock.assert_async().await;
+    }
+
+    /// Tests that reconnecting to a stream the server doesn't have,
+    /// e.g. after a restart, is a 404 rather than re-running the turn
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_404_resuming_unknown_chat_stream() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let url = server.url();
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.openai_api_hostname = url;
+        })
+        .await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .header("last-event-id", "3")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "session_id": "test-session-unknown-stream",
+                            "message": "Count to three",
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        mock.assert_async().await;
+    }
+
+    /// Tests that tool calls are surrounded by tool events in the
+    /// stream so clients can show progress while a tool runs
+    #[tokio::test]
+    #[serial]
+    async fn it_streams_tool_events() {
+        let mut server = mockito::Server::new_async().await;
+        let tool_call = r#"data: {"id":"chunk1","created":1234567890,"model":"gpt-4o","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"tool_calls":[{"id":"call_1","index":0,"function":{"name":"web_search","arguments":"{\"query\":\"rust\",\"limit\":1}"},"type":"function"}]},"finish_reason":null}]}
+
+data: {"id":"chunk2","created":1234567890,"model":"gpt-4o","system_fingerprint":"fp1","choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}
+
+data: [DONE]
+
+"#;
+        let content = r#"data: {"id":"chunk3","created":1234567890,"model":"gpt-4o","system_fingerprint":"fp1","choices":[{"index":0,"delta":{"content":"Found it"},"finish_reason":"stop"}]}
+
+data: [DONE]
+
+"#;
+        // Respond with the tool call first then the answer once the
+        // tool result is sent back
+        let completions = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body_from_request(move |req| {
+                let body = String::from_utf8_lossy(req.body().unwrap());
+                if body.contains(r#""role":"tool""#) {
+                    content.into()
+                } else {
+                    tool_call.into()
+                }
+            })
+            .expect(2)
+            .create_async()
+            .await;
+        let web_search = server
+            .mock("GET", "/api/web/search")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_body(r#"{"results": []}"#)
+            .create_async()
+            .await;
+
+        let url = server.url();
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.openai_api_hostname = url.clone();
+            config.note_search_api_url = url;
+        })
+        .await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "session_id": "test-session-tool-events",
+                            "message": "Search the web for rust",
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+
+        completions.assert_async().await;
+        web_search.assert_async().await;
+        let tool_start = body
+            .find(r#"{"type":"tool_start","name":"web_search"}"#)
+            .expect("Missing tool_start event");
+        let tool_end = body
+            .find(r#"{"type":"tool_end","name":"web_search"}"#)
+            .expect("Missing tool_end event");
+        let content = body.find("Found it").expect("Missing content");
+        assert!(tool_start < tool_end);
+        assert!(tool_end < content);
+    }
+
+    /// Tests that a message longer than the configured maximum is
+    /// rejected before calling the model
+    #[tokio::test]
+    #[serial]
+    async fn it_rejects_over_length_message() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chat")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "session_id": "test-session-too-long",
+                            "message": "hello ".repeat(500),
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = body_to_string(response.into_body()).await;
+        assert!(body.contains("Message is too long"));
+        assert!(body.contains("exceeds the maximum of 100"));
+    }
 }
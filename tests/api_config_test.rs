@@ -0,0 +1,78 @@
+//! Integration tests for the config API endpoints
+
+mod test_utils;
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use serial_test::serial;
+    use tower::util::ServiceExt;
+
+    use hq::openai::Provider;
+
+    use crate::test_utils::{TestApp, body_to_string, test_app, test_app_fixture_with_config};
+
+    /// Tests getting the config includes settings but not secrets
+    #[tokio::test]
+    #[serial]
+    async fn it_gets_redacted_config() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/config")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let config: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(config["openai_model"], "gpt-4o");
+        assert_eq!(config["openai_api_hostname"], "https://api.openai.com");
+        assert_eq!(config["openai_api_key"], "[REDACTED]");
+        assert_eq!(config["gmail_api_client_secret"], "[REDACTED]");
+        assert_eq!(config["google_search_api_key"], "[REDACTED]");
+        assert!(!body.contains("test-api-key"));
+        assert!(!body.contains("test_client_secret"));
+        assert!(!body.contains("test_google_search_key"));
+    }
+
+    /// Tests the API keys of fallback providers are redacted
+    #[tokio::test]
+    #[serial]
+    async fn it_redacts_fallback_api_keys() {
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.openai_fallbacks = vec![Provider::new(
+                "https://api.openai.com",
+                "sk-fallback",
+                "gpt-4.1-mini",
+            )];
+        })
+        .await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/config")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = body_to_string(response.into_body()).await;
+        let config: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let fallback = &config["openai_fallbacks"][0];
+        assert_eq!(fallback["model"], "gpt-4.1-mini");
+        assert_eq!(fallback["api_key"], "[REDACTED]");
+        assert!(!body.contains("sk-fallback"));
+    }
+}
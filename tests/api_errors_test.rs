@@ -0,0 +1,191 @@
+//! Integration tests for how upstream API failures are mapped to
+//! responses
+
+mod test_utils;
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+    use axum::{
+        Router,
+        body::Body,
+        extract::State,
+        http::{Request, StatusCode, header},
+        routing::get,
+    };
+    use serde_json::json;
+    use tower::util::ServiceExt;
+
+    use crate::test_utils::body_to_string;
+    use hq::api::public::ApiError;
+    use hq::google::custom_search::search_google;
+    use hq::openai::{CompletionOptions, Message, Role, completion};
+
+    /// Handler that sends a completion to the OpenAI compatible API
+    /// at the URL in state
+    async fn completion_handler(State(url): State<String>) -> Result<String, ApiError> {
+        let options = CompletionOptions {
+            max_retries: Some(0),
+            ..Default::default()
+        };
+        let messages = vec![Message::new(Role::User, "Hi")];
+        let resp = completion(&messages, &None, &url, "test-key", "gpt-4", &options).await?;
+        Ok(resp.to_string())
+    }
+
+    /// Same as `completion_handler` but adds context to the error
+    async fn completion_with_context_handler(
+        State(url): State<String>,
+    ) -> Result<String, ApiError> {
+        let options = CompletionOptions {
+            max_retries: Some(0),
+            ..Default::default()
+        };
+        let messages = vec![Message::new(Role::User, "Hi")];
+        let resp = completion(&messages, &None, &url, "test-key", "gpt-4", &options)
+            .await
+            .context("Failed to summarize")?;
+        Ok(resp.to_string())
+    }
+
+    /// Handler that searches the Google custom search API at the URL
+    /// in state
+    async fn google_search_handler(State(url): State<String>) -> Result<String, ApiError> {
+        let items = search_google("test", "test-key", "test-cx", Some(1), Some(&url)).await?;
+        Ok(items.len().to_string())
+    }
+
+    fn upstream_app(url: String) -> Router {
+        Router::new()
+            .route("/completion", get(completion_handler))
+            .route("/completion/context", get(completion_with_context_handler))
+            .route("/google", get(google_search_handler))
+            .with_state(url)
+    }
+
+    async fn get_response(app: Router, uri: &str) -> axum::response::Response {
+        app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Tests an upstream rate limit is returned as a 429 with the
+    /// upstream's Retry-After
+    #[tokio::test]
+    async fn it_maps_upstream_rate_limits_to_429() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "7")
+            .with_body(
+                json!({"error": {"message": "Rate limit reached", "code": "rate_limit_exceeded"}})
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let response = get_response(upstream_app(server.url()), "/completion").await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "7");
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["error"], "Rate limit reached");
+    }
+
+    /// Tests an upstream error is still mapped when context was added
+    /// to it
+    #[tokio::test]
+    async fn it_maps_upstream_errors_wrapped_in_context() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "7")
+            .with_body(
+                json!({"error": {"message": "Rate limit reached", "code": "rate_limit_exceeded"}})
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let response = get_response(upstream_app(server.url()), "/completion/context").await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "7");
+    }
+
+    /// Tests an upstream auth failure is returned as a 502 since it's
+    /// the server's credentials that are wrong
+    #[tokio::test]
+    async fn it_maps_upstream_auth_failures_to_502() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(401)
+            .with_body(json!({"error": {"message": "Incorrect API key provided"}}).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/customsearch/v1")
+            .match_query(mockito::Matcher::Any)
+            .with_status(403)
+            .with_body(
+                json!({"error": {"code": 403, "message": "The caller does not have permission"}})
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let app = upstream_app(server.url());
+        let response = get_response(app.clone(), "/completion").await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let app = upstream_app(format!("{}/customsearch/v1", server.url()));
+        let response = get_response(app, "/google").await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["error"], "The caller does not have permission");
+    }
+
+    /// Tests an upstream bad request is passed through as a 400 with
+    /// the upstream's message
+    #[tokio::test]
+    async fn it_passes_upstream_bad_requests_through_as_400() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(400)
+            .with_body(
+                json!({"error": {"message": "Invalid value for 'temperature'", "type": "invalid_request_error"}})
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let response = get_response(upstream_app(server.url()), "/completion").await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["error"], "Invalid value for 'temperature'");
+    }
+
+    /// Tests other upstream failures are still a 500
+    #[tokio::test]
+    async fn it_returns_500_for_other_upstream_failures() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(500)
+            .with_body("Internal error")
+            .create_async()
+            .await;
+
+        let response = get_response(upstream_app(server.url()), "/completion").await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
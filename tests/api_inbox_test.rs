@@ -0,0 +1,128 @@
+//! Integration tests for the inbox API endpoint
+
+mod test_utils;
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use serial_test::serial;
+    use tower::util::ServiceExt;
+
+    use crate::test_utils::{TestApp, body_to_string, test_app_fixture_with_config};
+
+    const CALENDAR_RESPONSE: &str = r#"[{"id":"evt_001","summary":"Team standup","start":"2026-02-06T17:00:00+00:00","end":"2026-02-06T17:15:00+00:00","attendees":null}]"#;
+
+    /// Mocks the email, task search, and calendar endpoints the inbox
+    /// is built from, returning `calendar_status` for the calendar.
+    async fn mock_sections(server: &mut mockito::ServerGuard, calendar_status: usize) {
+        server
+            .mock("GET", "/api/email/unread")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "email".into(),
+                "test@example.com".into(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(fs::read_to_string("./tests/data/email_unread_response.json").unwrap())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/notes/search")
+            .match_query(mockito::Matcher::Regex(
+                r"query=deadline%3A%3C%3D\d{4}-\d{2}-\d{2}".into(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(fs::read_to_string("./tests/data/tasks_search_response.json").unwrap())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/calendar")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("email".into(), "test@example.com".into()),
+                mockito::Matcher::UrlEncoded("days_ahead".into(), "1".into()),
+            ]))
+            .with_status(calendar_status)
+            .with_header("content-type", "application/json")
+            .with_body(CALENDAR_RESPONSE)
+            .create_async()
+            .await;
+    }
+
+    async fn get_inbox(url: String) -> serde_json::Value {
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.note_search_api_url = url;
+        })
+        .await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/inbox?email=test@example.com")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        serde_json::from_str(&body).unwrap()
+    }
+
+    /// Tests the inbox combines unread email, tasks due today, and
+    /// today's calendar events
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_all_inbox_sections() {
+        let mut server = mockito::Server::new_async().await;
+        mock_sections(&mut server, 200).await;
+
+        let inbox = get_inbox(server.url()).await;
+
+        assert_eq!(inbox["emails"][0]["id"], "thr_001");
+        assert_eq!(inbox["tasks"][0]["id"], "note-123");
+        assert_eq!(inbox["events"][0]["summary"], "Team standup");
+        assert_eq!(inbox["errors"], serde_json::json!([]));
+    }
+
+    /// Tests the inbox still returns the available sections when one
+    /// of them fails
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_partial_inbox_with_errors() {
+        let mut server = mockito::Server::new_async().await;
+        mock_sections(&mut server, 500).await;
+
+        let inbox = get_inbox(server.url()).await;
+
+        assert_eq!(inbox["emails"][0]["id"], "thr_001");
+        assert_eq!(inbox["tasks"][0]["id"], "note-123");
+        assert_eq!(inbox["events"], serde_json::json!([]));
+        assert_eq!(inbox["errors"][0]["section"], "events");
+    }
+
+    /// Tests the inbox endpoint requires an email
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_400_for_missing_email_param() {
+        let TestApp { app, .. } = test_app_fixture_with_config(|_| {}).await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/inbox")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -11,7 +11,10 @@ mod tests {
     use serial_test::serial;
     use tower::util::ServiceExt;
 
-    use crate::test_utils::{body_to_string, test_app};
+    use hq::api::public::metrics::{MetricName, ToolMetricsResponse};
+    use hq::api::routes::metrics::db::insert_metric_event;
+
+    use crate::test_utils::{TestApp, body_to_string, test_app, test_app_fixture};
 
     /// Tests recording a metric via POST
     #[tokio::test]
@@ -233,4 +236,151 @@ mod tests {
         // Missing required field should return 422 Unprocessable Entity (validation error)
         assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
     }
+
+    /// Tests summarizing tool call latency metrics
+    #[tokio::test]
+    #[serial]
+    async fn it_gets_tool_metrics() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        for (tool, ms) in [
+            ("search_notes", 100),
+            ("search_notes", 300),
+            ("web_search", 50),
+        ] {
+            insert_metric_event(&db, MetricName::ToolLatency, ms, Some(tool.to_string()))
+                .await
+                .unwrap();
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/metrics/tools")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let resp: ToolMetricsResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(resp.tools.len(), 2);
+        assert_eq!(resp.tools[0].name, "search_notes");
+        assert_eq!(resp.tools[0].count, 2);
+        assert_eq!(resp.tools[0].avg_ms, 200.0);
+        assert_eq!(resp.tools[0].max_ms, 300);
+        assert_eq!(resp.tools[1].name, "web_search");
+        assert_eq!(resp.tools[1].count, 1);
+    }
+
+    /// Tests that retrying a metric with the same idempotency key only
+    /// records it once
+    #[tokio::test]
+    #[serial]
+    async fn it_dedupes_metrics_by_idempotency_key() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/api/metrics")
+                        .method("POST")
+                        .header("content-type", "application/json")
+                        .body(Body::from(
+                            serde_json::json!({
+                                "name": "token-count",
+                                "value": 20,
+                                "idempotency_key": "retry-me",
+                            })
+                            .to_string(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let count: i64 = db
+            .call(|conn| {
+                Ok(conn.query_row(
+                    "SELECT COUNT(*) FROM metric_event WHERE idempotency_key = 'retry-me'",
+                    [],
+                    |row| row.get(0),
+                )?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    /// Tests exporting metrics in the Prometheus text format
+    #[tokio::test]
+    #[serial]
+    async fn it_exports_prometheus_metrics() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        insert_metric_event(&db, MetricName::TokenCount, 120, Some("gpt-4o".to_string()))
+            .await
+            .unwrap();
+        insert_metric_event(&db, MetricName::TokenCount, 30, Some("gpt-4o".to_string()))
+            .await
+            .unwrap();
+        insert_metric_event(
+            &db,
+            MetricName::ToolLatency,
+            100,
+            Some("search_notes".to_string()),
+        )
+        .await
+        .unwrap();
+
+        // Some requests for the request counts
+        for uri in ["/api/metrics", "/api/chat/missing-session"] {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/metrics/prometheus")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let content_type = response.headers()["content-type"].to_str().unwrap();
+        assert!(content_type.starts_with("text/plain"));
+
+        let body = body_to_string(response.into_body()).await;
+        let sample = regex::Regex::new(
+            r#"^[a-zA-Z_:][a-zA-Z0-9_:]*(\{[a-zA-Z_][a-zA-Z0-9_]*="[^"]*"(,[a-zA-Z_][a-zA-Z0-9_]*="[^"]*")*\})? -?[0-9.e+]+$"#,
+        )
+        .unwrap();
+        for line in body.lines() {
+            assert!(
+                line.starts_with("# HELP ") || line.starts_with("# TYPE ") || sample.is_match(line),
+                "Malformed metric line: {}",
+                line
+            );
+        }
+
+        let lines: Vec<&str> = body.lines().collect();
+        assert!(lines.contains(&"hq_http_requests_total{status=\"2xx\"} 1"));
+        assert!(lines.contains(&"hq_http_requests_total{status=\"4xx\"} 1"));
+        assert!(lines.contains(&"hq_llm_tokens_total{model=\"gpt-4o\"} 150"));
+        assert!(lines.contains(&"hq_tool_calls_total{tool=\"search_notes\"} 1"));
+        assert!(lines.contains(&"# TYPE hq_chat_sessions gauge"));
+    }
 }
@@ -11,7 +11,11 @@ mod tests {
     use serial_test::serial;
     use tower::util::ServiceExt;
 
-    use crate::test_utils::{body_to_string, test_app};
+    use crate::test_utils::{
+        TestApp, body_to_string, test_app, test_app_fixture, test_app_fixture_with_config,
+    };
+    use hq::core::NoteIdScheme;
+    use hq::search::index_all;
 
     /// Tests searching notes with a query
     #[tokio::test]
@@ -82,6 +86,200 @@ mod tests {
         assert!(body.contains("\"raw_query\""));
     }
 
+    /// Tests paging through search results with offset and limit
+    #[tokio::test]
+    #[serial]
+    async fn it_searches_notes_with_offset() {
+        let TestApp {
+            app,
+            db,
+            notes_path,
+        } = test_app_fixture().await;
+
+        let mut paths = Vec::new();
+        for i in 1..=5 {
+            let path = notes_path.join(format!("page-{}.org", i));
+            std::fs::write(
+                &path,
+                format!(
+                    ":PROPERTIES:\n:ID:       page-{i}\n:END:\n#+TITLE: pagination note {i}\n#+DATE: 2025-01-0{i}\n"
+                ),
+            )
+            .unwrap();
+            paths.push(path);
+        }
+        let index_path = notes_path.parent().unwrap().join("index");
+        index_all(
+            &db,
+            index_path.to_str().unwrap(),
+            notes_path.to_str().unwrap(),
+            true,
+            false,
+            Some(paths),
+        )
+        .await
+        .unwrap();
+
+        let search = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = body_to_string(response.into_body()).await;
+                let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+                let ids: Vec<String> = json["results"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|r| r["id"].as_str().unwrap().to_string())
+                    .collect();
+                (ids, json["total_hits"].clone())
+            }
+        };
+
+        let (all_ids, total_hits) = search("/api/notes/search?query=pagination&limit=10").await;
+        assert_eq!(all_ids.len(), 5);
+        assert_eq!(total_hits, 5);
+
+        let (page_ids, total_hits) =
+            search("/api/notes/search?query=pagination&offset=2&limit=2").await;
+        assert_eq!(page_ids, all_ids[2..4]);
+        assert_eq!(total_hits, 5);
+    }
+
+    /// Tests suggesting notes by title prefix for type-ahead
+    #[tokio::test]
+    #[serial]
+    async fn it_suggests_notes_by_title_prefix() {
+        let TestApp {
+            app,
+            db,
+            notes_path,
+        } = test_app_fixture().await;
+
+        let mut paths = Vec::new();
+        for (id, title) in [
+            ("suggest-1", "Project planning for the offsite"),
+            ("suggest-2", "Project"),
+            ("suggest-3", "Weekly project review"),
+            ("suggest-4", "Gardening"),
+        ] {
+            let path = notes_path.join(format!("{}.org", id));
+            std::fs::write(
+                &path,
+                format!(":PROPERTIES:\n:ID:       {id}\n:END:\n#+TITLE: {title}\n"),
+            )
+            .unwrap();
+            paths.push(path);
+        }
+        let index_path = notes_path.parent().unwrap().join("index");
+        index_all(
+            &db,
+            index_path.to_str().unwrap(),
+            notes_path.to_str().unwrap(),
+            true,
+            false,
+            Some(paths),
+        )
+        .await
+        .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/suggest?q=proj")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            json["suggestions"],
+            serde_json::json!([
+                {"id": "suggest-2", "title": "Project"},
+                {"id": "suggest-1", "title": "Project planning for the offsite"},
+                {"id": "suggest-3", "title": "Weekly project review"},
+            ])
+        );
+    }
+
+    /// Tests counting tags and status across all matching notes
+    #[tokio::test]
+    #[serial]
+    async fn it_searches_notes_with_facets() {
+        let TestApp {
+            app,
+            db,
+            notes_path,
+        } = test_app_fixture().await;
+
+        let path = notes_path.join("facets.org");
+        std::fs::write(
+            &path,
+            ":PROPERTIES:\n:ID:       facets\n:END:\n#+TITLE: Facets\n\n* TODO Pay taxes chores :finance:home:\n:PROPERTIES:\n:ID:       facet-taxes\n:END:\n* DONE File receipts chores :finance:\n:PROPERTIES:\n:ID:       facet-receipts\n:END:\n* TODO Water plants chores :home:\n:PROPERTIES:\n:ID:       facet-plants\n:END:\n* TODO Fix bike chores\n:PROPERTIES:\n:ID:       facet-bike\n:END:\n",
+        )
+        .unwrap();
+        let index_path = notes_path.parent().unwrap().join("index");
+        index_all(
+            &db,
+            index_path.to_str().unwrap(),
+            notes_path.to_str().unwrap(),
+            true,
+            false,
+            Some(vec![path]),
+        )
+        .await
+        .unwrap();
+
+        // Facets count every match, not just the first page
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=chores&limit=1&facets=tags,status")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        // The note itself matches along with its tasks
+        assert_eq!(json["total_hits"], 5);
+        assert_eq!(
+            json["facets"],
+            serde_json::json!({
+                "tags": [
+                    {"value": "finance", "count": 2},
+                    {"value": "home", "count": 2},
+                ],
+                "status": [
+                    {"value": "todo", "count": 3},
+                    {"value": "done", "count": 1},
+                ],
+            })
+        );
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=chores&facets=owner")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     /// Tests search with include_similarity parameter
     #[tokio::test]
     #[serial]
@@ -126,6 +324,30 @@ mod tests {
         assert!(body.contains("\"raw_query\""));
     }
 
+    /// Tests search results include a snippet highlighting the match
+    #[tokio::test]
+    #[serial]
+    async fn it_searches_notes_with_snippet() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=test&snippet_chars=50")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let snippet = json["results"][0]["snippet"].as_str().unwrap();
+        assert!(snippet.contains("<mark>test</mark>"), "{}", snippet);
+    }
+
     /// Tests search returns 400 when query is missing
     #[tokio::test]
     #[serial]
@@ -172,17 +394,18 @@ mod tests {
         assert!(body.contains("\"success\":true"));
     }
 
-    /// Tests viewing a note by ID that exists
+    /// Tests indexing notes streams progress events ending with a
+    /// summary
     #[tokio::test]
     #[serial]
-    async fn it_views_note_by_id() {
+    async fn it_streams_index_progress() {
         let app = test_app().await;
 
-        // The test_app creates a note with ID: 6A503659-15E4-4427-835F-7873F8FF8ECF
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/view")
+                    .uri("/api/notes/index/stream")
+                    .method("POST")
                     .body(Body::empty())
                     .unwrap(),
             )
@@ -190,55 +413,1324 @@ mod tests {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
 
+        // The stream ends when indexing is done
         let body = body_to_string(response.into_body()).await;
-        assert!(body.contains("\"id\""));
+        let events: Vec<serde_json::Value> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+
+        assert_eq!(
+            events,
+            vec![
+                serde_json::json!({"type": "started", "total": 1}),
+                serde_json::json!({
+                    "type": "indexed",
+                    "file_name": "test.org",
+                    "indexed": 1,
+                    "total": 1
+                }),
+                serde_json::json!({"type": "completed", "indexed": 1}),
+            ]
+        );
     }
 
-    /// Tests viewing a note by ID that doesn't exist returns 500 (not ideal, but current behavior)
+    /// Tests indexing that can't start is reported as the last event
+    /// and indexing a note while the index is busy is a conflict
     #[tokio::test]
     #[serial]
-    async fn it_returns_error_for_nonexistent_note() {
-        let app = test_app().await;
+    async fn it_reports_index_failures() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+        // Hold the index like another indexing run would
+        let idx = tantivy::Index::open_in_dir(notes_path.parent().unwrap().join("index")).unwrap();
+        let _writer: tantivy::IndexWriter = idx.writer(15_000_000).unwrap();
 
         let response = app
+            .clone()
             .oneshot(
                 Request::builder()
-                    .uri("/api/notes/nonexistent-id-123/view")
+                    .uri("/api/notes/index/stream")
+                    .method("POST")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let events: Vec<serde_json::Value> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![serde_json::json!({
+                "type": "failed",
+                "error": "Notes are already being indexed"
+            })]
+        );
 
-        // Currently returns 500 due to unhandled None in db.rs (should be 404)
-        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/reindex")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
     }
 
-    /// Tests searching notes with tags:meeting query (used by MeetingSearchTool)
+    /// Tests re-indexing a single note picks up changes to the file
     #[tokio::test]
     #[serial]
-    async fn it_searches_notes_with_meeting_tag() {
+    async fn it_reindexes_note_by_id() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        std::fs::write(
+            notes_path.join("test.org"),
+            r#":PROPERTIES:
+:ID:       6A503659-15E4-4427-835F-7873F8FF8ECF
+:END:
+#+TITLE: this is an updated aardvark
+#+DATE: 2025-01-28
+"#,
+        )
+        .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/reindex")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        assert!(body.contains("\"success\":true"));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=aardvark&include_similarity=false")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let results = json["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["id"], "6A503659-15E4-4427-835F-7873F8FF8ECF");
+        assert_eq!(results[0]["title"], "this is an updated aardvark");
+    }
+
+    /// Tests that a note modified after indexing shows up as stale
+    #[tokio::test]
+    #[serial]
+    async fn it_lists_stale_notes() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        // Nothing is stale right after indexing
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/stale")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["notes"].as_array().unwrap().len(), 0);
+
+        // Touch the note's file so it's newer than the last index
+        let file = std::fs::File::options()
+            .write(true)
+            .open(notes_path.join("test.org"))
+            .unwrap();
+        file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(120))
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/stale")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let notes = json["notes"].as_array().unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0]["id"], "6A503659-15E4-4427-835F-7873F8FF8ECF");
+        assert_eq!(notes[0]["file_name"], "test.org");
+        assert!(notes[0]["last_indexed_at"].is_string());
+
+        // Changes within the threshold are ignored
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/stale?older_than=3600")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["notes"].as_array().unwrap().len(), 0);
+    }
+
+    /// Tests re-indexing a note that doesn't exist returns 404
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_404_when_reindexing_unknown_note() {
         let app = test_app().await;
 
-        // This tests the query format used by MeetingSearchTool
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri("/api/notes/search?query=tags:meeting")
+                    .uri("/api/notes/nonexistent-id-123/reindex")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// Tests snoozing a task rewrites its scheduled date in the file
+    /// and the index
+    #[tokio::test]
+    #[serial]
+    async fn it_snoozes_task() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        std::fs::write(
+            notes_path.join("test.org"),
+            r#":PROPERTIES:
+:ID:       6A503659-15E4-4427-835F-7873F8FF8ECF
+:END:
+#+TITLE: this is a test
+#+DATE: 2025-01-28
+
+* TODO Renew passport
+SCHEDULED: <2025-10-14 Tue>
+:PROPERTIES:
+:ID:       passport-task
+:END:
+"#,
+        )
+        .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/reindex")
+                    .method("POST")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/passport-task/snooze")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"date": "2030-11-15"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
         assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["kind"], "scheduled");
+        assert_eq!(json["date"], "2030-11-15");
+
+        let content = std::fs::read_to_string(notes_path.join("test.org")).unwrap();
+        assert!(content.contains("* TODO Renew passport\nSCHEDULED: <2030-11-15 Fri>\n"));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=passport%20scheduled:2030-11-15&include_similarity=false")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
         let body = body_to_string(response.into_body()).await;
-        assert!(body.contains("\"raw_query\""));
-        assert!(body.contains("\"results\""));
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let results = json["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["id"], "passport-task");
+        assert_eq!(results[0]["task_scheduled"], "2030-11-15");
+    }
+
+    /// Tests relative dates in a search are resolved against today
+    #[tokio::test]
+    #[serial]
+    async fn it_searches_relative_dates() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        // The test app uses UTC
+        let today = chrono::Utc::now().date_naive();
+        let scheduled = |days: i64| {
+            (today + chrono::Duration::days(days))
+                .format("%Y-%m-%d %a")
+                .to_string()
+        };
+        std::fs::write(
+            notes_path.join("test.org"),
+            format!(
+                r#":PROPERTIES:
+:ID:       6A503659-15E4-4427-835F-7873F8FF8ECF
+:END:
+#+TITLE: this is a test
+#+DATE: 2025-01-28
+
+* TODO Water plants
+SCHEDULED: <{}>
+:PROPERTIES:
+:ID:       recent-task
+:END:
+* TODO Clean gutters
+SCHEDULED: <{}>
+:PROPERTIES:
+:ID:       old-task
+:END:
+* TODO Pay rent
+SCHEDULED: <{}>
+:PROPERTIES:
+:ID:       upcoming-task
+:END:
+"#,
+                scheduled(-3),
+                scheduled(-10),
+                scheduled(1)
+            ),
+        )
+        .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/reindex")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=status:todo%20scheduled:%5Btoday-7d%20TO%20today%5D&include_similarity=false")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let ids: Vec<&str> = json["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["recent-task"]);
     }
 
-    // Note: Empty query test is intentionally omitted - it causes a panic in the AQL parser
-    // which is a known bug. The endpoint should return 400 Bad Request instead.
+    /// Tests completing a task marks it done in the index so the task
+    /// tools no longer return it
+    #[tokio::test]
+    #[serial]
+    async fn it_completes_task() {
+        use hq::ai::tools::TasksDueTodayTool;
+        use hq::openai::ToolCall;
+
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        std::fs::write(
+            notes_path.join("test.org"),
+            r#":PROPERTIES:
+:ID:       6A503659-15E4-4427-835F-7873F8FF8ECF
+:END:
+#+TITLE: this is a test
+#+DATE: 2025-01-28
+
+* TODO Book flights
+* TODO Renew passport
+DEADLINE: <2025-10-14 Tue>
+:PROPERTIES:
+:ID:       passport-task
+:END:
+"#,
+        )
+        .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/reindex")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The task tools call the API over HTTP
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_app = app.clone();
+        tokio::spawn(async move { axum::serve(listener, server_app).await.unwrap() });
+        let tool = TasksDueTodayTool::new(&format!("http://{addr}"), chrono_tz::Tz::UTC);
+        assert!(tool.call("{}").await.unwrap().contains("Renew passport"));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/complete")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"heading": "Renew passport"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["id"], "passport-task");
+
+        let content = std::fs::read_to_string(notes_path.join("test.org")).unwrap();
+        assert!(content.contains("* TODO Book flights\n"));
+        assert!(content.contains("* DONE Renew passport\nCLOSED: ["));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=passport%20status:done&include_similarity=false")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let results = json["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["id"], "passport-task");
+
+        assert!(!tool.call("{}").await.unwrap().contains("Renew passport"));
+    }
+
+    /// Tests snoozing with an unparseable date returns 400
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_400_for_invalid_snooze_date() {
+        let app = test_app().await;
+
+        for date in ["someday", "+1é"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/snooze")
+                        .method("POST")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{"date": "{}"}}"#, date)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", date);
+        }
+    }
+
+    /// Tests viewing a note by ID that exists
+    #[tokio::test]
+    #[serial]
+    async fn it_views_note_by_id() {
+        let app = test_app().await;
+
+        // The test_app creates a note with ID: 6A503659-15E4-4427-835F-7873F8FF8ECF
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/view")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        assert!(body.contains("\"id\""));
+        assert!(body.contains("\"last_indexed_at\":\""));
+    }
+
+    /// Tests viewing a note by ID that doesn't exist returns 500 (not ideal, but current behavior)
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_error_for_nonexistent_note() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/nonexistent-id-123/view")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // Currently returns 500 due to unhandled None in db.rs (should be 404)
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    /// Tests viewing the raw org source of a note
+    #[tokio::test]
+    #[serial]
+    async fn it_views_raw_note() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/view?raw=true")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/x-org; charset=utf-8"
+        );
+
+        let body = body_to_string(response.into_body()).await;
+        assert!(body.contains(":ID:       6A503659-15E4-4427-835F-7873F8FF8ECF"));
+        assert!(body.contains("#+TITLE: this is a test"));
+    }
+
+    /// Tests that a path traversal attempt through the raw view does
+    /// not read files outside of the notes directory
+    #[tokio::test]
+    #[serial]
+    async fn it_rejects_raw_view_path_traversal() {
+        let TestApp { app, db, .. } = test_app_fixture().await;
+
+        // Point an existing note at a file outside of the notes
+        // directory so the request gets past the note lookup
+        db.call(|conn| {
+            conn.execute(
+                "UPDATE note_meta SET file_name = '../../../../../../etc/passwd' WHERE id = ?1",
+                ["6A503659-15E4-4427-835F-7873F8FF8ECF"],
+            )?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/view?raw=true")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = body_to_string(response.into_body()).await;
+        assert!(!body.contains("root:"));
+    }
+
+    /// Tests viewing a batch of notes where one ID is missing
+    #[tokio::test]
+    #[serial]
+    async fn it_views_notes_in_batch() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/view")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        r#"{"ids":["6A503659-15E4-4427-835F-7873F8FF8ECF","nonexistent-id-123"]}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let notes = json["notes"].as_array().unwrap();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0]["id"], "6A503659-15E4-4427-835F-7873F8FF8ECF");
+        assert_eq!(notes[0]["title"], "this is a test");
+        assert!(notes[1].is_null());
+    }
+
+    /// Tests searching notes with tags:meeting query (used by MeetingSearchTool)
+    #[tokio::test]
+    #[serial]
+    async fn it_searches_notes_with_meeting_tag() {
+        let app = test_app().await;
+
+        // This tests the query format used by MeetingSearchTool
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/search?query=tags:meeting")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        assert!(body.contains("\"raw_query\""));
+        assert!(body.contains("\"results\""));
+    }
+
+    async fn search_error(query: &str) -> (StatusCode, serde_json::Value) {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/notes/search?query={}", query))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = body_to_string(response.into_body()).await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    /// Tests search returns 400 for an empty query
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_400_for_empty_query() {
+        let (status, body) = search_error("").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "empty_query");
+        assert_eq!(body["error"], "Query is empty");
+    }
+
+    /// Tests search returns 400 for a whitespace only query
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_400_for_whitespace_query() {
+        let (status, body) = search_error("%20%20%09").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "empty_query");
+    }
+
+    /// Tests search returns 400 for a dangling negation
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_400_for_dangling_negation() {
+        let (status, body) = search_error("-").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "syntax");
+        assert_eq!(body["error"], "Invalid query at position 1");
+    }
+
+    /// Tests getting the heading outline of a note
+    #[tokio::test]
+    #[serial]
+    async fn it_gets_note_outline() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        std::fs::write(
+            notes_path.join("test.org"),
+            r#":PROPERTIES:
+:ID:       6A503659-15E4-4427-835F-7873F8FF8ECF
+:END:
+#+TITLE: this is a test
+
+* Projects
+** Outline endpoint
+*** Tests
+* Journal
+"#,
+        )
+        .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/outline")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let outline = json["outline"].as_array().unwrap();
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0]["text"], "Projects");
+        assert_eq!(outline[0]["level"], 1);
+        assert_eq!(outline[0]["line"], 6);
+        assert_eq!(outline[0]["children"][0]["text"], "Outline endpoint");
+        assert_eq!(outline[0]["children"][0]["level"], 2);
+        assert_eq!(outline[0]["children"][0]["children"][0]["text"], "Tests");
+        assert_eq!(outline[0]["children"][0]["children"][0]["level"], 3);
+        assert_eq!(outline[1]["text"], "Journal");
+        assert_eq!(outline[1]["children"].as_array().unwrap().len(), 0);
+    }
+
+    /// Tests getting the outline of a note that doesn't exist
+    #[tokio::test]
+    #[serial]
+    async fn it_returns_404_for_unknown_note_outline() {
+        let app = test_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/does-not-exist/outline")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    const PNG_BYTES: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F,
+        0x15, 0xC4, 0x89,
+    ];
+
+    /// Builds a multipart upload of `data` as the `file` field
+    fn attachment_request(
+        id: &str,
+        file_name: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> Request<Body> {
+        let boundary = "hq-test-boundary";
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: {content_type}\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        Request::builder()
+            .uri(format!("/api/notes/{}/attachments", id))
+            .method("POST")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    /// Tests uploading an image saves it and links it from the note
+    #[tokio::test]
+    #[serial]
+    async fn it_uploads_note_attachment() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        let response = app
+            .clone()
+            .oneshot(attachment_request(
+                "6A503659-15E4-4427-835F-7873F8FF8ECF",
+                "screenshot.png",
+                "image/png",
+                PNG_BYTES,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let file_name = json["file_name"].as_str().unwrap();
+        assert!(file_name.starts_with("attachments/"));
+        assert!(file_name.ends_with("-screenshot.png"));
+        assert_eq!(
+            std::fs::read(notes_path.join(file_name)).unwrap(),
+            PNG_BYTES
+        );
+
+        let link = format!("[[file:{}]]", file_name);
+        assert_eq!(json["link"], link);
+        let note = std::fs::read_to_string(notes_path.join("test.org")).unwrap();
+        assert!(note.ends_with(&format!("{}\n", link)), "{}", note);
+
+        // The note is re-indexed with the new attachment
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/view")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["attachments"], serde_json::json!([file_name]));
+    }
+
+    fn create_note_request(body: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/api/notes")
+            .method("POST")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    /// Tests creating a note writes it with a generated ID and indexes
+    /// it
+    #[tokio::test]
+    #[serial]
+    async fn it_creates_note() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+
+        let response = app
+            .clone()
+            .oneshot(create_note_request(
+                r#"{"title": "Weekly Planning", "body": "Plan the week.", "tags": ["work", "planning"]}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let id = json["id"].as_str().unwrap();
+        assert_eq!(uuid::Uuid::parse_str(id).unwrap().get_version_num(), 4);
+        assert_eq!(json["file_name"], "weekly_planning.org");
+
+        let content = std::fs::read_to_string(notes_path.join("weekly_planning.org")).unwrap();
+        assert_eq!(
+            content,
+            format!(
+                ":PROPERTIES:\n:ID:       {}\n:END:\n#+TITLE: Weekly Planning\n#+FILETAGS: :work:planning:\n\nPlan the week.\n",
+                id
+            )
+        );
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/notes/{}/view", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["title"], "Weekly Planning");
+
+        // An existing note is never overwritten
+        let response = app
+            .clone()
+            .oneshot(create_note_request(r#"{"title": "weekly planning"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        for body in [
+            r#"{"title": "  "}"#,
+            r#"{"title": "Two\nlines"}"#,
+            r#"{"title": "Tagged", "tags": ["has space"]}"#,
+        ] {
+            let response = app
+                .clone()
+                .oneshot(create_note_request(body))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", body);
+        }
+    }
+
+    /// Tests new notes use the configured ID scheme
+    #[tokio::test]
+    #[serial]
+    async fn it_creates_notes_with_configured_id_scheme() {
+        let TestApp { app, .. } = test_app_fixture_with_config(|config| {
+            config.note_id_scheme = NoteIdScheme::Timestamp;
+        })
+        .await;
+        let response = app
+            .oneshot(create_note_request(r#"{"title": "Timestamped"}"#))
+            .await
+            .unwrap();
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let id = json["id"].as_str().unwrap();
+        assert_eq!(id.len(), 18, "{}", id);
+        assert_eq!(&id[8..9], "T");
+        assert!(id.chars().filter(|c| *c != 'T').all(|c| c.is_ascii_digit()));
+
+        // Prefixed IDs count up and continue after notes that already
+        // have the prefix
+        let TestApp {
+            app,
+            db,
+            notes_path,
+        } = test_app_fixture_with_config(|config| {
+            config.note_id_scheme = NoteIdScheme::Prefix(String::from("note-"));
+        })
+        .await;
+        let mut ids = Vec::new();
+        for title in ["First", "Second"] {
+            let response = app
+                .clone()
+                .oneshot(create_note_request(&format!(r#"{{"title": "{}"}}"#, title)))
+                .await
+                .unwrap();
+            let body = body_to_string(response.into_body()).await;
+            let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+            ids.push(json["id"].as_str().unwrap().to_string());
+        }
+        assert_eq!(ids, vec!["note-1", "note-2"]);
+
+        let path = notes_path.join("existing.org");
+        std::fs::write(
+            &path,
+            ":PROPERTIES:\n:ID:       note-41\n:END:\n#+TITLE: Existing\n",
+        )
+        .unwrap();
+        let index_path = notes_path.parent().unwrap().join("index");
+        index_all(
+            &db,
+            index_path.to_str().unwrap(),
+            notes_path.to_str().unwrap(),
+            true,
+            false,
+            Some(vec![path]),
+        )
+        .await
+        .unwrap();
+        let response = app
+            .oneshot(create_note_request(r#"{"title": "Third"}"#))
+            .await
+            .unwrap();
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["id"], "note-42");
+    }
+
+    /// Tests attachments with a type that isn't allowed or over the
+    /// size limit are rejected
+    #[tokio::test]
+    #[serial]
+    async fn it_rejects_invalid_note_attachments() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+        let id = "6A503659-15E4-4427-835F-7873F8FF8ECF";
+
+        let response = app
+            .clone()
+            .oneshot(attachment_request(id, "notes.txt", "text/plain", b"hello"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // The contents and the extension have to match the type
+        let response = app
+            .clone()
+            .oneshot(attachment_request(id, "fake.png", "image/png", b"<script>"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = app
+            .clone()
+            .oneshot(attachment_request(id, "page.html", "image/png", PNG_BYTES))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // The test config limits attachments to 1024 bytes
+        let response = app
+            .clone()
+            .oneshot(attachment_request(id, "big.png", "image/png", &[0u8; 2048]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = app
+            .oneshot(attachment_request(
+                "does-not-exist",
+                "a.png",
+                "image/png",
+                PNG_BYTES,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        assert!(!notes_path.join("attachments").exists());
+        let note = std::fs::read_to_string(notes_path.join("test.org")).unwrap();
+        assert!(!note.contains("[[file:"));
+    }
+
+    /// Runs git in `dir` with an identity so commits work anywhere
+    fn git(dir: &std::path::Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args([
+                "-c",
+                "user.name=Test",
+                "-c",
+                "user.email=test@example.com",
+                "-c",
+                "commit.gpgsign=false",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    /// Tests listing a note's commits and viewing an older revision
+    #[tokio::test]
+    #[serial]
+    async fn it_lists_and_views_note_history() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+        let original = std::fs::read_to_string(notes_path.join("test.org")).unwrap();
+
+        git(&notes_path, &["init", "--quiet"]);
+        git(&notes_path, &["add", "test.org"]);
+        git(&notes_path, &["commit", "--quiet", "-m", "Add test note"]);
+        let first_sha = git(&notes_path, &["rev-parse", "HEAD"]);
+        std::fs::write(
+            notes_path.join("test.org"),
+            format!("{}\n* Added later\n", original),
+        )
+        .unwrap();
+        git(
+            &notes_path,
+            &["commit", "--quiet", "-am", "Update test note"],
+        );
+        let second_sha = git(&notes_path, &["rev-parse", "HEAD"]);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/history")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["file_name"], "test.org");
+        let revisions = json["revisions"].as_array().unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0]["sha"], second_sha);
+        assert_eq!(revisions[0]["message"], "Update test note");
+        assert_eq!(revisions[0]["author"], "Test");
+        assert_eq!(revisions[1]["sha"], first_sha);
+        assert_eq!(revisions[1]["message"], "Add test note");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/history/{}",
+                        first_sha
+                    ))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        assert_eq!(body, original);
+        assert!(!body.contains("Added later"));
+
+        // Unknown commits aren't found and anything other than a hash
+        // is rejected
+        for (sha, status) in [
+            (
+                "0000000000000000000000000000000000000000",
+                StatusCode::NOT_FOUND,
+            ),
+            ("HEAD~1", StatusCode::BAD_REQUEST),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!(
+                            "/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/history/{}",
+                            sha
+                        ))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+        }
+    }
+
+    /// Tests viewing a revision from before the note was renamed
+    #[tokio::test]
+    #[serial]
+    async fn it_views_note_revisions_from_before_a_rename() {
+        let TestApp {
+            app,
+            db,
+            notes_path,
+        } = test_app_fixture().await;
+        let original = std::fs::read_to_string(notes_path.join("test.org")).unwrap();
+
+        git(&notes_path, &["init", "--quiet"]);
+        git(&notes_path, &["add", "test.org"]);
+        git(&notes_path, &["commit", "--quiet", "-m", "Add test note"]);
+        let first_sha = git(&notes_path, &["rev-parse", "HEAD"]);
+        git(&notes_path, &["mv", "test.org", "renamed.org"]);
+        git(&notes_path, &["commit", "--quiet", "-m", "Rename test note"]);
+        db.call(|conn| {
+            conn.execute(
+                "UPDATE note_meta SET file_name = 'renamed.org' WHERE file_name = 'test.org'",
+                [],
+            )?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/history")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let revisions = json["revisions"].as_array().unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[1]["sha"], first_sha);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/history/{}",
+                        first_sha
+                    ))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_to_string(response.into_body()).await;
+        assert_eq!(body, original);
+    }
+
+    /// Tests a note edited through the API isn't reset when pulling
+    /// the notes repo before indexing
+    #[tokio::test]
+    #[serial]
+    async fn it_keeps_api_edits_when_indexing() {
+        let TestApp {
+            app, notes_path, ..
+        } = test_app_fixture().await;
+        std::fs::write(
+            notes_path.join("test.org"),
+            r#":PROPERTIES:
+:ID:       6A503659-15E4-4427-835F-7873F8FF8ECF
+:END:
+#+TITLE: this is a test
+
+* TODO Renew passport
+SCHEDULED: <2025-10-14 Tue>
+:PROPERTIES:
+:ID:       passport-task
+:END:
+"#,
+        )
+        .unwrap();
+
+        // Track the notes in a repo with an origin to pull from
+        let origin_path = notes_path.parent().unwrap().join("origin.git");
+        git(&notes_path, &["init", "--quiet", "--initial-branch=main"]);
+        git(&notes_path, &["add", "test.org"]);
+        git(&notes_path, &["commit", "--quiet", "-m", "Add test note"]);
+        git(
+            &notes_path,
+            &[
+                "clone",
+                "--bare",
+                "--quiet",
+                notes_path.to_str().unwrap(),
+                origin_path.to_str().unwrap(),
+            ],
+        );
+        git(
+            &notes_path,
+            &["remote", "add", "origin", origin_path.to_str().unwrap()],
+        );
+        git(&notes_path, &["fetch", "--quiet", "origin"]);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/6A503659-15E4-4427-835F-7873F8FF8ECF/reindex")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/passport-task/snooze")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"date": "2030-11-15"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The stream ends when indexing is done
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/notes/index/stream")
+                    .method("POST")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        body_to_string(response.into_body()).await;
+
+        let content = std::fs::read_to_string(notes_path.join("test.org")).unwrap();
+        assert!(content.contains("SCHEDULED: <2030-11-15 Fri>"));
+    }
 }
@@ -115,6 +115,7 @@ mod tests {
             "https://api.openai.com",
             "test-api-key",
             "gpt-4o",
+            &openai::CompletionOptions::default(),
         )
         .await;
         assert!(response.is_ok());
@@ -139,6 +140,7 @@ mod tests {
             "https://api.openai.com",
             "test-api-key",
             "gpt-4o",
+            &openai::CompletionOptions::default(),
         )
         .await;
 
@@ -204,6 +206,7 @@ mod tests {
             "https://api.openai.com",
             "test-api-key",
             "gpt-4o",
+            &openai::CompletionOptions::default(),
         )
         .await
         .unwrap();
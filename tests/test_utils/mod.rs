@@ -1,10 +1,12 @@
 //! Test utilities for integration tests
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
 
+use async_trait::async_trait;
 use axum::{
     body::Body,
     Router,
@@ -12,10 +14,23 @@ use axum::{
 
 use hq::api::app;
 use hq::api::AppState;
-use hq::core::AppConfig;
+use hq::core::{AppConfig, NoteIdScheme, Persona};
 use hq::core::db::async_db;
 use hq::core::db::initialize_db;
-use hq::search::index_all;
+use hq::search::embedding::Embedder;
+use hq::search::index_all_with_embedder;
+use hq::search::{SearchMode, SnippetStrategy};
+
+/// Embeds every text as the same vector so tests don't need the
+/// local embedding model
+pub struct FakeEmbedder;
+
+#[async_trait]
+impl Embedder for FakeEmbedder {
+    async fn embed(&self, texts: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|_| vec![0.1; 384]).collect())
+    }
+}
 
 /// Converts a response body to a string
 #[allow(dead_code)] // Otherwise test crates give dead code warning
@@ -32,6 +47,29 @@ pub async fn body_to_string(body: Body) -> String {
 /// --test-threads=1`.
 #[allow(dead_code)] // Otherwise test crates give dead code warning
 pub async fn test_app() -> Router {
+    test_app_fixture().await.app
+}
+
+/// The test application along with the resources it uses so tests
+/// can set up data directly.
+#[allow(dead_code)] // Otherwise test crates give dead code warning
+pub struct TestApp {
+    pub app: Router,
+    pub db: tokio_rusqlite::Connection,
+    pub notes_path: PathBuf,
+}
+
+/// Same as `test_app` but also returns the app's database and notes
+/// directory.
+#[allow(dead_code)] // Otherwise test crates give dead code warning
+pub async fn test_app_fixture() -> TestApp {
+    test_app_fixture_with_config(|_| {}).await
+}
+
+/// Same as `test_app_fixture` but allows changing the app config
+/// before the app is created e.g. to point at a mock server.
+#[allow(dead_code)] // Otherwise test crates give dead code warning
+pub async fn test_app_fixture_with_config(configure: impl FnOnce(&mut AppConfig)) -> TestApp {
     // Create a unique directory for the test with a randomly
     // generated name using a timestamp to avoid collisions and
     // vulnerabilities
@@ -67,7 +105,7 @@ pub async fn test_app() -> Router {
 
     index_dummy_notes_async(&db, dir.clone()).await;
 
-    let app_config = AppConfig {
+    let mut app_config = AppConfig {
         notes_path: notes_path.display().to_string(),
         index_path: index_path.display().to_string(),
         vec_db_path: vec_db_path.to_str().unwrap().to_string(),
@@ -83,9 +121,53 @@ pub async fn test_app() -> Router {
         openai_api_hostname: String::from("https://api.openai.com"),
         openai_api_key: String::from("test-api-key"),
         system_message: String::from("You are a helpful assistant."),
+        openai_max_retries: 3,
+        openai_retry_base_delay_ms: 500,
+        openai_fallbacks: Vec::new(),
+        openai_reasoning_effort: None,
+        search_default_limit: 20,
+        search_max_limit: 100,
+        title_boost: 3.0,
+        tags_boost: 2.0,
+        body_boost: 1.0,
+        snippet_strategy: SnippetStrategy::BestPassage,
+        search_mode: SearchMode::FullText,
+        rerank_url: None,
+        http_user_agent: String::from("hq-test"),
+        http_proxy: None,
+        chat_max_message_tokens: 100,
+        chat_max_concurrent_tools: 4,
+        chat_tool_timeout_secs: 30,
+        chat_stream_coalesce_ms: None,
+        chat_retry_budget: 5,
+        chat_include_tasks: false,
+        chat_store_reasoning: false,
+        chat_max_context_tokens: None,
+        chat_max_sessions: None,
+        push_max_concurrency: 10,
+        attachments_max_bytes: 1024,
+        attachments_allowed_types: vec![String::from("image/png")],
+        note_id_scheme: NoteIdScheme::Uuid,
+        personas: HashMap::from([(
+            String::from("journal"),
+            Persona {
+                system_message: String::from("You are a journaling companion."),
+                tools: Some(vec![String::from("search_notes"), String::from("memory")]),
+                include_tasks: None,
+            },
+        )]),
+        enabled_tools: None,
+        llm_log_path: None,
+        timezone: chrono_tz::Tz::UTC,
     };
-    let app_state = AppState::new(db, app_config);
-    app(Arc::new(RwLock::new(app_state)))
+    configure(&mut app_config);
+    let mut app_state = AppState::new(db.clone(), app_config);
+    app_state.embedder = Arc::new(FakeEmbedder);
+    TestApp {
+        app: app(Arc::new(RwLock::new(app_state))),
+        db,
+        notes_path,
+    }
 }
 
 async fn index_dummy_notes_async(db: &tokio_rusqlite::Connection, temp_dir: PathBuf) {
@@ -111,7 +193,15 @@ async fn index_dummy_notes_async(db: &tokio_rusqlite::Connection, temp_dir: Path
     )
     .unwrap();
 
-    index_all(db, index_dir_path, notes_dir_path, true, true, Some(paths))
-        .await
-        .unwrap();
+    index_all_with_embedder(
+        db,
+        index_dir_path,
+        notes_dir_path,
+        true,
+        Some(&FakeEmbedder),
+        Some(paths),
+        None,
+    )
+    .await
+    .unwrap();
 }
@@ -200,6 +200,16 @@ document.addEventListener('DOMContentLoaded', () => {
                 }
                 try {
                   const parsed = JSON.parse(data);
+
+                  // Tool progress events aren't completion chunks. The
+                  // bubble keeps loading until there is content.
+                  if (
+                    parsed.type === 'tool_start' ||
+                    parsed.type === 'tool_end'
+                  ) {
+                    return;
+                  }
+
                   const content = parsed.choices[0].delta.content;
                   const reasoning = parsed.choices[0].delta.reasoning;
                   const _toolCalls = parsed.choices[0].delta.tool_calls;